@@ -0,0 +1,39 @@
+//! Tempo of an input following its backlog: played faster while a lot is queued and back at its
+//! slowest once it drained, ramped so the speed never jumps.
+
+pub struct AdaptiveTempo {
+    /// Tempo of an input without a backlog
+    pub min: f64,
+    /// Tempo from `full_backlog` on
+    pub max: f64,
+    /// Seconds of backlog at which the tempo reaches its maximum, in between it rises linearly
+    pub full_backlog: f64,
+    /// Largest change of the tempo per second
+    pub ramp: f64,
+    current: f64,
+}
+
+impl AdaptiveTempo {
+    pub fn new(min: f64, max: f64, full_backlog: f64, ramp: f64) -> Self {
+        Self {
+            min,
+            max,
+            full_backlog,
+            ramp,
+            current: min,
+        }
+    }
+
+    pub fn tempo(&self) -> f64 {
+        self.current
+    }
+
+    /// Moves the tempo towards the one of `backlog` seconds queued, by at most the ramp over
+    /// `elapsed` seconds
+    pub fn update(&mut self, backlog: f64, elapsed: f64) {
+        let fill = (backlog / self.full_backlog).clamp(0.0, 1.0);
+        let target = self.min + (self.max - self.min) * fill;
+        let step = self.ramp * elapsed;
+        self.current += (target - self.current).clamp(-step, step);
+    }
+}
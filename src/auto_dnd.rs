@@ -0,0 +1,142 @@
+//! Do-not-disturb that turns itself on while a call is going on, detected by sound on an input or
+//! by a playback stream of the sound server, e.g. the one of a video conferencing app.
+
+use std::{
+    fmt,
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+use anyhow::Context;
+
+use crate::default_sink::pactl;
+
+/// Time without call activity after which do-not-disturb ends, when not given
+pub const DEFAULT_HANG_TIME: Duration = Duration::from_secs(30);
+
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// What tells that a call is going on
+#[derive(Clone, Debug)]
+pub enum CallSource {
+    /// Sound on an input, which keeps playing during the call
+    Input(String),
+    /// A running playback stream whose application or media name contains the pattern, ignoring
+    /// case
+    Stream(String),
+}
+
+impl fmt::Display for CallSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CallSource::Input(input) => write!(f, "sound on {input}"),
+            CallSource::Stream(pattern) => write!(f, "streams matching '{pattern}'"),
+        }
+    }
+}
+
+pub struct AutoDnd {
+    pub source: CallSource,
+    /// Time without activity before the call counts as over, so pauses in the conversation
+    /// don't let everything else through
+    pub hang_time: Duration,
+    /// Whether the current do-not-disturb was started by a call, a manual one is left alone
+    pub engaged: bool,
+    /// Whether do-not-disturb was turned off by hand during the current call
+    pub dismissed: bool,
+    /// When a matching stream was last seen playing
+    last_activity: Option<Instant>,
+    /// Whether a matching stream is playing, reported by the polling thread
+    streams: Option<mpsc::Receiver<bool>>,
+    stream_playing: bool,
+}
+
+impl AutoDnd {
+    pub fn new(source: CallSource, hang_time: Duration) -> Self {
+        let streams = match &source {
+            CallSource::Input(_) => None,
+            CallSource::Stream(pattern) => Some(watch_streams(pattern.to_lowercase())),
+        };
+        Self {
+            source,
+            hang_time,
+            engaged: false,
+            dismissed: false,
+            last_activity: None,
+            streams,
+            stream_playing: false,
+        }
+    }
+
+    /// The input whose sound starts calls
+    pub fn input(&self) -> Option<&str> {
+        match &self.source {
+            CallSource::Input(input) => Some(input),
+            CallSource::Stream(_) => None,
+        }
+    }
+
+    /// Whether a call is going on, given when the call input last had sound
+    pub fn in_call(&mut self, input_sound: Option<SystemTime>) -> bool {
+        if let Some(streams) = &self.streams {
+            if let Some(playing) = streams.try_iter().last() {
+                self.stream_playing = playing;
+            }
+        }
+        if self.stream_playing {
+            self.last_activity = Some(Instant::now());
+        }
+        let streaming = self
+            .last_activity
+            .is_some_and(|last_activity| last_activity.elapsed() < self.hang_time);
+        let talking = input_sound.is_some_and(|last_sound| {
+            SystemTime::now()
+                .duration_since(last_sound)
+                .unwrap_or_default()
+                < self.hang_time
+        });
+        streaming || talking
+    }
+}
+
+/// Polls the playback streams in a thread of its own, reporting changes of whether one matches
+fn watch_streams(pattern: String) -> mpsc::Receiver<bool> {
+    let (sender, changes) = mpsc::channel();
+    thread::spawn(move || {
+        let mut playing = false;
+        loop {
+            match stream_playing(&pattern) {
+                Ok(now_playing) if now_playing != playing => {
+                    // Gone with the auto-dnd that watched it
+                    if sender.send(now_playing).is_err() {
+                        return;
+                    }
+                    playing = now_playing;
+                }
+                Ok(_) => {}
+                Err(error) => eprintln!("<4>Failed to query the playback streams: {error:#}"),
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+    });
+    changes
+}
+
+fn stream_playing(pattern: &str) -> anyhow::Result<bool> {
+    let streams: serde_json::Value =
+        serde_json::from_str(&pactl(&["-f", "json", "list", "sink-inputs"])?)
+            .context("Failed to parse the playback streams listed by pactl")?;
+    Ok(streams.as_array().into_iter().flatten().any(|stream| {
+        // Corked streams are paused
+        stream["corked"] != true
+            && [
+                "application.name",
+                "application.process.binary",
+                "media.name",
+            ]
+            .iter()
+            .filter_map(|property| stream["properties"][property].as_str())
+            .any(|name| name.to_lowercase().contains(pattern))
+    }))
+}
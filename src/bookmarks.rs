@@ -0,0 +1,25 @@
+use std::time::{Duration, SystemTime};
+
+/// Audio exported around a bookmark by default, before and after it
+pub const DEFAULT_EXPORT_MARGIN: Duration = Duration::from_secs(30);
+
+/// Seeking to a bookmark starts this much earlier to give some context
+pub const SEEK_LEAD: Duration = Duration::from_secs(5);
+
+/// Named point in time of an input's audio
+pub struct Bookmark {
+    pub name: String,
+    pub input: String,
+    /// Wall clock time the bookmarked audio was captured at
+    pub time: SystemTime,
+}
+
+impl Bookmark {
+    /// Seconds between capturing the bookmarked audio and now
+    pub fn age(&self) -> f32 {
+        SystemTime::now()
+            .duration_since(self.time)
+            .unwrap_or_default()
+            .as_secs_f32()
+    }
+}
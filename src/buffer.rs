@@ -0,0 +1,488 @@
+use std::{
+    collections::{vec_deque, VecDeque},
+    mem::size_of,
+    ops::{Bound, Index, RangeBounds},
+    path::Path,
+    time::{Duration, SystemTime},
+};
+
+use crate::{segments::item_length, spill::Spill};
+
+/// Largest difference between where one run of samples ends and the next one starts for them to
+/// count as contiguous, covers the jitter of the capture timestamps
+const CONTIGUITY_TOLERANCE: Duration = Duration::from_millis(2);
+
+/// Frames captured runs of samples are merged up to by default, about 85 ms at 48 kHz. An hour of
+/// backlog then takes some 40000 items instead of one per period.
+pub const DEFAULT_BLOCK_FRAMES: usize = 4096;
+
+/// Frames of sound read back at once when spilled items are up next without having been read
+/// back ahead of time, a second at 48 kHz
+const READ_BACK_FRAMES: usize = 48000;
+
+pub enum BufferItem {
+    /// Per-channel samples and the wall clock time the first of them was captured at
+    Samples(Vec<Vec<f32>>, SystemTime),
+    Silence(usize),
+}
+
+/// Queue of an input's audio with running totals, so the scheduler learns the backlog without
+/// walking the queue.
+///
+/// Captured runs of samples that continue the one at the back are appended to it up to
+/// `block_frames` frames, trading the granularity of capture times and silence splitting for
+/// fewer, larger allocations. With `block_frames` at 0 every captured run is an item of its own,
+/// which is what a default `Buffer` does, the inputs of the engine get [`DEFAULT_BLOCK_FRAMES`].
+///
+/// Under memory pressure, items in the middle of the queue are spilled to disk. They are read
+/// back as the items ahead of them play, and are part of the backlog but not of the items
+/// iterated or indexed meanwhile.
+#[derive(Default)]
+pub struct Buffer {
+    items: VecDeque<BufferItem>,
+    /// Frames of sound in the items in memory
+    sample_frames: usize,
+    pub block_frames: usize,
+    /// Items moved to disk, they come right after the first `spill_at` items in memory
+    spill: Option<Spill>,
+    spill_at: usize,
+}
+
+impl Buffer {
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Frames of sound queued, stored silence not included and spilled audio included
+    pub fn sample_frames(&self) -> usize {
+        self.sample_frames + self.spill.as_ref().map_or(0, Spill::sample_frames)
+    }
+
+    /// Channels of the audio in memory, 0 without any
+    pub fn channel_count(&self) -> usize {
+        self.items
+            .iter()
+            .find_map(|item| match item {
+                BufferItem::Samples(samples, _) => Some(samples.len()),
+                BufferItem::Silence(_) => None,
+            })
+            .unwrap_or_default()
+    }
+
+    /// Memory the items take, the spilled ones not included
+    pub fn memory_bytes(&self) -> usize {
+        let channel_count = self.channel_count();
+        // Every item is a slot in the queue plus a Vec per channel
+        let item_overhead = size_of::<BufferItem>() + channel_count * size_of::<Vec<f32>>();
+        self.sample_frames * channel_count * size_of::<f32>() + self.items.len() * item_overhead
+    }
+
+    /// Bytes of spilled audio on disk
+    pub fn spilled_bytes(&self) -> u64 {
+        self.spill.as_ref().map_or(0, Spill::bytes)
+    }
+
+    pub fn iter(&self) -> vec_deque::Iter<'_, BufferItem> {
+        self.items.iter()
+    }
+
+    pub fn range<R: RangeBounds<usize>>(&self, range: R) -> vec_deque::Iter<'_, BufferItem> {
+        self.items.range(range)
+    }
+
+    pub fn front(&self) -> Option<&BufferItem> {
+        self.items.front()
+    }
+
+    pub fn back(&self) -> Option<&BufferItem> {
+        self.items.back()
+    }
+
+    pub fn push_back(&mut self, item: BufferItem) {
+        self.sample_frames += item_length(&item);
+        self.items.push_back(item);
+    }
+
+    pub fn push_front(&mut self, item: BufferItem) {
+        self.sample_frames += item_length(&item);
+        self.items.push_front(item);
+        if self.spill.is_some() {
+            self.spill_at += 1;
+        }
+    }
+
+    /// Takes the item up next, reading spilled items back first if they are
+    pub fn pop_front(&mut self) -> Option<BufferItem> {
+        if self.spill.is_some() && self.spill_at == 0 {
+            if let Err(error) = self.read_back(READ_BACK_FRAMES) {
+                eprintln!("<3>{error:#}, the spilled audio is lost");
+            }
+        }
+        let item = self.items.pop_front()?;
+        self.sample_frames -= item_length(&item);
+        self.spill_at = self.spill_at.saturating_sub(1);
+        Some(item)
+    }
+
+    pub fn drain<R: RangeBounds<usize> + Clone>(
+        &mut self,
+        range: R,
+    ) -> vec_deque::Drain<'_, BufferItem> {
+        self.sample_frames -= self
+            .items
+            .range(range.clone())
+            .map(item_length)
+            .sum::<usize>();
+        let start = match range.start_bound() {
+            Bound::Included(start) => *start,
+            Bound::Excluded(start) => start + 1,
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(end) => end + 1,
+            Bound::Excluded(end) => *end,
+            Bound::Unbounded => self.items.len(),
+        };
+        // The spilled items stay where the drained ones were, if they were around them
+        self.spill_at -= self.spill_at.min(end) - self.spill_at.min(start);
+        self.items.drain(range)
+    }
+
+    /// Empties the queue, spilled items included
+    pub fn clear(&mut self) {
+        self.items.clear();
+        self.sample_frames = 0;
+        self.spill = None;
+        self.spill_at = 0;
+    }
+
+    /// Drops items from the front until `frames` frames of sound are dropped, stopping at the
+    /// spilled ones. Returns the frames dropped.
+    pub fn drop_front(&mut self, frames: usize) -> usize {
+        let limit = match self.spill {
+            Some(_) => self.spill_at,
+            None => self.items.len(),
+        };
+        let mut end = 0;
+        let mut dropped = 0;
+        while end < limit && dropped < frames {
+            dropped += item_length(&self.items[end]);
+            end += 1;
+        }
+        self.drain(..end);
+        dropped
+    }
+
+    /// Moves about `frames` frames of sound to disk, starting after the first `head_frames`
+    /// frames or where the spilled items end. The item at the back stays, captured audio may
+    /// still be appended to it. Returns the frames moved.
+    pub fn spill(
+        &mut self,
+        directory: &Path,
+        head_frames: usize,
+        frames: usize,
+    ) -> anyhow::Result<usize> {
+        let start = match &self.spill {
+            Some(_) => self.spill_at,
+            None => {
+                let mut head = 0;
+                self.items
+                    .iter()
+                    .position(|item| {
+                        let reached = head >= head_frames;
+                        head += item_length(item);
+                        reached
+                    })
+                    .unwrap_or(self.items.len())
+            }
+        };
+        let back = self.items.len().saturating_sub(1);
+        let mut end = start;
+        let mut spilled = 0;
+        while end < back && spilled < frames {
+            spilled += item_length(&self.items[end]);
+            end += 1;
+        }
+        if end <= start {
+            return Ok(0);
+        }
+
+        let mut spill = match self.spill.take() {
+            Some(spill) => spill,
+            None => Spill::create(directory)?,
+        };
+        let result = spill.push(self.items.range(start..end));
+        if !spill.is_empty() {
+            self.spill = Some(spill);
+        }
+        result?;
+        self.spill_at = start;
+        self.sample_frames -= spilled;
+        self.items.drain(start..end);
+        Ok(spilled)
+    }
+
+    /// Reads spilled items back until `head_frames` frames of sound are in memory ahead of the
+    /// rest of them, or all of them are back. The spilled audio is lost if reading fails.
+    pub fn read_back(&mut self, head_frames: usize) -> anyhow::Result<()> {
+        let Some(spill) = self.spill.as_mut() else {
+            return Ok(());
+        };
+        let mut head: usize = self.items.range(..self.spill_at).map(item_length).sum();
+        let result = loop {
+            if head >= head_frames {
+                break Ok(());
+            }
+            match spill.pop() {
+                Ok(Some(item)) => {
+                    head += item_length(&item);
+                    self.sample_frames += item_length(&item);
+                    self.items.insert(self.spill_at, item);
+                    self.spill_at += 1;
+                }
+                Ok(None) => break Ok(()),
+                Err(error) => break Err(error),
+            }
+        };
+        if result.is_err() || spill.is_empty() {
+            self.spill = None;
+            self.spill_at = 0;
+        }
+        result
+    }
+
+    /// Whether the item at the back comes before the spilled ones, so nothing may be added to it
+    fn back_before_spill(&self) -> bool {
+        self.spill.is_some() && self.spill_at == self.items.len()
+    }
+
+    /// Queues captured samples, appended to the item at the back if they continue it and the
+    /// block has room. Returns whether they were queued as a new item.
+    pub fn push_samples(
+        &mut self,
+        samples: Vec<Vec<f32>>,
+        captured_at: SystemTime,
+        sample_rate: usize,
+    ) -> bool {
+        if self.back_before_spill() {
+            self.push_back(BufferItem::Samples(samples, captured_at));
+            return true;
+        }
+        if let Some(BufferItem::Samples(back, back_captured_at)) = self.items.back_mut() {
+            let fits = back[0].len() + samples[0].len() <= self.block_frames;
+            if fits && is_contiguous(back, *back_captured_at, &samples, captured_at, sample_rate) {
+                self.sample_frames += samples[0].len();
+                for (channel, samples) in back.iter_mut().zip(samples) {
+                    channel.extend(samples);
+                }
+                return false;
+            }
+        }
+        self.push_back(BufferItem::Samples(samples, captured_at));
+        true
+    }
+
+    /// Lengthens the stored silence at the back, up to `max_frames`
+    pub fn extend_silence(&mut self, frames: usize, max_frames: usize) {
+        if self.back_before_spill() {
+            return;
+        }
+        if let Some(BufferItem::Silence(length)) = self.items.back_mut() {
+            *length = max_frames.min(*length + frames);
+        }
+    }
+
+    /// Takes the front item off the queue. Runs of samples captured right after the front one are
+    /// merged into it, up to about `max_frames`, so the stretcher gets them as one continuous
+    /// stream with one tempo instead of item by item.
+    pub fn pop_coalesced(&mut self, max_frames: usize, sample_rate: usize) -> Option<BufferItem> {
+        let mut item = self.pop_front()?;
+        if let BufferItem::Samples(samples, captured_at) = &mut item {
+            while samples[0].len() < max_frames {
+                match self.pop_front() {
+                    Some(BufferItem::Samples(next, next_captured_at))
+                        if is_contiguous(
+                            samples,
+                            *captured_at,
+                            &next,
+                            next_captured_at,
+                            sample_rate,
+                        ) =>
+                    {
+                        for (channel, next_channel) in samples.iter_mut().zip(next) {
+                            channel.extend(next_channel);
+                        }
+                    }
+                    Some(other) => {
+                        self.push_front(other);
+                        break;
+                    }
+                    None => break,
+                }
+            }
+        }
+        Some(item)
+    }
+}
+
+impl Index<usize> for Buffer {
+    type Output = BufferItem;
+
+    fn index(&self, index: usize) -> &BufferItem {
+        &self.items[index]
+    }
+}
+
+/// Whether `next` starts where `samples` ends, with the same channels
+fn is_contiguous(
+    samples: &[Vec<f32>],
+    captured_at: SystemTime,
+    next: &[Vec<f32>],
+    next_captured_at: SystemTime,
+    sample_rate: usize,
+) -> bool {
+    let end =
+        captured_at + Duration::from_secs_f64(samples[0].len() as f64 / sample_rate.max(1) as f64);
+    let gap = next_captured_at
+        .duration_since(end)
+        .unwrap_or_else(|error| error.duration());
+    next.len() == samples.len() && gap <= CONTIGUITY_TOLERANCE
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: usize = 48000;
+
+    fn samples(frames: usize) -> Vec<Vec<f32>> {
+        vec![vec![0.5; frames]; 2]
+    }
+
+    /// The level of the samples of an item, to tell items apart
+    fn level(item: &BufferItem) -> f32 {
+        match item {
+            BufferItem::Samples(samples, _) => samples[0][0],
+            BufferItem::Silence(_) => 0.0,
+        }
+    }
+
+    /// Time `frames` frames after `start`
+    fn after(start: SystemTime, frames: usize) -> SystemTime {
+        start + Duration::from_secs_f64(frames as f64 / SAMPLE_RATE as f64)
+    }
+
+    #[test]
+    fn merges_contiguous_runs_up_to_a_block() {
+        let mut buffer = Buffer {
+            block_frames: DEFAULT_BLOCK_FRAMES,
+            ..Buffer::default()
+        };
+        let start = SystemTime::now();
+        for period in 0..8 {
+            buffer.push_samples(samples(1024), after(start, period * 1024), SAMPLE_RATE);
+        }
+        assert_eq!(buffer.len(), 2);
+        assert_eq!(buffer.sample_frames(), 8 * 1024);
+        assert_eq!(item_length(&buffer[0]), DEFAULT_BLOCK_FRAMES);
+    }
+
+    #[test]
+    fn keeps_runs_apart_without_blocks_or_across_gaps() {
+        let start = SystemTime::now();
+        let mut buffer = Buffer::default();
+        assert!(buffer.push_samples(samples(1024), start, SAMPLE_RATE));
+        assert!(buffer.push_samples(samples(1024), after(start, 1024), SAMPLE_RATE));
+        assert_eq!(buffer.len(), 2);
+
+        buffer.block_frames = DEFAULT_BLOCK_FRAMES;
+        // A second later, something was lost in between
+        assert!(buffer.push_samples(samples(1024), after(start, 50000), SAMPLE_RATE));
+        assert_eq!(buffer.len(), 3);
+    }
+
+    #[test]
+    fn keeps_the_running_total_of_sound() {
+        let start = SystemTime::now();
+        let mut buffer = Buffer::default();
+        buffer.push_back(BufferItem::Samples(samples(100), start));
+        buffer.push_back(BufferItem::Silence(50));
+        buffer.push_back(BufferItem::Samples(samples(200), start));
+        buffer.push_front(BufferItem::Samples(samples(10), start));
+        assert_eq!(buffer.sample_frames(), 310);
+
+        buffer.extend_silence(1000, 500);
+        assert_eq!(buffer.sample_frames(), 310);
+        assert_eq!(buffer.drain(1..3).count(), 2);
+        assert_eq!(buffer.sample_frames(), 210);
+        assert_eq!(buffer.drop_front(1), 10);
+        assert_eq!(buffer.sample_frames(), 200);
+        buffer.clear();
+        assert_eq!(buffer.sample_frames(), 0);
+        assert!(buffer.pop_front().is_none());
+    }
+
+    #[test]
+    fn spills_and_reads_back_in_order() {
+        let directory = std::env::temp_dir().join(format!("audiomux-test-{}", std::process::id()));
+        let start = SystemTime::now();
+        let mut buffer = Buffer::default();
+        for index in 0..10 {
+            let samples = vec![vec![index as f32; 1000]; 2];
+            buffer.push_back(BufferItem::Samples(samples, after(start, index * 1000)));
+        }
+
+        assert_eq!(buffer.spill(&directory, 2000, 3000).unwrap(), 3000);
+        assert_eq!(buffer.len(), 7);
+        assert_eq!(buffer.sample_frames(), 10000);
+        // Dropping stops where the spilled items start
+        assert_eq!(buffer.drop_front(5000), 2000);
+        assert_eq!(buffer.sample_frames(), 8000);
+
+        buffer.read_back(1000).unwrap();
+        assert_eq!(buffer.len(), 6);
+        assert_eq!(buffer.sample_frames(), 8000);
+
+        let levels: Vec<f32> = std::iter::from_fn(|| buffer.pop_front())
+            .map(|item| level(&item))
+            .collect();
+        assert_eq!(levels, [2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0, 9.0]);
+        assert_eq!(buffer.sample_frames(), 0);
+        assert_eq!(buffer.spilled_bytes(), 0);
+        std::fs::remove_dir(&directory).unwrap();
+    }
+
+    #[test]
+    fn coalesces_only_contiguous_runs() {
+        let start = SystemTime::now();
+        let mut buffer = Buffer::default();
+        for period in 0..3 {
+            buffer.push_samples(samples(1024), after(start, period * 1024), SAMPLE_RATE);
+        }
+        buffer.push_samples(samples(1024), after(start, 50000), SAMPLE_RATE);
+        buffer.push_back(BufferItem::Silence(480));
+        buffer.push_samples(vec![vec![0.5; 1024]], after(start, 51024), SAMPLE_RATE);
+
+        let lengths: Vec<usize> = std::iter::from_fn(|| buffer.pop_coalesced(4096, SAMPLE_RATE))
+            .map(|item| item_length(&item))
+            .collect();
+        // The gap, the silence and the change of channels all end a run
+        assert_eq!(lengths, [3072, 1024, 0, 1024]);
+    }
+
+    #[test]
+    fn coalesces_up_to_about_the_frames_asked_for() {
+        let start = SystemTime::now();
+        let mut buffer = Buffer::default();
+        for period in 0..3 {
+            buffer.push_samples(samples(1024), after(start, period * 1024), SAMPLE_RATE);
+        }
+        let first = buffer.pop_coalesced(1500, SAMPLE_RATE).unwrap();
+        assert_eq!(item_length(&first), 2048);
+        assert_eq!(buffer.sample_frames(), 1024);
+    }
+}
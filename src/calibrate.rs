@@ -0,0 +1,192 @@
+//! Measures the noise floor of an input while its source is silent, to suggest a silence
+//! threshold just above it, and how long a source takes to react to being paused and resumed.
+
+use std::time::{Duration, Instant};
+
+use crate::{meter::to_db, silence::Level};
+
+/// Time the noise floor is measured for, when not given
+pub const DEFAULT_DURATION: Duration = Duration::from_secs(5);
+
+/// Distance of the suggested threshold above the noise floor
+const MARGIN: f32 = 2.0; // +6 dB
+
+/// Share of the loudest windows ignored as clicks and pops
+const OUTLIER_FRACTION: f32 = 0.01;
+
+/// Lowest suggested threshold, for inputs that are digitally silent
+const MIN_THRESHOLD: f32 = 0.0005;
+
+/// Silence after which a paused source counts as stopped
+const SETTLE_TIME: Duration = Duration::from_millis(500);
+
+/// Longest a source gets to stop after being paused, or to play after being resumed
+const REACTION_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Measurement of the noise floor of an input, fed by the engine thread
+pub struct Calibration {
+    pub input: String,
+    /// The threshold is applied and stored in the config once measured
+    pub apply: bool,
+    window: usize,
+    /// How loud a window is, the way the input's silence detection takes it
+    level: Level,
+    /// Level of each window, allocated up front so measuring never allocates
+    levels: Vec<f32>,
+}
+
+pub struct Measurement {
+    /// Amplitude the input stays below, but for outliers
+    pub noise_floor: f32,
+    pub threshold: f32,
+}
+
+impl Calibration {
+    pub fn new(
+        input: String,
+        duration: Duration,
+        sample_rate: usize,
+        window: usize,
+        level: Level,
+        apply: bool,
+    ) -> Self {
+        let window = window.max(1);
+        let windows = (duration.as_secs_f64() * sample_rate as f64) as usize / window;
+        Self {
+            input,
+            apply,
+            window,
+            level,
+            levels: Vec::with_capacity(windows.max(1)),
+        }
+    }
+
+    /// Adds a captured period
+    pub fn push(&mut self, channels: &[Vec<f32>]) {
+        let frames = channels.first().map_or(0, Vec::len);
+        for start in (0..frames).step_by(self.window) {
+            if self.is_done() {
+                return;
+            }
+            let end = (start + self.window).min(frames);
+            let level = channels
+                .iter()
+                .map(|channel| self.level.of(&channel[start..end]))
+                .fold(0.0f32, f32::max);
+            self.levels.push(level);
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.levels.len() == self.levels.capacity()
+    }
+
+    pub fn measure(mut self) -> Measurement {
+        self.levels.sort_unstable_by(f32::total_cmp);
+        let index = ((self.levels.len() as f32 * (1.0 - OUTLIER_FRACTION)) as usize)
+            .min(self.levels.len().saturating_sub(1));
+        let noise_floor = self.levels.get(index).copied().unwrap_or(0.0);
+        Measurement {
+            noise_floor,
+            threshold: (noise_floor * MARGIN).clamp(MIN_THRESHOLD, 1.0),
+        }
+    }
+}
+
+impl Measurement {
+    pub fn noise_floor_db(&self) -> f32 {
+        to_db(self.noise_floor)
+    }
+
+    pub fn threshold_db(&self) -> f32 {
+        to_db(self.threshold)
+    }
+}
+
+/// Time a source keeps playing after being paused, and takes to play after being resumed
+#[derive(Clone, Copy, Debug)]
+pub struct PauseLatency {
+    pub pause: Duration,
+    pub resume: Duration,
+}
+
+/// Measurement of the pause latency of an input's source. The source is paused when it starts
+/// and resumed once it fell silent, the engine feeds it the captured periods in between.
+pub struct PauseCalibration {
+    pub input: String,
+    /// The latency is applied and stored in the config once measured
+    pub apply: bool,
+    /// Level above which the input counts as playing
+    threshold: f32,
+    resuming: bool,
+    /// When the source was asked to pause or resume
+    requested: Instant,
+    /// When the input last played since then
+    last_sound: Option<Instant>,
+    pause: Duration,
+}
+
+/// What a pause calibration asks for next
+pub enum PauseStep {
+    Wait,
+    /// The source stopped and is to be resumed now
+    Resume,
+    Done(PauseLatency),
+    /// The source didn't react
+    Failed(&'static str),
+}
+
+impl PauseCalibration {
+    /// Starts measuring when the source is asked to pause, right away
+    pub fn new(input: String, threshold: f32, apply: bool) -> Self {
+        Self {
+            input,
+            apply,
+            threshold,
+            resuming: false,
+            requested: Instant::now(),
+            last_sound: None,
+            pause: Duration::ZERO,
+        }
+    }
+
+    /// Adds a captured period
+    pub fn push(&mut self, channels: &[Vec<f32>]) {
+        let playing = channels
+            .iter()
+            .flatten()
+            .any(|sample| sample.abs() > self.threshold);
+        // While resuming only the first sound counts
+        if playing && !(self.resuming && self.last_sound.is_some()) {
+            self.last_sound = Some(Instant::now());
+        }
+    }
+
+    /// Advances the measurement, called regularly
+    pub fn step(&mut self) -> PauseStep {
+        if self.resuming {
+            return match self.last_sound {
+                Some(sound) => PauseStep::Done(PauseLatency {
+                    pause: self.pause,
+                    resume: sound.saturating_duration_since(self.requested),
+                }),
+                None if self.requested.elapsed() > REACTION_TIMEOUT => {
+                    PauseStep::Failed("the source didn't play again after resuming it")
+                }
+                None => PauseStep::Wait,
+            };
+        }
+        let quiet_since = self.last_sound.unwrap_or(self.requested);
+        if quiet_since.elapsed() >= SETTLE_TIME {
+            self.pause = quiet_since.saturating_duration_since(self.requested);
+            self.resuming = true;
+            self.requested = Instant::now();
+            self.last_sound = None;
+            PauseStep::Resume
+        } else if self.requested.elapsed() > REACTION_TIMEOUT {
+            PauseStep::Failed("the source kept playing after pausing it")
+        } else {
+            PauseStep::Wait
+        }
+    }
+}
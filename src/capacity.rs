@@ -0,0 +1,266 @@
+//! How much backlog fits into memory, and whether scheduling keeps up with it.
+
+use std::{
+    mem::size_of,
+    time::{Duration, Instant, SystemTime},
+};
+
+use anyhow::{bail, Context};
+
+use crate::{next_input, BufferItem, Input, Scheduling};
+
+/// Simulated periods the stress test measures
+const STRESS_PERIODS: usize = 20_000;
+
+/// Queue memory an hour of sound takes, in bytes
+pub fn bytes_per_hour(channel_count: usize, sample_rate: usize, item_frames: usize) -> usize {
+    let frames = 3600 * sample_rate;
+    // Every item is a slot in the queue plus a Vec per channel
+    let item_overhead = size_of::<BufferItem>() + channel_count * size_of::<Vec<f32>>();
+    frames * channel_count * size_of::<f32>() + frames.div_ceil(item_frames.max(1)) * item_overhead
+}
+
+/// Memory the kernel considers available for new allocations, in bytes
+pub fn available_memory() -> anyhow::Result<usize> {
+    let meminfo =
+        std::fs::read_to_string("/proc/meminfo").context("Failed to read /proc/meminfo")?;
+    let kilobytes = meminfo
+        .lines()
+        .find_map(|line| line.strip_prefix("MemAvailable:"))
+        .and_then(|value| {
+            value
+                .trim()
+                .trim_end_matches("kB")
+                .trim()
+                .parse::<usize>()
+                .ok()
+        })
+        .context("No MemAvailable in /proc/meminfo")?;
+    Ok(kilobytes * 1024)
+}
+
+/// Formats a number of bytes with a binary prefix
+pub fn format_bytes(bytes: usize) -> String {
+    let mut value = bytes as f64;
+    for unit in ["B", "KiB", "MiB", "GiB"] {
+        if value < 1024.0 {
+            return format!("{value:.1} {unit}");
+        }
+        value /= 1024.0;
+    }
+    format!("{value:.1} TiB")
+}
+
+/// Fills the queues of simulated inputs with hours of audio and measures how long scheduling
+/// one period takes, to check it stays well within the time a period lasts.
+///
+/// Usage: `stress [hours] [inputs] [frames per period] [sample rate] [channels]`, by default two
+/// inputs with two hours each at 256 frames, 48 kHz and two channels.
+pub fn run(mut args: impl Iterator<Item = String>) -> anyhow::Result<()> {
+    let mut next = |what: &str, default: f64| -> anyhow::Result<f64> {
+        match args.next() {
+            Some(arg) => arg
+                .parse()
+                .with_context(|| format!("Invalid {what} '{arg}'")),
+            None => Ok(default),
+        }
+    };
+    let hours = next("number of hours", 2.0)?;
+    let input_count = next("number of inputs", 2.0)? as usize;
+    let period = next("period size", 256.0)? as usize;
+    let sample_rate = next("sample rate", 48000.0)? as usize;
+    let channel_count = next("channel count", 2.0)? as usize;
+    if input_count == 0 || period == 0 || sample_rate == 0 || channel_count == 0 {
+        bail!("Inputs, period size, sample rate and channels must not be zero");
+    }
+
+    let needed = (bytes_per_hour(channel_count, sample_rate, period) as f64
+        * hours
+        * input_count as f64) as usize;
+    let available = available_memory()?;
+    if needed > available {
+        bail!(
+            "The backlog needs {}, only {} is available",
+            format_bytes(needed),
+            format_bytes(available)
+        );
+    }
+    println!(
+        "Filling {input_count} inputs with {hours}h of backlog each, {}",
+        format_bytes(needed)
+    );
+    let periods_per_input = (hours * 3600.0 * sample_rate as f64) as usize / period;
+    let mut stress = Stress::fill(
+        input_count,
+        periods_per_input,
+        period,
+        sample_rate,
+        channel_count,
+    );
+
+    println!("Scheduling {STRESS_PERIODS} periods");
+    let durations = stress.schedule(STRESS_PERIODS);
+    let (median, p99, max) = (
+        percentile(&durations, 0.5),
+        percentile(&durations, 0.99),
+        percentile(&durations, 1.0),
+    );
+    let period_length = stress.period_length;
+    let budget = period_length.as_secs_f64() * 1e6;
+    println!(
+        "Per period: median {:.0}µs, 99th percentile {:.0}µs, max {:.0}µs of {budget:.0}µs",
+        median.as_secs_f64() * 1e6,
+        p99.as_secs_f64() * 1e6,
+        max.as_secs_f64() * 1e6
+    );
+    // The rest of the period belongs to the stretcher, the effects and JACK itself
+    if p99 > period_length / 10 {
+        bail!("Scheduling takes more than a tenth of the period");
+    }
+    println!("Scheduling stays within the period budget");
+    Ok(())
+}
+
+/// Simulated inputs with a filled backlog, played back period by period
+struct Stress {
+    inputs: Vec<Input>,
+    start: SystemTime,
+    period: usize,
+    period_length: Duration,
+    sample_rate: usize,
+    channel_count: usize,
+    periods_captured: usize,
+}
+
+impl Stress {
+    /// Queues `periods` periods of sound on each of `input_count` inputs
+    fn fill(
+        input_count: usize,
+        periods: usize,
+        period: usize,
+        sample_rate: usize,
+        channel_count: usize,
+    ) -> Self {
+        let start = SystemTime::now();
+        let period_length = Duration::from_secs_f64(period as f64 / sample_rate as f64);
+        let mut inputs: Vec<Input> = (0..input_count)
+            .map(|index| Input {
+                name: format!("stress-{index}"),
+                rate: 1.0,
+                ..Default::default()
+            })
+            .collect();
+        for (index, input) in inputs.iter_mut().enumerate() {
+            for number in 0..periods {
+                // Some silence now and then, like speech
+                if number % 50 == 49 {
+                    input.buffer.push_back(BufferItem::Silence(period));
+                    continue;
+                }
+                let level = 0.1 + 0.01 * index as f32;
+                input.buffer.push_samples(
+                    vec![vec![level; period]; channel_count],
+                    start + period_length * number as u32,
+                    sample_rate,
+                );
+            }
+        }
+        Self {
+            inputs,
+            start,
+            period,
+            period_length,
+            sample_rate,
+            channel_count,
+            periods_captured: periods,
+        }
+    }
+
+    /// Runs `periods` periods and returns how long scheduling each took, sorted
+    fn schedule(&mut self, periods: usize) -> Vec<Duration> {
+        let period = self.period;
+        let mut durations = Vec::with_capacity(periods);
+        for _ in 0..periods {
+            let started = Instant::now();
+            // What the engine does per period: capture into every queue, then play from the most
+            // urgent until the period is full
+            let captured_at = self.start + self.period_length * self.periods_captured as u32;
+            for input in self.inputs.iter_mut() {
+                input.buffer.push_samples(
+                    vec![vec![0.1; period]; self.channel_count],
+                    captured_at,
+                    self.sample_rate,
+                );
+            }
+            self.periods_captured += 1;
+            let mut written = 0;
+            while written < period {
+                let Some(index) = next_input(Scheduling::default(), &self.inputs, None) else {
+                    break;
+                };
+                match self.inputs[index]
+                    .buffer
+                    .pop_coalesced(period - written, self.sample_rate)
+                {
+                    Some(BufferItem::Samples(samples, _)) => written += samples[0].len(),
+                    Some(BufferItem::Silence(frames)) => written += frames.min(period - written),
+                    None => break,
+                }
+            }
+            durations.push(started.elapsed());
+        }
+        durations.sort();
+        durations
+    }
+}
+
+/// The duration below which `fraction` of the sorted `durations` lie
+fn percentile(durations: &[Duration], fraction: f64) -> Duration {
+    durations[((durations.len() - 1) as f64 * fraction) as usize]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: usize = 48000;
+    const PERIOD: usize = 256;
+
+    /// Fills two stereo inputs with `minutes` of backlog each and checks the 99th percentile
+    /// of scheduling a period stays within a tenth of it, like `stress` does
+    fn assert_keeps_up(minutes: usize) {
+        let periods = minutes * 60 * SAMPLE_RATE / PERIOD;
+        let mut stress = Stress::fill(2, periods, PERIOD, SAMPLE_RATE, 2);
+        let durations = stress.schedule(STRESS_PERIODS);
+        let budget = stress.period_length / 10;
+        let p99 = percentile(&durations, 0.99);
+        assert!(p99 < budget, "99th percentile {p99:?} over {budget:?}");
+    }
+
+    #[test]
+    fn scheduling_keeps_up_with_a_backlog() {
+        assert_keeps_up(10);
+    }
+
+    #[test]
+    #[ignore = "fills two hours of backlog per input, several GiB"]
+    fn scheduling_keeps_up_with_hours_of_backlog() {
+        assert_keeps_up(120);
+    }
+
+    #[test]
+    fn schedules_a_full_period_while_there_is_backlog() {
+        let mut stress = Stress::fill(2, 100, PERIOD, SAMPLE_RATE, 2);
+        let frames = |stress: &Stress| {
+            stress
+                .inputs
+                .iter()
+                .map(|input| input.buffer.sample_frames())
+                .sum::<usize>()
+        };
+        let before = frames(&stress);
+        stress.schedule(10);
+        // Ten periods captured on each input, ten played in total
+        assert_eq!(frames(&stress), before + 10 * PERIOD);
+    }
+}
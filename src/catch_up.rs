@@ -0,0 +1,73 @@
+use std::{f32::consts::TAU, fmt, str::FromStr, time::Duration};
+
+use anyhow::bail;
+
+use crate::control::parse_duration;
+
+/// Backlog an input needs to have had for catching up with its source to count
+pub const MIN_BACKLOG_SECONDS: f64 = 2.0;
+
+/// Urgency multiplier given to the input focused by `CatchUp::FocusNext`
+pub const FOCUS_BOOST_FACTOR: f32 = 10.0;
+
+const CHIME_FREQUENCY: f32 = 880.0;
+const CHIME_SECONDS: f32 = 0.15;
+const CHIME_LEVEL: f32 = 0.25;
+
+/// What happens when an input has played its backlog and reached its live source
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum CatchUp {
+    /// Carries on with the live audio as it comes in
+    #[default]
+    Live,
+    /// Plays a short tone to mark the transition to live audio
+    Chime,
+    /// Plays nothing at all for a while before the next audio
+    Gap(Duration),
+    /// Boosts the input with the largest backlog until it had time to play it
+    FocusNext,
+}
+
+impl FromStr for CatchUp {
+    type Err = anyhow::Error;
+
+    /// Parses `live`, `chime`, `gap:<duration>` or `next`
+    fn from_str(text: &str) -> anyhow::Result<Self> {
+        Ok(match text {
+            "live" => CatchUp::Live,
+            "chime" => CatchUp::Chime,
+            "next" => CatchUp::FocusNext,
+            _ => match text.strip_prefix("gap:") {
+                Some(duration) => CatchUp::Gap(parse_duration(duration)?),
+                None => bail!(
+                    "Unknown catch-up behavior '{text}', expected live, chime, gap:<duration> \
+                     or next"
+                ),
+            },
+        })
+    }
+}
+
+impl fmt::Display for CatchUp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CatchUp::Live => write!(f, "live"),
+            CatchUp::Chime => write!(f, "chime"),
+            CatchUp::Gap(duration) => write!(f, "gap of {:.1}s", duration.as_secs_f32()),
+            CatchUp::FocusNext => write!(f, "focus next"),
+        }
+    }
+}
+
+/// A short sine tone fading out, on all channels
+pub fn chime(channel_count: usize, sample_rate: usize) -> Vec<Vec<f32>> {
+    let frame_count = (CHIME_SECONDS * sample_rate as f32) as usize;
+    let tone: Vec<f32> = (0..frame_count)
+        .map(|frame| {
+            let time = frame as f32 / sample_rate as f32;
+            let envelope = 1.0 - frame as f32 / frame_count as f32;
+            (TAU * CHIME_FREQUENCY * time).sin() * envelope * envelope * CHIME_LEVEL
+        })
+        .collect();
+    vec![tone; channel_count]
+}
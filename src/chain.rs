@@ -0,0 +1,620 @@
+use std::{
+    f32::consts::PI,
+    fmt,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, bail, Context};
+
+/// Processing step applied to the audio of an input
+pub trait Effect: Send {
+    /// Processes one block of per-channel samples in place
+    fn process(&mut self, channels: &mut [Vec<f32>]);
+
+    /// Changes a parameter at runtime
+    fn set_parameter(&mut self, name: &str, _value: f32) -> anyhow::Result<()> {
+        bail!("Unknown parameter '{name}'")
+    }
+}
+
+/// Builds an effect from the text after the node name, the channel count and the sample rate
+pub type Constructor = fn(&str, usize, usize) -> anyhow::Result<Box<dyn Effect>>;
+
+/// Duration of the crossfade when bypassing a chain in seconds
+const BYPASS_FADE: f32 = 0.02;
+
+/// Name of the pseudo node marking where the speed change happens in a chain
+const STRETCH: &str = "stretch";
+
+/// Weight of the latest period in the smoothed processing time of a node
+const COST_SMOOTHING: f64 = 0.05;
+
+/// Effect nodes by name
+fn registry() -> Vec<(&'static str, Constructor)> {
+    #[allow(unused_mut)]
+    let mut registry: Vec<(&'static str, Constructor)> = vec![
+        ("gate", |text, channel_count, sample_rate| {
+            let parameters = parameters(text)?;
+            Ok(Box::new(Gate::new(
+                parameter(&parameters, 0, -50.0),
+                channel_count,
+                sample_rate,
+            )))
+        }),
+        ("eq", |text, channel_count, sample_rate| {
+            let parameters = parameters(text)?;
+            Ok(Box::new(Equalizer::new(
+                parameter(&parameters, 0, 80.0),
+                parameter(&parameters, 1, 12000.0),
+                channel_count,
+                sample_rate,
+            )?))
+        }),
+        ("agc", |text, _, sample_rate| {
+            let parameters = parameters(text)?;
+            Ok(Box::new(AutomaticGain::new(
+                parameter(&parameters, 0, -20.0),
+                parameter(&parameters, 1, 20.0),
+                sample_rate,
+            )))
+        }),
+        ("limiter", |text, channel_count, sample_rate| {
+            let parameters = parameters(text)?;
+            Ok(Box::new(Limiter::new(
+                parameter(&parameters, 0, -1.0),
+                parameter(&parameters, 1, 1.0) as usize,
+                channel_count,
+                sample_rate,
+            )?))
+        }),
+        ("gain", |text, _, _| {
+            let parameters = parameters(text)?;
+            Ok(Box::new(Gain {
+                factor: db_to_factor(parameter(&parameters, 0, 0.0)),
+            }))
+        }),
+    ];
+    #[cfg(feature = "lv2")]
+    registry.push(("lv2", crate::lv2::create));
+    #[cfg(feature = "ladspa")]
+    registry.push(("ladspa", crate::ladspa::create));
+    #[cfg(feature = "clap-plugins")]
+    registry.push(("clap", crate::clap_plugin::create));
+    registry
+}
+
+/// Parses the `:` separated numeric parameters of a built-in node
+fn parameters(text: &str) -> anyhow::Result<Vec<f32>> {
+    text.split(':')
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            part.parse()
+                .with_context(|| format!("Invalid parameter '{part}'"))
+        })
+        .collect()
+}
+
+fn parameter(parameters: &[f32], index: usize, default: f32) -> f32 {
+    parameters.get(index).copied().unwrap_or(default)
+}
+
+pub fn db_to_factor(db: f32) -> f32 {
+    10.0_f32.powf(db / 20.0)
+}
+
+/// Effect in a chain
+struct Node {
+    /// Registry name, e.g. `gate`
+    name: String,
+    /// Full node description, e.g. `gate:-45`
+    text: String,
+    effect: Box<dyn Effect>,
+    /// Bypassed nodes pass the audio through untouched
+    bypassed: bool,
+    /// Time processing a period takes, smoothed
+    cost: Duration,
+}
+
+/// Ordered effect nodes of an input or the output bus, split at the speed change.
+///
+/// Nodes before `stretch` process the audio as it is captured, nodes after it process the audio
+/// as it is played.
+#[derive(Default)]
+pub struct Chain {
+    capture: Vec<Node>,
+    playback: Vec<Node>,
+    /// Whether the chain contains `stretch`, for display
+    has_stretch: bool,
+    /// Passes the raw audio through the whole chain, for comparing with the processed audio
+    pub bypassed: bool,
+    /// Share of processed audio in the output of each half, faded when bypassing
+    capture_mix: f32,
+    playback_mix: f32,
+    /// Change of the mix per sample while fading
+    fade_step: f32,
+}
+
+impl Chain {
+    /// Parses a comma separated list of `node[:parameter...]`, e.g. `gate:-45,eq:100,stretch,gain:3`.
+    ///
+    /// Without an explicit `stretch` node all effects process the captured audio.
+    pub fn parse(text: &str, channel_count: usize, sample_rate: usize) -> anyhow::Result<Self> {
+        let mut chain = Chain {
+            capture_mix: 1.0,
+            playback_mix: 1.0,
+            fade_step: 1.0 / (BYPASS_FADE * sample_rate as f32),
+            ..Default::default()
+        };
+        for node in text
+            .split(',')
+            .map(str::trim)
+            .filter(|node| !node.is_empty())
+        {
+            let (name, parameters) = node.split_once(':').unwrap_or((node, ""));
+
+            if name == STRETCH {
+                if chain.has_stretch {
+                    bail!("'{STRETCH}' can only appear once in a chain");
+                }
+                chain.has_stretch = true;
+                continue;
+            }
+            let registry = registry();
+            let Some((_, constructor)) = registry.iter().find(|(known, _)| *known == name) else {
+                let known: Vec<_> = registry.iter().map(|(known, _)| *known).collect();
+                bail!(
+                    "Unknown effect '{name}', expected one of {}, {STRETCH}",
+                    known.join(", ")
+                );
+            };
+            let node = Node {
+                name: name.to_string(),
+                text: node.to_string(),
+                effect: constructor(parameters, channel_count, sample_rate)
+                    .with_context(|| format!("Failed to create '{name}'"))?,
+                bypassed: false,
+                cost: Duration::ZERO,
+            };
+            if chain.has_stretch {
+                chain.playback.push(node);
+            } else {
+                chain.capture.push(node);
+            }
+        }
+        Ok(chain)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.capture.is_empty() && self.playback.is_empty()
+    }
+
+    /// Processes captured audio, `bypass_all` bypasses the chain in addition to its own switch
+    pub fn process_capture(&mut self, channels: &mut [Vec<f32>], bypass_all: bool) {
+        let bypassed = self.bypassed || bypass_all;
+        process_faded(
+            &mut self.capture,
+            &mut self.capture_mix,
+            bypassed,
+            self.fade_step,
+            channels,
+        );
+    }
+
+    /// Processes played audio, `bypass_all` bypasses the chain in addition to its own switch
+    pub fn process_playback(&mut self, channels: &mut [Vec<f32>], bypass_all: bool) {
+        let bypassed = self.bypassed || bypass_all;
+        process_faded(
+            &mut self.playback,
+            &mut self.playback_mix,
+            bypassed,
+            self.fade_step,
+            channels,
+        );
+    }
+
+    /// Finds the effect at 1-based position `node` (not counting `stretch`) or the first effect
+    /// named `node`
+    fn find(&mut self, node: &str) -> anyhow::Result<&mut Node> {
+        let mut nodes = self.capture.iter_mut().chain(self.playback.iter_mut());
+        let found = match node.parse::<usize>() {
+            Ok(position) => nodes.nth(position.wrapping_sub(1)),
+            Err(_) => nodes.find(|candidate| candidate.name == node),
+        };
+        found.ok_or_else(|| anyhow!("No effect '{node}' in the chain"))
+    }
+
+    /// Changes a parameter of an effect. Plugin nodes remember the value in their description,
+    /// so it is kept when the chain is saved.
+    pub fn set_parameter(&mut self, node: &str, parameter: &str, value: f32) -> anyhow::Result<()> {
+        let node = self.find(node)?;
+        node.effect
+            .set_parameter(parameter, value)
+            .with_context(|| format!("Failed to set parameter of '{}'", node.name))?;
+        if node.text.contains(';') {
+            let prefix = format!("{parameter}=");
+            let mut parts: Vec<String> = node
+                .text
+                .split(';')
+                .filter(|part| !part.starts_with(&prefix))
+                .map(str::to_string)
+                .collect();
+            parts.push(format!("{prefix}{value}"));
+            node.text = parts.join(";");
+        }
+        Ok(())
+    }
+
+    /// Bypasses an effect or puts it back in, returns whether it is bypassed now
+    pub fn set_bypassed(&mut self, node: &str, bypassed: Option<bool>) -> anyhow::Result<bool> {
+        let node = self.find(node)?;
+        node.bypassed = bypassed.unwrap_or(!node.bypassed);
+        Ok(node.bypassed)
+    }
+
+    /// Smoothed time each effect takes per period, by node description
+    pub fn costs(&self) -> impl Iterator<Item = (&str, Duration)> {
+        self.capture
+            .iter()
+            .chain(&self.playback)
+            .map(|node| (node.text.as_str(), node.cost))
+    }
+
+    /// Node descriptions in the syntax accepted by [`Chain::parse`]
+    pub fn description(&self) -> String {
+        let capture = self.capture.iter().map(|node| node.text.clone());
+        let stretch = self.has_stretch.then(|| STRETCH.to_string());
+        let playback = self.playback.iter().map(|node| node.text.clone());
+        capture
+            .chain(stretch)
+            .chain(playback)
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+}
+
+fn process(nodes: &mut [Node], channels: &mut [Vec<f32>]) {
+    for node in nodes.iter_mut().filter(|node| !node.bypassed) {
+        let started = Instant::now();
+        node.effect.process(channels);
+        node.cost =
+            node.cost.mul_f64(1.0 - COST_SMOOTHING) + started.elapsed().mul_f64(COST_SMOOTHING);
+    }
+}
+
+/// Processes the audio, crossfading between raw and processed audio after the bypass switch
+/// changed so switching doesn't click
+fn process_faded(
+    nodes: &mut [Node],
+    mix: &mut f32,
+    bypassed: bool,
+    fade_step: f32,
+    channels: &mut [Vec<f32>],
+) {
+    if nodes.is_empty() {
+        return;
+    }
+    let target = if bypassed { 0.0 } else { 1.0 };
+    if *mix == target {
+        if !bypassed {
+            process(nodes, channels);
+        }
+        return;
+    }
+
+    let raw = channels.to_vec();
+    process(nodes, channels);
+    let length = channels.first().map_or(0, |channel| channel.len());
+    for index in 0..length {
+        *mix += (target - *mix).clamp(-fade_step, fade_step);
+        for (processed, raw) in channels.iter_mut().zip(&raw) {
+            processed[index] = raw[index] + (processed[index] - raw[index]) * *mix;
+        }
+    }
+}
+
+impl fmt::Display for Chain {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut names: Vec<String> = self.capture.iter().map(node_label).collect();
+        if self.has_stretch {
+            names.push(STRETCH.to_string());
+        }
+        names.extend(self.playback.iter().map(node_label));
+        write!(f, "{}", names.join(" → "))?;
+        if self.bypassed {
+            write!(f, " (bypassed)")?;
+        }
+        Ok(())
+    }
+}
+
+fn node_label(node: &Node) -> String {
+    if node.bypassed {
+        format!("({}, bypassed)", node.text)
+    } else {
+        node.text.clone()
+    }
+}
+
+/// Mutes the input while its level stays below a threshold
+struct Gate {
+    threshold: f32,
+    /// Peak envelope shared by all channels
+    envelope: f32,
+    /// Current gain, faded towards open or closed to avoid clicks
+    gain: f32,
+    release: f32,
+    fade: f32,
+    channel_count: usize,
+}
+
+impl Gate {
+    fn new(threshold_db: f32, channel_count: usize, sample_rate: usize) -> Self {
+        Self {
+            threshold: db_to_factor(threshold_db),
+            envelope: 0.0,
+            gain: 0.0,
+            // ~100 ms envelope release, ~5 ms gain fade
+            release: (-1.0 / (0.1 * sample_rate as f32)).exp(),
+            fade: 1.0 / (0.005 * sample_rate as f32),
+            channel_count,
+        }
+    }
+}
+
+impl Effect for Gate {
+    fn set_parameter(&mut self, name: &str, value: f32) -> anyhow::Result<()> {
+        match name {
+            "threshold" => self.threshold = db_to_factor(value),
+            _ => bail!("Unknown parameter '{name}', expected 'threshold'"),
+        }
+        Ok(())
+    }
+
+    fn process(&mut self, channels: &mut [Vec<f32>]) {
+        let length = channels.first().map_or(0, |channel| channel.len());
+        for index in 0..length {
+            let peak = channels
+                .iter()
+                .take(self.channel_count)
+                .fold(0.0_f32, |peak, channel| peak.max(channel[index].abs()));
+            self.envelope = peak.max(self.envelope * self.release);
+            let target = if self.envelope >= self.threshold {
+                1.0
+            } else {
+                0.0
+            };
+            self.gain += (target - self.gain).clamp(-self.fade, self.fade);
+            for channel in channels.iter_mut() {
+                channel[index] *= self.gain;
+            }
+        }
+    }
+}
+
+/// Removes rumble and hiss with a one-pole high-pass and low-pass filter per channel
+struct Equalizer {
+    high_pass: f32,
+    low_pass: f32,
+    /// Per-channel (previous input, previous high-pass output, previous low-pass output)
+    state: Vec<(f32, f32, f32)>,
+}
+
+impl Equalizer {
+    fn new(
+        low_cut: f32,
+        high_cut: f32,
+        channel_count: usize,
+        sample_rate: usize,
+    ) -> anyhow::Result<Self> {
+        let nyquist = sample_rate as f32 / 2.0;
+        if !(0.0..nyquist).contains(&low_cut) || !(low_cut..=nyquist).contains(&high_cut) {
+            bail!("EQ cutoffs must satisfy 0 <= low < high <= {nyquist} Hz");
+        }
+        let dt = 1.0 / sample_rate as f32;
+        let rc = |cutoff: f32| 1.0 / (2.0 * PI * cutoff);
+        Ok(Self {
+            high_pass: rc(low_cut.max(1.0)) / (rc(low_cut.max(1.0)) + dt),
+            low_pass: dt / (rc(high_cut) + dt),
+            state: vec![(0.0, 0.0, 0.0); channel_count],
+        })
+    }
+}
+
+impl Effect for Equalizer {
+    fn process(&mut self, channels: &mut [Vec<f32>]) {
+        for (channel, (previous_input, high, low)) in channels.iter_mut().zip(&mut self.state) {
+            for sample in channel.iter_mut() {
+                *high = self.high_pass * (*high + *sample - *previous_input);
+                *previous_input = *sample;
+                *low += self.low_pass * (*high - *low);
+                *sample = *low;
+            }
+        }
+    }
+}
+
+/// Slowly pulls the input's loudness towards a target level
+struct AutomaticGain {
+    target: f32,
+    max_gain: f32,
+    /// Mean square of the input, smoothed over a few seconds
+    power: f32,
+    smoothing: f32,
+    gain: f32,
+}
+
+impl AutomaticGain {
+    fn new(target_db: f32, max_gain_db: f32, sample_rate: usize) -> Self {
+        Self {
+            target: db_to_factor(target_db),
+            max_gain: db_to_factor(max_gain_db),
+            power: 0.0,
+            // ~3 s time constant
+            smoothing: 1.0 - (-1.0 / (3.0 * sample_rate as f32)).exp(),
+            gain: 1.0,
+        }
+    }
+}
+
+impl Effect for AutomaticGain {
+    fn set_parameter(&mut self, name: &str, value: f32) -> anyhow::Result<()> {
+        match name {
+            "target" => self.target = db_to_factor(value),
+            "max" => self.max_gain = db_to_factor(value),
+            _ => bail!("Unknown parameter '{name}', expected 'target' or 'max'"),
+        }
+        Ok(())
+    }
+
+    fn process(&mut self, channels: &mut [Vec<f32>]) {
+        let length = channels.first().map_or(0, |channel| channel.len());
+        for index in 0..length {
+            let square = channels
+                .iter()
+                .map(|channel| channel[index] * channel[index])
+                .sum::<f32>()
+                / channels.len() as f32;
+            self.power += (square - self.power) * self.smoothing;
+            // Don't pull up silence
+            if self.power > 1e-8 {
+                let wanted = (self.target / self.power.sqrt()).min(self.max_gain);
+                self.gain += (wanted - self.gain) * self.smoothing;
+            }
+            for channel in channels.iter_mut() {
+                channel[index] *= self.gain;
+            }
+        }
+    }
+}
+
+/// Fixed gain
+struct Gain {
+    factor: f32,
+}
+
+impl Effect for Gain {
+    fn set_parameter(&mut self, name: &str, value: f32) -> anyhow::Result<()> {
+        match name {
+            "db" => self.factor = db_to_factor(value),
+            _ => bail!("Unknown parameter '{name}', expected 'db'"),
+        }
+        Ok(())
+    }
+
+    fn process(&mut self, channels: &mut [Vec<f32>]) {
+        for sample in channels.iter_mut().flatten() {
+            *sample *= self.factor;
+        }
+    }
+}
+
+/// Samples on each side of an interpolated point the oversampling filter looks at
+const INTERPOLATION_TAPS: usize = 4;
+
+/// Keeps the output below a ceiling, reacting instantly to peaks and releasing slowly.
+///
+/// With oversampling the peaks between samples are interpolated as well, those the DAC's
+/// reconstruction filter produces and that clip after it although no sample exceeds the ceiling.
+/// The output is delayed by a few samples, the ones the interpolation looks ahead.
+struct Limiter {
+    ceiling: f32,
+    /// Interpolated points per sample, 1 to only look at the samples
+    oversampling: usize,
+    /// Windowed sinc weights per interpolated point between two samples
+    kernels: Vec<[f32; 2 * INTERPOLATION_TAPS]>,
+    /// Latest samples per channel, the one being limited in the middle
+    history: Vec<[f32; 2 * INTERPOLATION_TAPS]>,
+    gain: f32,
+    release: f32,
+}
+
+impl Limiter {
+    fn new(
+        ceiling_db: f32,
+        oversampling: usize,
+        channel_count: usize,
+        sample_rate: usize,
+    ) -> anyhow::Result<Self> {
+        if ![1, 2, 4].contains(&oversampling) {
+            bail!("Oversampling must be 1, 2 or 4, not {oversampling}");
+        }
+        let kernels = (1..oversampling)
+            .map(|point| {
+                let fraction = point as f32 / oversampling as f32;
+                let mut kernel = [0.0; 2 * INTERPOLATION_TAPS];
+                for (tap, weight) in kernel.iter_mut().enumerate() {
+                    let distance = tap as f32 - (INTERPOLATION_TAPS - 1) as f32 - fraction;
+                    let sinc = (PI * distance).sin() / (PI * distance);
+                    let window = 0.5 + 0.5 * (PI * distance / INTERPOLATION_TAPS as f32).cos();
+                    *weight = sinc * window;
+                }
+                kernel
+            })
+            .collect();
+        Ok(Self {
+            ceiling: db_to_factor(ceiling_db),
+            oversampling,
+            kernels,
+            history: vec![[0.0; 2 * INTERPOLATION_TAPS]; channel_count],
+            gain: 1.0,
+            // ~200 ms release
+            release: 1.0 - (-1.0 / (0.2 * sample_rate as f32)).exp(),
+        })
+    }
+
+    /// Largest magnitude of the delayed sample of a channel and the points up to the next one
+    fn peak(&self, history: &[f32; 2 * INTERPOLATION_TAPS]) -> f32 {
+        self.kernels
+            .iter()
+            .map(|kernel| {
+                kernel
+                    .iter()
+                    .zip(history)
+                    .map(|(weight, sample)| weight * sample)
+                    .sum::<f32>()
+                    .abs()
+            })
+            .fold(history[INTERPOLATION_TAPS - 1].abs(), f32::max)
+    }
+}
+
+impl Effect for Limiter {
+    fn set_parameter(&mut self, name: &str, value: f32) -> anyhow::Result<()> {
+        match name {
+            "ceiling" => self.ceiling = db_to_factor(value),
+            _ => bail!("Unknown parameter '{name}', expected 'ceiling'"),
+        }
+        Ok(())
+    }
+
+    fn process(&mut self, channels: &mut [Vec<f32>]) {
+        let length = channels.first().map_or(0, |channel| channel.len());
+        for index in 0..length {
+            let mut peak = 0.0_f32;
+            for (channel, history) in channels.iter().zip(self.history.iter_mut()) {
+                history.rotate_left(1);
+                history[2 * INTERPOLATION_TAPS - 1] = channel[index];
+            }
+            for history in &self.history {
+                peak = peak.max(if self.oversampling > 1 {
+                    self.peak(history)
+                } else {
+                    history[INTERPOLATION_TAPS - 1].abs()
+                });
+            }
+            let target = if peak > self.ceiling {
+                self.ceiling / peak
+            } else {
+                1.0
+            };
+            if target < self.gain {
+                self.gain = target;
+            } else {
+                self.gain += (target - self.gain) * self.release;
+            }
+            for (channel, history) in channels.iter_mut().zip(&self.history) {
+                channel[index] = history[INTERPOLATION_TAPS - 1] * self.gain;
+            }
+        }
+    }
+}
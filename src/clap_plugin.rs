@@ -0,0 +1,461 @@
+//! CLAP plugin hosting, enabled with the `clap-plugins` feature
+
+use std::{
+    env,
+    ffi::{c_char, c_void, CStr, CString},
+    fs,
+    path::{Path, PathBuf},
+    ptr,
+};
+
+use anyhow::{anyhow, bail, Context};
+use clap_sys::{
+    audio_buffer::clap_audio_buffer,
+    entry::clap_plugin_entry,
+    events::{
+        clap_event_header, clap_event_param_value, clap_input_events, clap_output_events,
+        CLAP_CORE_EVENT_SPACE_ID, CLAP_EVENT_PARAM_VALUE,
+    },
+    ext::params::{clap_param_info, clap_plugin_params, CLAP_EXT_PARAMS},
+    factory::plugin_factory::{clap_plugin_factory, CLAP_PLUGIN_FACTORY_ID},
+    host::clap_host,
+    plugin::clap_plugin,
+    process::clap_process,
+    version::CLAP_VERSION,
+};
+use libloading::Library;
+
+use crate::chain::Effect;
+
+/// Directories searched for plugins when `CLAP_PATH` is not set, besides `~/.clap`
+const DEFAULT_PATH: &str = "/usr/local/lib/clap:/usr/lib/clap";
+
+/// Largest block handed to a plugin at once, longer blocks are split
+const MAX_FRAMES: usize = 8192;
+
+/// Plugin offered by a `.clap` file
+pub struct Discovered {
+    pub path: PathBuf,
+    pub id: String,
+    pub name: String,
+}
+
+/// Parameter of a plugin
+struct Parameter {
+    id: u32,
+    /// Name with everything but letters and digits replaced by `_`
+    name: String,
+    min: f64,
+    max: f64,
+}
+
+/// CLAP plugin, mostly used on the output bus
+pub struct ClapPlugin {
+    plugin: *const clap_plugin,
+    entry: *const clap_plugin_entry,
+    parameters: Vec<Parameter>,
+    /// Parameter changes delivered with the next processed block
+    pending: Vec<clap_event_param_value>,
+    /// Output buffer per channel
+    outputs: Vec<Vec<f32>>,
+    active: bool,
+    /// Plugins may only start processing on the audio thread, so this happens on the first block
+    processing: bool,
+    steady_time: i64,
+    /// Host description handed to the plugin, must stay at the same address
+    _host: Box<clap_host>,
+    _library: Library,
+}
+
+// The raw pointers are owned by the plugin and only used by whoever owns it
+unsafe impl Send for ClapPlugin {}
+
+/// Creates a plugin node from `<file.clap>;<plugin id>[;parameter=value...]`
+pub fn create(
+    text: &str,
+    channel_count: usize,
+    sample_rate: usize,
+) -> anyhow::Result<Box<dyn Effect>> {
+    let mut parts = text.split(';');
+    let (Some(file), Some(id)) = (parts.next(), parts.next()) else {
+        bail!("Usage: clap:<file.clap>;<plugin id>[;parameter=value...]");
+    };
+    let path = if file.contains('/') {
+        PathBuf::from(file)
+    } else {
+        search_path()
+            .into_iter()
+            .map(|directory| directory.join(file))
+            .find(|path| path.exists())
+            .ok_or_else(|| anyhow!("{file} not found in the CLAP search path"))?
+    };
+    let mut plugin = ClapPlugin::load(&path, id, channel_count, sample_rate)?;
+    for part in parts {
+        let (parameter, value) = part
+            .split_once('=')
+            .ok_or_else(|| anyhow!("Expected parameter=value, got '{part}'"))?;
+        let value: f32 = value
+            .parse()
+            .with_context(|| format!("Invalid value for '{parameter}'"))?;
+        plugin.set_parameter(parameter, value)?;
+    }
+    Ok(Box::new(plugin))
+}
+
+fn search_path() -> Vec<PathBuf> {
+    let mut directories: Vec<PathBuf> = env::var("CLAP_PATH")
+        .unwrap_or_else(|_| DEFAULT_PATH.to_string())
+        .split(':')
+        .map(PathBuf::from)
+        .collect();
+    if let Ok(home) = env::var("HOME") {
+        directories.insert(0, Path::new(&home).join(".clap"));
+    }
+    directories
+}
+
+/// Lists the plugins of all `.clap` files in the search path
+pub fn discover() -> Vec<Discovered> {
+    let mut files = Vec::new();
+    for directory in search_path() {
+        collect_files(&directory, &mut files);
+    }
+    let mut discovered = Vec::new();
+    for path in files {
+        // Broken files are skipped, they only matter once somebody tries to use them
+        let Ok((library, entry, factory)) = (unsafe { open(&path) }) else {
+            continue;
+        };
+        unsafe {
+            let factory = &*factory;
+            let count = factory.get_plugin_count.map_or(0, |count| count(factory));
+            for index in 0..count {
+                let Some(descriptor) = factory
+                    .get_plugin_descriptor
+                    .map(|get| get(factory, index))
+                    .filter(|descriptor| !descriptor.is_null())
+                else {
+                    continue;
+                };
+                discovered.push(Discovered {
+                    path: path.clone(),
+                    id: string((*descriptor).id),
+                    name: string((*descriptor).name),
+                });
+            }
+            if let Some(deinit) = (*entry).deinit {
+                deinit();
+            }
+        }
+        drop(library);
+    }
+    discovered
+}
+
+fn collect_files(directory: &Path, files: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(directory) else {
+        return;
+    };
+    for path in entries.flatten().map(|entry| entry.path()) {
+        if path
+            .extension()
+            .is_some_and(|extension| extension == "clap")
+        {
+            files.push(path);
+        } else if path.is_dir() {
+            collect_files(&path, files);
+        }
+    }
+}
+
+/// Loads a `.clap` file and initializes its entry point
+unsafe fn open(
+    path: &Path,
+) -> anyhow::Result<(
+    Library,
+    *const clap_plugin_entry,
+    *const clap_plugin_factory,
+)> {
+    let library =
+        Library::new(path).with_context(|| format!("Failed to load {}", path.display()))?;
+    let entry = *library
+        .get::<*const clap_plugin_entry>(b"clap_entry\0")
+        .with_context(|| format!("{} is not a CLAP plugin", path.display()))?;
+    let path_string = CString::new(path.to_string_lossy().as_bytes())?;
+    if !(*entry).init.is_some_and(|init| init(path_string.as_ptr())) {
+        bail!("Failed to initialize {}", path.display());
+    }
+    let factory = (*entry)
+        .get_factory
+        .map_or(ptr::null(), |get| get(CLAP_PLUGIN_FACTORY_ID.as_ptr()))
+        as *const clap_plugin_factory;
+    if factory.is_null() {
+        if let Some(deinit) = (*entry).deinit {
+            deinit();
+        }
+        bail!("{} has no plugin factory", path.display());
+    }
+    Ok((library, entry, factory))
+}
+
+impl ClapPlugin {
+    pub fn load(
+        path: &Path,
+        id: &str,
+        channel_count: usize,
+        sample_rate: usize,
+    ) -> anyhow::Result<Self> {
+        unsafe {
+            let (library, entry, factory) = open(path)?;
+            let host = Box::new(clap_host {
+                clap_version: CLAP_VERSION,
+                host_data: ptr::null_mut(),
+                name: c"Audio Multiplexer".as_ptr(),
+                vendor: c"audiomux".as_ptr(),
+                url: c"".as_ptr(),
+                version: c"0.1.0".as_ptr(),
+                get_extension: Some(host_get_extension),
+                request_restart: Some(host_request),
+                request_process: Some(host_request),
+                request_callback: Some(host_request),
+            });
+            let id_string = CString::new(id).context("Plugin id contains a null byte")?;
+            let plugin = (*factory).create_plugin.map_or(ptr::null(), |create| {
+                create(factory, &*host, id_string.as_ptr())
+            });
+            if plugin.is_null() {
+                if let Some(deinit) = (*entry).deinit {
+                    deinit();
+                }
+                bail!("{} has no plugin '{id}'", path.display());
+            }
+            let mut loaded = ClapPlugin {
+                plugin,
+                entry,
+                parameters: Vec::new(),
+                pending: Vec::new(),
+                outputs: vec![Vec::new(); channel_count],
+                active: false,
+                processing: false,
+                steady_time: 0,
+                _host: host,
+                _library: library,
+            };
+            if !(*plugin).init.is_some_and(|init| init(plugin)) {
+                bail!("Failed to initialize '{id}'");
+            }
+            loaded.parameters = parameters(plugin);
+            loaded.active = (*plugin)
+                .activate
+                .is_some_and(|activate| activate(plugin, sample_rate as f64, 1, MAX_FRAMES as u32));
+            if !loaded.active {
+                bail!("Failed to activate '{id}'");
+            }
+            Ok(loaded)
+        }
+    }
+
+    fn process_block(&mut self, channels: &mut [Vec<f32>], range: std::ops::Range<usize>) {
+        let length = range.len();
+        let mut inputs: Vec<*mut f32> = channels
+            .iter_mut()
+            .map(|channel| channel[range.clone()].as_mut_ptr())
+            .collect();
+        let mut outputs: Vec<*mut f32> = self
+            .outputs
+            .iter_mut()
+            .map(|output| output[range.clone()].as_mut_ptr())
+            .collect();
+        let input_buffer = clap_audio_buffer {
+            data32: inputs.as_mut_ptr(),
+            data64: ptr::null_mut(),
+            channel_count: inputs.len() as u32,
+            latency: 0,
+            constant_mask: 0,
+        };
+        let mut output_buffer = clap_audio_buffer {
+            data32: outputs.as_mut_ptr(),
+            data64: ptr::null_mut(),
+            channel_count: outputs.len() as u32,
+            latency: 0,
+            constant_mask: 0,
+        };
+        let in_events = clap_input_events {
+            ctx: &mut self.pending as *mut Vec<clap_event_param_value> as *mut c_void,
+            size: Some(input_events_size),
+            get: Some(input_events_get),
+        };
+        let out_events = clap_output_events {
+            ctx: ptr::null_mut(),
+            try_push: Some(output_events_push),
+        };
+        let process = clap_process {
+            steady_time: self.steady_time,
+            frames_count: length as u32,
+            transport: ptr::null(),
+            audio_inputs: &input_buffer,
+            audio_outputs: &mut output_buffer,
+            audio_inputs_count: 1,
+            audio_outputs_count: 1,
+            in_events: &in_events,
+            out_events: &out_events,
+        };
+        unsafe {
+            if let Some(process_function) = (*self.plugin).process {
+                process_function(self.plugin, &process);
+            }
+        }
+        self.pending.clear();
+        self.steady_time += length as i64;
+    }
+}
+
+impl Effect for ClapPlugin {
+    fn set_parameter(&mut self, name: &str, value: f32) -> anyhow::Result<()> {
+        let parameter = self
+            .parameters
+            .iter()
+            .find(|parameter| parameter.name == name)
+            .ok_or_else(|| {
+                let known: Vec<_> = self
+                    .parameters
+                    .iter()
+                    .map(|parameter| parameter.name.as_str())
+                    .collect();
+                anyhow!(
+                    "No parameter '{name}', expected one of {}",
+                    known.join(", ")
+                )
+            })?;
+        self.pending.push(clap_event_param_value {
+            header: clap_event_header {
+                size: std::mem::size_of::<clap_event_param_value>() as u32,
+                time: 0,
+                space_id: CLAP_CORE_EVENT_SPACE_ID,
+                type_: CLAP_EVENT_PARAM_VALUE,
+                flags: 0,
+            },
+            param_id: parameter.id,
+            cookie: ptr::null_mut(),
+            note_id: -1,
+            port_index: -1,
+            channel: -1,
+            key: -1,
+            value: (value as f64).clamp(parameter.min, parameter.max),
+        });
+        Ok(())
+    }
+
+    fn process(&mut self, channels: &mut [Vec<f32>]) {
+        if !self.processing {
+            self.processing = unsafe {
+                (*self.plugin)
+                    .start_processing
+                    .is_some_and(|start| start(self.plugin))
+            };
+        }
+        let length = channels.first().map_or(0, |channel| channel.len());
+        for output in self.outputs.iter_mut() {
+            output.resize(length, 0.0);
+        }
+        for start in (0..length).step_by(MAX_FRAMES) {
+            self.process_block(channels, start..(start + MAX_FRAMES).min(length));
+        }
+        for (channel, output) in channels.iter_mut().zip(&self.outputs) {
+            channel.copy_from_slice(output);
+        }
+    }
+}
+
+impl Drop for ClapPlugin {
+    fn drop(&mut self) {
+        unsafe {
+            let plugin = &*self.plugin;
+            if self.processing {
+                if let Some(stop) = plugin.stop_processing {
+                    stop(self.plugin);
+                }
+            }
+            if let (true, Some(deactivate)) = (self.active, plugin.deactivate) {
+                deactivate(self.plugin);
+            }
+            if let Some(destroy) = plugin.destroy {
+                destroy(self.plugin);
+            }
+            if let Some(deinit) = (*self.entry).deinit {
+                deinit();
+            }
+        }
+    }
+}
+
+unsafe fn parameters(plugin: *const clap_plugin) -> Vec<Parameter> {
+    let extension = (*plugin)
+        .get_extension
+        .map_or(ptr::null(), |get| get(plugin, CLAP_EXT_PARAMS.as_ptr()))
+        as *const clap_plugin_params;
+    if extension.is_null() {
+        return Vec::new();
+    }
+    let (Some(count), Some(get_info)) = ((*extension).count, (*extension).get_info) else {
+        return Vec::new();
+    };
+    (0..count(plugin))
+        .filter_map(|index| {
+            let mut info: clap_param_info = std::mem::zeroed();
+            get_info(plugin, index, &mut info).then(|| Parameter {
+                id: info.id,
+                name: string(info.name.as_ptr())
+                    .chars()
+                    .map(|character| {
+                        if character.is_ascii_alphanumeric() {
+                            character.to_ascii_lowercase()
+                        } else {
+                            '_'
+                        }
+                    })
+                    .collect(),
+                min: info.min_value,
+                max: info.max_value,
+            })
+        })
+        .collect()
+}
+
+unsafe fn string(text: *const c_char) -> String {
+    if text.is_null() {
+        return String::new();
+    }
+    CStr::from_ptr(text).to_string_lossy().into_owned()
+}
+
+unsafe extern "C" fn host_get_extension(
+    _host: *const clap_host,
+    _id: *const c_char,
+) -> *const c_void {
+    ptr::null()
+}
+
+unsafe extern "C" fn host_request(_host: *const clap_host) {}
+
+unsafe extern "C" fn input_events_size(list: *const clap_input_events) -> u32 {
+    (*((*list).ctx as *const Vec<clap_event_param_value>)).len() as u32
+}
+
+unsafe extern "C" fn input_events_get(
+    list: *const clap_input_events,
+    index: u32,
+) -> *const clap_event_header {
+    let events = &*((*list).ctx as *const Vec<clap_event_param_value>);
+    events
+        .get(index as usize)
+        .map_or(ptr::null(), |event| &event.header)
+}
+
+/// Events sent by the plugin (parameter changes from its own GUI and the like) are ignored
+unsafe extern "C" fn output_events_push(
+    _list: *const clap_output_events,
+    _event: *const clap_event_header,
+) -> bool {
+    true
+}
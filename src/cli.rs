@@ -0,0 +1,275 @@
+//! Command line of the engine and its helper subcommands.
+//!
+//! Settings given on the command line override those of the config file.
+
+use std::{path::PathBuf, sync::atomic::Ordering, time::Duration};
+
+use anyhow::Context;
+use clap::{Parser, Subcommand};
+
+use crate::{
+    buffer, capacity,
+    config::{self, Config, InputConfig},
+    control::{self, parse_duration},
+    discover, janitor, journal, latency_test, metrics,
+    net::{Codec, JitterBuffer},
+    setup, socket, Multiplexer, Options, PanicPolicy,
+};
+
+/// Runs what the command line asks for, the engine unless it names a subcommand
+pub fn run() -> anyhow::Result<()> {
+    let cli = Cli::parse();
+    match cli.tool {
+        Some(Tool::LatencyTest { args }) => latency_test::run(args.into_iter()),
+        Some(Tool::Events { args }) => journal::run(args.into_iter()),
+        Some(Tool::Janitor { args }) => janitor::run(args.into_iter()),
+        Some(Tool::Stress { args }) => capacity::run(args.into_iter()),
+        Some(Tool::Discover { args }) => discover::run(args.into_iter()),
+        Some(Tool::Setup { args }) => setup::run(args.into_iter()),
+        Some(Tool::Ctl { args }) => socket::run(args.into_iter()),
+        None => {
+            let (options, config) = cli.into_settings()?;
+            let multiplexer = Multiplexer::new(options, config);
+            let jack_state = multiplexer.jack_state.clone();
+            std::thread::spawn(move || control::serve(std::io::stdin().lock(), jack_state));
+            let shutdown = multiplexer.shutdown.clone();
+            ctrlc::set_handler(move || shutdown.store(true, Ordering::Relaxed))
+                .context("Failed to install the signal handler")?;
+            multiplexer.supervise()
+        }
+    }
+}
+
+#[derive(Parser)]
+#[command(
+    name = "audiomux",
+    about = "Plays several audio inputs one after the other, catching up on what was missed",
+    args_conflicts_with_subcommands = true
+)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub tool: Option<Tool>,
+
+    /// Config file, ~/.config/audiomux/config.toml is read if it exists
+    #[arg(long, value_name = "FILE")]
+    config: Option<PathBuf>,
+    /// Profile of the config to start with
+    #[arg(long, value_name = "NAME")]
+    profile: Option<String>,
+    /// Number of inputs with ports, named 1 to N
+    #[arg(long, value_name = "N")]
+    inputs: Option<usize>,
+    /// Channels of the output and of inputs without a channel count of their own
+    #[arg(long, value_name = "N")]
+    channels: Option<usize>,
+    /// Name of the JACK client
+    #[arg(long, value_name = "NAME")]
+    client_name: Option<String>,
+    /// Seconds of backlog at which sources are paused
+    #[arg(long, value_name = "SECONDS")]
+    pause_threshold: Option<f64>,
+    /// Amplitude below which inputs are considered silent
+    #[arg(long, value_name = "AMPLITUDE")]
+    silence_threshold: Option<f32>,
+
+    /// No status output, diagnostics go to the journal
+    #[arg(long)]
+    headless: bool,
+    /// Keeps the output connected to the default PipeWire sink as it changes
+    #[arg(long)]
+    follow_default_sink: bool,
+    /// Plays the output through the sound server while nothing is connected to its ports
+    #[arg(long)]
+    fallback_output: bool,
+    /// Boosts the input of the application whose window has the focus, on X11, Hyprland and Sway
+    #[arg(long)]
+    focus_follows_window: bool,
+    /// Holds every input and pauses its source while the session is idle or locked, catching up
+    /// once the user is back
+    #[arg(long)]
+    hold_when_away: bool,
+    /// Pauses the sources before the system suspends, keeping their backlog, and reconnects to
+    /// JACK after it woke up
+    #[arg(long)]
+    suspend_aware: bool,
+    /// Address to serve metrics on, by default only when headless
+    #[arg(long, value_name = "ADDRESS")]
+    metrics: Option<String>,
+    /// What happens when processing a period panics: silence, disable-input or abort
+    #[arg(long, value_name = "POLICY", default_value = "silence")]
+    on_panic: PanicPolicy,
+    /// Starts inputs with ports disabled, enabling them when something connects within this time
+    #[arg(long, value_name = "TIME", value_parser = parse_duration)]
+    auto_arm: Option<Duration>,
+    /// Queues captured audio in items of up to this many frames, 0 for an item per period
+    #[arg(long, value_name = "FRAMES", default_value_t = buffer::DEFAULT_BLOCK_FRAMES)]
+    block_frames: usize,
+    /// Shared memory file to publish the state frames in, e.g. in /dev/shm
+    #[arg(long, value_name = "FILE")]
+    shm: Option<PathBuf>,
+    /// Where the session report is written on shutdown
+    #[arg(long, value_name = "FILE")]
+    report: Option<PathBuf>,
+    /// Receiving instance to stream the output and timeline to
+    #[arg(long, value_name = "ADDRESS")]
+    send: Option<String>,
+    /// Encoding of the streamed output
+    #[arg(long, value_name = "CODEC", default_value = "pcm")]
+    send_codec: Codec,
+    /// Audio held back from the received stream, `<size>` or `<min>:<max>`
+    #[arg(long, value_name = "SIZE", value_parser = JitterBuffer::parse)]
+    jitter_buffer: Option<JitterBuffer>,
+    /// Address to receive another instance's stream on, played as the `remote` input
+    #[arg(long, value_name = "ADDRESS")]
+    receive: Option<String>,
+    /// File the timeline events are appended to, `off` for none
+    #[arg(long, value_name = "FILE")]
+    journal: Option<String>,
+    /// Unix socket taking JSON commands, `off` for none. By default `audiomux.sock` in
+    /// `$XDG_RUNTIME_DIR`, without it the socket must be named.
+    #[arg(long, value_name = "FILE")]
+    control_socket: Option<String>,
+}
+
+#[derive(Subcommand)]
+pub enum Tool {
+    /// Measures the round trip latency through what is connected between two test ports
+    LatencyTest {
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+    /// Prints the journal entries of a time range
+    Events {
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+    /// Resumes the sources left paused by an engine once it exits, started by the engine
+    #[command(hide = true)]
+    Janitor {
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+    /// Measures whether scheduling keeps up with hours of backlog
+    Stress {
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+    /// Writes a starter config from the running applications and media players, `default` as
+    /// the file writes where the engine reads it
+    Discover {
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+    /// Walks through choosing the inputs, trying out pausing them and calibrating the silence
+    /// threshold, then writes the config
+    Setup {
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+    /// Inspects and changes the running engine through its control socket, e.g. `ctl status` or
+    /// `ctl set-gain music -6`
+    Ctl {
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+}
+
+impl Cli {
+    /// The engine's options and its config, the file loaded and overridden by the arguments
+    fn into_settings(self) -> anyhow::Result<(Options, Config)> {
+        let config_path = self.config.clone().or_else(|| {
+            let path = config::default_path();
+            path.exists().then_some(path)
+        });
+        let mut config = match self.config {
+            Some(path) => Config::load(&path)?,
+            None => {
+                let path = config::default_path();
+                if path.exists() {
+                    Config::load(&path)?
+                } else {
+                    if !self.headless {
+                        eprintln!(
+                            "No config at {}, `audiomux setup` walks through writing one",
+                            path.display()
+                        );
+                    }
+                    Config::default()
+                }
+            }
+        };
+        if let Some(count) = self.inputs {
+            // Inputs declared in the config keep their settings
+            config.inputs = (1..=count)
+                .map(|number| {
+                    let name = number.to_string();
+                    config
+                        .inputs
+                        .iter()
+                        .find(|input| input.name == name)
+                        .cloned()
+                        .unwrap_or_else(|| InputConfig::new(&name))
+                })
+                .collect();
+        }
+        if let Some(channels) = self.channels {
+            config.channels = channels;
+        }
+        if let Some(client_name) = self.client_name {
+            config.client_name = client_name;
+        }
+        if let Some(threshold) = self.pause_threshold {
+            for pausing in config
+                .inputs
+                .iter_mut()
+                .filter_map(|input| input.pausing.as_mut())
+            {
+                pausing.pause_backlog = threshold;
+            }
+        }
+        if let Some(threshold) = self.silence_threshold {
+            config.silence_threshold = threshold;
+        }
+        config.validate()?;
+        // Fails for unknown profiles
+        config.with_profile(self.profile.as_deref())?;
+
+        let control_socket = match self.control_socket.as_deref() {
+            Some("off") => None,
+            Some(path) => Some(PathBuf::from(path)),
+            None => Some(
+                socket::default_path()
+                    .context("No default control socket, pass --control-socket <file> or off")?,
+            ),
+        };
+        let options = Options {
+            headless: self.headless,
+            metrics_address: self
+                .metrics
+                .or_else(|| self.headless.then(|| metrics::DEFAULT_ADDRESS.to_string())),
+            journal: match self.journal.as_deref() {
+                Some("off") => None,
+                Some(path) => Some(PathBuf::from(path)),
+                None => Some(journal::default_path()),
+            },
+            control_socket,
+            panic_policy: self.on_panic,
+            report: self.report,
+            send_address: self.send,
+            send_codec: self.send_codec,
+            receive_address: self.receive,
+            jitter_buffer: self.jitter_buffer.unwrap_or_default(),
+            auto_arm: self.auto_arm,
+            follow_default_sink: self.follow_default_sink,
+            fallback_output: self.fallback_output,
+            focus_follows_window: self.focus_follows_window,
+            hold_when_away: self.hold_when_away,
+            suspend_aware: self.suspend_aware,
+            block_frames: self.block_frames,
+            shm: self.shm,
+            profile: self.profile,
+            config_path,
+        };
+        Ok((options, config))
+    }
+}
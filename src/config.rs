@@ -0,0 +1,1097 @@
+//! Setup loaded at startup: the output channels, the inputs and how their sources are paused,
+//! and the speed change engine.
+//!
+//! ```toml
+//! client_name = "Audio Multiplexer"
+//! channels = 2
+//! silence_threshold = 0.01
+//! engine = "soundtouch"
+//! scheduling = "urgency"
+//! crossfade = 0.02
+//!
+//! [mixing]
+//! duck_db = -15.0
+//! attack = 0.05
+//! release = 0.8
+//!
+//! [memory]
+//! budget_mib = 2048
+//! pressure = 0.9
+//! actions = ["pause-sources", "spill", "drop-oldest"]
+//!
+//! [hurry_up]
+//! bpm = 100.0
+//! full_backlog = 120.0
+//!
+//! [midi_control]
+//! connect = ["a2j:nanoKONTROL2 [20] (capture): nanoKONTROL2 MIDI 1"]
+//!
+//! [[midi_control.mappings]]
+//! controller = 0
+//! input = "music"
+//! action = "gain"
+//!
+//! [[midi_control.mappings]]
+//! controller = 32
+//! channel = 1
+//! input = "music"
+//! action = "mute"
+//!
+//! [soundtouch]
+//! sequence_ms = 40
+//! quick_seek = true
+//!
+//! [[inputs]]
+//! name = "mic"
+//! channels = 1
+//! gain = 6.0
+//! silence_threshold = 0.002
+//! connect = ["system:capture_1"]
+//! priority = 1
+//!
+//! [[inputs]]
+//! name = "music"
+//! silence_threshold_db = -50.0
+//! silence_level = "rms"
+//! silence_hysteresis = 6.0
+//! silence_policy = "any"
+//! silence_channels = [0]
+//! silence_window_ms = 10.0
+//! min_silence = 0.5
+//! max_silence = 0.3
+//! window = "spotify"
+//!
+//! [inputs.pausing]
+//! pause_backlog = 1.0
+//! resume_backlog = 0.1
+//! player = "spotify"
+//!
+//! [inputs.tempo]
+//! max_tempo = 1.3
+//! full_backlog = 20.0
+//!
+//! [inputs.cue]
+//! say = "music"
+//! after_gap = 60.0
+//!
+//! [inputs.drop_filler]
+//! backlog = 600.0
+//! monotony_db = 1.0
+//!
+//! [[inputs]]
+//! name = "notifications"
+//!
+//! [inputs.suppress_duplicates]
+//! window = 30.0
+//!
+//! [[inputs]]
+//! name = "podcast"
+//! preset = "podcast"
+//!
+//! [profiles.radio-logging]
+//! silence_threshold = 0.003
+//!
+//! [[profiles.radio-logging.inputs]]
+//! name = "radio"
+//! connect = ["radio:out_l", "radio:out_r"]
+//! ```
+//!
+//! Everything is optional, left out settings are the ones used without a config file. Profiles
+//! replace the settings they contain, the inputs as a whole. They are selected with `--profile`
+//! and switched with the `profile` command.
+
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context};
+use serde::Deserialize;
+
+use crate::{
+    chain::db_to_factor,
+    control::{check_range, GAIN_RANGE, SPEED_RANGE},
+    crossfade::{DEFAULT_CROSSFADE, MAX_CROSSFADE},
+    cue::Tone,
+    duplicates::MAX_WINDOW,
+    memory::PressureAction,
+    meter::to_db,
+    midi_control::{self, MidiAction},
+    scheduling::Scheduling,
+    silence::{Level, SilencePolicy, SILENCE_THRESHOLD},
+    stretch::{Engine, SoundTouchSettings},
+};
+
+/// Where the config is read from when no file is given, if it exists
+pub fn default_path() -> PathBuf {
+    let config_home = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+        .unwrap_or_default();
+    config_home.join("audiomux").join("config.toml")
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Name of the JACK client, prefixes the port names
+    pub client_name: String,
+    /// Channels of the output, inputs with fewer channels are spread over them
+    pub channels: usize,
+    /// Amplitude below which inputs are considered silent
+    pub silence_threshold: f32,
+    /// Engine changing the playback speed
+    pub engine: Engine,
+    pub soundtouch: SoundTouchSettings,
+    /// Policy picking the input to play next: `urgency`, `priority`, `round-robin` or
+    /// `least-recent`
+    pub scheduling: Scheduling,
+    /// Seconds the input switched away from fades out while the next one fades in, up to 0.1
+    pub crossfade: f64,
+    /// Plays all inputs at once, those of higher priority tiers ducking the others, instead of
+    /// one after the other. Off without the section.
+    pub mixing: Option<MixingConfig>,
+    /// Limit on the memory audio takes, none without the section
+    pub memory: Option<MemoryConfig>,
+    /// MIDI clock and control voltage showing how far behind the playing input is, no ports
+    /// without the section
+    pub hurry_up: Option<HurryUpConfig>,
+    /// Controllers setting the gain, tempo and mute of inputs, no MIDI port without the section
+    pub midi_control: Option<MidiControlConfig>,
+    /// Inputs with JACK ports, the generator, file and remote inputs are always there
+    pub inputs: Vec<InputConfig>,
+    pub profiles: BTreeMap<String, Profile>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            client_name: "Audio Multiplexer".to_string(),
+            channels: 2,
+            silence_threshold: SILENCE_THRESHOLD,
+            engine: Engine::SoundTouch,
+            soundtouch: SoundTouchSettings::default(),
+            scheduling: Scheduling::default(),
+            crossfade: DEFAULT_CROSSFADE.as_secs_f64(),
+            mixing: None,
+            memory: None,
+            hurry_up: None,
+            midi_control: None,
+            inputs: vec![
+                InputConfig::new("1"),
+                InputConfig {
+                    pausing: Some(PausingConfig::default()),
+                    ..InputConfig::new("2")
+                },
+            ],
+            profiles: BTreeMap::new(),
+        }
+    }
+}
+
+impl Config {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let mut config: Config =
+            toml::from_str(&text).with_context(|| format!("Failed to parse {}", path.display()))?;
+        config.apply_presets();
+        config
+            .validate()
+            .with_context(|| format!("Invalid config {}", path.display()))?;
+        for name in config.profiles.keys() {
+            config
+                .with_profile(Some(name))?
+                .validate()
+                .with_context(|| format!("Invalid profile '{name}' in {}", path.display()))?;
+        }
+        Ok(config)
+    }
+
+    /// The settings with those of a profile in place of the general ones
+    pub fn with_profile(&self, name: Option<&str>) -> anyhow::Result<Config> {
+        let mut config = self.clone();
+        let Some(name) = name else {
+            return Ok(config);
+        };
+        let Some(profile) = self.profiles.get(name) else {
+            let known: Vec<&str> = self.profiles.keys().map(String::as_str).collect();
+            bail!(
+                "Unknown profile '{name}', expected one of: {}",
+                known.join(", ")
+            );
+        };
+        let profile = profile.clone();
+        if let Some(threshold) = profile.silence_threshold {
+            config.silence_threshold = threshold;
+        }
+        if let Some(engine) = profile.engine {
+            config.engine = engine;
+        }
+        if let Some(soundtouch) = profile.soundtouch {
+            config.soundtouch = soundtouch;
+        }
+        if let Some(scheduling) = profile.scheduling {
+            config.scheduling = scheduling;
+        }
+        if let Some(inputs) = profile.inputs {
+            config.inputs = inputs;
+        }
+        Ok(config)
+    }
+
+    /// Silence threshold of the input, its own if it has one
+    pub fn silence_threshold_of(&self, input: &str) -> f32 {
+        self.inputs
+            .iter()
+            .find(|declared| declared.name == input)
+            .and_then(InputConfig::threshold)
+            .unwrap_or(self.silence_threshold)
+    }
+
+    /// Fills in the settings of the presets of the inputs, those of the profiles included
+    pub fn apply_presets(&mut self) {
+        let profile_inputs = self
+            .profiles
+            .values_mut()
+            .flat_map(|profile| profile.inputs.iter_mut().flatten());
+        for input in self.inputs.iter_mut().chain(profile_inputs) {
+            input.apply_preset();
+        }
+    }
+
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.client_name.is_empty() {
+            bail!("The client name must not be empty");
+        }
+        if self.channels == 0 {
+            bail!("The output needs at least one channel");
+        }
+        if !(0.0..=1.0).contains(&self.silence_threshold) {
+            bail!("The silence threshold is an amplitude between 0 and 1");
+        }
+        if !(0.0..=MAX_CROSSFADE.as_secs_f64()).contains(&self.crossfade) {
+            bail!(
+                "The crossfade is between 0 and {} seconds",
+                MAX_CROSSFADE.as_secs_f64()
+            );
+        }
+        if let Some(mixing) = &self.mixing {
+            if mixing.duck_db > 0.0 || mixing.threshold_db > 0.0 {
+                bail!("The ducking depth and threshold are at most 0 dB");
+            }
+            if mixing.attack < 0.0 || mixing.release < 0.0 {
+                bail!("The ducking attack and release are not negative");
+            }
+        }
+        let soundtouch = std::iter::once(&self.soundtouch).chain(
+            self.profiles
+                .values()
+                .filter_map(|profile| profile.soundtouch.as_ref()),
+        );
+        for settings in soundtouch {
+            if settings
+                .anti_alias_length
+                .is_some_and(|taps| !(8..=128).contains(&taps) || taps % 4 != 0)
+            {
+                bail!("The anti-alias filter length is a multiple of 4 from 8 to 128");
+            }
+        }
+        if let Some(memory) = &self.memory {
+            if memory.budget_mib <= 0.0 {
+                bail!("The memory budget is positive");
+            }
+            if memory.pressure <= 0.0 || memory.pressure > 1.0 {
+                bail!("The memory pressure is a fraction of the budget, above 0 and up to 1");
+            }
+        }
+        if let Some(hurry_up) = &self.hurry_up {
+            if hurry_up.bpm <= 0.0 || hurry_up.full_backlog <= 0.0 {
+                bail!("The hurry-up tempo and full backlog are positive");
+            }
+        }
+        for mapping in self
+            .midi_control
+            .iter()
+            .flat_map(|midi_control| &midi_control.mappings)
+        {
+            mapping.validate()?;
+        }
+        for (index, input) in self.inputs.iter().enumerate() {
+            if input.name.is_empty() {
+                bail!("Input {} has no name", index + 1);
+            }
+            if self.inputs[..index]
+                .iter()
+                .any(|other| other.name == input.name)
+            {
+                bail!("Input '{}' is declared twice", input.name);
+            }
+            if input.channels == Some(0) {
+                bail!("Input '{}' needs at least one channel", input.name);
+            }
+            if input
+                .silence_threshold
+                .is_some_and(|threshold| !(0.0..=1.0).contains(&threshold))
+            {
+                bail!(
+                    "The silence threshold of input '{}' is an amplitude between 0 and 1",
+                    input.name
+                );
+            }
+            if input.silence_threshold.is_some() && input.silence_threshold_db.is_some() {
+                bail!(
+                    "Input '{}' has a silence threshold both as an amplitude and in dBFS",
+                    input.name
+                );
+            }
+            if input.silence_threshold_db.is_some_and(|db| db > 0.0) {
+                bail!(
+                    "The silence threshold of input '{}' is at most 0 dBFS",
+                    input.name
+                );
+            }
+            if input.silence_hysteresis < 0.0 {
+                bail!(
+                    "The silence hysteresis of input '{}' is negative",
+                    input.name
+                );
+            }
+            if let SilencePolicy::Weighted(weights) = &input.silence_policy {
+                if weights.iter().any(|weight| *weight < 0.0) {
+                    bail!("The silence weights of input '{}' are negative", input.name);
+                }
+            }
+            if let Some(channels) = &input.silence_channels {
+                if channels.is_empty() {
+                    bail!(
+                        "Input '{}' detects silence on no channel, leave silence_channels out \
+                         for all of them",
+                        input.name
+                    );
+                }
+                if let Some(&channel) = channels
+                    .iter()
+                    .find(|&&channel| input.channels.is_some_and(|count| channel >= count))
+                {
+                    bail!("Input '{}' has no channel {channel}", input.name);
+                }
+            }
+            if input.silence_window_ms.is_some_and(|window| window < 0.0) {
+                bail!("The silence window of input '{}' is negative", input.name);
+            }
+            if input.min_silence < 0.0 || input.max_silence.is_some_and(|max| max < 0.0) {
+                bail!(
+                    "The silence durations of input '{}' are negative",
+                    input.name
+                );
+            }
+            if let Some(pausing) = &input.pausing {
+                if pausing.resume_backlog > pausing.pause_backlog {
+                    bail!(
+                        "Input '{}' resumes at a larger backlog than it pauses at",
+                        input.name
+                    );
+                }
+                if pausing.pause_command.is_some() != pausing.resume_command.is_some() {
+                    bail!(
+                        "Input '{}' needs both a pause and a resume command",
+                        input.name
+                    );
+                }
+                if pausing.player.is_some() && pausing.pause_command.is_some() {
+                    bail!(
+                        "Input '{}' is paused either through a player or by commands",
+                        input.name
+                    );
+                }
+                if pausing.pause_latency < 0.0 || pausing.resume_latency < 0.0 {
+                    bail!("The pause latencies of input '{}' are negative", input.name);
+                }
+            }
+            if let Some(tempo) = &input.tempo {
+                if !(0.25..=4.0).contains(&tempo.min_tempo())
+                    || !(0.25..=4.0).contains(&tempo.max_tempo())
+                {
+                    bail!(
+                        "The tempo of input '{}' is between 0.25 and 4.0",
+                        input.name
+                    );
+                }
+                if tempo.min_tempo() > tempo.max_tempo() {
+                    bail!(
+                        "The minimum tempo of input '{}' is above its maximum",
+                        input.name
+                    );
+                }
+                if tempo.full_backlog() <= 0.0 || tempo.ramp() <= 0.0 {
+                    bail!(
+                        "The full backlog and ramp of input '{}' are positive",
+                        input.name
+                    );
+                }
+            }
+            if let Some(cue) = &input.cue {
+                if cue.after_gap < 0.0 || cue.min_interval < 0.0 {
+                    bail!(
+                        "The gap and interval of the cue of input '{}' are negative",
+                        input.name
+                    );
+                }
+            }
+            if let Some(filler) = &input.drop_filler {
+                if filler.backlog < 0.0 || filler.min_monotonous < 0.0 || filler.min_repeated < 0.0
+                {
+                    bail!(
+                        "The filler backlog and durations of input '{}' are negative",
+                        input.name
+                    );
+                }
+                if filler.max_segment <= 0.0 {
+                    bail!(
+                        "The longest filler segment of input '{}' is positive",
+                        input.name
+                    );
+                }
+                if !(-1.0..=1.0).contains(&filler.repeat_similarity) {
+                    bail!(
+                        "The repeat similarity of input '{}' is between -1 and 1",
+                        input.name
+                    );
+                }
+            }
+            if let Some(duplicates) = &input.suppress_duplicates {
+                if !(0.0..=MAX_WINDOW.as_secs_f64()).contains(&duplicates.window) {
+                    bail!(
+                        "The duplicate window of input '{}' is between 0 and {} seconds",
+                        input.name,
+                        MAX_WINDOW.as_secs_f64()
+                    );
+                }
+                if duplicates.max_clip <= 0.0 {
+                    bail!(
+                        "The longest duplicate clip of input '{}' is positive",
+                        input.name
+                    );
+                }
+                if !(-1.0..=1.0).contains(&duplicates.similarity) {
+                    bail!(
+                        "The duplicate similarity of input '{}' is between -1 and 1",
+                        input.name
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Sets the silence threshold of an input declared in the config file, in dBFS if the input
+/// has it in dBFS, leaving the rest of the file, its comments included, as it is
+pub fn store_silence_threshold(path: &Path, input: &str, threshold: f32) -> anyhow::Result<()> {
+    edit_input(path, input, |table| {
+        // Rounded, the file isn't meant to show float noise
+        if table.contains_key("silence_threshold_db") {
+            let db = (to_db(threshold) as f64 * 10.0).round() / 10.0;
+            table["silence_threshold_db"] = toml_edit::value(db);
+        } else {
+            let amplitude = (threshold as f64 * 1e5).round() / 1e5;
+            table["silence_threshold"] = toml_edit::value(amplitude);
+        }
+        Ok(())
+    })
+}
+
+/// Sets settings of the pausing of an input declared in the config file, like
+/// [`store_silence_threshold`]
+pub fn store_pausing_settings(
+    path: &Path,
+    input: &str,
+    settings: &[(&str, f64)],
+) -> anyhow::Result<()> {
+    edit_input(path, input, |table| {
+        let pausing = table
+            .get_mut("pausing")
+            .and_then(toml_edit::Item::as_table_like_mut)
+            .with_context(|| format!("Input '{input}' has no pausing"))?;
+        for (key, value) in settings {
+            pausing.insert(key, toml_edit::value(*value));
+        }
+        Ok(())
+    })
+}
+
+fn edit_input(
+    path: &Path,
+    input: &str,
+    edit: impl FnOnce(&mut toml_edit::Table) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    let text =
+        fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let mut document: toml_edit::DocumentMut = text
+        .parse()
+        .with_context(|| format!("Failed to parse {}", path.display()))?;
+    let table = document
+        .get_mut("inputs")
+        .and_then(toml_edit::Item::as_array_of_tables_mut)
+        .and_then(|inputs| {
+            inputs
+                .iter_mut()
+                .find(|table| table.get("name").and_then(toml_edit::Item::as_str) == Some(input))
+        })
+        .with_context(|| format!("Input '{input}' isn't declared in {}", path.display()))?;
+    edit(table)?;
+    fs::write(path, document.to_string())
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Settings of a setup that differ from the general ones
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Profile {
+    pub silence_threshold: Option<f32>,
+    pub engine: Option<Engine>,
+    pub soundtouch: Option<SoundTouchSettings>,
+    pub scheduling: Option<Scheduling>,
+    pub inputs: Option<Vec<InputConfig>>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct InputConfig {
+    pub name: String,
+    /// Number of ports, the output's channel count when left out
+    pub channels: Option<usize>,
+    /// Gain in dB applied to the captured audio, to match the levels of the inputs
+    #[serde(default)]
+    pub gain: f32,
+    /// Amplitude below which the input is considered silent, the general one when left out.
+    /// Stored by `calibrate`.
+    pub silence_threshold: Option<f32>,
+    /// The silence threshold in dBFS instead, e.g. -40.0
+    pub silence_threshold_db: Option<f32>,
+    /// What is compared to the threshold: the peak of each analysis window, or its RMS level
+    /// which brief transients and dither barely raise
+    #[serde(default)]
+    pub silence_level: Level,
+    /// dB above the threshold the level has to rise to end a silence, so noise hovering around
+    /// the threshold doesn't break it up
+    #[serde(default)]
+    pub silence_hysteresis: f32,
+    /// How the channels are combined: silent when `all` of them are, when `any` is, or when the
+    /// mean of their levels `{ weighted = [...] }` by channel is
+    #[serde(default)]
+    pub silence_policy: SilencePolicy,
+    /// Channels taking part in silence detection, counted from 0 like the ports, all when left
+    /// out. E.g. `[0]` for a mono source on a stereo pair with a dead right channel.
+    pub silence_channels: Option<Vec<usize>>,
+    /// Length of the windows the level is measured over in ms, which is how finely silence is
+    /// trimmed. `0` measures whole periods, about 5 ms when left out.
+    pub silence_window_ms: Option<f64>,
+    /// Seconds of silence before the input counts as silent, so quiet passages of music aren't
+    /// taken for silence
+    #[serde(default)]
+    pub min_silence: f64,
+    /// Longest silence kept between sounds in seconds, longer silences are shortened to it. About
+    /// 0.1 seconds when left out.
+    pub max_silence: Option<f64>,
+    /// Ports connected to the input's ports, in order and starting over when there are more
+    /// of either
+    #[serde(default)]
+    pub connect: Vec<String>,
+    /// Settings for a kind of source, filling in those the input leaves out
+    pub preset: Option<Preset>,
+    /// Tier of the input, inputs of higher tiers always play before those of lower ones, e.g. 1
+    /// for a mic that preempts everything else
+    #[serde(default)]
+    pub priority: i32,
+    /// Pauses the source while the input's backlog is long
+    pub pausing: Option<PausingConfig>,
+    /// Plays the input faster while its backlog is long
+    pub tempo: Option<TempoConfig>,
+    /// Part of the window class or app id of the application the input plays, boosting it while
+    /// its window has the focus. Matched against the clients connected to its ports when left
+    /// out.
+    pub window: Option<String>,
+    /// Announces the input when it plays after a long gap
+    pub cue: Option<CueConfig>,
+    /// Drops monotonous and repeated segments while the backlog is very long
+    pub drop_filler: Option<FillerConfig>,
+    /// Plays a notification clip queued several times in a row only once
+    pub suppress_duplicates: Option<DuplicatesConfig>,
+}
+
+impl InputConfig {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            channels: None,
+            gain: 0.0,
+            silence_threshold: None,
+            silence_threshold_db: None,
+            silence_level: Level::default(),
+            silence_hysteresis: 0.0,
+            silence_policy: SilencePolicy::default(),
+            silence_channels: None,
+            silence_window_ms: None,
+            min_silence: 0.0,
+            max_silence: None,
+            connect: Vec::new(),
+            preset: None,
+            priority: 0,
+            pausing: None,
+            tempo: None,
+            window: None,
+            cue: None,
+            drop_filler: None,
+            suppress_duplicates: None,
+        }
+    }
+
+    /// Fills in the settings of the preset the input leaves out, if it has one
+    pub fn apply_preset(&mut self) {
+        if let Some(preset) = self.preset {
+            preset.apply(self);
+        }
+    }
+
+    /// Silence threshold of the input as an amplitude, if it has its own
+    pub fn threshold(&self) -> Option<f32> {
+        self.silence_threshold
+            .or(self.silence_threshold_db.map(db_to_factor))
+    }
+
+    /// Pairs of source port and input port the config asks to be connected, given the names of
+    /// the input's ports
+    pub fn connections(&self, ports: &[String]) -> Vec<(String, String)> {
+        if self.connect.is_empty() || ports.is_empty() {
+            return Vec::new();
+        }
+        (0..self.connect.len().max(ports.len()))
+            .map(|index| {
+                (
+                    self.connect[index % self.connect.len()].clone(),
+                    ports[index % ports.len()].clone(),
+                )
+            })
+            .collect()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PausingConfig {
+    /// Seconds of backlog at which the source is paused
+    pub pause_backlog: f64,
+    /// Seconds of backlog below which the source is resumed
+    pub resume_backlog: f64,
+    /// MPRIS player of the source, e.g. `spotify`, the first player on the session bus when left
+    /// out
+    pub player: Option<String>,
+    /// Commands run to pause and resume the source instead, for sources that aren't MPRIS
+    /// players
+    pub pause_command: Option<String>,
+    pub resume_command: Option<String>,
+    /// Seconds the source keeps playing after being paused, it is paused this much earlier.
+    /// Stored by `calibrate-pause`, like the resume latency.
+    pub pause_latency: f64,
+    /// Seconds the source takes to play after being resumed, it is resumed this much earlier
+    pub resume_latency: f64,
+}
+
+/// Settings bundled for a kind of source
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Preset {
+    /// Spoken word: pauses cut short, played a bit faster all the time and faster still while
+    /// there is a backlog to catch up on
+    Podcast,
+}
+
+impl Preset {
+    /// Fills in the settings of the preset the input leaves out
+    fn apply(self, input: &mut InputConfig) {
+        match self {
+            Preset::Podcast => {
+                input.max_silence.get_or_insert(0.05);
+                let tempo = input.tempo.get_or_insert_with(TempoConfig::default);
+                tempo.min_tempo.get_or_insert(1.25);
+                tempo.max_tempo.get_or_insert(1.75);
+                tempo.full_backlog.get_or_insert(60.0);
+                tempo.ramp.get_or_insert(0.05);
+            }
+        }
+    }
+}
+
+/// Settings left out are filled in by the preset, or take the default the getter gives
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TempoConfig {
+    /// Tempo without a backlog
+    pub min_tempo: Option<f64>,
+    /// Tempo from the full backlog on
+    pub max_tempo: Option<f64>,
+    /// Seconds of backlog at which the tempo reaches its maximum, rising linearly until then
+    pub full_backlog: Option<f64>,
+    /// Largest change of the tempo per second
+    pub ramp: Option<f64>,
+}
+
+impl TempoConfig {
+    /// 1.0 when left out
+    pub fn min_tempo(&self) -> f64 {
+        self.min_tempo.unwrap_or(1.0)
+    }
+
+    /// 1.5 when left out
+    pub fn max_tempo(&self) -> f64 {
+        self.max_tempo.unwrap_or(1.5)
+    }
+
+    /// 30 seconds when left out
+    pub fn full_backlog(&self) -> f64 {
+        self.full_backlog.unwrap_or(30.0)
+    }
+
+    /// 0.1 when left out
+    pub fn ramp(&self) -> f64 {
+        self.ramp.unwrap_or(0.1)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CueConfig {
+    /// Tone played, `beep` or `chirp`
+    pub tone: Tone,
+    /// Spoken by espeak-ng instead of the tone, e.g. the name of the input
+    pub say: Option<String>,
+    /// Seconds the input has to have been quiet for to be announced
+    pub after_gap: f64,
+    /// Fewest seconds between two announcements of the input
+    pub min_interval: f64,
+}
+
+impl Default for CueConfig {
+    fn default() -> Self {
+        Self {
+            tone: Tone::default(),
+            say: None,
+            after_gap: 30.0,
+            min_interval: 120.0,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FillerConfig {
+    /// Seconds of backlog from which on filler is dropped
+    pub backlog: f64,
+    /// Seconds after which a segment ends even without a silence
+    pub max_segment: f64,
+    /// Shortest monotonous segment dropped in seconds
+    pub min_monotonous: f64,
+    /// dB the energy of a segment varies by at most to count as monotonous
+    pub monotony_db: f32,
+    /// Shortest repeated segment dropped in seconds
+    pub min_repeated: f64,
+    /// Similarity from -1 to 1 from which on a segment repeats one heard before
+    pub repeat_similarity: f32,
+}
+
+impl Default for FillerConfig {
+    fn default() -> Self {
+        Self {
+            backlog: 300.0,
+            max_segment: 10.0,
+            min_monotonous: 5.0,
+            monotony_db: 1.5,
+            min_repeated: 2.0,
+            repeat_similarity: 0.9,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DuplicatesConfig {
+    /// Seconds after a clip was first queued during which it is suppressed
+    pub window: f64,
+    /// Longest clip in seconds, longer segments aren't notifications
+    pub max_clip: f64,
+    /// Similarity from -1 to 1 from which on two clips are the same
+    pub similarity: f32,
+}
+
+impl Default for DuplicatesConfig {
+    fn default() -> Self {
+        Self {
+            window: 10.0,
+            max_clip: 5.0,
+            similarity: 0.95,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MixingConfig {
+    /// dB inputs are turned down by while one of a higher priority tier has sound
+    pub duck_db: f32,
+    /// Seconds the ducking takes to set in
+    pub attack: f64,
+    /// Seconds the ducked inputs take to come back up
+    pub release: f64,
+    /// Level in dBFS above which an input has sound and ducks the others
+    pub threshold_db: f32,
+}
+
+impl Default for MixingConfig {
+    fn default() -> Self {
+        Self {
+            duck_db: -12.0,
+            attack: 0.05,
+            release: 0.5,
+            threshold_db: -45.0,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MemoryConfig {
+    /// MiB the input buffers, replay history, caches and recording queues take at most together
+    pub budget_mib: f64,
+    /// Fraction of the budget in use at which the actions start
+    pub pressure: f64,
+    /// Taken in order until enough memory is freed: `pause-sources`, `spill` and `drop-oldest`,
+    /// the first two by default
+    pub actions: Vec<PressureAction>,
+    /// Where spilled audio goes, `$XDG_CACHE_HOME/audiomux/spill` when left out
+    pub spill_directory: Option<PathBuf>,
+}
+
+impl Default for MemoryConfig {
+    fn default() -> Self {
+        Self {
+            budget_mib: 1024.0,
+            pressure: 0.9,
+            actions: vec![PressureAction::PauseSources, PressureAction::Spill],
+            spill_directory: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HurryUpConfig {
+    /// Tempo of the MIDI clock at natural speed, it runs faster as the input catches up faster
+    pub bpm: f64,
+    /// Seconds of backlog at which the control voltage reaches its full level of 1
+    pub full_backlog: f64,
+}
+
+impl Default for HurryUpConfig {
+    fn default() -> Self {
+        Self {
+            bpm: 120.0,
+            full_backlog: 60.0,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MidiControlConfig {
+    /// MIDI ports of the controllers, connected to the `midi-control` port
+    pub connect: Vec<String>,
+    pub mappings: Vec<MidiMapping>,
+}
+
+/// Control change messages of a controller mapped to what they change on an input
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MidiMapping {
+    /// Controller number, 0 to 127
+    pub controller: u8,
+    /// MIDI channel from 1 to 16, any when left out
+    pub channel: Option<u8>,
+    pub input: String,
+    /// `gain`, `tempo` or `mute`
+    pub action: MidiAction,
+    /// Value at the bottom of the controller's range, dB for the gain (-60 when left out) and
+    /// the speed for the tempo (0.5 when left out)
+    pub min: Option<f64>,
+    /// Value at the top of the controller's range, 6 dB and 2.0 when left out
+    pub max: Option<f64>,
+}
+
+impl MidiMapping {
+    /// The range given, completed by `default`
+    pub fn range(&self, default: (f64, f64)) -> (f64, f64) {
+        (self.min.unwrap_or(default.0), self.max.unwrap_or(default.1))
+    }
+
+    fn validate(&self) -> anyhow::Result<()> {
+        if self.controller > 127 {
+            bail!(
+                "MIDI controller {} is not between 0 and 127",
+                self.controller
+            );
+        }
+        if self
+            .channel
+            .is_some_and(|channel| !(1..=16).contains(&channel))
+        {
+            bail!(
+                "The MIDI channels of controller {} are 1 to 16",
+                self.controller
+            );
+        }
+        let (range, default, what) = match self.action {
+            MidiAction::Gain => (GAIN_RANGE, midi_control::DEFAULT_GAIN, "gain"),
+            MidiAction::Tempo => (SPEED_RANGE, midi_control::DEFAULT_TEMPO, "speed"),
+            MidiAction::Mute => return Ok(()),
+        };
+        let (min, max) = self.range(default);
+        for value in [min, max] {
+            check_range(value, what, range.clone())
+                .with_context(|| format!("Controller {} of {}", self.controller, self.input))?;
+        }
+        Ok(())
+    }
+}
+
+impl Default for PausingConfig {
+    fn default() -> Self {
+        Self {
+            pause_backlog: 1.0,
+            resume_backlog: 0.1,
+            player: None,
+            pause_command: None,
+            resume_command: None,
+            pause_latency: 0.0,
+            resume_latency: 0.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Config {
+        let mut config: Config = toml::from_str(text).unwrap();
+        config.apply_presets();
+        config
+    }
+
+    #[test]
+    fn preset_fills_in_the_tempo_settings_left_out() {
+        let config = parse(
+            "[[inputs]]\nname = \"podcast\"\npreset = \"podcast\"\n\n\
+             [inputs.tempo]\nmax_tempo = 2.0\n",
+        );
+        let tempo = config.inputs[0].tempo.as_ref().unwrap();
+        assert_eq!(tempo.min_tempo(), 1.25);
+        assert_eq!(tempo.max_tempo(), 2.0);
+        assert_eq!(tempo.full_backlog(), 60.0);
+        assert_eq!(config.inputs[0].max_silence, Some(0.05));
+    }
+
+    #[test]
+    fn tempo_without_preset_takes_the_defaults() {
+        let config = parse("[[inputs]]\nname = \"music\"\n\n[inputs.tempo]\nramp = 0.2\n");
+        let tempo = config.inputs[0].tempo.as_ref().unwrap();
+        assert_eq!(tempo.min_tempo(), 1.0);
+        assert_eq!(tempo.max_tempo(), 1.5);
+        assert_eq!(tempo.ramp(), 0.2);
+    }
+
+    /// The message `text` fails validation with
+    fn error(text: &str) -> String {
+        format!("{:#}", parse(text).validate().unwrap_err())
+    }
+
+    #[test]
+    fn the_documented_example_is_valid() {
+        let example: String = include_str!("config.rs")
+            .lines()
+            .filter_map(|line| line.strip_prefix("//!"))
+            .skip_while(|line| *line != " ```toml")
+            .skip(1)
+            .take_while(|line| *line != " ```")
+            .map(|line| format!("{}\n", line.trim_start()))
+            .collect();
+        let config = parse(&example);
+        config.validate().unwrap();
+        assert_eq!(config.inputs.len(), 4);
+        config.with_profile(Some("radio-logging")).unwrap();
+    }
+
+    #[test]
+    fn rejects_invalid_outputs() {
+        assert_eq!(
+            error("client_name = \"\""),
+            "The client name must not be empty"
+        );
+        assert_eq!(
+            error("crossfade = 10.0"),
+            format!(
+                "The crossfade is between 0 and {} seconds",
+                MAX_CROSSFADE.as_secs_f64()
+            )
+        );
+        assert_eq!(
+            error("[soundtouch]\nanti_alias_length = 10\n"),
+            "The anti-alias filter length is a multiple of 4 from 8 to 128"
+        );
+        assert_eq!(
+            error(
+                "[[midi_control.mappings]]\ncontroller = 200\ninput = \"music\"\n\
+                 action = \"mute\"\n"
+            ),
+            "MIDI controller 200 is not between 0 and 127"
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_inputs() {
+        let input = |settings: &str| format!("[[inputs]]\nname = \"music\"\n{settings}\n");
+        assert_eq!(
+            error(&format!("{}{}", input(""), input(""))),
+            "Input 'music' is declared twice"
+        );
+        assert_eq!(
+            error(&input(
+                "silence_threshold = 0.1\nsilence_threshold_db = -40.0"
+            )),
+            "Input 'music' has a silence threshold both as an amplitude and in dBFS"
+        );
+        assert_eq!(
+            error(&input("channels = 2\nsilence_channels = [2]")),
+            "Input 'music' has no channel 2"
+        );
+        assert_eq!(
+            error(&input(
+                "[inputs.pausing]\npause_backlog = 1.0\nresume_backlog = 2.0"
+            )),
+            "Input 'music' resumes at a larger backlog than it pauses at"
+        );
+        assert_eq!(
+            error(&input("[inputs.pausing]\npause_command = \"true\"")),
+            "Input 'music' needs both a pause and a resume command"
+        );
+        assert_eq!(
+            error(&input("[inputs.tempo]\nmin_tempo = 2.0\nmax_tempo = 1.5")),
+            "The minimum tempo of input 'music' is above its maximum"
+        );
+        assert_eq!(
+            error(&input("[inputs.tempo]\nmax_tempo = 8.0")),
+            "The tempo of input 'music' is between 0.25 and 4.0"
+        );
+    }
+}
@@ -0,0 +1,2213 @@
+use std::{
+    io::BufRead,
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, PoisonError},
+    time::{Duration, Instant, SystemTime},
+};
+
+use anyhow::{anyhow, bail, Context};
+
+#[cfg(feature = "clap-plugins")]
+use crate::clap_plugin;
+use crate::{
+    auto_dnd::{AutoDnd, CallSource, DEFAULT_HANG_TIME},
+    bookmarks::{Bookmark, DEFAULT_EXPORT_MARGIN, SEEK_LEAD},
+    calibrate::{self, Calibration, PauseCalibration},
+    capacity,
+    catch_up::CatchUp,
+    chain::{db_to_factor, Chain},
+    config::InputConfig,
+    crossfade,
+    encoder::Encoding,
+    export,
+    file_player::{FilePlayer, GainMode},
+    generator::Waveform,
+    jingles::{JingleAction, JingleSkip},
+    meter::{to_db, MeterMode},
+    overlap::Overlap,
+    recorder::{self, Recorder, Rotation},
+    schedule::RecordingRule,
+    scheduling::Scheduling,
+    segments::item_length,
+    silence,
+    spectrum::{SpectrumSource, Tap},
+    stretch::Engine,
+    timeline::{DropReason, Event},
+    timeshift::TimeShift,
+    transcription::{Transcriber, Transcript},
+    Boost, BufferItem, Input, InputRequest, JackState, LeadIn, Source, TransportGate,
+};
+#[cfg(feature = "opus")]
+use crate::{listen_later, ogg_opus};
+
+/// Urgency multiplier used by `boost` when no factor is given
+const DEFAULT_BOOST_FACTOR: f32 = 10.0;
+
+/// Length of the audio `replay` plays again, when not given
+const DEFAULT_REPLAY_LENGTH: Duration = Duration::from_secs(10);
+
+/// Urgency multiplier of an input while it replays, high enough to take over from any other
+const REPLAY_BOOST_FACTOR: f32 = 100.0;
+
+/// Time an input has to be unheard before `lead-in` replays anything, when not given
+const DEFAULT_LEAD_IN_AFTER: Duration = Duration::from_secs(300);
+
+/// Longest `soft-preempt` waits for a pause, when not given
+const DEFAULT_PREEMPTION_WAIT: Duration = Duration::from_secs(10);
+
+/// Range of playback speeds accepted by `set-speed` and `set-rate`
+pub const SPEED_RANGE: RangeInclusive<f64> = 0.25..=4.0;
+
+/// Range of pitch shifts in semitones accepted by `set-pitch`, an octave either way
+const PITCH_RANGE: RangeInclusive<f64> = -12.0..=12.0;
+
+/// Range of input gains in dB accepted by `set-gain`
+pub const GAIN_RANGE: RangeInclusive<f64> = -60.0..=24.0;
+
+/// Range of global speed multipliers accepted by `speed-trim`
+const TRIM_RANGE: RangeInclusive<f64> = 0.5..=2.0;
+
+/// Range of ducking depths in dB accepted by `mixing`
+const DUCK_RANGE: RangeInclusive<f64> = -60.0..=0.0;
+
+/// Commands accepted on the control interface, one per line
+pub enum Command {
+    /// `boost <input> <duration> [factor]`: temporarily multiply an input's urgency
+    Boost {
+        input: String,
+        duration: Duration,
+        factor: f32,
+    },
+    /// `generator <waveform> [level dBFS]` or `generator off`: control the test signal input
+    Generator {
+        waveform: Option<Waveform>,
+        level_db: Option<f32>,
+    },
+    /// `play <file|playlist.m3u>`, `play next`, `play previous`, `play seek <position>` or
+    /// `play off`: play audio files (WAV, FLAC, Ogg Vorbis, MP3) on the file input, a playlist
+    /// without gaps between the files
+    Play { action: PlayAction },
+    /// `replay-gain track|album|off [preamp dB]`: which gain stored in the tags of played files
+    /// is applied, and how much is added to it
+    ReplayGain {
+        mode: GainMode,
+        preamp_db: Option<f32>,
+    },
+    /// `logger <input> <directory> [format]` or `logger <input> off`: continuously record an
+    /// input to hourly rotated files. The format is `wav[:<sample format>]` (the default, the
+    /// only one `seek` and `export` read back), `flac[:<level 0-8>]`, `opus[:<kbit/s>]` or
+    /// `mp3[:<kbit/s>]`, Opus and MP3 need the features of the same name.
+    Logger {
+        input: String,
+        directory: Option<PathBuf>,
+        encoding: Encoding,
+    },
+    /// `seek <input> -<duration>`, `seek <input> <HH:MM[:SS]>` or `seek <input> @<bookmark>`:
+    /// play a logged input from its recording, starting at the given time
+    Seek { input: String, target: SeekTarget },
+    /// `live <input>`: return a time shifted input to its live source
+    Live { input: String },
+    /// `enable <input>`: capture and play a disabled input again, resuming its source if it was
+    /// paused
+    Enable { input: String },
+    /// `disable <input> [pause]`: stop capturing and playing an input while keeping its ports
+    /// and connections, with `pause` also pause its source. The queued audio is kept.
+    Disable { input: String, pause_source: bool },
+    /// `bookmark <input> <name> [capture]`: bookmark the audio currently played on an input, or
+    /// with `capture` the audio currently captured
+    Bookmark {
+        input: String,
+        name: String,
+        at_capture: bool,
+    },
+    /// `bookmarks`: list all bookmarks
+    Bookmarks,
+    /// `schedule <input> <days> <HH:MM-HH:MM> <directory> [template] [--format <format>]` or
+    /// `schedule <input> off`: record an input at the same time on some days, e.g. `schedule
+    /// news weekdays 08:00-08:15 /srv/news --format opus:64`, or remove its rules. `days` is
+    /// `daily`, `weekdays`, `weekends` or a list like `mon,thu`. The file name template replaces
+    /// `{input}`, `{date}` and `{time}`, it defaults to `{input}-{date}-{time}`. The format is
+    /// one of `logger`'s.
+    Schedule {
+        input: String,
+        rule: Option<RecordingRule>,
+    },
+    /// `schedules`: list the rules of the recording schedule
+    Schedules,
+    /// `jingles <input> <directory> [skip|rush[:factor]]` or `jingles <input> off`: look out
+    /// for the WAV snippets in a directory (station jingles, ad bumpers) in the buffered audio
+    /// of an input and skip them, or play them faster (3x by default)
+    Jingles {
+        input: String,
+        directory: Option<PathBuf>,
+        action: JingleAction,
+    },
+    /// `transcribe <input> <command...>` or `transcribe <input> off`: pass the speech segments
+    /// queued on an input to a speech recognizer as a WAV file and show the text it prints in
+    /// the timeline, e.g. `transcribe 2 whisper-cli -nt -m ggml-base.bin -f {file}`
+    Transcribe {
+        input: String,
+        command: Option<String>,
+    },
+    /// `messages [input]`: list the transcribed segments of all or one input, queued and played
+    Messages { input: Option<String> },
+    /// `search <text...>`: list the transcribed segments containing all the given words
+    Search { query: String },
+    /// `jump <input> <message>`: continue playing an input at a transcribed segment, skipping
+    /// what is queued before it, or from its recording if it was played already
+    Jump { input: String, message: usize },
+    /// `hold <input> [on|off]`: keep capturing an input without playing it, e.g. a network input
+    /// while the listener is busy. Toggles without `on`/`off`.
+    Hold { input: String, held: Option<bool> },
+    /// `replay <input> [duration]`: play the end of what an input played again right away, up to
+    /// the last 30 seconds
+    Replay { input: String, length: Duration },
+    /// `dnd [on|off]`: do not disturb, hold all inputs but the critical ones and pause their
+    /// sources, e.g. for a call. Afterwards the inputs catch up as usual. Toggles without
+    /// `on`/`off`.
+    DoNotDisturb { on: Option<bool> },
+    /// `critical <input> [on|off]`: keep playing an input during do-not-disturb, e.g. alarms.
+    /// Toggles without `on`/`off`.
+    Critical {
+        input: String,
+        critical: Option<bool>,
+    },
+    /// `add-input <name> [channels] [port...]`: add an input with ports while running, connected
+    /// to the given ports. Gets the output's channel count without one.
+    AddInput { input: Box<InputConfig> },
+    /// `remove-input <name>`: remove an input with ports, resuming its source if it is paused
+    RemoveInput { input: String },
+    /// `profile [name|none]`: switch to a profile of the config, `none` for the general
+    /// settings. Lists the profiles without a name.
+    Profile { profile: Option<Option<String>> },
+    /// `auto-dnd input <input> [hang time]`, `auto-dnd stream <pattern> [hang time]` or
+    /// `auto-dnd off`: turn do-not-disturb on while there is sound on a call input or a playback
+    /// stream of the sound server matches, e.g. `auto-dnd stream zoom`. It ends once the call was
+    /// quiet for the hang time.
+    AutoDnd {
+        source: Option<CallSource>,
+        hang_time: Duration,
+    },
+    /// `calibrate <input> [duration] [apply]`: measure the noise floor of an input for
+    /// `duration` (five seconds by default) while its source is silent and suggest a silence
+    /// threshold above it. `apply` uses the threshold and stores it in the config file.
+    Calibrate {
+        input: String,
+        duration: Duration,
+        apply: bool,
+    },
+    /// `calibrate-pause <input> [apply]`: pause the source of an input while it plays, measure
+    /// how long it keeps playing, resume it and measure how long it takes to play again. `apply`
+    /// pauses and resumes the source that much earlier and stores the latencies in the config
+    /// file.
+    CalibratePause { input: String, apply: bool },
+    /// `flush <input>`: discard the audio queued on an input
+    Flush { input: String },
+    /// `skip [input]`: discard the rest of the sound an input is playing, up to its next pause.
+    /// Skips on the playing input without one.
+    Skip { input: Option<String> },
+    /// `priority <input> <tier>`: put an input in a priority tier, inputs of higher tiers always
+    /// play first whatever the backlog of the others. All start in tier 0.
+    Priority { input: String, priority: i32 },
+    /// `soft-preempt <input> [max wait]` or `soft-preempt <input> off`: when the input becomes
+    /// the most urgent, let the playing input reach a pause before taking over, but wait no longer
+    /// than `max wait` (ten seconds by default)
+    SoftPreempt {
+        input: String,
+        max_wait: Option<Duration>,
+    },
+    /// `overlap <input> <input> mix|serialize`: whether two inputs with audio queued at the
+    /// same time play together or one after the other, the default
+    Overlap {
+        first: String,
+        second: String,
+        overlap: Overlap,
+    },
+    /// `focus <input>` or `focus off`: play only this input until its backlog is played, the
+    /// others keep buffering
+    Focus { input: Option<String> },
+    /// `catch-up <input> live|chime|gap:<duration>|next`: what happens when an input played its
+    /// backlog, carry on live, play a chime, leave a gap or boost the largest other backlog
+    CatchUp { input: String, catch_up: CatchUp },
+    /// `lead-in <input> <length> [after]` or `lead-in <input> off`: before the paused source of an
+    /// input resumes, replay the last `length` it played if it wasn't heard for `after` (five
+    /// minutes by default)
+    LeadIn {
+        input: String,
+        lead_in: Option<LeadIn>,
+    },
+    /// `transport-gate <input> rolling|stopped|off`: only capture an input while the JACK
+    /// transport is rolling, or while it is stopped
+    TransportGate {
+        input: String,
+        gate: Option<TransportGate>,
+    },
+    /// `capacity`: estimate the memory an hour of backlog takes per input and how many hours fit
+    /// into the available memory
+    Capacity,
+    /// `stats`: show how active each input was this session, its backlog and speed
+    Stats,
+    /// `export-bookmark <name> <file.wav> [margin]`: write the logged audio around a bookmark
+    /// to a file
+    ExportBookmark {
+        name: String,
+        path: PathBuf,
+        margin: Duration,
+    },
+    /// `export <input> [--from <time>] [--to <time>] [--consume] [--format <format>]
+    /// <file.wav>`: write the audio captured in a time range at natural speed to a file. Uses
+    /// the buffered audio if there is any in the range, the logger recording otherwise.
+    /// `--consume` removes exported audio from the queue, `--format` is a sample format of a
+    /// WAV file, `s16`, `s24`, `s32` or `f32` (the default), or one of `logger`'s formats.
+    Export {
+        input: String,
+        from: Option<SystemTime>,
+        to: Option<SystemTime>,
+        consume: bool,
+        encoding: Encoding,
+        path: PathBuf,
+    },
+    /// `listen-later <input> [--trim] [--normalize] [--bitrate <kbit/s>] <file.opus>`: take the
+    /// whole backlog of an input out of the queue and save it to an Opus file at natural speed,
+    /// to listen to later. `--trim` leaves out the pauses, `--normalize` brings the peak to
+    /// -1 dBFS. The bitrate defaults to 48 kbit/s per channel.
+    #[cfg(feature = "opus")]
+    ListenLater {
+        input: String,
+        trim: bool,
+        normalize: bool,
+        bitrate: Option<u32>,
+        path: PathBuf,
+    },
+    /// `set-speed <input> <speed>` or `set-speed <input> auto`: pin an input's playback speed
+    /// regardless of its backlog, or return it to automatic control
+    SetSpeed { input: String, speed: Option<f64> },
+    /// `speed-trim <factor>`: speed up (or slow down) all inputs permanently on top of their
+    /// automatic speed, pitch is preserved
+    SpeedTrim { factor: f64 },
+    /// `set-rate <input> <rate>`: change speed and pitch of an input together, on top of its
+    /// tempo
+    SetRate { input: String, rate: f64 },
+    /// `set-pitch <input> <semitones>`: shift the pitch of an input without changing its speed,
+    /// -12 to 12 semitones. Needs the SoundTouch engine.
+    SetPitch { input: String, semitones: f64 },
+    /// `set-gain <input> <dB>`: amplify or attenuate what an input captures, to match it to the
+    /// level of the others
+    SetGain { input: String, gain: f32 },
+    /// `mute <input> [on|off]`: capture silence on an input instead of what comes in, what is
+    /// queued still plays. Toggles without `on`/`off`.
+    Mute { input: String, muted: Option<bool> },
+    /// `chain <input> <node[:parameter...],...>` or `chain <input> none`: set the ordered effect
+    /// chain of an input, e.g. `gate:-45,eq:80:12000,agc:-20,stretch,gain:3`. With the `lv2`
+    /// feature, `lv2:<uri>[;symbol=value...]` inserts an LV2 plugin, with the `ladspa` feature
+    /// `ladspa:<library>;<label>[;port=value...]` a LADSPA plugin.
+    Chain { input: String, chain: String },
+    /// `param <input> <effect> <parameter> <value>`: change a parameter of an effect in an
+    /// input's chain, the effect given by name or position
+    Param {
+        input: String,
+        effect: String,
+        parameter: String,
+        value: f32,
+    },
+    /// `bypass <input> [effect] [on|off]`: bypass an input's whole chain, crossfading to the raw
+    /// audio, or a single effect of it. Toggles without `on`/`off`.
+    Bypass {
+        input: String,
+        effect: Option<String>,
+        bypassed: Option<bool>,
+    },
+    /// `bypass-all [on|off]`: bypass the chains of all inputs and the output bus, toggles without
+    /// `on`/`off`
+    BypassAll { bypassed: Option<bool> },
+    /// `bus <node[:parameter...],...>` or `bus none`: set the effects processing the mixed
+    /// output, same syntax as `chain`. `limiter:<ceiling dB>:<oversampling 1|2|4>` keeps the
+    /// output below a ceiling, checking between samples with oversampling. With the
+    /// `clap-plugins` feature, `clap:<file.clap>;<plugin id>[;parameter=value...]` inserts a
+    /// CLAP plugin.
+    Bus { chain: String },
+    /// `bus-param <effect> <parameter> <value>`: change a parameter of an output bus effect
+    BusParam {
+        effect: String,
+        parameter: String,
+        value: f32,
+    },
+    /// `bus-bypass [effect] [on|off]`: bypass the output bus or a single effect of it, toggles
+    /// without `on`/`off`
+    BusBypass {
+        effect: Option<String>,
+        bypassed: Option<bool>,
+    },
+    /// `clap-plugins`: list the installed CLAP plugins
+    #[cfg(feature = "clap-plugins")]
+    ClapPlugins,
+    /// `spectrum <output|input name> [spectrogram]` or `spectrum off`: show the spectrum of the
+    /// output or of an input's captured audio in the status, with `spectrogram` also its recent
+    /// history
+    Spectrum {
+        source: Option<SpectrumSource>,
+        spectrogram: bool,
+    },
+    /// `meter <peak|rms|lufs> [floor dB] [ceiling dB] [decay dB/s]`: choose what the level
+    /// meters show and how
+    Meter {
+        mode: MeterMode,
+        floor_db: Option<f32>,
+        ceiling_db: Option<f32>,
+        decay_db_per_second: Option<f32>,
+    },
+    /// `engine <soundtouch|resample>`: switch the engine changing the playback speed
+    Engine { engine: Engine },
+    /// `crossfade <duration>`: fade between inputs for this long when playback switches, e.g.
+    /// `crossfade 20ms`, at most 100 ms. `0` switches at once.
+    Crossfade { length: Duration },
+    /// `scheduling <urgency|priority|round-robin|least-recent>`: switch the policy picking the
+    /// input to play next
+    Scheduling { scheduling: Scheduling },
+    /// `mixing [on|off] [duck dB]`: play all inputs at once, those of lower priority tiers
+    /// turned down by the given dB while one above them has sound, or one after the other
+    /// again. Toggles without `on` or `off`.
+    Mixing {
+        on: Option<bool>,
+        duck_db: Option<f32>,
+    },
+}
+
+pub enum SeekTarget {
+    Time(SystemTime),
+    Bookmark(String),
+}
+
+pub enum PlayAction {
+    File(PathBuf),
+    Next,
+    Previous,
+    Seek(Duration),
+    Off,
+}
+
+impl Command {
+    pub fn parse(line: &str) -> anyhow::Result<Self> {
+        let mut words = line.split_whitespace();
+        let name = words.next().ok_or_else(|| anyhow!("Empty command"))?;
+        let mut argument = |what: &str| {
+            words
+                .next()
+                .ok_or_else(|| anyhow!("Missing {what} for '{name}'"))
+        };
+
+        let command = match name {
+            "boost" => {
+                let input = argument("input")?.to_string();
+                let duration = parse_duration(argument("duration")?)?;
+                let factor = match argument("factor") {
+                    Ok(factor) => factor.parse().context("Invalid boost factor")?,
+                    Err(_) => DEFAULT_BOOST_FACTOR,
+                };
+                Command::Boost {
+                    input,
+                    duration,
+                    factor,
+                }
+            }
+            "generator" => {
+                let waveform = match argument("waveform")? {
+                    "off" => None,
+                    waveform => Some(waveform.parse()?),
+                };
+                let level_db = match argument("level") {
+                    Ok(level) => Some(level.parse().context("Invalid level")?),
+                    Err(_) => None,
+                };
+                Command::Generator { waveform, level_db }
+            }
+            "play" => Command::Play {
+                action: match argument("file")? {
+                    "next" => PlayAction::Next,
+                    "previous" => PlayAction::Previous,
+                    "seek" => PlayAction::Seek(parse_duration(argument("position")?)?),
+                    "off" => PlayAction::Off,
+                    path => PlayAction::File(PathBuf::from(path)),
+                },
+            },
+            "replay-gain" => Command::ReplayGain {
+                mode: argument("mode")?.parse()?,
+                preamp_db: match argument("preamp") {
+                    Ok(preamp) => Some(preamp.parse().context("Invalid preamp")?),
+                    Err(_) => None,
+                },
+            },
+            "logger" => {
+                let input = argument("input")?.to_string();
+                let directory = match argument("directory")? {
+                    "off" => None,
+                    directory => Some(PathBuf::from(directory)),
+                };
+                let encoding = match argument("format") {
+                    Ok(format) => format.parse()?,
+                    Err(_) => Encoding::default(),
+                };
+                Command::Logger {
+                    input,
+                    directory,
+                    encoding,
+                }
+            }
+            "seek" => {
+                let input = argument("input")?.to_string();
+                let target = match argument("time")? {
+                    time if time.starts_with('@') => SeekTarget::Bookmark(time[1..].to_string()),
+                    time => SeekTarget::Time(parse_time(time)?),
+                };
+                Command::Seek { input, target }
+            }
+            "bookmark" => Command::Bookmark {
+                input: argument("input")?.to_string(),
+                name: argument("name")?.to_string(),
+                at_capture: match argument("position") {
+                    Ok("capture") => true,
+                    Ok(position) => bail!("Unknown bookmark position '{position}'"),
+                    Err(_) => false,
+                },
+            },
+            "bookmarks" => Command::Bookmarks,
+            "schedule" => {
+                let input = argument("input")?.to_string();
+                let rule = match argument("days")? {
+                    "off" => None,
+                    days => {
+                        let days = days.parse()?;
+                        let range = argument("time range")?;
+                        let directory = PathBuf::from(argument("directory")?);
+                        let (mut template, mut encoding) = (None, Encoding::default());
+                        while let Ok(word) = argument("template") {
+                            match word {
+                                "--format" => encoding = argument("--format format")?.parse()?,
+                                _ => template = Some(word.to_string()),
+                            }
+                        }
+                        Some(RecordingRule::new(
+                            input.clone(),
+                            days,
+                            range,
+                            directory,
+                            template,
+                            encoding,
+                        )?)
+                    }
+                };
+                Command::Schedule { input, rule }
+            }
+            "schedules" => Command::Schedules,
+            "transcribe" => {
+                let input = argument("input")?.to_string();
+                let command = match argument("command")? {
+                    "off" => None,
+                    first => Some(
+                        std::iter::once(first)
+                            .chain(std::iter::from_fn(|| argument("").ok()))
+                            .collect::<Vec<_>>()
+                            .join(" "),
+                    ),
+                };
+                Command::Transcribe { input, command }
+            }
+            "messages" => Command::Messages {
+                input: argument("input").ok().map(str::to_string),
+            },
+            "search" => {
+                let first = argument("text")?;
+                Command::Search {
+                    query: std::iter::once(first)
+                        .chain(std::iter::from_fn(|| argument("").ok()))
+                        .collect::<Vec<_>>()
+                        .join(" "),
+                }
+            }
+            "jump" => Command::Jump {
+                input: argument("input")?.to_string(),
+                message: {
+                    let message = argument("message")?;
+                    message
+                        .trim_start_matches('#')
+                        .parse()
+                        .with_context(|| format!("Invalid message number '{message}'"))?
+                },
+            },
+            "jingles" => Command::Jingles {
+                input: argument("input")?.to_string(),
+                directory: match argument("directory")? {
+                    "off" => None,
+                    directory => Some(PathBuf::from(directory)),
+                },
+                action: match argument("action") {
+                    Ok(action) => action.parse()?,
+                    Err(_) => JingleAction::Skip,
+                },
+            },
+            "stats" => Command::Stats,
+            "capacity" => Command::Capacity,
+            "hold" => Command::Hold {
+                input: argument("input")?.to_string(),
+                held: parse_switch(argument("on|off").ok())?,
+            },
+            "replay" => Command::Replay {
+                input: argument("input")?.to_string(),
+                length: match argument("duration") {
+                    Ok(length) => parse_duration(length)?,
+                    Err(_) => DEFAULT_REPLAY_LENGTH,
+                },
+            },
+            "dnd" => Command::DoNotDisturb {
+                on: parse_switch(argument("on|off").ok())?,
+            },
+            "auto-dnd" => {
+                let source = match argument("input|stream|off")? {
+                    "off" => None,
+                    "input" => Some(CallSource::Input(argument("input")?.to_string())),
+                    "stream" => Some(CallSource::Stream(argument("pattern")?.to_string())),
+                    source => bail!("Unknown call source '{source}', expected input or stream"),
+                };
+                let hang_time = match argument("hang time") {
+                    Ok(hang_time) => parse_duration(hang_time)?,
+                    Err(_) => DEFAULT_HANG_TIME,
+                };
+                Command::AutoDnd { source, hang_time }
+            }
+            "critical" => Command::Critical {
+                input: argument("input")?.to_string(),
+                critical: parse_switch(argument("on|off").ok())?,
+            },
+            "add-input" => {
+                let mut input = InputConfig::new(argument("name")?);
+                let mut rest = std::iter::from_fn(|| argument("").ok()).peekable();
+                if let Some(channels) = rest.next_if(|part| part.parse::<usize>().is_ok()) {
+                    input.channels = Some(channels.parse()?);
+                }
+                input.connect = rest.map(str::to_string).collect();
+                Command::AddInput {
+                    input: Box::new(input),
+                }
+            }
+            "calibrate" => {
+                let input = argument("input")?.to_string();
+                let mut duration = calibrate::DEFAULT_DURATION;
+                let mut apply = false;
+                while let Ok(part) = argument("") {
+                    match part {
+                        "apply" => apply = true,
+                        part => duration = parse_duration(part)?,
+                    }
+                }
+                Command::Calibrate {
+                    input,
+                    duration,
+                    apply,
+                }
+            }
+            "calibrate-pause" => Command::CalibratePause {
+                input: argument("input")?.to_string(),
+                apply: match argument("") {
+                    Ok("apply") => true,
+                    Ok(part) => bail!("Unknown option '{part}', expected 'apply'"),
+                    Err(_) => false,
+                },
+            },
+            "remove-input" => Command::RemoveInput {
+                input: argument("name")?.to_string(),
+            },
+            "profile" => Command::Profile {
+                profile: match argument("name") {
+                    Ok("none") => Some(None),
+                    Ok(name) => Some(Some(name.to_string())),
+                    Err(_) => None,
+                },
+            },
+            "flush" => Command::Flush {
+                input: argument("input")?.to_string(),
+            },
+            "skip" => Command::Skip {
+                input: argument("input").ok().map(str::to_string),
+            },
+            "priority" => Command::Priority {
+                input: argument("input")?.to_string(),
+                priority: argument("tier")?.parse().context("Invalid priority tier")?,
+            },
+            "soft-preempt" => Command::SoftPreempt {
+                input: argument("input")?.to_string(),
+                max_wait: match argument("max wait") {
+                    Ok("off") => None,
+                    Ok(max_wait) => Some(parse_duration(max_wait)?),
+                    Err(_) => Some(DEFAULT_PREEMPTION_WAIT),
+                },
+            },
+            "overlap" => Command::Overlap {
+                first: argument("input")?.to_string(),
+                second: argument("other input")?.to_string(),
+                overlap: argument("mix|serialize")?.parse()?,
+            },
+            "focus" => Command::Focus {
+                input: match argument("input")? {
+                    "off" => None,
+                    input => Some(input.to_string()),
+                },
+            },
+            "catch-up" => Command::CatchUp {
+                input: argument("input")?.to_string(),
+                catch_up: argument("live|chime|gap:<duration>|next")?.parse()?,
+            },
+            "lead-in" => Command::LeadIn {
+                input: argument("input")?.to_string(),
+                lead_in: match argument("length")? {
+                    "off" => None,
+                    length => Some(LeadIn {
+                        length: parse_duration(length)?,
+                        after: match argument("after") {
+                            Ok(after) => parse_duration(after)?,
+                            Err(_) => DEFAULT_LEAD_IN_AFTER,
+                        },
+                    }),
+                },
+            },
+            "transport-gate" => Command::TransportGate {
+                input: argument("input")?.to_string(),
+                gate: match argument("rolling|stopped|off")? {
+                    "off" => None,
+                    gate => Some(gate.parse()?),
+                },
+            },
+            "export-bookmark" => Command::ExportBookmark {
+                name: argument("bookmark")?.to_string(),
+                path: PathBuf::from(argument("file")?),
+                margin: match argument("margin") {
+                    Ok(margin) => parse_duration(margin)?,
+                    Err(_) => DEFAULT_EXPORT_MARGIN,
+                },
+            },
+            "live" => Command::Live {
+                input: argument("input")?.to_string(),
+            },
+            "enable" => Command::Enable {
+                input: argument("input")?.to_string(),
+            },
+            "disable" => Command::Disable {
+                input: argument("input")?.to_string(),
+                pause_source: match argument("option") {
+                    Ok("pause") => true,
+                    Ok(option) => bail!("Unknown option '{option}' for 'disable'"),
+                    Err(_) => false,
+                },
+            },
+            "export" => {
+                let mut positional = Vec::new();
+                let (mut from, mut to, mut consume) = (None, None, false);
+                let mut encoding = Encoding::default();
+                while let Ok(word) = argument("input and file") {
+                    match word {
+                        "--from" => from = Some(parse_time(argument("--from time")?)?),
+                        "--to" => to = Some(parse_time(argument("--to time")?)?),
+                        "--consume" => consume = true,
+                        "--format" => encoding = argument("--format format")?.parse()?,
+                        _ => positional.push(word),
+                    }
+                }
+                let [input, path] = positional[..] else {
+                    bail!(
+                        "Usage: export <input> [--from <time>] [--to <time>] [--consume] \
+                         [--format <format>] <file.wav>"
+                    );
+                };
+                Command::Export {
+                    input: input.to_string(),
+                    from,
+                    to,
+                    consume,
+                    encoding,
+                    path: PathBuf::from(path),
+                }
+            }
+            #[cfg(feature = "opus")]
+            "listen-later" => {
+                let mut positional = Vec::new();
+                let (mut trim, mut normalize, mut bitrate) = (false, false, None);
+                while let Ok(word) = argument("input and file") {
+                    match word {
+                        "--trim" => trim = true,
+                        "--normalize" => normalize = true,
+                        "--bitrate" => {
+                            let text = argument("--bitrate bitrate")?;
+                            let kbps: u32 = text
+                                .parse()
+                                .with_context(|| format!("Invalid bitrate '{text}'"))?;
+                            if !ogg_opus::BITRATES.contains(&kbps) {
+                                bail!(
+                                    "The bitrate must be between {} and {} kbit/s",
+                                    ogg_opus::BITRATES.start(),
+                                    ogg_opus::BITRATES.end()
+                                );
+                            }
+                            bitrate = Some(kbps);
+                        }
+                        _ => positional.push(word),
+                    }
+                }
+                let [input, path] = positional[..] else {
+                    bail!(
+                        "Usage: listen-later <input> [--trim] [--normalize] [--bitrate <kbit/s>] \
+                         <file.opus>"
+                    );
+                };
+                Command::ListenLater {
+                    input: input.to_string(),
+                    trim,
+                    normalize,
+                    bitrate,
+                    path: PathBuf::from(path),
+                }
+            }
+            "set-speed" => {
+                let input = argument("input")?.to_string();
+                let speed = match argument("speed")? {
+                    "auto" => None,
+                    speed => Some(parse_in_range(speed, "speed", SPEED_RANGE)?),
+                };
+                Command::SetSpeed { input, speed }
+            }
+            "set-rate" => Command::SetRate {
+                input: argument("input")?.to_string(),
+                rate: parse_in_range(argument("rate")?, "rate", SPEED_RANGE)?,
+            },
+            "set-pitch" => Command::SetPitch {
+                input: argument("input")?.to_string(),
+                semitones: parse_in_range(argument("semitones")?, "pitch", PITCH_RANGE)?,
+            },
+            "set-gain" => Command::SetGain {
+                input: argument("input")?.to_string(),
+                gain: parse_in_range(argument("gain")?, "gain", GAIN_RANGE)? as f32,
+            },
+            "mute" => Command::Mute {
+                input: argument("input")?.to_string(),
+                muted: parse_switch(argument("on|off").ok())?,
+            },
+            "chain" => Command::Chain {
+                input: argument("input")?.to_string(),
+                chain: match argument("chain")? {
+                    "none" => String::new(),
+                    chain => chain.to_string(),
+                },
+            },
+            "param" => Command::Param {
+                input: argument("input")?.to_string(),
+                effect: argument("effect")?.to_string(),
+                parameter: argument("parameter")?.to_string(),
+                value: argument("value")?
+                    .parse()
+                    .context("Invalid parameter value")?,
+            },
+            "bypass" => {
+                let input = argument("input")?.to_string();
+                let (effect, bypassed) =
+                    parse_bypass(argument("effect").ok(), argument("on/off").ok())?;
+                Command::Bypass {
+                    input,
+                    effect,
+                    bypassed,
+                }
+            }
+            "bypass-all" => Command::BypassAll {
+                bypassed: parse_switch(argument("on/off").ok())?,
+            },
+            "bus" => Command::Bus {
+                chain: match argument("chain")? {
+                    "none" => String::new(),
+                    chain => chain.to_string(),
+                },
+            },
+            "bus-param" => Command::BusParam {
+                effect: argument("effect")?.to_string(),
+                parameter: argument("parameter")?.to_string(),
+                value: argument("value")?
+                    .parse()
+                    .context("Invalid parameter value")?,
+            },
+            "bus-bypass" => {
+                let (effect, bypassed) =
+                    parse_bypass(argument("effect").ok(), argument("on/off").ok())?;
+                Command::BusBypass { effect, bypassed }
+            }
+            #[cfg(feature = "clap-plugins")]
+            "clap-plugins" => Command::ClapPlugins,
+            "spectrum" => {
+                let source = match argument("source")? {
+                    "off" => None,
+                    "output" => Some(SpectrumSource::Output),
+                    input => Some(SpectrumSource::Input(input.to_string())),
+                };
+                let spectrogram = match argument("view") {
+                    Ok("spectrogram") => true,
+                    Ok(view) => bail!("Unknown view '{view}', expected 'spectrogram'"),
+                    Err(_) => false,
+                };
+                Command::Spectrum {
+                    source,
+                    spectrogram,
+                }
+            }
+            "meter" => {
+                let mode = argument("mode")?.parse()?;
+                let mut number = |what: &str| match argument(what) {
+                    Ok(value) => value
+                        .parse()
+                        .map(Some)
+                        .with_context(|| format!("Invalid {what}")),
+                    Err(_) => Ok(None),
+                };
+                Command::Meter {
+                    mode,
+                    floor_db: number("floor")?,
+                    ceiling_db: number("ceiling")?,
+                    decay_db_per_second: number("decay")?,
+                }
+            }
+            "engine" => Command::Engine {
+                engine: argument("engine")?.parse()?,
+            },
+            "crossfade" => {
+                let length = parse_duration(argument("duration")?)?;
+                if length > crossfade::MAX_CROSSFADE {
+                    bail!(
+                        "The crossfade is at most {}ms",
+                        crossfade::MAX_CROSSFADE.as_millis()
+                    );
+                }
+                Command::Crossfade { length }
+            }
+            "scheduling" => Command::Scheduling {
+                scheduling: argument("policy")?.parse()?,
+            },
+            "mixing" => Command::Mixing {
+                on: parse_switch(argument("on|off").ok())?,
+                duck_db: argument("duck dB")
+                    .ok()
+                    .map(|db| parse_in_range(db, "ducking depth", DUCK_RANGE))
+                    .transpose()?
+                    .map(|db| db as f32),
+            },
+            "speed-trim" => Command::SpeedTrim {
+                factor: parse_in_range(argument("factor")?, "speed trim", TRIM_RANGE)?,
+            },
+            _ => bail!("Unknown command '{name}'"),
+        };
+        Ok(command)
+    }
+
+    /// Applies the command to the running multiplexer and returns a response for the user
+    pub fn apply(self, state: &mut JackState) -> anyhow::Result<String> {
+        match self {
+            Command::Boost {
+                input,
+                duration,
+                factor,
+            } => {
+                let until = Instant::now()
+                    .checked_add(duration)
+                    .ok_or_else(|| anyhow!("Can't boost {input} for that long"))?;
+                find_input(&mut state.inputs, &input)?.boost = Some(Boost { factor, until });
+                state.timeline.push(Event::Boosted {
+                    input: input.clone(),
+                    factor,
+                    seconds: duration.as_secs_f32(),
+                });
+                Ok(format!(
+                    "Boosted {input} by {factor} for {:.0}s",
+                    duration.as_secs_f32()
+                ))
+            }
+            Command::Generator { waveform, level_db } => {
+                let generator = state
+                    .inputs
+                    .iter_mut()
+                    .find_map(|input| match &mut input.source {
+                        Source::Generator(generator) => Some(generator),
+                        Source::Ports(_) | Source::Network(_) | Source::File(_) => None,
+                    })
+                    .ok_or_else(|| anyhow!("No generator input"))?;
+                if let Some(level_db) = level_db {
+                    generator.level_db = level_db;
+                }
+                generator.waveform = waveform;
+                Ok(match &generator.waveform {
+                    Some(waveform) => {
+                        format!("Generating {waveform:?} at {} dBFS", generator.level_db)
+                    }
+                    None => "Generator off".to_string(),
+                })
+            }
+            Command::Play { action } => {
+                let player = file_player(&mut state.inputs)?;
+                match action {
+                    PlayAction::File(path) => player.play(&path)?,
+                    PlayAction::Next => player.next()?,
+                    PlayAction::Previous => player.previous()?,
+                    PlayAction::Seek(position) => player.seek(position)?,
+                    PlayAction::Off => {
+                        player.stop();
+                        return Ok("Stopped playing".to_string());
+                    }
+                }
+                let (position, length) = player.playlist_position();
+                Ok(match player.playing() {
+                    Some((path, replay_gain)) => format!(
+                        "Playing {position}/{length}: {} from {:.0}s ({replay_gain})",
+                        path.display(),
+                        player.elapsed().as_secs_f32()
+                    ),
+                    None => "Playing nothing".to_string(),
+                })
+            }
+            Command::ReplayGain { mode, preamp_db } => {
+                let player = file_player(&mut state.inputs)?;
+                player.gain_mode = mode;
+                if let Some(preamp_db) = preamp_db {
+                    player.preamp_db = preamp_db;
+                }
+                Ok(format!(
+                    "ReplayGain {mode} with {:+.1} dB preamp",
+                    player.preamp_db
+                ))
+            }
+            Command::Logger {
+                input,
+                directory,
+                encoding,
+            } => {
+                let channel_count = state.output.len();
+                let sample_rate = state.sample_rate;
+                let input = find_input(&mut state.inputs, &input)?;
+                // Stop the old recorder first so the files are finalized
+                input.logger = None;
+                match directory {
+                    Some(directory) => {
+                        input.logger = Some(Recorder::start(
+                            &input.name,
+                            &directory,
+                            channel_count,
+                            sample_rate,
+                            Rotation::default(),
+                            encoding,
+                        )?);
+                        Ok(format!(
+                            "Logging {} to {} as {encoding}",
+                            input.name,
+                            directory.display()
+                        ))
+                    }
+                    None => Ok(format!("Stopped logging {}", input.name)),
+                }
+            }
+            Command::Seek { input, target } => {
+                let time = match target {
+                    SeekTarget::Time(time) => time,
+                    SeekTarget::Bookmark(name) => {
+                        let bookmark = find_bookmark(&state.bookmarks, &name)?;
+                        if bookmark.input != input {
+                            bail!("Bookmark '{name}' belongs to {}", bookmark.input);
+                        }
+                        bookmark.time - SEEK_LEAD
+                    }
+                };
+                time_shift(state, &input, time)
+            }
+            Command::Live { input } => {
+                let input = find_input(&mut state.inputs, &input)?;
+                if input.timeshift.take().is_none() {
+                    bail!("{} is already live", input.name);
+                }
+                input.buffer.clear();
+                let name = input.name.clone();
+                state.timeline.push(Event::Live {
+                    input: name.clone(),
+                });
+                Ok(format!("{name} is live again"))
+            }
+            Command::Capacity => {
+                let available = capacity::available_memory()?;
+                let mut lines = Vec::new();
+                let mut per_hour_total = 0;
+                for input in &state.inputs {
+                    let channel_count = input
+                        .buffer
+                        .iter()
+                        .find_map(|item| match item {
+                            BufferItem::Samples(samples, _) => Some(samples.len()),
+                            BufferItem::Silence(_) => None,
+                        })
+                        .unwrap_or(state.output.len());
+                    let item_frames = match input.buffer.block_frames {
+                        0 => state.period_frames,
+                        block_frames => block_frames,
+                    };
+                    let per_hour =
+                        capacity::bytes_per_hour(channel_count, state.sample_rate, item_frames);
+                    per_hour_total += per_hour;
+                    let backlog = input.buffered_samples() as f64 / state.sample_rate.max(1) as f64;
+                    lines.push(format!(
+                        "{}: {}/h, backlog {:.0}s taking {}",
+                        input.name,
+                        capacity::format_bytes(per_hour),
+                        backlog,
+                        capacity::format_bytes((per_hour as f64 * backlog / 3600.0) as usize)
+                    ));
+                }
+                lines.push(format!(
+                    "{} available, {:.1}h of backlog on all inputs at once",
+                    capacity::format_bytes(available),
+                    available as f64 / per_hour_total.max(1) as f64
+                ));
+                Ok(lines.join("\n"))
+            }
+            Command::Stats => Ok(state
+                .inputs
+                .iter()
+                .map(|input| format!("{}: {}", input.name, input.stats))
+                .collect::<Vec<_>>()
+                .join("\n")),
+            Command::Hold { input, held } => {
+                let input = find_input(&mut state.inputs, &input)?;
+                input.held = held.unwrap_or(!input.held);
+                let (name, held) = (input.name.clone(), input.held);
+                state.timeline.push(Event::Held {
+                    input: name.clone(),
+                    held,
+                });
+                Ok(format!("{name} {}", if held { "held" } else { "released" }))
+            }
+            Command::Replay { input, length } => {
+                let sample_rate = state.sample_rate.max(1);
+                let input = find_input(&mut state.inputs, &input)?;
+                let frames =
+                    input.requeue_played((length.as_secs_f64() * sample_rate as f64) as usize);
+                if frames == 0 {
+                    bail!("{} played nothing to replay", input.name);
+                }
+                let seconds = frames as f32 / sample_rate as f32;
+                input.boost = Some(Boost {
+                    factor: REPLAY_BOOST_FACTOR,
+                    until: Instant::now() + Duration::from_secs_f32(seconds),
+                });
+                let name = input.name.clone();
+                state.timeline.push(Event::Replayed {
+                    input: name.clone(),
+                    seconds,
+                });
+                Ok(format!("Replaying {seconds:.1}s of {name}"))
+            }
+            Command::DoNotDisturb { on } => {
+                let on = on.unwrap_or(!state.do_not_disturb);
+                if on == state.do_not_disturb {
+                    bail!(
+                        "Do not disturb is already {}",
+                        if on { "on" } else { "off" }
+                    );
+                }
+                state.do_not_disturb = on;
+                if let Some(auto_dnd) = state.auto_dnd.as_mut().filter(|_| !on) {
+                    // Not turned on again until the next call
+                    auto_dnd.dismissed = auto_dnd.engaged;
+                    auto_dnd.engaged = false;
+                }
+                state.apply_do_not_disturb();
+                state.timeline.push(Event::DoNotDisturb { on });
+                let deferred = state.inputs.iter().filter(|input| input.deferred).count();
+                Ok(if on {
+                    format!("Do not disturb, holding {deferred} inputs")
+                } else {
+                    "Do not disturb ended".to_string()
+                })
+            }
+            Command::AutoDnd { source, hang_time } => {
+                if let Some(CallSource::Input(input)) = &source {
+                    find_input(&mut state.inputs, input)?;
+                }
+                // A do-not-disturb started by the previous call source ends with it
+                if state
+                    .auto_dnd
+                    .take()
+                    .is_some_and(|auto_dnd| auto_dnd.engaged)
+                {
+                    state.do_not_disturb = false;
+                    state.timeline.push(Event::DoNotDisturb { on: false });
+                }
+                let response = match &source {
+                    Some(source) => format!("Do not disturb during calls, detected by {source}"),
+                    None => "Stopped detecting calls".to_string(),
+                };
+                state.auto_dnd = source.map(|source| AutoDnd::new(source, hang_time));
+                state.apply_do_not_disturb();
+                Ok(response)
+            }
+            Command::Critical { input, critical } => {
+                let name = find_input(&mut state.inputs, &input)?.name.clone();
+                let was_critical = state.critical_inputs.contains(&name);
+                if critical.unwrap_or(!was_critical) {
+                    if !was_critical {
+                        state.critical_inputs.push(name.clone());
+                    }
+                } else {
+                    state.critical_inputs.retain(|critical| *critical != name);
+                }
+                state.apply_do_not_disturb();
+                Ok(if state.critical_inputs.contains(&name) {
+                    format!("{name} plays during do-not-disturb")
+                } else {
+                    format!("{name} is held during do-not-disturb")
+                })
+            }
+            Command::AddInput { input } => {
+                if state
+                    .inputs
+                    .iter()
+                    .any(|existing| existing.name == input.name)
+                {
+                    bail!("There already is an input {}", input.name);
+                }
+                if input.channels == Some(0) {
+                    bail!("An input needs at least one channel");
+                }
+                let response = format!("Adding input {}", input.name);
+                state.input_requests.push(InputRequest::Add(input));
+                Ok(response)
+            }
+            Command::Calibrate {
+                input,
+                duration,
+                apply,
+            } => {
+                if let Some(calibration) = &state.calibration {
+                    bail!("Still calibrating {}", calibration.input);
+                }
+                let sample_rate = state.sample_rate;
+                let input = find_input(&mut state.inputs, &input)?;
+                let window = input.silence.window.unwrap_or(silence::DEFAULT_WINDOW);
+                let level = input.silence.level;
+                let name = input.name.clone();
+                state.calibration = Some(Calibration::new(
+                    name.clone(),
+                    duration,
+                    sample_rate,
+                    window,
+                    level,
+                    apply,
+                ));
+                Ok(format!(
+                    "Measuring the noise floor of {name} for {:.0}s, keep its source silent",
+                    duration.as_secs_f64()
+                ))
+            }
+            Command::CalibratePause { input, apply } => {
+                if let Some(calibration) = &state.pause_calibration {
+                    bail!("Still calibrating {}", calibration.input);
+                }
+                let input = find_input(&mut state.inputs, &input)?;
+                let name = input.name.clone();
+                let pausing = input
+                    .pausing
+                    .as_mut()
+                    .ok_or_else(|| anyhow!("{name} isn't paused automatically"))?;
+                if pausing.source_paused {
+                    bail!("The source of {name} is paused, wait for it to play");
+                }
+                if !pausing.pause()? {
+                    bail!("The source of {name} isn't playing");
+                }
+                let threshold = input.silence.threshold;
+                state.pause_calibration =
+                    Some(PauseCalibration::new(name.clone(), threshold, apply));
+                Ok(format!(
+                    "Measuring the pause latency of {name}, keep its source playing"
+                ))
+            }
+            Command::RemoveInput { input } => {
+                let input = find_input(&mut state.inputs, &input)?;
+                if !matches!(input.source, Source::Ports(_)) {
+                    bail!("Only inputs with ports can be removed");
+                }
+                let name = input.name.clone();
+                state
+                    .input_requests
+                    .push(InputRequest::Remove(name.clone()));
+                Ok(format!("Removing input {name}"))
+            }
+            Command::Profile { profile: None } => {
+                let active = state.profile.as_deref().unwrap_or("none");
+                if state.profiles.is_empty() {
+                    return Ok("The config has no profiles".to_string());
+                }
+                Ok(format!(
+                    "Profiles: {}, active: {active}",
+                    state.profiles.join(", ")
+                ))
+            }
+            Command::Profile {
+                profile: Some(profile),
+            } => {
+                if let Some(name) = &profile {
+                    if !state.profiles.contains(name) {
+                        bail!("Unknown profile '{name}'");
+                    }
+                }
+                if profile == state.profile {
+                    bail!("The profile is active already");
+                }
+                let response = match &profile {
+                    Some(name) => format!("Switching to profile {name}"),
+                    None => "Switching to the general settings".to_string(),
+                };
+                state.requested_profile = Some(profile);
+                Ok(response)
+            }
+            Command::Flush { input } => {
+                let sample_rate = state.sample_rate.max(1);
+                let input = find_input(&mut state.inputs, &input)?;
+                let seconds = input.buffered_samples() as f32 / sample_rate as f32;
+                input.buffer.clear();
+                let name = input.name.clone();
+                state.timeline.push(Event::Flushed {
+                    input: name.clone(),
+                    seconds,
+                });
+                Ok(format!("Flushed {seconds:.1}s of {name}"))
+            }
+            Command::Skip { input } => {
+                let Some(input) = input.or_else(|| state.playing.clone()) else {
+                    bail!("Nothing is playing");
+                };
+                let sample_rate = state.sample_rate.max(1);
+                let input = find_input(&mut state.inputs, &input)?;
+                // Spilled audio may be part of what is skipped
+                input.buffer.read_back(usize::MAX)?;
+                let pause = input
+                    .buffer
+                    .iter()
+                    .position(|item| matches!(item, BufferItem::Silence(_)))
+                    .unwrap_or(input.buffer.len());
+                let seconds = input
+                    .buffer
+                    .drain(..pause)
+                    .map(|item| item_length(&item))
+                    .sum::<usize>() as f32
+                    / sample_rate as f32;
+                if let Some(stretcher) = input.stretcher.as_mut() {
+                    stretcher.clear();
+                }
+                let name = input.name.clone();
+                state.timeline.push(Event::Dropped {
+                    input: name.clone(),
+                    seconds,
+                    reason: DropReason::Skipped,
+                });
+                Ok(format!("Skipped {seconds:.1}s of {name}"))
+            }
+            Command::Priority { input, priority } => {
+                let input = find_input(&mut state.inputs, &input)?;
+                input.priority = priority;
+                Ok(format!("{} is in priority tier {priority}", input.name))
+            }
+            Command::SoftPreempt { input, max_wait } => {
+                let input = find_input(&mut state.inputs, &input)?;
+                input.soft_preemption = max_wait;
+                Ok(match max_wait {
+                    Some(max_wait) => format!(
+                        "{} waits up to {:.1}s for a pause before taking over",
+                        input.name,
+                        max_wait.as_secs_f32()
+                    ),
+                    None => format!("{} takes over immediately", input.name),
+                })
+            }
+            Command::Overlap {
+                first,
+                second,
+                overlap,
+            } => {
+                let first = find_input(&mut state.inputs, &first)?.name.clone();
+                let second = find_input(&mut state.inputs, &second)?.name.clone();
+                if first == second {
+                    bail!("An input always serializes with itself");
+                }
+                state.overlap.set(&first, &second, overlap);
+                Ok(format!("{first} and {second}: {overlap}"))
+            }
+            Command::Focus { input: None } => {
+                let Some(focus) = state.focus.take() else {
+                    bail!("No input is focused");
+                };
+                state.timeline.push(Event::Focused {
+                    input: focus.clone(),
+                    focused: false,
+                });
+                Ok(format!("Released the focus on {focus}"))
+            }
+            Command::Focus { input: Some(input) } => {
+                let input = find_input(&mut state.inputs, &input)?;
+                if input.disabled || input.held {
+                    bail!("{} can't be played right now", input.name);
+                }
+                if input.buffered_samples() == 0 {
+                    bail!("{} has nothing queued", input.name);
+                }
+                let name = input.name.clone();
+                if let Some(previous) = state.focus.replace(name.clone()) {
+                    state.timeline.push(Event::Focused {
+                        input: previous,
+                        focused: false,
+                    });
+                }
+                state.timeline.push(Event::Focused {
+                    input: name.clone(),
+                    focused: true,
+                });
+                Ok(format!("Playing only {name} until its backlog is played"))
+            }
+            Command::CatchUp { input, catch_up } => {
+                let input = find_input(&mut state.inputs, &input)?;
+                input.catch_up = catch_up;
+                Ok(format!("{} catches up with: {catch_up}", input.name))
+            }
+            Command::LeadIn { input, lead_in } => {
+                let input = find_input(&mut state.inputs, &input)?;
+                let Some(pausing) = input.pausing.as_mut() else {
+                    bail!("{} doesn't pause its source", input.name);
+                };
+                pausing.lead_in = lead_in;
+                Ok(match lead_in {
+                    Some(lead_in) => format!(
+                        "{} replays {:.0}s when resuming after {:.0}s",
+                        input.name,
+                        lead_in.length.as_secs_f64(),
+                        lead_in.after.as_secs_f64()
+                    ),
+                    None => format!("{} resumes without a lead-in", input.name),
+                })
+            }
+            Command::TransportGate { input, gate } => {
+                let input = find_input(&mut state.inputs, &input)?;
+                input.transport_gate = gate;
+                Ok(match gate {
+                    Some(gate) => format!("{} captured only while {gate}", input.name),
+                    None => format!("{} captured regardless of the transport", input.name),
+                })
+            }
+            Command::Enable { input } => {
+                let input = find_input(&mut state.inputs, &input)?;
+                if !input.disabled {
+                    bail!("{} is already enabled", input.name);
+                }
+                input.disabled = false;
+                input.awaiting_connection = false;
+                let name = input.name.clone();
+                state.timeline.push(Event::Enabled {
+                    input: name.clone(),
+                });
+                Ok(format!("Enabled {name}"))
+            }
+            Command::Disable {
+                input,
+                pause_source,
+            } => {
+                let input = find_input(&mut state.inputs, &input)?;
+                if input.disabled && !input.awaiting_connection {
+                    bail!("{} is already disabled", input.name);
+                }
+                input.disabled = true;
+                input.awaiting_connection = false;
+                let name = input.name.clone();
+                if pause_source {
+                    let Some(pausing) = input.pausing.as_mut() else {
+                        bail!("{name} doesn't pause its source");
+                    };
+                    if pausing.pause()? {
+                        state.timeline.push(Event::Paused {
+                            input: name.clone(),
+                        });
+                    }
+                }
+                state.timeline.push(Event::Disabled {
+                    input: name.clone(),
+                });
+                Ok(format!("Disabled {name}"))
+            }
+            Command::Bookmark {
+                input,
+                name,
+                at_capture,
+            } => {
+                if state.bookmarks.iter().any(|bookmark| bookmark.name == name) {
+                    bail!("Bookmark '{name}' already exists");
+                }
+                let output_latency = state.output_latency;
+                let input = find_input(&mut state.inputs, &input)?;
+                let time = match (at_capture, input.playback_position) {
+                    // What is heard right now left the output that much earlier
+                    (false, Some(position)) => position - output_latency,
+                    (false, None) => bail!("Nothing has been played on {} yet", input.name),
+                    (true, _) => match &input.timeshift {
+                        Some(timeshift) => timeshift.position(),
+                        None => SystemTime::now(),
+                    },
+                };
+                let bookmark = Bookmark {
+                    name,
+                    input: input.name.clone(),
+                    time,
+                };
+                let response = format!(
+                    "Bookmarked {} {:.0}s ago as '{}'",
+                    bookmark.input,
+                    bookmark.age(),
+                    bookmark.name
+                );
+                state.timeline.push(Event::Bookmarked {
+                    input: bookmark.input.clone(),
+                    name: bookmark.name.clone(),
+                });
+                state.bookmarks.push(bookmark);
+                Ok(response)
+            }
+            Command::Schedule { input, rule } => {
+                find_input(&mut state.inputs, &input)?;
+                match rule {
+                    Some(rule) => {
+                        let response = format!("Scheduled recording {rule}");
+                        state.recording_rules.push(rule);
+                        Ok(response)
+                    }
+                    None => {
+                        let count = state.recording_rules.len();
+                        state.recording_rules.retain(|rule| rule.input != input);
+                        Ok(format!(
+                            "Removed {} scheduled recordings of {input}",
+                            count - state.recording_rules.len()
+                        ))
+                    }
+                }
+            }
+            Command::Jingles {
+                input,
+                directory,
+                action,
+            } => {
+                let sample_rate = state.sample_rate;
+                let input = find_input(&mut state.inputs, &input)?;
+                match directory {
+                    Some(directory) => {
+                        let jingles = JingleSkip::load(&directory, sample_rate, action)?;
+                        let response = format!(
+                            "Looking for {} snippets on {}, {action}",
+                            jingles.snippet_count(),
+                            input.name
+                        );
+                        input.jingles = Some(jingles);
+                        Ok(response)
+                    }
+                    None => {
+                        input.jingles = None;
+                        Ok(format!("Stopped looking for jingles on {}", input.name))
+                    }
+                }
+            }
+            Command::Transcribe { input, command } => {
+                let channel_count = state.output.len();
+                let sample_rate = state.sample_rate;
+                let input = find_input(&mut state.inputs, &input)?;
+                match command {
+                    Some(command) => {
+                        input.transcriber =
+                            Some(Transcriber::start(&command, channel_count, sample_rate)?);
+                        Ok(format!("Transcribing {} with '{command}'", input.name))
+                    }
+                    None => {
+                        input.transcriber = None;
+                        Ok(format!("Stopped transcribing {}", input.name))
+                    }
+                }
+            }
+            Command::Messages { input } => {
+                if let Some(input) = &input {
+                    find_input(&mut state.inputs, input)?;
+                }
+                Ok(list_messages(&state.inputs, |name, _| {
+                    input.as_ref().is_none_or(|input| input == name)
+                }))
+            }
+            Command::Search { query } => {
+                let found =
+                    list_messages(&state.inputs, |_, transcript| transcript.matches(&query));
+                if found.is_empty() {
+                    bail!("No message says '{query}'");
+                }
+                Ok(found)
+            }
+            Command::Jump { input, message } => {
+                let sample_rate = state.sample_rate.max(1);
+                let found = find_input(&mut state.inputs, &input)?;
+                let Some(transcriber) = &found.transcriber else {
+                    bail!("{} is not transcribed", found.name);
+                };
+                let Some(transcript) = transcriber.find(message) else {
+                    bail!("No message #{message} on {}", found.name);
+                };
+                let captured_at = transcript.captured_at;
+                found.buffer.read_back(usize::MAX)?;
+                // Segments start with a fresh item, unless the audio was dropped in the meantime
+                let queued = found.buffer.iter().position(
+                    |item| matches!(item, BufferItem::Samples(_, time) if *time == captured_at),
+                );
+                match queued {
+                    Some(0) => Ok(format!("Message #{message} is up next on {}", found.name)),
+                    Some(index) => {
+                        let seconds = found
+                            .buffer
+                            .drain(..index)
+                            .map(|item| item_length(&item))
+                            .sum::<usize>() as f32
+                            / sample_rate as f32;
+                        let name = found.name.clone();
+                        state.timeline.push(Event::Dropped {
+                            input: name.clone(),
+                            seconds,
+                            reason: DropReason::Skipped,
+                        });
+                        Ok(format!(
+                            "Skipped {seconds:.1}s of {name} to message #{message}"
+                        ))
+                    }
+                    None => time_shift(state, &input, captured_at),
+                }
+            }
+            Command::Schedules => Ok(state
+                .recording_rules
+                .iter()
+                .map(RecordingRule::to_string)
+                .collect::<Vec<_>>()
+                .join("\n")),
+            Command::Bookmarks => Ok(state
+                .bookmarks
+                .iter()
+                .map(|bookmark| {
+                    format!(
+                        "{}: {} {:.0}s ago",
+                        bookmark.name,
+                        bookmark.input,
+                        bookmark.age()
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join("\n")),
+            Command::ExportBookmark { name, path, margin } => {
+                let bookmark = find_bookmark(&state.bookmarks, &name)?;
+                let input = find_input(&mut state.inputs, &bookmark.input)?;
+                let directory = match &input.logger {
+                    Some(logger) => logger.directory().to_path_buf(),
+                    None => bail!("{} is not logged, nothing to export", input.name),
+                };
+                let frames = recorder::export(
+                    &directory,
+                    &input.name,
+                    bookmark.time - margin,
+                    bookmark.time + margin,
+                    Encoding::default(),
+                    &path,
+                )?;
+                Ok(format!(
+                    "Exported {:.1}s around '{name}' to {}",
+                    frames as f32 / state.sample_rate as f32,
+                    path.display()
+                ))
+            }
+            Command::Export {
+                input,
+                from,
+                to,
+                consume,
+                encoding,
+                path,
+            } => export(state, &input, from, to, consume, encoding, &path),
+            #[cfg(feature = "opus")]
+            Command::ListenLater {
+                input,
+                trim,
+                normalize,
+                bitrate,
+                path,
+            } => {
+                let channel_count = state.output.len();
+                let sample_rate = state.sample_rate.max(1);
+                let input = find_input(&mut state.inputs, &input)?;
+                // The whole backlog is saved, spilled audio included
+                input.buffer.read_back(usize::MAX)?;
+                let first_capture = input.buffer.iter().find_map(|item| match item {
+                    BufferItem::Samples(_, captured_at) => Some(*captured_at),
+                    BufferItem::Silence(_) => None,
+                });
+                let Some(first_capture) = first_capture else {
+                    bail!("{} has no backlog to save", input.name);
+                };
+                let items: Vec<BufferItem> = input.buffer.drain(..).collect();
+                let audio = listen_later::render(items, channel_count, trim, normalize);
+                let seconds = audio[0].len() as f32 / sample_rate as f32;
+                let captured = chrono::DateTime::<chrono::Local>::from(first_capture);
+                let metadata = listen_later::Metadata {
+                    title: format!("{} from {}", input.name, captured.format("%Y-%m-%d %H:%M")),
+                    artist: input.name.clone(),
+                    date: captured.format("%Y-%m-%d").to_string(),
+                };
+                let bitrate =
+                    bitrate.unwrap_or(listen_later::BITRATE_PER_CHANNEL * channel_count as u32);
+                listen_later::save(audio, sample_rate, bitrate, metadata, path.clone());
+                let name = input.name.clone();
+                state.timeline.push(Event::SavedForLater {
+                    input: name.clone(),
+                    seconds,
+                    path: path.display().to_string(),
+                });
+                Ok(format!(
+                    "Saving {seconds:.1}s of {name} to {}",
+                    path.display()
+                ))
+            }
+            Command::SetSpeed { input, speed } => {
+                let input = find_input(&mut state.inputs, &input)?;
+                input.speed_override = speed;
+                Ok(match speed {
+                    Some(speed) => format!("Pinned {} to {speed:.2}x", input.name),
+                    None => format!("{} follows its backlog again", input.name),
+                })
+            }
+            Command::SetRate { input, rate } => {
+                let input = find_input(&mut state.inputs, &input)?;
+                input.rate = rate;
+                Ok(format!("Set rate of {} to {rate:.2}x", input.name))
+            }
+            Command::SetPitch { input, semitones } => {
+                let keeps_pitch = state.engine == Engine::SoundTouch;
+                let input = find_input(&mut state.inputs, &input)?;
+                input.pitch = semitones;
+                let mut response =
+                    format!("Set pitch of {} to {semitones:+.1} semitones", input.name);
+                if !keeps_pitch {
+                    response.push_str(", the resample engine can't shift the pitch");
+                }
+                Ok(response)
+            }
+            Command::SetGain { input, gain } => {
+                let input = find_input(&mut state.inputs, &input)?;
+                input.gain = db_to_factor(gain);
+                Ok(format!("Set gain of {} to {gain:+.1} dB", input.name))
+            }
+            Command::Mute { input, muted } => {
+                let input = find_input(&mut state.inputs, &input)?;
+                input.muted = muted.unwrap_or(!input.muted);
+                Ok(format!(
+                    "{} {}",
+                    input.name,
+                    if input.muted { "muted" } else { "unmuted" }
+                ))
+            }
+            Command::SpeedTrim { factor } => {
+                state.speed_trim = factor;
+                Ok(format!("Speed trim set to {factor:.2}x"))
+            }
+            Command::Chain { input, chain } => {
+                let chain = Chain::parse(&chain, state.output.len(), state.sample_rate)?;
+                let input = find_input(&mut state.inputs, &input)?;
+                input.chain = chain;
+                Ok(if input.chain.is_empty() {
+                    format!("Cleared the chain of {}", input.name)
+                } else {
+                    format!("Chain of {}: {}", input.name, input.chain)
+                })
+            }
+            Command::Param {
+                input,
+                effect,
+                parameter,
+                value,
+            } => {
+                let input = find_input(&mut state.inputs, &input)?;
+                input.chain.set_parameter(&effect, &parameter, value)?;
+                Ok(format!(
+                    "Set {parameter} of {effect} on {} to {value}",
+                    input.name
+                ))
+            }
+            Command::Bypass {
+                input,
+                effect,
+                bypassed,
+            } => {
+                let input = find_input(&mut state.inputs, &input)?;
+                let (what, bypassed) = match effect {
+                    Some(effect) => (effect.clone(), input.chain.set_bypassed(&effect, bypassed)?),
+                    None => {
+                        input.chain.bypassed = bypassed.unwrap_or(!input.chain.bypassed);
+                        ("Processing".to_string(), input.chain.bypassed)
+                    }
+                };
+                Ok(format!(
+                    "{what} on {} {}",
+                    input.name,
+                    if bypassed { "bypassed" } else { "active" }
+                ))
+            }
+            Command::BypassAll { bypassed } => {
+                state.bypass_all = bypassed.unwrap_or(!state.bypass_all);
+                Ok(if state.bypass_all {
+                    "All processing bypassed".to_string()
+                } else {
+                    "Processing active again".to_string()
+                })
+            }
+            Command::Bus { chain } => {
+                state.bus = Chain::parse(&chain, state.output.len(), state.sample_rate)?;
+                Ok(if state.bus.is_empty() {
+                    "Cleared the output bus".to_string()
+                } else {
+                    format!("Output bus: {}", state.bus)
+                })
+            }
+            Command::BusParam {
+                effect,
+                parameter,
+                value,
+            } => {
+                state.bus.set_parameter(&effect, &parameter, value)?;
+                Ok(format!(
+                    "Set {parameter} of {effect} to {value}, bus is now {}",
+                    state.bus.description()
+                ))
+            }
+            Command::BusBypass { effect, bypassed } => {
+                let (what, bypassed) = match effect {
+                    Some(effect) => (effect.clone(), state.bus.set_bypassed(&effect, bypassed)?),
+                    None => {
+                        state.bus.bypassed = bypassed.unwrap_or(!state.bus.bypassed);
+                        ("Processing".to_string(), state.bus.bypassed)
+                    }
+                };
+                Ok(format!(
+                    "{what} on the output bus {}",
+                    if bypassed { "bypassed" } else { "active" }
+                ))
+            }
+            #[cfg(feature = "clap-plugins")]
+            Command::ClapPlugins => Ok(clap_plugin::discover()
+                .iter()
+                .map(|plugin| format!("{};{} ({})", plugin.path.display(), plugin.id, plugin.name))
+                .collect::<Vec<_>>()
+                .join("\n")),
+            Command::Spectrum {
+                source,
+                spectrogram,
+            } => {
+                if let Some(SpectrumSource::Input(name)) = &source {
+                    find_input(&mut state.inputs, name)?;
+                }
+                state.spectrum = source.map(|source| Tap::new(source, spectrogram));
+                Ok(match &state.spectrum {
+                    Some(tap) => format!("Analyzing the spectrum of the {}", tap.source),
+                    None => "Spectrum analyzer off".to_string(),
+                })
+            }
+            Command::Meter {
+                mode,
+                floor_db,
+                ceiling_db,
+                decay_db_per_second,
+            } => {
+                let mut settings = state.meter_settings.clone();
+                settings.mode = mode;
+                settings.floor_db = floor_db.unwrap_or(settings.floor_db);
+                settings.ceiling_db = ceiling_db.unwrap_or(settings.ceiling_db);
+                settings.decay_db_per_second =
+                    decay_db_per_second.unwrap_or(settings.decay_db_per_second);
+                if settings.floor_db >= settings.ceiling_db {
+                    bail!("The meter floor must be below its ceiling");
+                }
+                if settings.decay_db_per_second <= 0.0 {
+                    bail!("The meter decay must be positive");
+                }
+                state.meter_settings = settings;
+                let settings = &state.meter_settings;
+                Ok(format!(
+                    "Meters show {} from {} to {} dB, falling {} dB/s",
+                    settings.mode,
+                    settings.floor_db,
+                    settings.ceiling_db,
+                    settings.decay_db_per_second
+                ))
+            }
+            Command::Engine { engine } => {
+                let soundtouch_settings = state.soundtouch_settings;
+                state.set_engine(engine, soundtouch_settings);
+                Ok(format!("Using the {engine:?} engine"))
+            }
+            Command::Crossfade { length } => {
+                state.crossfade = length;
+                Ok(if length.is_zero() {
+                    "Switching between inputs at once".to_string()
+                } else {
+                    format!("Crossfading for {}ms", length.as_millis())
+                })
+            }
+            Command::Scheduling { scheduling } => {
+                state.scheduling = scheduling;
+                Ok(format!("Scheduling by {scheduling}"))
+            }
+            Command::Mixing { on, duck_db } => {
+                state.mixing = on.unwrap_or(!state.mixing);
+                if let Some(db) = duck_db {
+                    state.ducking.depth = db_to_factor(db);
+                }
+                Ok(if state.mixing {
+                    format!(
+                        "Mixing all inputs, ducking by {:.1} dB",
+                        to_db(state.ducking.depth)
+                    )
+                } else {
+                    "Playing inputs one after the other".to_string()
+                })
+            }
+        }
+    }
+}
+
+/// Parses an optional `on`/`off`, `None` toggles
+fn parse_switch(text: Option<&str>) -> anyhow::Result<Option<bool>> {
+    match text {
+        None => Ok(None),
+        Some("on") => Ok(Some(true)),
+        Some("off") => Ok(Some(false)),
+        Some(text) => bail!("Expected 'on' or 'off', got '{text}'"),
+    }
+}
+
+/// Parses the optional effect and `on`/`off` of the bypass commands, where a lone `on`/`off`
+/// refers to the whole chain
+fn parse_bypass(
+    first: Option<&str>,
+    second: Option<&str>,
+) -> anyhow::Result<(Option<String>, Option<bool>)> {
+    match first {
+        Some("on" | "off") if second.is_none() => Ok((None, parse_switch(first)?)),
+        first => Ok((first.map(str::to_string), parse_switch(second)?)),
+    }
+}
+
+/// Reads commands line by line and applies them until the reader is exhausted
+pub fn serve(reader: impl BufRead, jack_state: Arc<Mutex<JackState>>) {
+    for line in reader.lines() {
+        let Ok(line) = line else {
+            break;
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = Command::parse(&line)
+            // Keeps working while a panicked engine is being restarted
+            .and_then(|command| {
+                command.apply(&mut jack_state.lock().unwrap_or_else(PoisonError::into_inner))
+            });
+        match response {
+            Ok(response) => println!("{response}"),
+            Err(error) => println!("Error: {error:#}"),
+        }
+    }
+}
+
+fn find_input<'a>(inputs: &'a mut [Input], name: &str) -> anyhow::Result<&'a mut Input> {
+    inputs
+        .iter_mut()
+        .find(|input| input.name == name)
+        .ok_or_else(|| anyhow!("No input named '{name}'"))
+}
+
+fn file_player(inputs: &mut [Input]) -> anyhow::Result<&mut FilePlayer> {
+    inputs
+        .iter_mut()
+        .find_map(|input| match &mut input.source {
+            Source::File(player) => Some(player),
+            Source::Ports(_) | Source::Generator(_) | Source::Network(_) => None,
+        })
+        .ok_or_else(|| anyhow!("No file input"))
+}
+
+fn parse_in_range(text: &str, what: &str, range: RangeInclusive<f64>) -> anyhow::Result<f64> {
+    let value: f64 = text
+        .parse()
+        .with_context(|| format!("Invalid {what} '{text}'"))?;
+    check_range(value, what, range)
+}
+
+pub fn check_range(value: f64, what: &str, range: RangeInclusive<f64>) -> anyhow::Result<f64> {
+    if !range.contains(&value) {
+        bail!(
+            "The {what} must be between {} and {}",
+            range.start(),
+            range.end()
+        );
+    }
+    Ok(value)
+}
+
+fn find_bookmark<'a>(bookmarks: &'a [Bookmark], name: &str) -> anyhow::Result<&'a Bookmark> {
+    bookmarks
+        .iter()
+        .find(|bookmark| bookmark.name == name)
+        .ok_or_else(|| anyhow!("No bookmark named '{name}'"))
+}
+
+/// Plays a logged input from its recording, starting at `time`
+fn time_shift(state: &mut JackState, input: &str, time: SystemTime) -> anyhow::Result<String> {
+    let channel_count = state.output.len();
+    let sample_rate = state.sample_rate;
+    let input = find_input(&mut state.inputs, input)?;
+    let directory = match &input.logger {
+        Some(logger) if !matches!(logger.encoding(), Encoding::Wav(_)) => bail!(
+            "{} is logged as {}, only WAV recordings can be played back",
+            input.name,
+            logger.encoding()
+        ),
+        Some(logger) => logger.directory().to_path_buf(),
+        None => bail!("{} is not logged, nothing to seek in", input.name),
+    };
+    let timeshift = TimeShift::start(&input.name, &directory, channel_count, sample_rate, time)?;
+    let seconds_behind = SystemTime::now()
+        .duration_since(timeshift.start)
+        .unwrap_or_default()
+        .as_secs_f32();
+    // Whatever was queued belongs to the old position
+    input.buffer.clear();
+    input.timeshift = Some(timeshift);
+    let name = input.name.clone();
+    state.timeline.push(Event::TimeShifted {
+        input: name.clone(),
+        seconds_behind,
+    });
+    Ok(format!("Playing {name} from {seconds_behind:.0}s ago"))
+}
+
+/// One line per transcribed segment the filter accepts, oldest first per input
+fn list_messages(inputs: &[Input], filter: impl Fn(&str, &Transcript) -> bool) -> String {
+    let now = SystemTime::now();
+    let mut lines = Vec::new();
+    for input in inputs {
+        let Some(transcriber) = &input.transcriber else {
+            continue;
+        };
+        for transcript in transcriber.transcripts() {
+            if !filter(&input.name, transcript) {
+                continue;
+            }
+            let played = input
+                .playback_position
+                .is_some_and(|position| transcript.captured_at <= position);
+            lines.push(format!(
+                "{} #{} {} {:.0}s ago, {:.1}s: {}",
+                input.name,
+                transcript.id,
+                if played { "played" } else { "queued" },
+                now.duration_since(transcript.captured_at)
+                    .unwrap_or_default()
+                    .as_secs_f32(),
+                transcript.seconds,
+                transcript.text
+            ));
+        }
+    }
+    lines.join("\n")
+}
+
+fn export(
+    state: &mut JackState,
+    input: &str,
+    from: Option<SystemTime>,
+    to: Option<SystemTime>,
+    consume: bool,
+    encoding: Encoding,
+    path: &Path,
+) -> anyhow::Result<String> {
+    let channel_count = state.output.len();
+    let sample_rate = state.sample_rate;
+    let input = find_input(&mut state.inputs, input)?;
+    // Spilled audio may be part of the range
+    input.buffer.read_back(usize::MAX)?;
+
+    let frames = match export::buffered_range(&input.buffer, from, to) {
+        Some(range) => {
+            let frames = export::write_items(
+                input.buffer.range(range.clone()),
+                channel_count,
+                sample_rate,
+                encoding,
+                path,
+            )?;
+            if consume {
+                input.buffer.drain(range);
+            }
+            frames
+        }
+        None => {
+            let Some(logger) = &input.logger else {
+                bail!("No buffered audio of {} in the requested range", input.name);
+            };
+            if consume {
+                bail!("Only buffered audio can be consumed");
+            }
+            let from = from.ok_or_else(|| anyhow!("Exporting logged audio needs --from"))?;
+            let to = to.unwrap_or_else(SystemTime::now);
+            recorder::export(logger.directory(), &input.name, from, to, encoding, path)?
+        }
+    };
+    Ok(format!(
+        "Exported {:.1}s of {} to {}",
+        frames as f32 / sample_rate as f32,
+        input.name,
+        path.display()
+    ))
+}
+
+/// Parses `-<duration>` relative to now or a wall clock time `HH:MM[:SS]` within the last day
+fn parse_time(text: &str) -> anyhow::Result<SystemTime> {
+    if let Some(duration) = text.strip_prefix('-') {
+        let duration = parse_duration(duration)?;
+        return SystemTime::now()
+            .checked_sub(duration)
+            .ok_or_else(|| anyhow!("Invalid time '{text}'"));
+    }
+
+    let time = chrono::NaiveTime::parse_from_str(text, "%H:%M:%S")
+        .or_else(|_| chrono::NaiveTime::parse_from_str(text, "%H:%M"))
+        .with_context(|| format!("Invalid time '{text}'"))?;
+    let now = chrono::Local::now();
+    let mut date = now.date_naive();
+    // Times later than now refer to yesterday
+    if time > now.time() {
+        date = date.pred_opt().unwrap_or(date);
+    }
+    let time = date
+        .and_time(time)
+        .and_local_timezone(chrono::Local)
+        .earliest()
+        .ok_or_else(|| anyhow!("Invalid time '{text}'"))?;
+    Ok(time.into())
+}
+
+/// Parses durations like `90`, `90s`, `5m` or `1h`
+pub fn parse_duration(text: &str) -> anyhow::Result<Duration> {
+    let (number, unit) = match text.find(|c: char| c.is_ascii_alphabetic()) {
+        Some(index) => text.split_at(index),
+        None => (text, "s"),
+    };
+    let number: f64 = number
+        .parse()
+        .with_context(|| format!("Invalid duration '{text}'"))?;
+    let seconds = match unit {
+        "ms" => number / 1000.0,
+        "s" => number,
+        "m" | "min" => number * 60.0,
+        "h" => number * 3600.0,
+        _ => bail!("Unknown duration unit '{unit}'"),
+    };
+    Duration::try_from_secs_f64(seconds).with_context(|| format!("Invalid duration '{text}'"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(line: &str) -> String {
+        match Command::parse(line) {
+            Ok(_) => panic!("'{line}' parsed"),
+            Err(error) => format!("{error:#}"),
+        }
+    }
+
+    #[test]
+    fn parses_durations_with_and_without_units() {
+        assert_eq!(parse_duration("90").unwrap(), Duration::from_secs(90));
+        assert_eq!(parse_duration("90s").unwrap(), Duration::from_secs(90));
+        assert_eq!(parse_duration("250ms").unwrap(), Duration::from_millis(250));
+        assert_eq!(parse_duration("1.5m").unwrap(), Duration::from_secs(90));
+        assert_eq!(parse_duration("5min").unwrap(), Duration::from_secs(300));
+        assert_eq!(parse_duration("1h").unwrap(), Duration::from_secs(3600));
+    }
+
+    #[test]
+    fn rejects_invalid_durations() {
+        assert!(parse_duration("").is_err());
+        assert!(parse_duration("s").is_err());
+        assert!(parse_duration("-5s").is_err());
+        assert!(parse_duration("1e400").is_err());
+        assert_eq!(
+            format!("{:#}", parse_duration("5d").unwrap_err()),
+            "Unknown duration unit 'd'"
+        );
+    }
+
+    #[test]
+    fn parses_commands_with_their_defaults() {
+        assert!(matches!(
+            Command::parse("set-speed podcast 1.5").unwrap(),
+            Command::SetSpeed { input, speed: Some(speed) } if input == "podcast" && speed == 1.5
+        ));
+        assert!(matches!(
+            Command::parse("set-speed podcast auto").unwrap(),
+            Command::SetSpeed { speed: None, .. }
+        ));
+        assert!(matches!(
+            Command::parse("  boost   music 30s ").unwrap(),
+            Command::Boost { duration, factor, .. }
+                if duration == Duration::from_secs(30) && factor == DEFAULT_BOOST_FACTOR
+        ));
+        assert!(matches!(
+            Command::parse("mute music").unwrap(),
+            Command::Mute { muted: None, .. }
+        ));
+        assert!(matches!(
+            Command::parse("profile none").unwrap(),
+            Command::Profile {
+                profile: Some(None)
+            }
+        ));
+        assert!(matches!(
+            Command::parse("add-input radio 2 radio:out_l radio:out_r").unwrap(),
+            Command::AddInput { input }
+                if input.channels == Some(2) && input.connect == ["radio:out_l", "radio:out_r"]
+        ));
+    }
+
+    #[test]
+    fn rejects_invalid_commands() {
+        assert_eq!(error(""), "Empty command");
+        assert_eq!(error("speed-up music"), "Unknown command 'speed-up'");
+        assert_eq!(error("set-gain"), "Missing input for 'set-gain'");
+        assert_eq!(
+            error("set-speed music 10"),
+            "The speed must be between 0.25 and 4"
+        );
+        assert_eq!(
+            error("set-gain music loud"),
+            "Invalid gain 'loud': invalid float literal"
+        );
+        assert_eq!(
+            error("mute music maybe"),
+            "Expected 'on' or 'off', got 'maybe'"
+        );
+        assert_eq!(
+            error("disable music now"),
+            "Unknown option 'now' for 'disable'"
+        );
+    }
+}
@@ -0,0 +1,52 @@
+//! Crossfade between inputs when playback switches from one to another, so the switch doesn't
+//! click: the audio still in the stretcher of the previous input fades out while the new one
+//! fades in.
+
+use std::time::Duration;
+
+/// Crossfade of the configs that don't set one, long enough to hide the click of a switch
+pub const DEFAULT_CROSSFADE: Duration = Duration::from_millis(10);
+
+/// Longest crossfade the config and the `crossfade` command accept
+pub const MAX_CROSSFADE: Duration = Duration::from_millis(100);
+
+/// A crossfade in progress
+pub struct Crossfade {
+    /// What the previous input would have played next, per channel
+    tail: Vec<Vec<f32>>,
+    length: usize,
+    position: usize,
+}
+
+impl Crossfade {
+    /// Fades over `length` frames from `tail`, which may be shorter or empty if the previous
+    /// input had less left
+    pub fn new(tail: Vec<Vec<f32>>, length: usize) -> Self {
+        Self {
+            tail,
+            length,
+            position: 0,
+        }
+    }
+
+    /// Fades the frames of `output` from `start` on to `end` in and mixes the tail under them,
+    /// returns whether the crossfade is over
+    pub fn apply(&mut self, output: &mut [Vec<f32>], start: usize, end: usize) -> bool {
+        let frames = (end - start).min(self.length - self.position);
+        for (index, channel) in output.iter_mut().enumerate() {
+            let tail = self.tail.get(index);
+            for frame in 0..frames {
+                let position = self.position + frame;
+                // Equal power, so the level doesn't dip in the middle of the fade
+                let progress = position as f32 / self.length as f32;
+                let fade_in = (progress * std::f32::consts::FRAC_PI_2).sin();
+                let fade_out = (progress * std::f32::consts::FRAC_PI_2).cos();
+                let previous = tail.and_then(|tail| tail.get(position)).copied();
+                let sample = &mut channel[start + frame];
+                *sample = *sample * fade_in + previous.unwrap_or(0.0) * fade_out;
+            }
+        }
+        self.position += frames;
+        self.position >= self.length
+    }
+}
@@ -0,0 +1,139 @@
+//! Short sound announcing an input when playback switches to it after it was quiet for a while,
+//! so it's clear what is playing: a tone, or its name spoken by espeak-ng.
+
+use std::{
+    env,
+    f32::consts::TAU,
+    fs,
+    mem::size_of,
+    process::{self, Command},
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Context};
+use serde::Deserialize;
+
+use crate::{config::CueConfig, sample_format::wav_channels};
+
+const CUE_LEVEL: f32 = 0.25;
+const BEEP_FREQUENCY: f32 = 660.0;
+const BEEP_SECONDS: f32 = 0.12;
+/// The chirp sweeps up an octave
+const CHIRP_FREQUENCIES: (f32, f32) = (660.0, 1320.0);
+const CHIRP_SECONDS: f32 = 0.2;
+/// espeak-ng speaks at about full scale
+const SPEECH_LEVEL: f32 = 0.5;
+
+/// Tone of a cue
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Tone {
+    /// A short steady tone
+    Beep,
+    /// A tone rising quickly, stands out more
+    #[default]
+    Chirp,
+}
+
+impl Tone {
+    fn render(self, channel_count: usize, sample_rate: usize) -> Vec<Vec<f32>> {
+        let (start, end, seconds) = match self {
+            Tone::Beep => (BEEP_FREQUENCY, BEEP_FREQUENCY, BEEP_SECONDS),
+            Tone::Chirp => (CHIRP_FREQUENCIES.0, CHIRP_FREQUENCIES.1, CHIRP_SECONDS),
+        };
+        let frame_count = (seconds * sample_rate as f32) as usize;
+        let mut phase = 0.0;
+        let tone: Vec<f32> = (0..frame_count)
+            .map(|frame| {
+                let progress = frame as f32 / frame_count as f32;
+                let frequency = start + (end - start) * progress;
+                phase = (phase + TAU * frequency / sample_rate as f32) % TAU;
+                // Faded in and out so it doesn't click
+                let envelope = (progress * (1.0 - progress) * 4.0).min(1.0);
+                phase.sin() * envelope * CUE_LEVEL
+            })
+            .collect();
+        vec![tone; channel_count]
+    }
+}
+
+/// Announces an input when it plays after a gap, at most once per `min_interval`
+pub struct Cue {
+    audio: Vec<Vec<f32>>,
+    /// Time the input has to have been quiet for
+    after_gap: Duration,
+    min_interval: Duration,
+    last_cued: Option<Instant>,
+}
+
+impl Cue {
+    /// Renders the cue of the config, speaking its text if it has one
+    pub fn from_config(
+        config: &CueConfig,
+        channel_count: usize,
+        sample_rate: usize,
+    ) -> anyhow::Result<Self> {
+        let audio = match &config.say {
+            Some(text) => speak(text, channel_count, sample_rate)?,
+            None => config.tone.render(channel_count, sample_rate),
+        };
+        Ok(Self {
+            audio,
+            after_gap: Duration::from_secs_f64(config.after_gap),
+            min_interval: Duration::from_secs_f64(config.min_interval),
+            last_cued: None,
+        })
+    }
+
+    /// The cue to play ahead of the input's audio, if the input last played long enough ago and
+    /// the cue wasn't played too recently
+    pub fn due(&mut self, last_played: Option<Instant>) -> Option<Vec<Vec<f32>>> {
+        let gap = last_played.is_none_or(|played| played.elapsed() >= self.after_gap);
+        let rested = self
+            .last_cued
+            .is_none_or(|cued| cued.elapsed() >= self.min_interval);
+        if !gap || !rested {
+            return None;
+        }
+        self.last_cued = Some(Instant::now());
+        Some(self.audio.clone())
+    }
+
+    /// Memory the rendered cue takes
+    pub fn memory_bytes(&self) -> usize {
+        self.audio
+            .iter()
+            .map(|channel| channel.capacity() * size_of::<f32>())
+            .sum()
+    }
+}
+
+/// `text` spoken by espeak-ng, on all channels
+fn speak(text: &str, channel_count: usize, sample_rate: usize) -> anyhow::Result<Vec<Vec<f32>>> {
+    // Written to a file, the WAV header espeak-ng streams to stdout has no length
+    let path = env::temp_dir().join(format!("audiomux-cue-{}.wav", process::id()));
+    let result = Command::new("espeak-ng")
+        .arg("-w")
+        .arg(&path)
+        .arg("--")
+        .arg(text)
+        .output()
+        .context("Failed to run espeak-ng")
+        .and_then(|output| {
+            if !output.status.success() {
+                bail!(
+                    "espeak-ng failed: {}",
+                    String::from_utf8_lossy(&output.stderr).trim()
+                );
+            }
+            let mut reader = hound::WavReader::open(&path)?;
+            Ok(wav_channels(&mut reader, sample_rate)?)
+        });
+    let _ = fs::remove_file(&path);
+    let speech = result.with_context(|| format!("Failed to speak '{text}'"))?;
+    let voice: Vec<f32> = speech[0]
+        .iter()
+        .map(|sample| sample * SPEECH_LEVEL)
+        .collect();
+    Ok(vec![voice; channel_count])
+}
@@ -0,0 +1,115 @@
+//! Follows the default sink of a PipeWire (or PulseAudio) server so the output can move along
+//! with it, e.g. to headphones when they are plugged in.
+//!
+//! The default is polled through `pactl`, PipeWire's JACK emulation has no notification for it.
+
+use std::{process::Command, sync::mpsc, thread, time::Duration};
+
+use anyhow::{bail, Context};
+use jack::{Client, LatencyType, PortFlags};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// JACK's type name of the ports `AudioIn` and `AudioOut` register
+pub const AUDIO_PORT_TYPE: &str = "32 bit float mono audio";
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Sink {
+    /// Node name, as `pactl` knows it
+    pub name: String,
+    /// Human readable name, which PipeWire's JACK emulation uses as the client name of its ports
+    pub description: String,
+}
+
+/// Reports changes of the default sink, starting with the one in effect when created
+pub struct DefaultSink {
+    changes: mpsc::Receiver<Sink>,
+}
+
+impl DefaultSink {
+    pub fn watch() -> Self {
+        let (sender, changes) = mpsc::channel();
+        thread::spawn(move || {
+            let mut current: Option<Sink> = None;
+            loop {
+                match default_sink() {
+                    Ok(sink) if current.as_ref() != Some(&sink) => {
+                        // Gone with the engine that watched it
+                        if sender.send(sink.clone()).is_err() {
+                            return;
+                        }
+                        current = Some(sink);
+                    }
+                    Ok(_) => {}
+                    Err(error) => eprintln!("<4>Failed to query the default sink: {error:#}"),
+                }
+                thread::sleep(POLL_INTERVAL);
+            }
+        });
+        DefaultSink { changes }
+    }
+
+    /// The latest default sink if it changed since the last call
+    pub fn changed(&self) -> Option<Sink> {
+        self.changes.try_iter().last()
+    }
+}
+
+fn default_sink() -> anyhow::Result<Sink> {
+    let name = pactl(&["get-default-sink"])?.trim().to_string();
+    let sinks: serde_json::Value = serde_json::from_str(&pactl(&["-f", "json", "list", "sinks"])?)
+        .context("Failed to parse the sinks listed by pactl")?;
+    let description = sinks
+        .as_array()
+        .into_iter()
+        .flatten()
+        .find(|sink| sink["name"] == name.as_str())
+        .and_then(|sink| sink["description"].as_str())
+        .unwrap_or(&name)
+        .to_string();
+    Ok(Sink { name, description })
+}
+
+pub fn pactl(args: &[&str]) -> anyhow::Result<String> {
+    let output = Command::new("pactl")
+        .args(args)
+        .output()
+        .context("Failed to run pactl")?;
+    if !output.status.success() {
+        bail!(
+            "pactl {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8(output.stdout)?)
+}
+
+/// Names of the JACK ports playing on `sink`, in channel order
+pub fn playback_ports(client: &Client, sink: &Sink) -> Vec<String> {
+    let pattern = format!("^{}:playback_", escape_regex(&sink.description));
+    client.ports(Some(&pattern), Some(AUDIO_PORT_TYPE), PortFlags::IS_INPUT)
+}
+
+/// Latency from the ports of a sink to the speakers, the largest one of any of them
+pub fn playback_latency(client: &Client, ports: &[String]) -> Duration {
+    let frames = ports
+        .iter()
+        .filter_map(|name| client.port_by_name(name))
+        .map(|port| port.get_latency_range(LatencyType::Playback).1)
+        .max()
+        .unwrap_or(0);
+    Duration::from_secs_f64(f64::from(frames) / client.sample_rate().max(1) as f64)
+}
+
+/// JACK matches port names as extended regular expressions, descriptions may contain anything
+fn escape_regex(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for character in text.chars() {
+        if "\\^$.|?*+()[]{}".contains(character) {
+            escaped.push('\\');
+        }
+        escaped.push(character);
+    }
+    escaped
+}
@@ -0,0 +1,176 @@
+//! Writes a starter config from what is running: an input per application with audio ports in
+//! the JACK (or PipeWire) graph, paused through its MPRIS player if one matches.
+
+use std::{
+    fmt::Write as _,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context};
+use jack::{Client, PortFlags};
+
+use crate::{config, default_sink::AUDIO_PORT_TYPE, mpris};
+
+/// Clients whose ports are never worth an input, e.g. our own
+const IGNORED_CLIENTS: &[&str] = &["Audio Multiplexer", "audiomux-discover"];
+
+/// An application with audio output ports
+pub struct Application {
+    pub client: String,
+    pub ports: Vec<String>,
+    /// MPRIS player pausing it
+    pub player: Option<String>,
+}
+
+/// Prints a starter config, or writes it to a file that doesn't exist yet.
+///
+/// Usage: `discover [file]`, `discover default` writes to where the engine looks for its config.
+pub fn run(mut args: impl Iterator<Item = String>) -> anyhow::Result<()> {
+    let path = args.next().map(|arg| match arg.as_str() {
+        "default" => config::default_path(),
+        _ => PathBuf::from(arg),
+    });
+    let players = match mpris::players() {
+        Ok(players) => players,
+        Err(error) => {
+            eprintln!("Not suggesting players to pause: {error:#}");
+            Vec::new()
+        }
+    };
+    let mut applications = applications()?;
+    for application in applications.iter_mut() {
+        application.player = matching_player(&application.client, &players);
+    }
+    if applications.is_empty() {
+        eprintln!(
+            "No application has audio ports, start the players first for inputs to be set up"
+        );
+    }
+
+    let text = starter_config(&applications, None);
+    match path {
+        Some(path) => {
+            if path.exists() {
+                bail!("{} exists already, not overwriting it", path.display());
+            }
+            write(&path, &text)?;
+            println!(
+                "Wrote a config with {} inputs to {}",
+                applications.len(),
+                path.display()
+            );
+        }
+        None => print!("{text}"),
+    }
+    Ok(())
+}
+
+/// Applications with audio output ports, by JACK client name. Sound cards and the monitors of
+/// sinks are left out, they aren't sources one catches up on.
+pub fn applications() -> anyhow::Result<Vec<Application>> {
+    let (client, _status) = Client::new("audiomux-discover", jack::ClientOptions::NO_START_SERVER)
+        .context("Failed to connect to the JACK server (or PipeWire's JACK emulation)")?;
+    let mut applications: Vec<Application> = Vec::new();
+    for port in client.ports(None, Some(AUDIO_PORT_TYPE), PortFlags::IS_OUTPUT) {
+        let Some((name, short_name)) = port.split_once(':') else {
+            continue;
+        };
+        let physical = client
+            .port_by_name(&port)
+            .is_some_and(|port| port.flags().contains(PortFlags::IS_PHYSICAL));
+        if physical || short_name.starts_with("monitor_") || IGNORED_CLIENTS.contains(&name) {
+            continue;
+        }
+        match applications
+            .iter_mut()
+            .find(|application| application.client == name)
+        {
+            Some(application) => application.ports.push(port),
+            None => applications.push(Application {
+                client: name.to_string(),
+                ports: vec![port],
+                player: None,
+            }),
+        }
+    }
+    Ok(applications)
+}
+
+/// The player whose name resembles the client's, without the instance so it still matches
+/// once the player restarted
+pub fn matching_player(client: &str, players: &[String]) -> Option<String> {
+    let client = client.to_lowercase();
+    players
+        .iter()
+        // Players are named like `firefox.instance_1_23`
+        .map(|player| player.split('.').next().unwrap_or(player))
+        .find(|name| {
+            let name = name.to_lowercase();
+            client.contains(&name) || name.contains(&client)
+        })
+        .map(str::to_string)
+}
+
+/// Writes a config, creating the directory it is in
+pub fn write(path: &Path, text: &str) -> anyhow::Result<()> {
+    if let Some(directory) = path.parent() {
+        fs::create_dir_all(directory)
+            .with_context(|| format!("Failed to create {}", directory.display()))?;
+    }
+    fs::write(path, text).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Config text with an input per application, the silence threshold left at its default
+/// unless given
+pub fn starter_config(applications: &[Application], silence_threshold: Option<f32>) -> String {
+    let quote = |text: &str| toml::Value::String(text.to_string()).to_string();
+    let mut text = String::from(
+        "# Starter config, one input per application that had audio ports when it was written\n",
+    );
+    let _ = writeln!(text, "channels = 2");
+    if let Some(threshold) = silence_threshold {
+        let _ = writeln!(text, "silence_threshold = {threshold:.4}");
+    }
+    let mut names: Vec<String> = Vec::new();
+    for application in applications {
+        let base: String = application
+            .client
+            .to_lowercase()
+            .chars()
+            .map(|character| match character {
+                'a'..='z' | '0'..='9' | '-' | '_' => character,
+                _ => '-',
+            })
+            .collect();
+        // Clients differing only in case or punctuation would declare an input twice
+        let mut name = base.clone();
+        let mut number = 1;
+        while names.contains(&name) {
+            number += 1;
+            name = format!("{base}-{number}");
+        }
+        names.push(name.clone());
+        let ports: Vec<String> = application.ports.iter().map(|port| quote(port)).collect();
+        let _ = writeln!(text);
+        let _ = writeln!(text, "[[inputs]]");
+        let _ = writeln!(text, "name = {}", quote(&name));
+        let _ = writeln!(text, "channels = {}", application.ports.len().min(2));
+        let _ = writeln!(text, "connect = [{}]", ports.join(", "));
+        match &application.player {
+            Some(player) => {
+                let _ = writeln!(text);
+                let _ = writeln!(text, "[inputs.pausing]");
+                let _ = writeln!(text, "player = {}", quote(player));
+            }
+            None => {
+                let _ = writeln!(
+                    text,
+                    "# No MPRIS player matches {}, [inputs.pausing] takes commands pausing it",
+                    application.client
+                );
+            }
+        }
+    }
+    text
+}
@@ -0,0 +1,105 @@
+//! Mixing mode: all inputs play at once instead of one after the other, and while an input has
+//! sound the inputs of lower priority tiers are turned down under it, like a compressor keyed by
+//! a sidechain. Notification sounds stay audible over music without stopping it.
+
+use std::time::Duration;
+
+use crate::{chain::db_to_factor, config::MixingConfig};
+
+/// How far and how fast inputs are ducked under those of higher priority
+#[derive(Clone, Copy, Debug)]
+pub struct Ducking {
+    /// Gain of ducked inputs, as a factor
+    pub depth: f32,
+    /// Time the gain takes to go down once a higher input has sound
+    pub attack: Duration,
+    /// Time the gain takes to come back up once it is quiet again
+    pub release: Duration,
+    /// Amplitude above which an input has sound and ducks the others
+    pub threshold: f32,
+}
+
+impl Ducking {
+    pub fn from_config(config: &MixingConfig) -> Self {
+        Self {
+            depth: db_to_factor(config.duck_db),
+            attack: Duration::from_secs_f64(config.attack),
+            release: Duration::from_secs_f64(config.release),
+            threshold: db_to_factor(config.threshold_db),
+        }
+    }
+}
+
+impl Default for Ducking {
+    fn default() -> Self {
+        Self::from_config(&MixingConfig::default())
+    }
+}
+
+/// Time the detected level of the sidechain is held for, so the gain doesn't follow the
+/// waveform between its peaks
+const DETECTOR_DECAY: Duration = Duration::from_millis(50);
+
+/// Gain of one input over time, kept between periods so ducking ramps smoothly
+pub struct Ducker {
+    gain: f32,
+    /// Level of the sidechain, decaying after peaks
+    envelope: f32,
+}
+
+impl Default for Ducker {
+    fn default() -> Self {
+        Self {
+            gain: 1.0,
+            envelope: 0.0,
+        }
+    }
+}
+
+impl Ducker {
+    pub fn gain(&self) -> f32 {
+        self.gain
+    }
+
+    /// Turns `audio` down while any of `keys`, the audio of the inputs above it, has sound
+    pub fn apply(
+        &mut self,
+        ducking: &Ducking,
+        keys: &[&[Vec<f32>]],
+        audio: &mut [Vec<f32>],
+        sample_rate: usize,
+    ) {
+        let decay = coefficient(DETECTOR_DECAY, sample_rate);
+        let attack = coefficient(ducking.attack, sample_rate);
+        let release = coefficient(ducking.release, sample_rate);
+        let frame_count = audio.first().map_or(0, Vec::len);
+        for frame in 0..frame_count {
+            let level = keys
+                .iter()
+                .flat_map(|key| key.iter())
+                .filter_map(|channel| channel.get(frame))
+                .fold(0.0f32, |level, sample| level.max(sample.abs()));
+            self.envelope = level.max(self.envelope * decay);
+            let target = if self.envelope > ducking.threshold {
+                ducking.depth
+            } else {
+                1.0
+            };
+            let coefficient = if target < self.gain { attack } else { release };
+            self.gain = target + (self.gain - target) * coefficient;
+            for channel in audio.iter_mut() {
+                channel[frame] *= self.gain;
+            }
+        }
+    }
+}
+
+/// Per-sample factor of a one-pole smoother taking about `time` to settle
+fn coefficient(time: Duration, sample_rate: usize) -> f32 {
+    let samples = time.as_secs_f32() * sample_rate as f32;
+    if samples < 1.0 {
+        0.0
+    } else {
+        (-1.0 / samples).exp()
+    }
+}
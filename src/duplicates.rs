@@ -0,0 +1,123 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    buffer::Buffer,
+    fingerprint::{Fingerprint, BLOCK_SIZE},
+    segments::{chunks, completed_segments},
+    timeline::{Event, Timeline},
+};
+
+/// Blocks two clips may be shifted against each other when comparing them
+const MAX_OFFSET: usize = 4;
+
+/// Longest window, the clips queued within it are remembered
+pub const MAX_WINDOW: Duration = Duration::from_secs(3600);
+
+struct Clip {
+    fingerprint: Fingerprint,
+    queued: Instant,
+    count: usize,
+}
+
+/// Collapses a notification clip that is queued several times within a short window into a
+/// single playback
+pub struct DuplicateSuppression {
+    /// Clips queued again within this window after the first one are suppressed
+    pub window: Duration,
+    /// Longer segments are not considered notification clips
+    pub max_clip: usize,
+    /// Similarity (-1.0 to 1.0) above which two clips are considered the same
+    pub similarity: f32,
+    /// Number of items at the back of the buffer that have not been checked yet
+    unchecked: usize,
+    clips: VecDeque<Clip>,
+}
+
+impl Default for DuplicateSuppression {
+    fn default() -> Self {
+        Self {
+            window: Duration::from_secs(10),
+            max_clip: 48000 * 5,
+            similarity: 0.95,
+            unchecked: 0,
+            clips: VecDeque::new(),
+        }
+    }
+}
+
+impl DuplicateSuppression {
+    /// Has to be called for every item added to the back of the buffer
+    pub fn item_added(&mut self) {
+        self.unchecked += 1;
+    }
+
+    /// Memory the fingerprints of the recent clips take
+    pub fn memory_bytes(&self) -> usize {
+        self.clips
+            .iter()
+            .map(|clip| clip.fingerprint.memory_bytes())
+            .sum()
+    }
+
+    /// How often each clip queued within the window was queued, for those queued more than once
+    pub fn repeats(&self) -> impl Iterator<Item = usize> + '_ {
+        self.clips
+            .iter()
+            .filter(|clip| clip.count > 1 && clip.queued.elapsed() < self.window)
+            .map(|clip| clip.count)
+    }
+
+    /// Removes clips from `buffer` that repeat a clip queued within the window
+    pub fn suppress_duplicates(
+        &mut self,
+        input_name: &str,
+        buffer: &mut Buffer,
+        timeline: &mut Timeline,
+    ) {
+        let now = Instant::now();
+        self.clips
+            .retain(|clip| now.duration_since(clip.queued) < self.window);
+
+        self.unchecked = self.unchecked.min(buffer.len());
+        // The front item is up next, leave it alone
+        let start = (buffer.len() - self.unchecked).max(1);
+        let (segments, open_start) = completed_segments(buffer, start, self.max_clip);
+        self.unchecked = buffer.len() - open_start;
+
+        let mut duplicates = Vec::new();
+        for segment in segments {
+            let fingerprint = Fingerprint::from_chunks(chunks(buffer, segment.clone()));
+            // Segments cut at the maximum length are no clips
+            if fingerprint.len() * BLOCK_SIZE >= self.max_clip {
+                continue;
+            }
+
+            let original = self.clips.iter_mut().find(|clip| {
+                clip.fingerprint.similarity(&fingerprint, MAX_OFFSET) >= self.similarity
+            });
+            match original {
+                Some(original) => {
+                    original.count += 1;
+                    duplicates.push((segment, original.count));
+                }
+                None => self.clips.push_back(Clip {
+                    fingerprint,
+                    queued: now,
+                    count: 1,
+                }),
+            }
+        }
+
+        // Remove back to front so earlier ranges stay valid
+        for (segment, count) in duplicates.into_iter().rev() {
+            buffer.drain(segment);
+            timeline.push(Event::Repeated {
+                input: input_name.to_string(),
+                count,
+            });
+        }
+    }
+}
@@ -0,0 +1,215 @@
+//! Encodings recordings are written in: WAV and FLAC always, Ogg Opus with the `opus` feature
+//! and MP3 with the `mp3` feature.
+//!
+//! Encoders take interleaved frames in chunks of any length and are only ever fed from the
+//! recorders' encoder thread or a command's own thread, never from the process callback.
+
+use std::{fmt, fs::File, io::BufWriter, path::Path, str::FromStr};
+
+use anyhow::{bail, Context};
+
+#[cfg(feature = "mp3")]
+use crate::mp3::{self, Mp3Encoder};
+#[cfg(feature = "opus")]
+use crate::ogg_opus::{self, OpusFile};
+use crate::{
+    flac::{self, FlacEncoder},
+    sample_format::{write_wav_sample, SampleFormat},
+};
+
+/// File extensions of all encodings, recordings of any of them are listed and pruned
+pub const EXTENSIONS: [&str; 4] = ["wav", "flac", "opus", "mp3"];
+
+const WAV_HEADER_BYTES: u64 = 44;
+
+/// Encoding of a recording, with its quality settings
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Encoding {
+    /// Uncompressed in the given sample format
+    Wav(SampleFormat),
+    /// Lossless at 24 bits, the level trades speed for size from 0 (fastest) to 8 (smallest)
+    Flac { level: u8 },
+    /// Lossy, at a bitrate in kbit/s for all channels together
+    #[cfg(feature = "opus")]
+    Opus { bitrate: u32 },
+    /// Lossy, at a constant bitrate in kbit/s
+    #[cfg(feature = "mp3")]
+    Mp3 { bitrate: u32 },
+}
+
+impl Default for Encoding {
+    fn default() -> Self {
+        Encoding::Wav(SampleFormat::F32)
+    }
+}
+
+impl Encoding {
+    pub fn extension(self) -> &'static str {
+        match self {
+            Encoding::Wav(_) => "wav",
+            Encoding::Flac { .. } => "flac",
+            #[cfg(feature = "opus")]
+            Encoding::Opus { .. } => "opus",
+            #[cfg(feature = "mp3")]
+            Encoding::Mp3 { .. } => "mp3",
+        }
+    }
+
+    /// Creates `path` and an encoder writing to it
+    pub fn create(
+        self,
+        path: &Path,
+        channel_count: usize,
+        sample_rate: usize,
+    ) -> anyhow::Result<Box<dyn Encoder>> {
+        Ok(match self {
+            Encoding::Wav(format) => Box::new(WavEncoder::create(
+                path,
+                format.wav_spec(channel_count, sample_rate)?,
+            )?),
+            Encoding::Flac { level } => Box::new(FlacEncoder::create(
+                path,
+                channel_count,
+                sample_rate,
+                level,
+            )?),
+            #[cfg(feature = "opus")]
+            Encoding::Opus { bitrate } => Box::new(OpusFile::create(
+                path,
+                channel_count,
+                sample_rate,
+                bitrate,
+                &[],
+            )?),
+            #[cfg(feature = "mp3")]
+            Encoding::Mp3 { bitrate } => Box::new(Mp3Encoder::create(
+                path,
+                channel_count,
+                sample_rate,
+                bitrate,
+            )?),
+        })
+    }
+}
+
+impl FromStr for Encoding {
+    type Err = anyhow::Error;
+
+    /// Parses `wav[:<sample format>]`, `flac[:<level>]`, `opus[:<kbit/s>]` or `mp3[:<kbit/s>]`.
+    /// A bare sample format like `s16` is a WAV file of that format.
+    fn from_str(text: &str) -> anyhow::Result<Self> {
+        let (codec, setting) = match text.split_once(':') {
+            Some((codec, setting)) => (codec, Some(setting)),
+            None => (text, None),
+        };
+        let number = |what: &str| -> anyhow::Result<Option<u32>> {
+            setting
+                .map(|setting| setting.trim_end_matches('k').parse())
+                .transpose()
+                .with_context(|| format!("Invalid {what} '{}'", setting.unwrap_or_default()))
+        };
+        Ok(match codec {
+            "wav" => Encoding::Wav(setting.map_or(Ok(SampleFormat::F32), str::parse)?),
+            "flac" => {
+                let level = number("FLAC level")?.unwrap_or(flac::DEFAULT_LEVEL.into());
+                if level > flac::MAX_LEVEL.into() {
+                    bail!("FLAC levels go from 0 to {}, not {level}", flac::MAX_LEVEL);
+                }
+                Encoding::Flac { level: level as u8 }
+            }
+            #[cfg(feature = "opus")]
+            "opus" => {
+                let bitrate = number("bitrate")?.unwrap_or(ogg_opus::DEFAULT_BITRATE);
+                if !ogg_opus::BITRATES.contains(&bitrate) {
+                    bail!(
+                        "Opus bitrates go from {} to {} kbit/s, not {bitrate}",
+                        ogg_opus::BITRATES.start(),
+                        ogg_opus::BITRATES.end()
+                    );
+                }
+                Encoding::Opus { bitrate }
+            }
+            #[cfg(not(feature = "opus"))]
+            "opus" => bail!("Opus needs the 'opus' feature"),
+            #[cfg(feature = "mp3")]
+            "mp3" => {
+                let bitrate = number("bitrate")?.unwrap_or(mp3::DEFAULT_BITRATE);
+                if !mp3::BITRATES.contains(&bitrate) {
+                    bail!("MP3 has no bitrate of {bitrate} kbit/s, e.g. 128, 192 or 320");
+                }
+                Encoding::Mp3 { bitrate }
+            }
+            #[cfg(not(feature = "mp3"))]
+            "mp3" => bail!("MP3 needs the 'mp3' feature"),
+            _ => match text.parse() {
+                Ok(format) => Encoding::Wav(format),
+                Err(_) => bail!("Unknown encoding '{text}', expected wav, flac, opus or mp3"),
+            },
+        })
+    }
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Encoding::Wav(format) => write!(f, "wav:{format}"),
+            Encoding::Flac { level } => write!(f, "flac:{level}"),
+            #[cfg(feature = "opus")]
+            Encoding::Opus { bitrate } => write!(f, "opus:{bitrate}k"),
+            #[cfg(feature = "mp3")]
+            Encoding::Mp3 { bitrate } => write!(f, "mp3:{bitrate}k"),
+        }
+    }
+}
+
+/// Writes a file of one encoding
+pub trait Encoder: Send {
+    /// Encodes interleaved samples, whole frames of them
+    fn write(&mut self, samples: &[f32]) -> anyhow::Result<()>;
+    /// Makes what was written so far readable by other programs as far as the encoding allows,
+    /// e.g. by updating the WAV header
+    fn flush(&mut self) -> anyhow::Result<()>;
+    /// Size of the file so far
+    fn bytes(&self) -> u64;
+    /// Encodes what is still buffered and completes the file
+    fn finish(self: Box<Self>) -> anyhow::Result<()>;
+}
+
+struct WavEncoder {
+    writer: hound::WavWriter<BufWriter<File>>,
+    bytes: u64,
+}
+
+impl WavEncoder {
+    fn create(path: &Path, spec: hound::WavSpec) -> anyhow::Result<Self> {
+        let writer = hound::WavWriter::create(path, spec)
+            .with_context(|| format!("Failed to create {}", path.display()))?;
+        Ok(Self {
+            writer,
+            bytes: WAV_HEADER_BYTES,
+        })
+    }
+}
+
+impl Encoder for WavEncoder {
+    fn write(&mut self, samples: &[f32]) -> anyhow::Result<()> {
+        for sample in samples {
+            write_wav_sample(&mut self.writer, *sample)?;
+        }
+        let sample_bytes = u64::from(self.writer.spec().bits_per_sample / 8);
+        self.bytes += samples.len() as u64 * sample_bytes;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> anyhow::Result<()> {
+        Ok(self.writer.flush()?)
+    }
+
+    fn bytes(&self) -> u64 {
+        self.bytes
+    }
+
+    fn finish(self: Box<Self>) -> anyhow::Result<()> {
+        Ok(self.writer.finalize()?)
+    }
+}
@@ -0,0 +1,65 @@
+use std::{ops::RangeInclusive, path::Path, time::SystemTime};
+
+use crate::{buffer::Buffer, encoder::Encoding, BufferItem};
+
+/// Items of `buffer` holding audio captured between `from` and `to`, including the silence in
+/// between. `None` if no buffered audio falls into the range.
+pub fn buffered_range(
+    buffer: &Buffer,
+    from: Option<SystemTime>,
+    to: Option<SystemTime>,
+) -> Option<RangeInclusive<usize>> {
+    let in_range = |captured_at: &SystemTime| {
+        from.is_none_or(|from| *captured_at >= from) && to.is_none_or(|to| *captured_at < to)
+    };
+    let mut selected = buffer
+        .iter()
+        .enumerate()
+        .filter_map(|(index, item)| match item {
+            BufferItem::Samples(_, captured_at) if in_range(captured_at) => Some(index),
+            _ => None,
+        });
+    let first = selected.next()?;
+    let last = selected.next_back().unwrap_or(first);
+    Some(first..=last)
+}
+
+/// Writes buffered items at natural speed to a file of the given encoding, stored silence is
+/// written as such.
+///
+/// Returns the number of frames written.
+pub fn write_items<'a>(
+    items: impl IntoIterator<Item = &'a BufferItem>,
+    channel_count: usize,
+    sample_rate: usize,
+    encoding: Encoding,
+    path: &Path,
+) -> anyhow::Result<usize> {
+    let mut encoder = encoding.create(path, channel_count, sample_rate)?;
+
+    let mut frames_written = 0;
+    let mut interleaved = Vec::new();
+    for item in items {
+        interleaved.clear();
+        match item {
+            BufferItem::Samples(samples, _) => {
+                let frame_count = samples[0].len();
+                for frame in 0..frame_count {
+                    for channel in 0..channel_count {
+                        // Inputs with fewer channels than the output are padded with silence
+                        let sample = samples.get(channel).map_or(0.0, |channel| channel[frame]);
+                        interleaved.push(sample);
+                    }
+                }
+                frames_written += frame_count;
+            }
+            BufferItem::Silence(frame_count) => {
+                interleaved.resize(frame_count * channel_count, 0.0);
+                frames_written += frame_count;
+            }
+        }
+        encoder.write(&interleaved)?;
+    }
+    encoder.finish()?;
+    Ok(frames_written)
+}
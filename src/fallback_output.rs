@@ -0,0 +1,146 @@
+//! Plays the output through the sound server while nothing is connected to the output ports, so
+//! it is still heard when there is no JACK playback port to connect to.
+//!
+//! The stream is played by `pacat`, which PipeWire's PulseAudio server takes as well.
+
+use std::{
+    io::Write,
+    process::{Child, ChildStdin, Command, Stdio},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use anyhow::Context;
+use ringbuf::{HeapConsumer, HeapProducer, HeapRb};
+
+/// Time the output may be unconnected before the fallback starts, connections made right after
+/// starting or while switching devices don't start it
+const DELAY: Duration = Duration::from_secs(2);
+
+/// Audio queued for `pacat`, in seconds
+const BUFFER_SECONDS: usize = 1;
+
+/// Buffering `pacat` is asked for
+const LATENCY_MS: usize = 100;
+
+/// How long the output ports are unconnected
+#[derive(Default)]
+pub struct Unconnected {
+    since: Option<Instant>,
+    /// The fallback was started, or failed to, since the ports are unconnected
+    handled: bool,
+}
+
+impl Unconnected {
+    /// The ports are connected
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    /// The ports are unconnected, returns whether it is time to start the fallback. Only once
+    /// per time they are unconnected, a fallback that fails to start isn't tried over and over.
+    pub fn is_due(&mut self) -> bool {
+        let since = *self.since.get_or_insert_with(Instant::now);
+        if self.handled || since.elapsed() < DELAY {
+            return false;
+        }
+        self.handled = true;
+        true
+    }
+}
+
+/// Playback stream of the output, fed by the engine
+pub struct FallbackOutput {
+    producer: HeapProducer<f32>,
+    channel_count: usize,
+    child: Child,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl FallbackOutput {
+    pub fn start(
+        client_name: &str,
+        channel_count: usize,
+        sample_rate: usize,
+    ) -> anyhow::Result<Self> {
+        let mut child = Command::new("pacat")
+            .arg("--playback")
+            .arg("--raw")
+            .arg("--format=float32le")
+            .arg(format!("--rate={sample_rate}"))
+            .arg(format!("--channels={channel_count}"))
+            .arg(format!("--latency-msec={LATENCY_MS}"))
+            .arg(format!("--client-name={client_name}"))
+            .arg("--stream-name=Fallback output")
+            .stdin(Stdio::piped())
+            .spawn()
+            .context("Failed to run pacat")?;
+        let stdin = child.stdin.take().context("pacat has no stdin")?;
+
+        let (producer, consumer) =
+            HeapRb::new(BUFFER_SECONDS * sample_rate * channel_count).split();
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = stop.clone();
+        let thread = thread::spawn(move || feed(stdin, consumer, &thread_stop));
+        Ok(Self {
+            producer,
+            channel_count,
+            child,
+            stop,
+            thread: Some(thread),
+        })
+    }
+
+    /// Queues a period of the output, dropped if `pacat` doesn't keep up
+    pub fn write(&mut self, channels: &[&[f32]]) {
+        let frame_count = channels.first().map_or(0, |channel| channel.len());
+        if self.producer.free_len() < frame_count * self.channel_count {
+            return;
+        }
+        for frame in 0..frame_count {
+            for channel in channels {
+                let _ = self.producer.push(channel[frame]);
+            }
+        }
+    }
+}
+
+impl Drop for FallbackOutput {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        // Also ends a write the thread is blocked in
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Writes the queued audio to `pacat` until stopped or `pacat` exits
+fn feed(mut stdin: ChildStdin, mut consumer: HeapConsumer<f32>, stop: &AtomicBool) {
+    let mut samples = vec![0.0; consumer.capacity()];
+    let mut bytes = Vec::with_capacity(samples.len() * 4);
+    while !stop.load(Ordering::Relaxed) {
+        let count = consumer.pop_slice(&mut samples);
+        if count == 0 {
+            thread::sleep(Duration::from_millis(10));
+            continue;
+        }
+        bytes.clear();
+        for sample in &samples[..count] {
+            bytes.extend_from_slice(&sample.to_le_bytes());
+        }
+        if let Err(error) = stdin.write_all(&bytes) {
+            if !stop.load(Ordering::Relaxed) {
+                eprintln!("<3>Fallback output stopped: {error}");
+            }
+            return;
+        }
+    }
+}
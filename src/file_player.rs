@@ -0,0 +1,637 @@
+//! Playing local audio files into an input.
+//!
+//! Files are decoded with symphonia on a thread of their own, converted to the channel count
+//! and sample rate of the engine and handed to the audio thread through a ring buffer. The gain
+//! stored in ReplayGain or R128 tags is applied while playing, so files come out as loud as the
+//! loudness-normalized live sources.
+//!
+//! M3U playlists play one file after the other. The next file is decoded ahead while the
+//! current one plays, so it follows without a gap.
+
+use std::{
+    fmt,
+    fs::{self, File},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::JoinHandle,
+    time::Duration,
+};
+
+use anyhow::{anyhow, bail, Context};
+use ringbuf::{HeapConsumer, HeapProducer, HeapRb};
+use symphonia::core::{
+    audio::SampleBuffer,
+    codecs::{Decoder, DecoderOptions},
+    errors::Error as DecodeError,
+    formats::{FormatOptions, FormatReader, SeekMode, SeekTo},
+    io::MediaSourceStream,
+    meta::{MetadataOptions, StandardTagKey, Tag},
+    probe::Hint,
+    units::Time,
+};
+
+use crate::stretch::{Resampler, TimeStretch};
+
+/// Seconds of decoded audio held ahead of playback
+const BUFFER_SECONDS: usize = 5;
+
+/// How long the decoder thread sleeps while the buffer is full
+const DECODE_INTERVAL: Duration = Duration::from_millis(20);
+
+/// R128 gains are relative to -23 LUFS, ReplayGain to about -18 LUFS
+const R128_TO_REPLAY_GAIN_DB: f32 = 5.0;
+
+/// Which of the gains stored in a file's tags is applied
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum GainMode {
+    /// Each file normalized on its own, the default
+    #[default]
+    Track,
+    /// Files of an album keep their relative loudness, track gain if there is no album gain
+    Album,
+    /// Files play as they are
+    Off,
+}
+
+impl FromStr for GainMode {
+    type Err = anyhow::Error;
+
+    fn from_str(text: &str) -> anyhow::Result<Self> {
+        Ok(match text {
+            "track" => GainMode::Track,
+            "album" => GainMode::Album,
+            "off" => GainMode::Off,
+            _ => bail!("Unknown ReplayGain mode '{text}', expected track, album or off"),
+        })
+    }
+}
+
+impl fmt::Display for GainMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GainMode::Track => write!(f, "track"),
+            GainMode::Album => write!(f, "album"),
+            GainMode::Off => write!(f, "off"),
+        }
+    }
+}
+
+/// Loudness information of a file, gains in dB relative to the ReplayGain reference level and
+/// peaks as linear sample values
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ReplayGain {
+    pub track_gain: Option<f32>,
+    pub track_peak: Option<f32>,
+    pub album_gain: Option<f32>,
+    pub album_peak: Option<f32>,
+}
+
+impl ReplayGain {
+    /// Collects ReplayGain tags, and R128 tags (as written for Opus files) where those are
+    /// missing
+    fn from_tags<'a>(tags: impl Iterator<Item = &'a Tag>) -> Self {
+        let mut replay_gain = ReplayGain::default();
+        let mut r128 = ReplayGain::default();
+        for tag in tags {
+            let value = tag.value.to_string();
+            match tag.std_key {
+                Some(StandardTagKey::ReplayGainTrackGain) => {
+                    replay_gain.track_gain = parse_number(&value)
+                }
+                Some(StandardTagKey::ReplayGainTrackPeak) => {
+                    replay_gain.track_peak = parse_number(&value)
+                }
+                Some(StandardTagKey::ReplayGainAlbumGain) => {
+                    replay_gain.album_gain = parse_number(&value)
+                }
+                Some(StandardTagKey::ReplayGainAlbumPeak) => {
+                    replay_gain.album_peak = parse_number(&value)
+                }
+                _ => match tag.key.to_ascii_uppercase().as_str() {
+                    "R128_TRACK_GAIN" => r128.track_gain = parse_r128(&value),
+                    "R128_ALBUM_GAIN" => r128.album_gain = parse_r128(&value),
+                    _ => {}
+                },
+            }
+        }
+        replay_gain.track_gain = replay_gain.track_gain.or(r128.track_gain);
+        replay_gain.album_gain = replay_gain.album_gain.or(r128.album_gain);
+        replay_gain
+    }
+
+    /// Gain in dB applied in `mode`, without the preamp and peak limiting
+    pub fn gain_db(&self, mode: GainMode) -> Option<f32> {
+        match mode {
+            GainMode::Track => self.track_gain,
+            GainMode::Album => self.album_gain.or(self.track_gain),
+            GainMode::Off => None,
+        }
+    }
+
+    /// Factor the samples are multiplied with, lowered if the known peak would clip
+    pub fn factor(&self, mode: GainMode, preamp_db: f32) -> f32 {
+        let Some(gain_db) = self.gain_db(mode) else {
+            return 1.0;
+        };
+        let peak = match mode {
+            GainMode::Album if self.album_gain.is_some() => self.album_peak,
+            _ => self.track_peak,
+        };
+        let factor = 10f32.powf((gain_db + preamp_db) / 20.0);
+        match peak {
+            Some(peak) if peak > 0.0 => factor.min(1.0 / peak),
+            _ => factor,
+        }
+    }
+}
+
+impl fmt::Display for ReplayGain {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.track_gain, self.album_gain) {
+            (None, None) => write!(f, "no ReplayGain"),
+            (Some(track), None) => write!(f, "track {track:+.1} dB"),
+            (None, Some(album)) => write!(f, "album {album:+.1} dB"),
+            (Some(track), Some(album)) => write!(f, "track {track:+.1} dB, album {album:+.1} dB"),
+        }
+    }
+}
+
+/// Parses values like `-6.48 dB` or `0.988553`
+fn parse_number(value: &str) -> Option<f32> {
+    value
+        .split_whitespace()
+        .next()?
+        .trim_end_matches("dB")
+        .parse()
+        .ok()
+}
+
+/// Parses a gain in Q7.8 fixed point dB relative to -23 LUFS
+fn parse_r128(value: &str) -> Option<f32> {
+    let gain: i16 = value.trim().parse().ok()?;
+    Some(f32::from(gain) / 256.0 + R128_TO_REPLAY_GAIN_DB)
+}
+
+/// Source of the file input, quiet while no file is playing
+pub struct FilePlayer {
+    channel_count: usize,
+    sample_rate: usize,
+    pub gain_mode: GainMode,
+    /// Added to the stored gain, negative values leave headroom
+    pub preamp_db: f32,
+    /// Files played one after the other, a single file is a playlist of one
+    playlist: Vec<PathBuf>,
+    /// Index of the playing file in `playlist`
+    position: usize,
+    playback: Option<Playback>,
+    /// The file after the playing one, decoding ahead so it follows without a gap
+    next: Option<Playback>,
+    /// Whether a file started since the last `maintain`
+    started: bool,
+}
+
+impl FilePlayer {
+    pub fn new(channel_count: usize, sample_rate: usize) -> Self {
+        Self {
+            channel_count,
+            sample_rate,
+            gain_mode: GainMode::default(),
+            preamp_db: 0.0,
+            playlist: Vec::new(),
+            position: 0,
+            playback: None,
+            next: None,
+            started: false,
+        }
+    }
+
+    /// Starts playing a file or an M3U playlist instead of whatever played before
+    pub fn play(&mut self, path: &Path) -> anyhow::Result<()> {
+        let playlist = if is_playlist(path) {
+            read_playlist(path)?
+        } else {
+            vec![path.to_path_buf()]
+        };
+        let playback = self.open(&playlist[0], Duration::ZERO)?;
+        self.playlist = playlist;
+        self.start(0, playback);
+        Ok(())
+    }
+
+    pub fn stop(&mut self) {
+        self.playlist.clear();
+        self.playback = None;
+        self.next = None;
+    }
+
+    /// Skips to the next file of the playlist
+    pub fn next(&mut self) -> anyhow::Result<()> {
+        let position = self.position + 1;
+        if self.playback.is_none() || position >= self.playlist.len() {
+            bail!("No next file in the playlist");
+        }
+        let playback = match self.next.take() {
+            Some(playback) => playback,
+            None => self.open(&self.playlist[position], Duration::ZERO)?,
+        };
+        self.start(position, playback);
+        Ok(())
+    }
+
+    /// Goes back to the previous file of the playlist
+    pub fn previous(&mut self) -> anyhow::Result<()> {
+        if self.playback.is_none() || self.position == 0 {
+            bail!("No previous file in the playlist");
+        }
+        let position = self.position - 1;
+        let playback = self.open(&self.playlist[position], Duration::ZERO)?;
+        self.start(position, playback);
+        // Decoded ahead for the file that was playing, which now comes next again
+        self.next = None;
+        Ok(())
+    }
+
+    /// Plays the current file from `offset` on
+    pub fn seek(&mut self, offset: Duration) -> anyhow::Result<()> {
+        let Some(playback) = &self.playback else {
+            bail!("No file playing");
+        };
+        let playback = self.open(&playback.path, offset)?;
+        self.playback = Some(playback);
+        Ok(())
+    }
+
+    /// The file being played with its position in the playlist, until all of it was read
+    pub fn playing(&self) -> Option<(&Path, &ReplayGain)> {
+        self.playback
+            .as_ref()
+            .filter(|playback| !playback.is_over())
+            .map(|playback| (playback.path.as_path(), &playback.replay_gain))
+    }
+
+    /// Position in the playlist counting from one, and the length of the playlist
+    pub fn playlist_position(&self) -> (usize, usize) {
+        (self.position + 1, self.playlist.len())
+    }
+
+    /// How far into the playing file playback is
+    pub fn elapsed(&self) -> Duration {
+        self.playback.as_ref().map_or(Duration::ZERO, |playback| {
+            playback.offset
+                + Duration::from_secs_f64(playback.played as f64 / self.sample_rate.max(1) as f64)
+        })
+    }
+
+    /// Factor the playing file is multiplied with
+    pub fn gain(&self) -> f32 {
+        self.playback.as_ref().map_or(1.0, |playback| {
+            playback.replay_gain.factor(self.gain_mode, self.preamp_db)
+        })
+    }
+
+    /// Starts decoding the file after the playing one, outside of the audio thread. Returns the
+    /// file that started playing since the last call, if any.
+    pub fn maintain(&mut self) -> Option<&Path> {
+        let position = self.position + 1;
+        if self.playback.is_some() && self.next.is_none() && position < self.playlist.len() {
+            match self.open(&self.playlist[position], Duration::ZERO) {
+                Ok(playback) => self.next = Some(playback),
+                // Tried again with the one after it on the next call
+                Err(error) => {
+                    eprintln!("<4>Skipping playlist entry: {error:#}");
+                    self.playlist.remove(position);
+                }
+            }
+        }
+        let started = std::mem::take(&mut self.started);
+        self.playback
+            .as_ref()
+            .filter(|_| started)
+            .map(|playback| playback.path.as_path())
+    }
+
+    /// Takes one period of the playing file, `None` while nothing plays or the decoder is
+    /// getting started. When a file ends, the period is filled up from the next one, the end of
+    /// the playlist is padded with silence.
+    pub fn read(&mut self, frame_count: usize) -> Option<Vec<Vec<f32>>> {
+        let playback = self.playback.as_ref()?;
+        let starting = !playback.is_finished() && playback.frames() < frame_count;
+        if starting || (playback.is_over() && self.next.is_none()) {
+            return None;
+        }
+
+        let mut period = vec![Vec::with_capacity(frame_count); self.channel_count];
+        let mut missing = frame_count;
+        while missing > 0 {
+            let gain = self.gain();
+            let Some(playback) = self.playback.as_mut() else {
+                break;
+            };
+            let frames = missing.min(playback.frames());
+            for _ in 0..frames {
+                for channel in period.iter_mut() {
+                    channel.push(playback.consumer.pop().unwrap_or(0.0) * gain);
+                }
+            }
+            playback.played += frames;
+            missing -= frames;
+            if missing > 0 {
+                if !playback.is_over() || self.next.is_none() {
+                    break;
+                }
+                // The decoder thread of the finished file has already ended, so dropping it
+                // doesn't wait
+                self.playback = self.next.take();
+                self.position += 1;
+                self.started = true;
+            }
+        }
+        for channel in period.iter_mut() {
+            channel.resize(frame_count, 0.0);
+        }
+        Some(period)
+    }
+
+    fn open(&self, path: &Path, offset: Duration) -> anyhow::Result<Playback> {
+        Playback::start(path, offset, self.channel_count, self.sample_rate)
+    }
+
+    fn start(&mut self, position: usize, playback: Playback) {
+        self.position = position;
+        self.playback = Some(playback);
+        self.started = true;
+    }
+}
+
+fn is_playlist(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| {
+            extension.eq_ignore_ascii_case("m3u") || extension.eq_ignore_ascii_case("m3u8")
+        })
+}
+
+/// Reads the files of an M3U playlist, relative paths are relative to the playlist
+fn read_playlist(path: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let text = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read playlist {}", path.display()))?;
+    let directory = path.parent().unwrap_or(Path::new(""));
+    let files: Vec<PathBuf> = text
+        .trim_start_matches('\u{feff}')
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| directory.join(line.strip_prefix("file://").unwrap_or(line)))
+        .collect();
+    if files.is_empty() {
+        bail!("Playlist {} has no files", path.display());
+    }
+    Ok(files)
+}
+
+/// One file being decoded
+struct Playback {
+    path: PathBuf,
+    replay_gain: ReplayGain,
+    /// Where in the file decoding started
+    offset: Duration,
+    /// Frames taken out of the buffer
+    played: usize,
+    consumer: HeapConsumer<f32>,
+    channel_count: usize,
+    /// Set by the decoder thread once the whole file is in the buffer
+    finished: Arc<AtomicBool>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Playback {
+    fn start(
+        path: &Path,
+        offset: Duration,
+        channel_count: usize,
+        sample_rate: usize,
+    ) -> anyhow::Result<Self> {
+        let file =
+            File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+        let stream = MediaSourceStream::new(Box::new(file), Default::default());
+        let mut hint = Hint::new();
+        if let Some(extension) = path.extension().and_then(|extension| extension.to_str()) {
+            hint.with_extension(extension);
+        }
+        let mut probed = symphonia::default::get_probe()
+            .format(
+                &hint,
+                stream,
+                &FormatOptions::default(),
+                &MetadataOptions::default(),
+            )
+            .with_context(|| format!("Unsupported file {}", path.display()))?;
+        let mut reader = probed.format;
+
+        // Tags in front of the container (ID3 on MP3 files) and in the container itself
+        let mut tags: Vec<Tag> = Vec::new();
+        if let Some(metadata) = probed.metadata.get() {
+            if let Some(revision) = metadata.current() {
+                tags.extend_from_slice(revision.tags());
+            }
+        }
+        if let Some(revision) = reader.metadata().current() {
+            tags.extend_from_slice(revision.tags());
+        }
+        let replay_gain = ReplayGain::from_tags(tags.iter());
+
+        let track = reader
+            .default_track()
+            .ok_or_else(|| anyhow!("No audio track in {}", path.display()))?;
+        let track_id = track.id;
+        let file_rate = track
+            .codec_params
+            .sample_rate
+            .ok_or_else(|| anyhow!("Unknown sample rate of {}", path.display()))?;
+        let decoder = symphonia::default::get_codecs()
+            .make(&track.codec_params, &DecoderOptions::default())
+            .with_context(|| format!("Unsupported codec in {}", path.display()))?;
+        if !offset.is_zero() {
+            let time = Time::new(offset.as_secs(), f64::from(offset.subsec_nanos()) / 1e9);
+            reader
+                .seek(
+                    SeekMode::Accurate,
+                    SeekTo::Time {
+                        time,
+                        track_id: Some(track_id),
+                    },
+                )
+                .with_context(|| format!("Failed to seek in {}", path.display()))?;
+        }
+
+        let (producer, consumer) =
+            HeapRb::new(BUFFER_SECONDS * sample_rate * channel_count).split();
+        let finished = Arc::new(AtomicBool::new(false));
+        let stop = Arc::new(AtomicBool::new(false));
+        let mut converter = Converter::new(channel_count, file_rate as usize, sample_rate);
+        let thread_path = path.to_path_buf();
+        let thread_finished = finished.clone();
+        let thread_stop = stop.clone();
+        let thread = std::thread::spawn(move || {
+            if let Err(error) = decode_loop(
+                &mut *reader,
+                decoder,
+                track_id,
+                &mut converter,
+                producer,
+                &thread_stop,
+            ) {
+                eprintln!("<3>Failed to decode {}: {error:#}", thread_path.display());
+            }
+            thread_finished.store(true, Ordering::Release);
+        });
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            replay_gain,
+            offset,
+            played: 0,
+            consumer,
+            channel_count,
+            finished,
+            stop,
+            thread: Some(thread),
+        })
+    }
+
+    /// Decoded frames waiting to be played
+    fn frames(&self) -> usize {
+        self.consumer.len() / self.channel_count
+    }
+
+    fn is_finished(&self) -> bool {
+        self.finished.load(Ordering::Acquire)
+    }
+
+    /// Whether all of the file was played
+    fn is_over(&self) -> bool {
+        self.is_finished() && self.consumer.is_empty()
+    }
+}
+
+impl Drop for Playback {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn decode_loop(
+    reader: &mut dyn FormatReader,
+    mut decoder: Box<dyn Decoder>,
+    track_id: u32,
+    converter: &mut Converter,
+    mut producer: HeapProducer<f32>,
+    stop: &AtomicBool,
+) -> anyhow::Result<()> {
+    let mut decoded_samples: Option<SampleBuffer<f32>> = None;
+    let mut converted = Vec::new();
+    while !stop.load(Ordering::Relaxed) {
+        let packet = match reader.next_packet() {
+            Ok(packet) => packet,
+            Err(DecodeError::IoError(error))
+                if error.kind() == std::io::ErrorKind::UnexpectedEof =>
+            {
+                break;
+            }
+            Err(error) => return Err(error.into()),
+        };
+        if packet.track_id() != track_id {
+            continue;
+        }
+        let decoded = match decoder.decode(&packet) {
+            Ok(decoded) => decoded,
+            // A corrupt packet costs a few milliseconds of audio, not the whole file
+            Err(DecodeError::DecodeError(_)) => continue,
+            Err(error) => return Err(error.into()),
+        };
+        let spec = *decoded.spec();
+        let samples = match &mut decoded_samples {
+            Some(samples) if samples.capacity() >= decoded.capacity() * spec.channels.count() => {
+                samples
+            }
+            samples => samples.insert(SampleBuffer::new(decoded.capacity() as u64, spec)),
+        };
+        samples.copy_interleaved_ref(decoded);
+
+        converted.clear();
+        converter.convert(samples.samples(), spec.channels.count(), &mut converted);
+        push_all(&mut producer, &converted, stop);
+    }
+    Ok(())
+}
+
+/// Pushes all of `samples`, waiting while the buffer is full
+fn push_all(producer: &mut HeapProducer<f32>, mut samples: &[f32], stop: &AtomicBool) {
+    while !samples.is_empty() && !stop.load(Ordering::Relaxed) {
+        let pushed = producer.push_slice(samples);
+        samples = &samples[pushed..];
+        if !samples.is_empty() {
+            std::thread::sleep(DECODE_INTERVAL);
+        }
+    }
+}
+
+/// Brings decoded audio to the engine's channel count and sample rate
+struct Converter {
+    channel_count: usize,
+    /// Linear interpolation when the file has another sample rate than the engine
+    resampler: Option<Resampler>,
+    /// Engine frames per file frame
+    ratio: f64,
+    mapped: Vec<f32>,
+}
+
+impl Converter {
+    fn new(channel_count: usize, file_rate: usize, sample_rate: usize) -> Self {
+        let resampler = (file_rate != sample_rate).then(|| {
+            let mut resampler = Resampler::default();
+            resampler.set_channels(channel_count);
+            resampler.set_rate(file_rate as f64 / sample_rate as f64);
+            resampler
+        });
+        Self {
+            channel_count,
+            resampler,
+            ratio: sample_rate as f64 / file_rate as f64,
+            mapped: Vec::new(),
+        }
+    }
+
+    /// Appends the interleaved `samples` of a file with `file_channels` channels to `output`.
+    /// Mono files play on all channels, files with more channels than the engine lose the rest.
+    fn convert(&mut self, samples: &[f32], file_channels: usize, output: &mut Vec<f32>) {
+        let file_channels = file_channels.max(1);
+        self.mapped.clear();
+        for frame in samples.chunks_exact(file_channels) {
+            self.mapped.extend(
+                (0..self.channel_count).map(|channel| frame[channel.min(file_channels - 1)]),
+            );
+        }
+        let frame_count = self.mapped.len() / self.channel_count;
+        match self.resampler.as_mut() {
+            None => output.extend_from_slice(&self.mapped),
+            Some(resampler) => {
+                resampler.put_samples(&self.mapped, frame_count);
+                // Room for all of it, the resampler keeps what it can't interpolate yet
+                let max_frames = (frame_count as f64 * self.ratio).ceil() as usize + 1;
+                let start = output.len();
+                output.resize(start + max_frames * self.channel_count, 0.0);
+                let frames = resampler.receive_samples(&mut output[start..], max_frames);
+                output.truncate(start + frames * self.channel_count);
+            }
+        }
+    }
+}
@@ -0,0 +1,118 @@
+use std::collections::VecDeque;
+
+use crate::{
+    buffer::Buffer,
+    fingerprint::{Fingerprint, BLOCK_SIZE},
+    segments::{chunks, completed_segments, item_length},
+    timeline::{DropReason, Event, Timeline},
+};
+
+/// Number of fingerprints remembered to recognize repeated segments
+const REMEMBERED_SEGMENTS: usize = 32;
+
+/// Blocks two fingerprints may be shifted against each other when comparing them
+const MAX_OFFSET: usize = 8;
+
+/// Opt-in dropping of low-information audio when an input's backlog gets out of hand.
+///
+/// Buffered audio is cut into segments at silence (or after `max_segment` samples), every
+/// completed segment is scored and monotonous or repeated segments are removed from the queue.
+pub struct FillerDropping {
+    /// Backlog in samples above which filler is dropped
+    pub backlog_threshold: usize,
+    /// Segments are closed after this many samples even without silence
+    pub max_segment: usize,
+    /// Monotonous segments must be at least this many samples long to be dropped
+    pub min_monotonous: usize,
+    /// Segments whose energy varies less than this many dB are considered monotonous
+    pub monotony_db: f32,
+    /// Segments must be at least this many samples long to be recognized as repeated
+    pub min_repeated: usize,
+    /// Similarity (-1.0 to 1.0) above which a segment counts as a repetition
+    pub repeat_similarity: f32,
+    /// Number of items at the back of the buffer that have not been scored yet
+    unchecked: usize,
+    remembered: VecDeque<Fingerprint>,
+}
+
+impl Default for FillerDropping {
+    fn default() -> Self {
+        Self {
+            backlog_threshold: 48000 * 60 * 5,
+            max_segment: 48000 * 10,
+            min_monotonous: 48000 * 5,
+            monotony_db: 1.5,
+            min_repeated: 48000 * 2,
+            repeat_similarity: 0.9,
+            unchecked: 0,
+            remembered: VecDeque::new(),
+        }
+    }
+}
+
+impl FillerDropping {
+    /// Has to be called for every item added to the back of the buffer
+    pub fn item_added(&mut self) {
+        self.unchecked += 1;
+    }
+
+    /// Memory the remembered fingerprints take
+    pub fn memory_bytes(&self) -> usize {
+        self.remembered.iter().map(Fingerprint::memory_bytes).sum()
+    }
+
+    /// Scores all completed segments added since the last call and drops filler from `buffer`
+    pub fn drop_filler(
+        &mut self,
+        input_name: &str,
+        buffer: &mut Buffer,
+        sample_rate: usize,
+        timeline: &mut Timeline,
+    ) {
+        self.unchecked = self.unchecked.min(buffer.len());
+        let backlog = buffer.sample_frames();
+        if backlog < self.backlog_threshold {
+            return;
+        }
+
+        // The front item is up next, leave it alone
+        let start = (buffer.len() - self.unchecked).max(1);
+        let (segments, open_start) = completed_segments(buffer, start, self.max_segment);
+        self.unchecked = buffer.len() - open_start;
+
+        let mut dropped = Vec::new();
+        for segment in segments {
+            let fingerprint = Fingerprint::from_chunks(chunks(buffer, segment.clone()));
+            let length = fingerprint.len() * BLOCK_SIZE;
+
+            let monotonous =
+                length >= self.min_monotonous && fingerprint.variation() < self.monotony_db;
+            let repeated = length >= self.min_repeated
+                && self.remembered.iter().any(|remembered| {
+                    remembered.similarity(&fingerprint, MAX_OFFSET) >= self.repeat_similarity
+                });
+
+            if self.remembered.len() == REMEMBERED_SEGMENTS {
+                self.remembered.pop_front();
+            }
+            self.remembered.push_back(fingerprint);
+
+            let reason = match (monotonous, repeated) {
+                (true, _) => DropReason::Monotonous,
+                (false, true) => DropReason::Repeated,
+                (false, false) => continue,
+            };
+            dropped.push((segment, reason));
+        }
+
+        // Remove back to front so earlier ranges stay valid
+        for (segment, reason) in dropped.into_iter().rev() {
+            let samples: usize = buffer.drain(segment).map(|item| item_length(&item)).sum();
+            timeline.push(Event::Dropped {
+                input: input_name.to_string(),
+                seconds: samples as f32 / sample_rate as f32,
+                reason,
+            });
+        }
+    }
+}
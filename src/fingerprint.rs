@@ -0,0 +1,144 @@
+use std::{mem::size_of, ops::Range};
+
+/// Number of samples summarized by one fingerprint block (~21 ms at 48 kHz)
+pub const BLOCK_SIZE: usize = 1024;
+
+/// Energy floor in dB, keeps digital silence from dominating comparisons
+const ENERGY_FLOOR_DB: f32 = -90.0;
+
+/// Coarse description of a piece of audio: its energy envelope in dB, one value per block.
+///
+/// Cheap enough to compute outside the process callback for every buffered segment and robust
+/// enough to recognize the same clip (jingles, notification sounds) played several times.
+#[derive(Clone, Debug, Default)]
+pub struct Fingerprint {
+    envelope: Vec<f32>,
+}
+
+impl Fingerprint {
+    /// Computes the fingerprint of consecutive chunks of per-channel samples
+    pub fn from_chunks<'a>(chunks: impl IntoIterator<Item = &'a Vec<Vec<f32>>>) -> Self {
+        let mut envelope = Vec::new();
+        let mut energy = 0.0;
+        let mut count = 0;
+
+        for chunk in chunks {
+            let channel_count = chunk.len().max(1);
+            let length = chunk.first().map_or(0, |channel| channel.len());
+            for index in 0..length {
+                energy += chunk
+                    .iter()
+                    .map(|channel| channel[index] * channel[index])
+                    .sum::<f32>()
+                    / channel_count as f32;
+                count += 1;
+                if count == BLOCK_SIZE {
+                    envelope.push(to_db(energy / count as f32));
+                    energy = 0.0;
+                    count = 0;
+                }
+            }
+        }
+        // Only keep the trailing partial block if it is long enough to be meaningful
+        if count > BLOCK_SIZE / 2 {
+            envelope.push(to_db(energy / count as f32));
+        }
+
+        Self { envelope }
+    }
+
+    /// Number of blocks covered by the fingerprint
+    pub fn len(&self) -> usize {
+        self.envelope.len()
+    }
+
+    /// Memory the fingerprint takes
+    pub fn memory_bytes(&self) -> usize {
+        size_of::<Self>() + self.envelope.capacity() * size_of::<f32>()
+    }
+
+    /// Fingerprint of a part of the audio, given in blocks
+    pub fn slice(&self, blocks: Range<usize>) -> Fingerprint {
+        Self {
+            envelope: self.envelope[blocks].to_vec(),
+        }
+    }
+
+    /// Mean energy in dB
+    pub fn mean_energy(&self) -> f32 {
+        if self.envelope.is_empty() {
+            return ENERGY_FLOOR_DB;
+        }
+        self.envelope.iter().sum::<f32>() / self.envelope.len() as f32
+    }
+
+    /// Standard deviation of the energy envelope in dB.
+    ///
+    /// Speech and music move around a lot, a steady hum or drone barely does.
+    pub fn variation(&self) -> f32 {
+        if self.envelope.is_empty() {
+            return 0.0;
+        }
+        let mean = self.mean_energy();
+        let variance = self
+            .envelope
+            .iter()
+            .map(|energy| (energy - mean).powi(2))
+            .sum::<f32>()
+            / self.envelope.len() as f32;
+        variance.sqrt()
+    }
+
+    /// Similarity of two fingerprints between -1.0 and 1.0.
+    ///
+    /// Computes the normalized correlation of both envelopes, allowing them to be shifted
+    /// against each other by up to `max_offset` blocks. Fingerprints of very different length
+    /// are never similar.
+    pub fn similarity(&self, other: &Fingerprint, max_offset: usize) -> f32 {
+        let (shorter, longer) = if self.len() <= other.len() {
+            (self, other)
+        } else {
+            (other, self)
+        };
+        if shorter.len() < 2 || (longer.len() - shorter.len()) * 10 > longer.len() {
+            return -1.0;
+        }
+
+        let max_offset = max_offset.min(longer.len() - 2);
+        (0..=max_offset)
+            .map(|offset| correlation(&shorter.envelope, &longer.envelope[offset..]))
+            .fold(-1.0, f32::max)
+    }
+}
+
+fn to_db(energy: f32) -> f32 {
+    (10.0 * energy.log10()).max(ENERGY_FLOOR_DB)
+}
+
+fn correlation(a: &[f32], b: &[f32]) -> f32 {
+    let length = a.len().min(b.len());
+    if length < 2 {
+        return -1.0;
+    }
+    let (a, b) = (&a[..length], &b[..length]);
+    let mean_a = a.iter().sum::<f32>() / length as f32;
+    let mean_b = b.iter().sum::<f32>() / length as f32;
+
+    let mut covariance = 0.0;
+    let mut variance_a = 0.0;
+    let mut variance_b = 0.0;
+    for (a, b) in a.iter().zip(b) {
+        covariance += (a - mean_a) * (b - mean_b);
+        variance_a += (a - mean_a).powi(2);
+        variance_b += (b - mean_b).powi(2);
+    }
+    if variance_a <= f32::EPSILON || variance_b <= f32::EPSILON {
+        // Flat envelopes: equal if they sit at the same level
+        return if (mean_a - mean_b).abs() < 1.0 {
+            1.0
+        } else {
+            0.0
+        };
+    }
+    covariance / (variance_a * variance_b).sqrt()
+}
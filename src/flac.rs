@@ -0,0 +1,395 @@
+//! A small FLAC encoder for recordings, so lossless files need no library: fixed size blocks,
+//! independent channels, the fixed predictors of the format and Rice coded residuals. The files
+//! come out a little larger than libFLAC's, any FLAC decoder reads them.
+
+use std::{
+    fs::File,
+    io::{BufWriter, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use anyhow::{bail, Context};
+
+use crate::{encoder::Encoder, sample_format::to_int};
+
+pub const DEFAULT_LEVEL: u8 = 5;
+pub const MAX_LEVEL: u8 = 8;
+
+/// Frames per block, the common size for 44.1 and 48 kHz
+const BLOCK_SIZE: usize = 4096;
+const BITS_PER_SAMPLE: u32 = 24;
+/// Largest parameter of the 4 bit Rice coding, 15 means the partition is stored unencoded
+const MAX_RICE_PARAMETER: u32 = 14;
+const STREAMINFO_BYTES: u32 = 34;
+/// Where the STREAMINFO block starts, after the `fLaC` marker and the block header
+const STREAMINFO_OFFSET: u64 = 8;
+
+pub struct FlacEncoder {
+    file: BufWriter<File>,
+    channel_count: usize,
+    sample_rate: usize,
+    /// Highest fixed predictor order tried
+    max_order: usize,
+    /// Highest number of Rice partitions tried, as a power of two
+    max_partition_order: u32,
+    /// Samples of the block being filled, per channel
+    block: Vec<Vec<i32>>,
+    frame_number: u32,
+    total_frames: u64,
+    bytes: u64,
+}
+
+impl FlacEncoder {
+    /// Higher levels try more predictors and finer Rice partitions, for smaller files at more
+    /// CPU time
+    pub fn create(
+        path: &Path,
+        channel_count: usize,
+        sample_rate: usize,
+        level: u8,
+    ) -> anyhow::Result<Self> {
+        if !(1..=8).contains(&channel_count) {
+            bail!("FLAC files have one to eight channels, not {channel_count}");
+        }
+        let file =
+            File::create(path).with_context(|| format!("Failed to create {}", path.display()))?;
+        let mut encoder = Self {
+            file: BufWriter::new(file),
+            channel_count,
+            sample_rate,
+            max_order: if level <= 2 { 2 } else { 4 },
+            max_partition_order: level.min(MAX_LEVEL).into(),
+            block: vec![Vec::with_capacity(BLOCK_SIZE); channel_count],
+            frame_number: 0,
+            total_frames: 0,
+            bytes: 0,
+        };
+        encoder.file.write_all(b"fLaC")?;
+        // The only metadata block, so it's marked as the last one
+        let header = (1 << 31) | STREAMINFO_BYTES;
+        encoder.file.write_all(&header.to_be_bytes())?;
+        let streaminfo = encoder.streaminfo();
+        encoder.file.write_all(&streaminfo)?;
+        encoder.bytes = STREAMINFO_OFFSET + u64::from(STREAMINFO_BYTES);
+        Ok(encoder)
+    }
+
+    /// The STREAMINFO block, with the length of what was written so far. The MD5 signature is
+    /// left out, decoders skip the check then.
+    fn streaminfo(&self) -> Vec<u8> {
+        let mut bits = BitWriter::default();
+        bits.write(BLOCK_SIZE as u64, 16);
+        bits.write(BLOCK_SIZE as u64, 16);
+        // Smallest and largest frame size, unknown
+        bits.write(0, 24);
+        bits.write(0, 24);
+        bits.write(self.sample_rate as u64, 20);
+        bits.write(self.channel_count as u64 - 1, 3);
+        bits.write(u64::from(BITS_PER_SAMPLE) - 1, 5);
+        bits.write(self.total_frames, 36);
+        for _ in 0..4 {
+            bits.write(0, 32);
+        }
+        bits.bytes
+    }
+
+    fn write_frame(&mut self) -> anyhow::Result<()> {
+        let block_size = self.block[0].len();
+        let mut bits = BitWriter::default();
+        // Sync code, then the flag for fixed block sizes
+        bits.write(0b11_1111_1111_1110, 14);
+        bits.write(0, 2);
+        // The block size follows the header as 16 bits, the sample rate is STREAMINFO's
+        bits.write(0b0111, 4);
+        bits.write(0b0000, 4);
+        bits.write(self.channel_count as u64 - 1, 4);
+        // 24 bits per sample
+        bits.write(0b110, 3);
+        bits.write(0, 1);
+        bits.bytes
+            .extend_from_slice(&coded_number(self.frame_number));
+        bits.write(block_size as u64 - 1, 16);
+        let crc = crc8(&bits.bytes);
+        bits.write(crc.into(), 8);
+
+        for channel in &self.block {
+            self.write_subframe(&mut bits, channel);
+        }
+        bits.align();
+        let crc = crc16(&bits.bytes);
+        bits.write(crc.into(), 16);
+
+        self.file.write_all(&bits.bytes)?;
+        self.bytes += bits.bytes.len() as u64;
+        self.total_frames += block_size as u64;
+        self.frame_number += 1;
+        self.block.iter_mut().for_each(Vec::clear);
+        Ok(())
+    }
+
+    /// Writes a channel of the block as the smallest of a constant, the best fixed predictor and
+    /// verbatim samples
+    fn write_subframe(&self, bits: &mut BitWriter, samples: &[i32]) {
+        if samples.iter().all(|sample| *sample == samples[0]) {
+            bits.write(0b0000_0000, 8);
+            bits.write_signed(samples[0].into(), BITS_PER_SAMPLE);
+            return;
+        }
+
+        let verbatim_bits = samples.len() as u64 * u64::from(BITS_PER_SAMPLE);
+        let best = (0..=self.max_order.min(samples.len() - 1))
+            .filter_map(|order| {
+                let residuals = fixed_residuals(samples, order);
+                let rice = RicePlan::best(&residuals, order, self.max_partition_order)?;
+                let size = order as u64 * u64::from(BITS_PER_SAMPLE) + rice.bits;
+                Some((size, order, residuals, rice))
+            })
+            .min_by_key(|(size, ..)| *size);
+
+        match best {
+            Some((size, order, residuals, rice)) if size < verbatim_bits => {
+                bits.write(0b0001_0000 | (order as u64) << 1, 8);
+                for sample in &samples[..order] {
+                    bits.write_signed((*sample).into(), BITS_PER_SAMPLE);
+                }
+                rice.write(bits, &residuals, order);
+            }
+            _ => {
+                bits.write(0b0000_0010, 8);
+                for sample in samples {
+                    bits.write_signed((*sample).into(), BITS_PER_SAMPLE);
+                }
+            }
+        }
+    }
+}
+
+impl Encoder for FlacEncoder {
+    fn write(&mut self, samples: &[f32]) -> anyhow::Result<()> {
+        for frame in samples.chunks(self.channel_count) {
+            for (channel, sample) in self.block.iter_mut().zip(frame) {
+                channel.push(to_int(*sample, BITS_PER_SAMPLE));
+            }
+            if self.block[0].len() == BLOCK_SIZE {
+                self.write_frame()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> anyhow::Result<()> {
+        Ok(self.file.flush()?)
+    }
+
+    fn bytes(&self) -> u64 {
+        self.bytes
+    }
+
+    fn finish(mut self: Box<Self>) -> anyhow::Result<()> {
+        if !self.block[0].is_empty() {
+            self.write_frame()?;
+        }
+        // Now that the length is known
+        let streaminfo = self.streaminfo();
+        self.file.seek(SeekFrom::Start(STREAMINFO_OFFSET))?;
+        self.file.write_all(&streaminfo)?;
+        Ok(self.file.flush()?)
+    }
+}
+
+/// Residuals of the fixed predictor of `order`, after its warm-up samples
+fn fixed_residuals(samples: &[i32], order: usize) -> Vec<i64> {
+    let x = |index: usize| i64::from(samples[index]);
+    (order..samples.len())
+        .map(|i| match order {
+            0 => x(i),
+            1 => x(i) - x(i - 1),
+            2 => x(i) - 2 * x(i - 1) + x(i - 2),
+            3 => x(i) - 3 * x(i - 1) + 3 * x(i - 2) - x(i - 3),
+            _ => x(i) - 4 * x(i - 1) + 6 * x(i - 2) - 4 * x(i - 3) + x(i - 4),
+        })
+        .collect()
+}
+
+/// How the residuals are split into partitions and the Rice parameter of each
+struct RicePlan {
+    partition_order: u32,
+    parameters: Vec<u32>,
+    /// Size of the coded residuals, headers included
+    bits: u64,
+}
+
+impl RicePlan {
+    /// The smallest plan up to `max_partition_order`. Residuals too large for Rice coding make
+    /// for a plan larger than the verbatim samples.
+    fn best(residuals: &[i64], order: usize, max_partition_order: u32) -> Option<Self> {
+        let block_size = residuals.len() + order;
+        let folded: Vec<u64> = residuals.iter().map(|residual| fold(*residual)).collect();
+        (0..=max_partition_order)
+            // Partitions split the block evenly, the first one's share of it covers the warm-up
+            .take_while(|&partition_order| {
+                block_size.is_multiple_of(1 << partition_order)
+                    && block_size >> partition_order > order
+            })
+            .map(|partition_order| {
+                let mut parameters = Vec::new();
+                // Coding method and partition order
+                let mut bits = 6;
+                for partition in partitions(&folded, block_size, order, partition_order) {
+                    let sum: u64 = partition.iter().sum();
+                    let parameter = rice_parameter(sum, partition.len());
+                    let size = partition.len() as u64 * u64::from(parameter + 1)
+                        + partition
+                            .iter()
+                            .map(|value| value >> parameter)
+                            .sum::<u64>();
+                    parameters.push(parameter);
+                    bits += 4 + size;
+                }
+                Self {
+                    partition_order,
+                    parameters,
+                    bits,
+                }
+            })
+            .min_by_key(|plan| plan.bits)
+    }
+
+    fn write(&self, bits: &mut BitWriter, residuals: &[i64], order: usize) {
+        let block_size = residuals.len() + order;
+        let folded: Vec<u64> = residuals.iter().map(|residual| fold(*residual)).collect();
+        // Rice coding with 4 bit parameters
+        bits.write(0b00, 2);
+        bits.write(self.partition_order.into(), 4);
+        let partitions = partitions(&folded, block_size, order, self.partition_order);
+        for (partition, &parameter) in partitions.zip(&self.parameters) {
+            bits.write(parameter.into(), 4);
+            for value in partition {
+                bits.write_unary(value >> parameter);
+                bits.write(value & ((1 << parameter) - 1), parameter);
+            }
+        }
+    }
+}
+
+/// Splits the residuals into `2^partition_order` partitions of the block, the first one shorter
+/// by the warm-up samples
+fn partitions(
+    folded: &[u64],
+    block_size: usize,
+    order: usize,
+    partition_order: u32,
+) -> impl Iterator<Item = &[u64]> {
+    let length = block_size >> partition_order;
+    let first = length - order;
+    std::iter::once(&folded[..first]).chain(folded[first..].chunks(length))
+}
+
+/// Maps signed residuals to unsigned ones, 0, -1, 1, -2, ... to 0, 1, 2, 3, ...
+fn fold(residual: i64) -> u64 {
+    ((residual << 1) ^ (residual >> 63)) as u64
+}
+
+/// Rice parameter for a partition with values summing to `sum`, estimated from their mean
+fn rice_parameter(sum: u64, count: usize) -> u32 {
+    let mut parameter = 0;
+    while parameter < MAX_RICE_PARAMETER && (count as u64) << (parameter + 1) < sum {
+        parameter += 1;
+    }
+    parameter
+}
+
+/// Frame number in the variable length code FLAC borrows from UTF-8
+fn coded_number(number: u32) -> Vec<u8> {
+    if number < 0x80 {
+        return vec![number as u8];
+    }
+    let length = match number {
+        0x80..0x800 => 2,
+        0x800..0x1_0000 => 3,
+        0x1_0000..0x20_0000 => 4,
+        0x20_0000..0x400_0000 => 5,
+        _ => 6,
+    };
+    let mut bytes = vec![0; length];
+    let mut rest = number;
+    for byte in bytes[1..].iter_mut().rev() {
+        *byte = 0x80 | (rest & 0x3f) as u8;
+        rest >>= 6;
+    }
+    // As many leading ones as there are bytes
+    bytes[0] = (0xff00u16 >> length) as u8 | rest as u8;
+    bytes
+}
+
+/// CRC-8 of frame headers: polynomial 0x07, starting from zero
+fn crc8(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |crc, &byte| {
+        let mut crc = crc ^ byte;
+        for _ in 0..8 {
+            crc = if crc & 0x80 != 0 {
+                (crc << 1) ^ 0x07
+            } else {
+                crc << 1
+            };
+        }
+        crc
+    })
+}
+
+/// CRC-16 of whole frames: polynomial 0x8005, starting from zero
+fn crc16(data: &[u8]) -> u16 {
+    data.iter().fold(0u16, |crc, &byte| {
+        let mut crc = crc ^ (u16::from(byte) << 8);
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x8005
+            } else {
+                crc << 1
+            };
+        }
+        crc
+    })
+}
+
+/// Packs values most significant bit first
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    /// Bits not making up a whole byte yet, in the low end
+    pending: u64,
+    pending_bits: u32,
+}
+
+impl BitWriter {
+    /// Writes the low `bits` bits of `value`, at most 32
+    fn write(&mut self, value: u64, bits: u32) {
+        self.pending = (self.pending << bits) | (value & ((1 << bits) - 1));
+        self.pending_bits += bits;
+        while self.pending_bits >= 8 {
+            self.pending_bits -= 8;
+            self.bytes.push((self.pending >> self.pending_bits) as u8);
+        }
+        self.pending &= (1 << self.pending_bits) - 1;
+    }
+
+    fn write_signed(&mut self, value: i64, bits: u32) {
+        self.write(value as u64, bits);
+    }
+
+    /// `value` zeros and a one
+    fn write_unary(&mut self, mut value: u64) {
+        while value >= 32 {
+            self.write(0, 32);
+            value -= 32;
+        }
+        self.write(1, value as u32 + 1);
+    }
+
+    /// Pads with zeros to a whole byte
+    fn align(&mut self) {
+        if self.pending_bits > 0 {
+            self.write(0, 8 - self.pending_bits);
+        }
+    }
+}
@@ -0,0 +1,175 @@
+//! Follows the focused window so the input of the application in front gets a boost: whatever is
+//! looked at plays first.
+//!
+//! The focused window is polled from the compositor: `hyprctl` on Hyprland, `swaymsg` on Sway and
+//! `xprop` on X11 (including XWayland-only sessions, through EWMH). Other Wayland compositors
+//! don't tell other clients about their windows without the foreign-toplevel protocol, which
+//! isn't spoken here.
+
+use std::{env, process::Command, sync::mpsc, thread, time::Duration};
+
+use anyhow::{bail, Context};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Urgency multiplier of the input of the focused window
+pub const FOCUS_BOOST_FACTOR: f32 = 4.0;
+
+/// How long the boost lasts after the window lost the focus, so switching to another window for a
+/// moment doesn't take it away
+pub const FOCUS_LINGER: Duration = Duration::from_secs(30);
+
+/// Where the focused window is asked for
+#[derive(Clone, Copy, Debug)]
+enum Backend {
+    Hyprland,
+    Sway,
+    X11,
+}
+
+impl Backend {
+    /// The backend of the running session, if there is one
+    fn detect() -> Option<Self> {
+        if env::var_os("HYPRLAND_INSTANCE_SIGNATURE").is_some() {
+            Some(Backend::Hyprland)
+        } else if env::var_os("SWAYSOCK").is_some() {
+            Some(Backend::Sway)
+        } else if env::var_os("DISPLAY").is_some() {
+            Some(Backend::X11)
+        } else {
+            None
+        }
+    }
+
+    /// Application of the focused window, lowercase: its class on X11 and its app id on Wayland.
+    /// `None` when no window has the focus.
+    fn focused(self) -> anyhow::Result<Option<String>> {
+        let application = match self {
+            Backend::Hyprland => {
+                let window: serde_json::Value =
+                    serde_json::from_str(&run("hyprctl", &["activewindow", "-j"])?)
+                        .context("Failed to parse the window listed by hyprctl")?;
+                window["class"].as_str().map(str::to_string)
+            }
+            Backend::Sway => {
+                let tree: serde_json::Value =
+                    serde_json::from_str(&run("swaymsg", &["-t", "get_tree"])?)
+                        .context("Failed to parse the tree listed by swaymsg")?;
+                focused_node(&tree).and_then(|node| {
+                    node["app_id"]
+                        .as_str()
+                        .or(node["window_properties"]["class"].as_str())
+                        .map(str::to_string)
+                })
+            }
+            Backend::X11 => {
+                // `_NET_ACTIVE_WINDOW(WINDOW): window id # 0x3a00007`
+                let active = run("xprop", &["-root", "_NET_ACTIVE_WINDOW"])?;
+                match active.rsplit(' ').next().map(str::trim) {
+                    Some(id) if id.starts_with("0x") && id != "0x0" => {
+                        // `WM_CLASS(STRING) = "navigator", "firefox"`, instance then class
+                        let class = run("xprop", &["-id", id, "WM_CLASS"])?;
+                        class
+                            .split('"')
+                            .nth(3)
+                            .or(class.split('"').nth(1))
+                            .map(str::to_string)
+                    }
+                    _ => None,
+                }
+            }
+        };
+        Ok(application
+            .filter(|application| !application.is_empty())
+            .map(|application| application.to_lowercase()))
+    }
+}
+
+/// The focused node of a Sway tree
+fn focused_node(node: &serde_json::Value) -> Option<&serde_json::Value> {
+    if node["focused"] == true {
+        return Some(node);
+    }
+    ["nodes", "floating_nodes"]
+        .iter()
+        .filter_map(|key| node[key].as_array())
+        .flatten()
+        .find_map(focused_node)
+}
+
+fn run(program: &str, args: &[&str]) -> anyhow::Result<String> {
+    let output = Command::new(program)
+        .args(args)
+        .output()
+        .with_context(|| format!("Failed to run {program}"))?;
+    if !output.status.success() {
+        bail!(
+            "{program} {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Reports changes of the focused window's application, starting with the one focused when
+/// created
+pub struct FocusedWindow {
+    changes: mpsc::Receiver<Option<String>>,
+}
+
+impl FocusedWindow {
+    /// Starts polling, `None` without a session whose windows can be asked for
+    pub fn watch() -> Option<Self> {
+        let Some(backend) = Backend::detect() else {
+            eprintln!("<4>No X11, Hyprland or Sway session, the focused window isn't followed");
+            return None;
+        };
+        let (sender, changes) = mpsc::channel();
+        thread::spawn(move || {
+            let mut current = None;
+            let mut failing = false;
+            loop {
+                match backend.focused() {
+                    Ok(application) => {
+                        failing = false;
+                        if current.as_ref() != Some(&application) {
+                            // Gone with the engine that watched it
+                            if sender.send(application.clone()).is_err() {
+                                return;
+                            }
+                            current = Some(application);
+                        }
+                    }
+                    // Reported once per time it fails, it's polled often
+                    Err(error) if !failing => {
+                        failing = true;
+                        eprintln!("<4>Failed to query the focused window: {error:#}");
+                    }
+                    Err(_) => {}
+                }
+                thread::sleep(POLL_INTERVAL);
+            }
+        });
+        Some(FocusedWindow { changes })
+    }
+
+    /// The application of the latest focused window if it changed since the last call,
+    /// `Some(None)` when no window has the focus anymore
+    pub fn changed(&self) -> Option<Option<String>> {
+        self.changes.try_iter().last()
+    }
+}
+
+/// Whether an input belongs to the focused application: its `window` pattern is part of the
+/// application's name, or without a pattern, a JACK client connected to its ports is named like
+/// the application
+pub fn matches(application: &str, pattern: Option<&str>, connected_clients: &[String]) -> bool {
+    match pattern {
+        Some(pattern) => application.contains(&pattern.to_lowercase()),
+        None => connected_clients.iter().any(|client| {
+            let client = client.to_lowercase();
+            !client.is_empty() && (client.contains(application) || application.contains(&client))
+        }),
+    }
+}
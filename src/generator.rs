@@ -0,0 +1,153 @@
+use std::{f32::consts::PI, str::FromStr};
+
+use anyhow::{anyhow, bail, Context};
+
+#[derive(Clone, Debug)]
+pub enum Waveform {
+    Sine {
+        frequency: f32,
+    },
+    WhiteNoise,
+    PinkNoise,
+    /// Logarithmic sweep, restarting after `seconds`
+    Sweep {
+        start: f32,
+        end: f32,
+        seconds: f32,
+    },
+}
+
+impl FromStr for Waveform {
+    type Err = anyhow::Error;
+
+    /// Parses `sine[:frequency]`, `white`, `pink` and `sweep[:start:end:seconds]`
+    fn from_str(text: &str) -> anyhow::Result<Self> {
+        let mut parts = text.split(':');
+        let kind = parts.next().unwrap_or_default();
+        let mut parameter = |default: f32| -> anyhow::Result<f32> {
+            parts.next().map_or(Ok(default), |part| {
+                part.parse()
+                    .with_context(|| format!("Invalid waveform parameter '{part}'"))
+            })
+        };
+
+        let waveform = match kind {
+            "sine" => Waveform::Sine {
+                frequency: parameter(440.0)?,
+            },
+            "white" => Waveform::WhiteNoise,
+            "pink" => Waveform::PinkNoise,
+            "sweep" => Waveform::Sweep {
+                start: parameter(20.0)?,
+                end: parameter(20000.0)?,
+                seconds: parameter(10.0)?,
+            },
+            _ => bail!("Unknown waveform '{kind}'"),
+        };
+        if let Waveform::Sweep {
+            start,
+            end,
+            seconds,
+        } = waveform
+        {
+            if start <= 0.0 || end <= 0.0 || seconds <= 0.0 {
+                return Err(anyhow!("Sweep frequencies and duration must be positive"));
+            }
+        }
+        Ok(waveform)
+    }
+}
+
+/// Built-in test signal source, replaces the ports of an input
+pub struct Generator {
+    /// Signal to produce, `None` keeps the generator quiet
+    pub waveform: Option<Waveform>,
+    /// Output level in dBFS
+    pub level_db: f32,
+    channel_count: usize,
+    sample_rate: usize,
+    /// Phase of the periodic waveforms in radians
+    phase: f32,
+    /// Samples since the start of the current sweep
+    sweep_position: usize,
+    rng_state: u32,
+    /// State of the pink noise filter
+    pink: [f32; 7],
+}
+
+impl Generator {
+    pub fn new(channel_count: usize, sample_rate: usize) -> Self {
+        Self {
+            waveform: None,
+            level_db: -20.0,
+            channel_count,
+            sample_rate,
+            phase: 0.0,
+            sweep_position: 0,
+            rng_state: 0x9e37_79b9,
+            pink: [0.0; 7],
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.waveform.is_some()
+    }
+
+    /// Produces the next `frame_count` samples, the same signal on every channel
+    pub fn generate(&mut self, frame_count: usize) -> Vec<Vec<f32>> {
+        let gain = 10.0_f32.powf(self.level_db / 20.0);
+        let signal: Vec<f32> = (0..frame_count)
+            .map(|_| self.next_sample() * gain)
+            .collect();
+        vec![signal; self.channel_count]
+    }
+
+    fn next_sample(&mut self) -> f32 {
+        match self.waveform.clone() {
+            None => 0.0,
+            Some(Waveform::Sine { frequency }) => self.oscillate(frequency),
+            Some(Waveform::WhiteNoise) => self.white_noise(),
+            Some(Waveform::PinkNoise) => self.pink_noise(),
+            Some(Waveform::Sweep {
+                start,
+                end,
+                seconds,
+            }) => {
+                let length = (seconds * self.sample_rate as f32) as usize;
+                let progress = self.sweep_position as f32 / length as f32;
+                self.sweep_position = (self.sweep_position + 1) % length.max(1);
+                self.oscillate(start * (end / start).powf(progress))
+            }
+        }
+    }
+
+    fn oscillate(&mut self, frequency: f32) -> f32 {
+        let sample = self.phase.sin();
+        self.phase = (self.phase + 2.0 * PI * frequency / self.sample_rate as f32) % (2.0 * PI);
+        sample
+    }
+
+    /// Uniform noise between -1.0 and 1.0 (xorshift32)
+    fn white_noise(&mut self) -> f32 {
+        self.rng_state ^= self.rng_state << 13;
+        self.rng_state ^= self.rng_state >> 17;
+        self.rng_state ^= self.rng_state << 5;
+        self.rng_state as f32 / u32::MAX as f32 * 2.0 - 1.0
+    }
+
+    /// Paul Kellet's refined pink noise filter applied to white noise
+    fn pink_noise(&mut self) -> f32 {
+        let white = self.white_noise();
+        let b = &mut self.pink;
+        b[0] = 0.99886 * b[0] + white * 0.0555179;
+        b[1] = 0.99332 * b[1] + white * 0.0750759;
+        b[2] = 0.96900 * b[2] + white * 0.153852;
+        b[3] = 0.86650 * b[3] + white * 0.3104856;
+        b[4] = 0.55000 * b[4] + white * 0.5329522;
+        b[5] = -0.7616 * b[5] - white * 0.0168980;
+        let pink = b[0] + b[1] + b[2] + b[3] + b[4] + b[5] + b[6] + white * 0.5362;
+        b[6] = white * 0.115926;
+        // Scale roughly back into -1.0..1.0
+        pink * 0.11
+    }
+}
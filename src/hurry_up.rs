@@ -0,0 +1,103 @@
+//! Hurry-up output, for visualizers and hardware showing how far behind the listener is: a MIDI
+//! clock running while the playing input is behind, its tempo following the speed the input
+//! catches up at, and an audio port carrying a control voltage that follows the backlog.
+//!
+//! The engine thread hands the speed and level over once a period, the process callback writes
+//! the ports from them without locking.
+
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use jack::{AudioOut, Client, MidiOut, Port, ProcessScope, RawMidi};
+
+use crate::config::HurryUpConfig;
+
+const CLOCK: u8 = 0xf8;
+const START: u8 = 0xfa;
+const STOP: u8 = 0xfc;
+/// Clock ticks per quarter note, as MIDI defines them
+const TICKS_PER_BEAT: f64 = 24.0;
+
+/// What the engine thread hands the callback, as the bits of `f32`s
+#[derive(Default)]
+pub struct HurryUpLevels {
+    /// Playback speed of the playing input while it is behind, 0 while the clock is stopped
+    speed: AtomicU32,
+    /// Backlog of the playing input, from 0 to 1 at the full backlog
+    level: AtomicU32,
+}
+
+impl HurryUpLevels {
+    pub fn set(&self, speed: f32, level: f32) {
+        self.speed.store(speed.to_bits(), Ordering::Relaxed);
+        self.level.store(level.to_bits(), Ordering::Relaxed);
+    }
+
+    fn speed(&self) -> f32 {
+        f32::from_bits(self.speed.load(Ordering::Relaxed))
+    }
+
+    fn level(&self) -> f32 {
+        f32::from_bits(self.level.load(Ordering::Relaxed))
+    }
+}
+
+/// The ports, owned by the callback
+pub struct HurryUp {
+    clock: Port<MidiOut>,
+    voltage: Port<AudioOut>,
+    /// Tempo of the clock at natural speed
+    bpm: f64,
+    sample_rate: f64,
+    running: bool,
+    /// Frames into the period the next tick is due at
+    next_tick: f64,
+}
+
+impl HurryUp {
+    /// Registers the `hurry-up.clock` MIDI port and the `hurry-up.cv` audio port
+    pub fn register(client: &Client, config: &HurryUpConfig) -> Self {
+        Self {
+            clock: client
+                .register_port("hurry-up.clock", MidiOut::default())
+                .expect("Failed to register port"),
+            voltage: client
+                .register_port("hurry-up.cv", AudioOut::default())
+                .expect("Failed to register port"),
+            bpm: config.bpm,
+            sample_rate: client.sample_rate() as f64,
+            running: false,
+            next_tick: 0.0,
+        }
+    }
+
+    pub fn process(&mut self, levels: &HurryUpLevels, scope: &ProcessScope) {
+        self.voltage.as_mut_slice(scope).fill(levels.level());
+
+        let speed = levels.speed() as f64;
+        let running = speed > 0.0;
+        let mut writer = self.clock.writer(scope);
+        // Events that don't fit are lost, the next period carries on
+        if running != self.running {
+            self.running = running;
+            self.next_tick = 0.0;
+            let status = if running { START } else { STOP };
+            let _ = writer.write(&RawMidi {
+                time: 0,
+                bytes: &[status],
+            });
+        }
+        if !running {
+            return;
+        }
+        let frames = scope.n_frames() as f64;
+        let interval = self.sample_rate * 60.0 / (self.bpm * TICKS_PER_BEAT * speed);
+        while self.next_tick < frames {
+            let _ = writer.write(&RawMidi {
+                time: self.next_tick as u32,
+                bytes: &[CLOCK],
+            });
+            self.next_tick += interval;
+        }
+        self.next_tick -= frames;
+    }
+}
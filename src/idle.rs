@@ -0,0 +1,82 @@
+//! Tells when the user is away: the login session is idle or locked, as logind knows it.
+//!
+//! The desktop sets the idle hint once the session was idle for its idle delay, which idle
+//! inhibitors (e.g. of a video player in full screen) hold off. Both hints are polled from the
+//! session of the engine over the system bus.
+
+use std::{sync::mpsc, thread, time::Duration};
+
+use anyhow::Context;
+use zbus::{blocking::Connection, zvariant::OwnedValue};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The session the calling process belongs to
+const SESSION_PATH: &str = "/org/freedesktop/login1/session/auto";
+const SESSION_INTERFACE: &str = "org.freedesktop.login1.Session";
+
+/// Reports whether the user is away as it changes, starting with the state when created
+pub struct Idle {
+    changes: mpsc::Receiver<bool>,
+}
+
+impl Idle {
+    pub fn watch() -> Self {
+        let (sender, changes) = mpsc::channel();
+        thread::spawn(move || {
+            let connection =
+                match Connection::system().context("Failed to connect to the system bus") {
+                    Ok(connection) => connection,
+                    Err(error) => {
+                        eprintln!("<4>Not holding while away: {error:#}");
+                        return;
+                    }
+                };
+            let mut current = None;
+            let mut failing = false;
+            loop {
+                match is_away(&connection) {
+                    Ok(away) => {
+                        failing = false;
+                        if current != Some(away) {
+                            // Gone with the engine that watched it
+                            if sender.send(away).is_err() {
+                                return;
+                            }
+                            current = Some(away);
+                        }
+                    }
+                    // Reported once per time it fails, it's polled every second
+                    Err(error) if !failing => {
+                        failing = true;
+                        eprintln!("<4>Failed to query the session's idle state: {error:#}");
+                    }
+                    Err(_) => {}
+                }
+                thread::sleep(POLL_INTERVAL);
+            }
+        });
+        Idle { changes }
+    }
+
+    /// Whether the user is away, if it changed since the last call
+    pub fn changed(&self) -> Option<bool> {
+        self.changes.try_iter().last()
+    }
+}
+
+fn is_away(connection: &Connection) -> anyhow::Result<bool> {
+    Ok(hint(connection, "IdleHint")? || hint(connection, "LockedHint")?)
+}
+
+fn hint(connection: &Connection, property: &str) -> anyhow::Result<bool> {
+    let reply = connection.call_method(
+        Some("org.freedesktop.login1"),
+        SESSION_PATH,
+        Some("org.freedesktop.DBus.Properties"),
+        "Get",
+        &(SESSION_INTERFACE, property),
+    )?;
+    let value: OwnedValue = reply.body().deserialize()?;
+    Ok(bool::try_from(value)?)
+}
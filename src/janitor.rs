@@ -0,0 +1,106 @@
+use std::{
+    env,
+    fs::{self, File},
+    io::{self, BufRead, BufReader, Write},
+    os::unix::process::CommandExt,
+    path::{Path, PathBuf},
+    process::{Child, Command, Stdio},
+};
+
+use anyhow::{anyhow, Context};
+
+use crate::mpris;
+
+/// Marks records of sources resumed through their MPRIS player, followed by the player's name
+pub const MPRIS_PREFIX: &str = "mpris:";
+
+/// `$XDG_RUNTIME_DIR/audiomux/paused-sources`, falling back to the temporary directory
+pub fn default_path() -> PathBuf {
+    env::var_os("XDG_RUNTIME_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(env::temp_dir)
+        .join("audiomux")
+        .join("paused-sources")
+}
+
+/// Starts the janitor watching the calling process.
+///
+/// The janitor notices the engine is gone when the returned child's stdin closes, so it has to
+/// be kept alive as long as the engine runs. It gets its own process group so a Ctrl-C meant for
+/// the engine doesn't take it down as well.
+pub fn spawn(path: &Path) -> anyhow::Result<Child> {
+    Command::new(env::current_exe()?)
+        .arg("janitor")
+        .arg(path)
+        .stdin(Stdio::piped())
+        .process_group(0)
+        .spawn()
+        .context("Failed to start the janitor")
+}
+
+/// Records how the currently paused sources are resumed, one line each, replacing the previous
+/// record
+pub fn record_paused(path: &Path, paused_sources: &[String]) -> anyhow::Result<()> {
+    if let Some(directory) = path.parent() {
+        fs::create_dir_all(directory)
+            .with_context(|| format!("Failed to create {}", directory.display()))?;
+    }
+    // Written next to the record and renamed, so the janitor never sees half a file
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".tmp");
+    let mut file = File::create(&temporary)?;
+    for source in paused_sources {
+        writeln!(file, "{source}")?;
+    }
+    fs::rename(&temporary, path)?;
+    Ok(())
+}
+
+/// Resumes the sources recorded as paused and clears the record, returns the number of resumed
+/// sources
+pub fn restore(path: &Path) -> anyhow::Result<usize> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(0),
+        Err(error) => return Err(error).context(format!("Failed to open {}", path.display())),
+    };
+    let mut resumed = 0;
+    for command in BufReader::new(file).lines() {
+        let command = command?;
+        if command.trim().is_empty() {
+            continue;
+        }
+        if let Some(player) = command.strip_prefix(MPRIS_PREFIX) {
+            let player = (!player.is_empty()).then_some(player);
+            if let Err(error) = mpris::call_now(player, mpris::Action::Play) {
+                eprintln!("<4>Failed to resume a player: {error:#}");
+            }
+            resumed += 1;
+            continue;
+        }
+        let status = Command::new("bash").arg("-c").arg(&command).status()?;
+        if !status.success() {
+            eprintln!("<4>'{command}' failed with {status}");
+        }
+        resumed += 1;
+    }
+    fs::remove_file(path)?;
+    Ok(resumed)
+}
+
+/// Waits for the engine to exit, then resumes every source it left paused.
+///
+/// Usage: `janitor <record>`, started by the engine with a pipe as stdin.
+pub fn run(mut args: impl Iterator<Item = String>) -> anyhow::Result<()> {
+    let path = PathBuf::from(
+        args.next()
+            .ok_or_else(|| anyhow!("Missing the paused sources record"))?,
+    );
+    // Returns once the engine closed its end of the pipe, by exiting or crashing
+    io::copy(&mut io::stdin().lock(), &mut io::sink())?;
+    let resumed = restore(&path)?;
+    if resumed > 0 {
+        eprintln!("<5>Engine exited, resumed {resumed} paused sources");
+    }
+    Ok(())
+}
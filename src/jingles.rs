@@ -0,0 +1,295 @@
+use std::{
+    collections::VecDeque,
+    fmt,
+    ops::Range,
+    path::Path,
+    str::FromStr,
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{bail, Context};
+
+use crate::{
+    buffer::Buffer,
+    fingerprint::{Fingerprint, BLOCK_SIZE},
+    sample_format::wav_channels,
+    segments::{chunks, item_length},
+    timeline::{DropReason, Event, Timeline},
+    BufferItem,
+};
+
+/// Speed-up through matched audio when `rush` is given no factor
+pub const DEFAULT_RUSH_FACTOR: f64 = 3.0;
+
+/// What happens to buffered audio matching a snippet
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum JingleAction {
+    /// Removed from the queue
+    Skip,
+    /// Played faster by the given factor
+    Rush(f64),
+}
+
+impl FromStr for JingleAction {
+    type Err = anyhow::Error;
+
+    /// Parses `skip` or `rush[:factor]`
+    fn from_str(text: &str) -> anyhow::Result<Self> {
+        Ok(match text.split_once(':') {
+            None if text == "skip" => JingleAction::Skip,
+            None if text == "rush" => JingleAction::Rush(DEFAULT_RUSH_FACTOR),
+            Some(("rush", factor)) => {
+                let factor: f64 = factor
+                    .parse()
+                    .with_context(|| format!("Invalid rush factor '{factor}'"))?;
+                if !(1.0..=4.0).contains(&factor) {
+                    bail!("Rush factor {factor} out of range 1.0 to 4.0");
+                }
+                JingleAction::Rush(factor)
+            }
+            _ => bail!("Unknown jingle action '{text}', expected skip or rush[:factor]"),
+        })
+    }
+}
+
+impl fmt::Display for JingleAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JingleAction::Skip => write!(f, "skip"),
+            JingleAction::Rush(factor) => write!(f, "rush at {factor}x"),
+        }
+    }
+}
+
+/// A clip to look out for, like a station jingle or an ad bumper
+struct Snippet {
+    name: String,
+    fingerprint: Fingerprint,
+}
+
+/// Opt-in skipping of known clips in an input's buffered audio.
+///
+/// Every newly buffered block of audio is compared against the fingerprints of the snippets,
+/// looking back far enough to cover the longest of them. Unlike duplicates and filler, jingles
+/// are usually not set apart by silence, so the comparison slides along the audio instead of
+/// working on segments.
+pub struct JingleSkip {
+    pub action: JingleAction,
+    /// Similarity (-1.0 to 1.0) above which audio counts as a snippet
+    pub similarity: f32,
+    snippets: Vec<Snippet>,
+    /// Number of items at the back of the buffer that have not been checked yet
+    unchecked: usize,
+    /// Capture time ranges of matched audio that is played faster
+    rushed: VecDeque<Range<SystemTime>>,
+}
+
+impl JingleSkip {
+    /// Loads all WAV files in `directory` as snippets, named after the files
+    pub fn load(
+        directory: &Path,
+        sample_rate: usize,
+        action: JingleAction,
+    ) -> anyhow::Result<Self> {
+        let mut snippets = Vec::new();
+        for entry in std::fs::read_dir(directory)
+            .with_context(|| format!("Failed to list {}", directory.display()))?
+        {
+            let path = entry?.path();
+            if path.extension().and_then(|extension| extension.to_str()) != Some("wav") {
+                continue;
+            }
+            let fingerprint = fingerprint_file(&path, sample_rate)?;
+            // The correlation needs a few blocks to mean anything
+            if fingerprint.len() < 8 {
+                bail!("Snippet {} is too short to be recognized", path.display());
+            }
+            snippets.push(Snippet {
+                name: path
+                    .file_stem()
+                    .and_then(|name| name.to_str())
+                    .unwrap_or_default()
+                    .to_string(),
+                fingerprint,
+            });
+        }
+        if snippets.is_empty() {
+            bail!("No WAV snippets in {}", directory.display());
+        }
+        Ok(Self {
+            action,
+            similarity: 0.9,
+            snippets,
+            unchecked: 0,
+            rushed: VecDeque::new(),
+        })
+    }
+
+    pub fn snippet_count(&self) -> usize {
+        self.snippets.len()
+    }
+
+    /// Has to be called for every item added to the back of the buffer
+    pub fn item_added(&mut self) {
+        self.unchecked += 1;
+    }
+
+    /// Memory the fingerprints of the snippets take
+    pub fn memory_bytes(&self) -> usize {
+        self.snippets
+            .iter()
+            .map(|snippet| snippet.fingerprint.memory_bytes())
+            .sum()
+    }
+
+    /// Speed multiplier for audio captured at `position`
+    pub fn speed_at(&self, position: Option<SystemTime>) -> f64 {
+        match (self.action, position) {
+            (JingleAction::Rush(factor), Some(position))
+                if self.rushed.iter().any(|range| range.contains(&position)) =>
+            {
+                factor
+            }
+            _ => 1.0,
+        }
+    }
+
+    /// Looks for snippets in the audio buffered since the last call and skips or marks them
+    pub fn skip_jingles(
+        &mut self,
+        input_name: &str,
+        buffer: &mut Buffer,
+        playback_position: Option<SystemTime>,
+        sample_rate: usize,
+        timeline: &mut Timeline,
+    ) {
+        if let Some(position) = playback_position {
+            while self
+                .rushed
+                .front()
+                .is_some_and(|range| range.end <= position)
+            {
+                self.rushed.pop_front();
+            }
+        }
+
+        self.unchecked = self.unchecked.min(buffer.len());
+        if self.unchecked == 0 {
+            return;
+        }
+        let first_unchecked = buffer.len() - self.unchecked;
+        self.unchecked = 0;
+
+        // Far enough back for a snippet that began before the new items, but the front item is
+        // up next, leave it alone
+        let longest = self
+            .snippets
+            .iter()
+            .map(|snippet| snippet.fingerprint.len())
+            .max()
+            .unwrap_or(0);
+        let mut start = first_unchecked;
+        let mut looked_back = 0;
+        while start > 1 && looked_back < longest * BLOCK_SIZE {
+            start -= 1;
+            looked_back += item_length(&buffer[start]);
+        }
+        let start = start.max(1);
+        if start >= buffer.len() {
+            return;
+        }
+        let audio = Fingerprint::from_chunks(chunks(buffer, start..buffer.len()));
+        // Item and sample offset of every item with sound, relative to `start`
+        let mut offsets = Vec::new();
+        let mut samples = 0;
+        for (index, item) in buffer.iter().enumerate().skip(start) {
+            offsets.push((samples, index));
+            samples += item_length(item);
+        }
+        let item_at = |sample: usize| {
+            offsets
+                .iter()
+                .rev()
+                .find(|(offset, _)| *offset <= sample)
+                .map_or(start, |(_, index)| *index)
+        };
+        let first_new_block = looked_back.min(samples) / BLOCK_SIZE;
+
+        let mut matches: Vec<(Range<usize>, &str)> = Vec::new();
+        let mut block = 0;
+        while block < audio.len() {
+            let found = self.snippets.iter().find(|snippet| {
+                let end = block + snippet.fingerprint.len();
+                // Windows ending before the new audio were checked last time
+                end > first_new_block
+                    && end <= audio.len()
+                    && snippet.fingerprint.similarity(&audio.slice(block..end), 0)
+                        >= self.similarity
+            });
+            match found {
+                Some(snippet) => {
+                    let end = block + snippet.fingerprint.len();
+                    let items = item_at(block * BLOCK_SIZE)..item_at(end * BLOCK_SIZE - 1) + 1;
+                    matches.push((items, &snippet.name));
+                    block = end;
+                }
+                None => block += 1,
+            }
+        }
+
+        // Back to front so earlier ranges stay valid
+        for (items, name) in matches.into_iter().rev() {
+            let seconds = buffer.range(items.clone()).map(item_length).sum::<usize>() as f32
+                / sample_rate.max(1) as f32;
+            match self.action {
+                JingleAction::Skip => {
+                    buffer.drain(items);
+                    timeline.push(Event::Dropped {
+                        input: input_name.to_string(),
+                        seconds,
+                        reason: DropReason::Jingle(name.to_string()),
+                    });
+                }
+                JingleAction::Rush(_) => {
+                    if let Some(range) = capture_range(buffer, items, sample_rate) {
+                        self.rushed.push_back(range);
+                    }
+                    timeline.push(Event::Rushed {
+                        input: input_name.to_string(),
+                        jingle: name.to_string(),
+                        seconds,
+                    });
+                }
+            }
+        }
+        // Found back to front as well
+        self.rushed
+            .make_contiguous()
+            .sort_by_key(|range| range.start);
+    }
+}
+
+/// Wall clock times the audio of `items` was captured between
+fn capture_range(
+    buffer: &Buffer,
+    items: Range<usize>,
+    sample_rate: usize,
+) -> Option<Range<SystemTime>> {
+    let mut captured = buffer.range(items).filter_map(|item| match item {
+        BufferItem::Samples(samples, captured_at) => Some((*captured_at, samples[0].len())),
+        BufferItem::Silence(_) => None,
+    });
+    let first = captured.next()?;
+    let (last, length) = captured.next_back().unwrap_or(first);
+    let end = last + Duration::from_secs_f64(length as f64 / sample_rate.max(1) as f64);
+    Some(first.0..end)
+}
+
+/// Fingerprint of a WAV file as it would be captured at `sample_rate`
+fn fingerprint_file(path: &Path, sample_rate: usize) -> anyhow::Result<Fingerprint> {
+    let mut reader = hound::WavReader::open(path)
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+    let channels = wav_channels(&mut reader, sample_rate)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    Ok(Fingerprint::from_chunks([&channels]))
+}
@@ -0,0 +1,143 @@
+use std::{
+    env,
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{anyhow, bail, Context};
+use chrono::{DateTime, Local, SecondsFormat};
+
+use crate::{control::parse_duration, timeline::Entry};
+
+/// Size at which the journal is rotated
+const MAX_BYTES: u64 = 1024 * 1024;
+
+/// Number of rotated journals kept next to the current one, as `<name>.1` (newest) to `<name>.5`
+const KEEP_ROTATED: usize = 5;
+
+/// Append-only file of timeline events, one `<RFC 3339 time> <event>` line each
+pub struct Journal {
+    path: PathBuf,
+    file: File,
+    size: u64,
+}
+
+/// `$XDG_STATE_HOME/audiomux/events.log`, falling back to `~/.local/state`
+pub fn default_path() -> PathBuf {
+    let state_home = env::var_os("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".local/state")))
+        .unwrap_or_default();
+    state_home.join("audiomux").join("events.log")
+}
+
+impl Journal {
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        if let Some(directory) = path.parent() {
+            fs::create_dir_all(directory)
+                .with_context(|| format!("Failed to create {}", directory.display()))?;
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed to open {}", path.display()))?;
+        let size = file.metadata()?.len();
+        Ok(Self {
+            path: path.to_path_buf(),
+            file,
+            size,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn append(&mut self, entry: &Entry) -> anyhow::Result<()> {
+        if self.size >= MAX_BYTES {
+            self.rotate()?;
+        }
+        let time: DateTime<Local> = entry.time.into();
+        let line = format!(
+            "{} {}\n",
+            time.to_rfc3339_opts(SecondsFormat::Millis, false),
+            entry.event
+        );
+        self.file.write_all(line.as_bytes())?;
+        self.size += line.len() as u64;
+        Ok(())
+    }
+
+    /// Shifts the rotated journals by one, dropping the oldest, and starts a new one
+    fn rotate(&mut self) -> anyhow::Result<()> {
+        for index in (1..KEEP_ROTATED).rev() {
+            let from = rotated_path(&self.path, index);
+            if from.exists() {
+                fs::rename(&from, rotated_path(&self.path, index + 1))?;
+            }
+        }
+        fs::rename(&self.path, rotated_path(&self.path, 1))?;
+        *self = Self::open(&self.path.clone())?;
+        Ok(())
+    }
+}
+
+fn rotated_path(path: &Path, index: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{index}"));
+    PathBuf::from(name)
+}
+
+/// Lines of the journal at `path` and its rotations written at or after `since`, oldest first
+pub fn read_since(path: &Path, since: SystemTime) -> anyhow::Result<Vec<String>> {
+    let mut lines = Vec::new();
+    let files = (1..=KEEP_ROTATED)
+        .rev()
+        .map(|index| rotated_path(path, index))
+        .chain([path.to_path_buf()]);
+    for file in files {
+        let Ok(file) = File::open(&file) else {
+            continue;
+        };
+        for line in BufReader::new(file).lines() {
+            let line = line?;
+            let time = line
+                .split_once(' ')
+                .and_then(|(time, _)| DateTime::parse_from_rfc3339(time).ok());
+            if matches!(time, Some(time) if SystemTime::from(time) >= since) {
+                lines.push(line);
+            }
+        }
+    }
+    Ok(lines)
+}
+
+/// Prints the journal entries of a time range.
+///
+/// Usage: `events [--since <duration>] [--journal <file>]`, by default the last hour of the
+/// default journal.
+pub fn run(mut args: impl Iterator<Item = String>) -> anyhow::Result<()> {
+    let mut since = Duration::from_secs(3600);
+    let mut path = default_path();
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| anyhow!("Missing value for {arg}"))
+        };
+        match arg.as_str() {
+            "--since" => since = parse_duration(&value()?)?,
+            "--journal" => path = PathBuf::from(value()?),
+            _ => bail!("Unknown option '{arg}'"),
+        }
+    }
+    let since = SystemTime::now()
+        .checked_sub(since)
+        .unwrap_or(SystemTime::UNIX_EPOCH);
+    for line in read_since(&path, since)? {
+        println!("{line}");
+    }
+    Ok(())
+}
@@ -0,0 +1,352 @@
+//! LADSPA plugin hosting, enabled with the `ladspa` feature
+
+use std::{
+    env,
+    ffi::{c_char, c_int, c_ulong, c_void, CStr},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, bail, Context};
+use libloading::Library;
+
+use crate::chain::Effect;
+
+const PORT_INPUT: c_int = 0x1;
+const PORT_CONTROL: c_int = 0x4;
+const PORT_AUDIO: c_int = 0x8;
+
+const HINT_BOUNDED_BELOW: c_int = 0x1;
+const HINT_BOUNDED_ABOVE: c_int = 0x2;
+const HINT_SAMPLE_RATE: c_int = 0x8;
+const HINT_LOGARITHMIC: c_int = 0x10;
+const HINT_DEFAULT_MASK: c_int = 0x3c0;
+
+/// Directories searched for plugin libraries when `LADSPA_PATH` is not set
+const DEFAULT_PATH: &str = "/usr/local/lib/ladspa:/usr/lib/ladspa:/usr/lib64/ladspa";
+
+#[repr(C)]
+struct PortRangeHint {
+    hint_descriptor: c_int,
+    lower_bound: f32,
+    upper_bound: f32,
+}
+
+#[repr(C)]
+struct Descriptor {
+    unique_id: c_ulong,
+    label: *const c_char,
+    properties: c_int,
+    name: *const c_char,
+    maker: *const c_char,
+    copyright: *const c_char,
+    port_count: c_ulong,
+    port_descriptors: *const c_int,
+    port_names: *const *const c_char,
+    port_range_hints: *const PortRangeHint,
+    implementation_data: *mut c_void,
+    instantiate: unsafe extern "C" fn(*const Descriptor, c_ulong) -> *mut c_void,
+    connect_port: unsafe extern "C" fn(*mut c_void, c_ulong, *mut f32),
+    activate: Option<unsafe extern "C" fn(*mut c_void)>,
+    run: unsafe extern "C" fn(*mut c_void, c_ulong),
+    run_adding: *const c_void,
+    set_run_adding_gain: *const c_void,
+    deactivate: Option<unsafe extern "C" fn(*mut c_void)>,
+    cleanup: unsafe extern "C" fn(*mut c_void),
+}
+
+type DescriptorFunction = unsafe extern "C" fn(c_ulong) -> *const Descriptor;
+
+enum PortKind {
+    AudioIn,
+    AudioOut,
+    /// Index into the control values
+    Control(usize),
+}
+
+struct Control {
+    /// Port name with everything but letters and digits replaced by `_`, e.g. `gain__db_`
+    name: String,
+    value: f32,
+}
+
+/// LADSPA plugin in an input's chain.
+///
+/// Mono plugins on multi-channel inputs get one instance per channel.
+pub struct LadspaPlugin {
+    descriptor: *const Descriptor,
+    instances: Vec<*mut c_void>,
+    ports: Vec<PortKind>,
+    /// Control port values, boxed so the addresses handed to the plugin stay valid
+    controls: Box<[Control]>,
+    /// Output buffer per audio output of every instance
+    outputs: Vec<Vec<f32>>,
+    audio_inputs: usize,
+    /// Keeps the code of the plugin loaded, dropped last
+    _library: Library,
+}
+
+// The raw pointers are owned by the plugin and only used by whoever owns it
+unsafe impl Send for LadspaPlugin {}
+
+/// Creates a plugin node from `<library>;<label>[;port=value...]`
+pub fn create(
+    text: &str,
+    channel_count: usize,
+    sample_rate: usize,
+) -> anyhow::Result<Box<dyn Effect>> {
+    let mut parts = text.split(';');
+    let (Some(library), Some(label)) = (parts.next(), parts.next()) else {
+        bail!("Usage: ladspa:<library>;<label>[;port=value...]");
+    };
+    let values = parts
+        .map(|part| {
+            let (port, value) = part
+                .split_once('=')
+                .ok_or_else(|| anyhow!("Expected port=value, got '{part}'"))?;
+            let value: f32 = value
+                .parse()
+                .with_context(|| format!("Invalid value for '{port}'"))?;
+            Ok((port.to_string(), value))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    Ok(Box::new(LadspaPlugin::load(
+        &find_library(library)?,
+        label,
+        &values,
+        channel_count,
+        sample_rate,
+    )?))
+}
+
+/// Looks up plugin libraries given without a path in `LADSPA_PATH`
+fn find_library(library: &str) -> anyhow::Result<PathBuf> {
+    if library.contains('/') {
+        return Ok(PathBuf::from(library));
+    }
+    let search_path = env::var("LADSPA_PATH").unwrap_or_else(|_| DEFAULT_PATH.to_string());
+    search_path
+        .split(':')
+        .map(|directory| Path::new(directory).join(library))
+        .find(|path| path.exists())
+        .ok_or_else(|| anyhow!("{library} not found in {search_path}"))
+}
+
+impl LadspaPlugin {
+    /// Instantiates the plugin `label` from `library` and sets the given control values, all
+    /// other controls keep their defaults
+    pub fn load(
+        library: &Path,
+        label: &str,
+        values: &[(String, f32)],
+        channel_count: usize,
+        sample_rate: usize,
+    ) -> anyhow::Result<Self> {
+        unsafe {
+            let library_handle = Library::new(library)
+                .with_context(|| format!("Failed to load {}", library.display()))?;
+            let descriptor_function = *library_handle
+                .get::<DescriptorFunction>(b"ladspa_descriptor\0")
+                .with_context(|| format!("{} is not a LADSPA library", library.display()))?;
+            let descriptor = (0..)
+                .map(|index| descriptor_function(index))
+                .take_while(|descriptor| !descriptor.is_null())
+                .find(|&descriptor| {
+                    CStr::from_ptr((*descriptor).label).to_bytes() == label.as_bytes()
+                })
+                .ok_or_else(|| anyhow!("{} has no plugin '{label}'", library.display()))?;
+
+            let mut plugin = LadspaPlugin {
+                descriptor,
+                instances: Vec::new(),
+                ports: Vec::new(),
+                controls: Box::new([]),
+                outputs: Vec::new(),
+                audio_inputs: 0,
+                _library: library_handle,
+            };
+            let mut controls = Vec::new();
+            let mut audio_outputs = 0;
+            for index in 0..(*descriptor).port_count as usize {
+                let kind = *(*descriptor).port_descriptors.add(index);
+                let is_input = kind & PORT_INPUT != 0;
+                if kind & PORT_AUDIO != 0 {
+                    if is_input {
+                        plugin.audio_inputs += 1;
+                        plugin.ports.push(PortKind::AudioIn);
+                    } else {
+                        audio_outputs += 1;
+                        plugin.ports.push(PortKind::AudioOut);
+                    }
+                } else if kind & PORT_CONTROL != 0 {
+                    let name = port_name(
+                        &CStr::from_ptr(*(*descriptor).port_names.add(index)).to_string_lossy(),
+                    );
+                    let hint = &*(*descriptor).port_range_hints.add(index);
+                    let value = values
+                        .iter()
+                        .find(|(port, _)| is_input && *port == name)
+                        .map_or_else(|| default_value(hint, sample_rate), |(_, value)| *value);
+                    plugin.ports.push(PortKind::Control(controls.len()));
+                    controls.push(Control { name, value });
+                } else {
+                    bail!("Port {index} of '{label}' has an unknown type");
+                }
+            }
+            if let Some((port, _)) = values
+                .iter()
+                .find(|(port, _)| !controls.iter().any(|control| control.name == *port))
+            {
+                let known: Vec<_> = controls
+                    .iter()
+                    .map(|control| control.name.as_str())
+                    .collect();
+                bail!(
+                    "'{label}' has no control port '{port}', expected one of {}",
+                    known.join(", ")
+                );
+            }
+            if plugin.audio_inputs == 0
+                || plugin.audio_inputs != audio_outputs
+                || !channel_count.is_multiple_of(plugin.audio_inputs)
+            {
+                bail!(
+                    "'{label}' has {} audio inputs and {audio_outputs} outputs, can't process {channel_count} channels",
+                    plugin.audio_inputs
+                );
+            }
+            plugin.controls = controls.into_boxed_slice();
+
+            for _ in 0..channel_count / plugin.audio_inputs {
+                let instance = ((*descriptor).instantiate)(descriptor, sample_rate as c_ulong);
+                if instance.is_null() {
+                    bail!("Failed to instantiate '{label}'");
+                }
+                plugin.instances.push(instance);
+                for (index, port) in plugin.ports.iter().enumerate() {
+                    if let PortKind::Control(control) = port {
+                        ((*descriptor).connect_port)(
+                            instance,
+                            index as c_ulong,
+                            &mut plugin.controls[*control].value,
+                        );
+                    }
+                }
+                if let Some(activate) = (*descriptor).activate {
+                    activate(instance);
+                }
+            }
+            plugin.outputs = vec![Vec::new(); channel_count];
+            Ok(plugin)
+        }
+    }
+}
+
+impl Effect for LadspaPlugin {
+    fn set_parameter(&mut self, name: &str, value: f32) -> anyhow::Result<()> {
+        let control = self
+            .controls
+            .iter_mut()
+            .find(|control| control.name == name)
+            .ok_or_else(|| anyhow!("No control port '{name}'"))?;
+        control.value = value;
+        Ok(())
+    }
+
+    fn process(&mut self, channels: &mut [Vec<f32>]) {
+        let length = channels.first().map_or(0, |channel| channel.len());
+        for output in self.outputs.iter_mut() {
+            output.resize(length, 0.0);
+        }
+        for (instance_index, &instance) in self.instances.iter().enumerate() {
+            let first_channel = instance_index * self.audio_inputs;
+            let (mut input, mut output) = (first_channel, first_channel);
+            unsafe {
+                for (index, port) in self.ports.iter().enumerate() {
+                    let buffer = match port {
+                        PortKind::AudioIn => {
+                            input += 1;
+                            channels[input - 1].as_mut_ptr()
+                        }
+                        PortKind::AudioOut => {
+                            output += 1;
+                            self.outputs[output - 1].as_mut_ptr()
+                        }
+                        PortKind::Control(_) => continue,
+                    };
+                    ((*self.descriptor).connect_port)(instance, index as c_ulong, buffer);
+                }
+                ((*self.descriptor).run)(instance, length as c_ulong);
+            }
+        }
+        for (channel, output) in channels.iter_mut().zip(&self.outputs) {
+            channel.copy_from_slice(output);
+        }
+    }
+}
+
+impl Drop for LadspaPlugin {
+    fn drop(&mut self) {
+        unsafe {
+            for &instance in &self.instances {
+                if let Some(deactivate) = (*self.descriptor).deactivate {
+                    deactivate(instance);
+                }
+                ((*self.descriptor).cleanup)(instance);
+            }
+        }
+    }
+}
+
+/// Makes port names like `Gain (dB)` usable as single words: `gain__db_`
+fn port_name(name: &str) -> String {
+    name.chars()
+        .map(|character| {
+            if character.is_ascii_alphanumeric() {
+                character.to_ascii_lowercase()
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// Default value of a control port as suggested by its range hint
+fn default_value(hint: &PortRangeHint, sample_rate: usize) -> f32 {
+    let scale = if hint.hint_descriptor & HINT_SAMPLE_RATE != 0 {
+        sample_rate as f32
+    } else {
+        1.0
+    };
+    let lower = hint.lower_bound * scale;
+    let upper = hint.upper_bound * scale;
+    let between = |upper_weight: f32| {
+        if hint.hint_descriptor & HINT_LOGARITHMIC != 0 && lower > 0.0 && upper > 0.0 {
+            (lower.ln() * (1.0 - upper_weight) + upper.ln() * upper_weight).exp()
+        } else {
+            lower * (1.0 - upper_weight) + upper * upper_weight
+        }
+    };
+    match hint.hint_descriptor & HINT_DEFAULT_MASK {
+        0x040 => lower,
+        0x080 => between(0.25),
+        0x0c0 => between(0.5),
+        0x100 => between(0.75),
+        0x140 => upper,
+        0x200 => 0.0,
+        0x240 => 1.0,
+        0x280 => 100.0,
+        0x2c0 => 440.0,
+        // No default, stay within the bounds
+        _ if hint.hint_descriptor & HINT_BOUNDED_BELOW != 0 => {
+            lower
+                .max(0.0)
+                .min(if hint.hint_descriptor & HINT_BOUNDED_ABOVE != 0 {
+                    upper
+                } else {
+                    f32::MAX
+                })
+        }
+        _ if hint.hint_descriptor & HINT_BOUNDED_ABOVE != 0 => upper.min(0.0),
+        _ => 0.0,
+    }
+}
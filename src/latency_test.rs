@@ -0,0 +1,156 @@
+use std::{f32::consts::PI, sync::mpsc, time::Duration};
+
+use anyhow::{bail, Context};
+use jack::{Client, Control, ProcessScope};
+
+/// Length of the emitted chirp in seconds
+const CHIRP_SECONDS: f32 = 0.05;
+const CHIRP_START_HZ: f32 = 500.0;
+const CHIRP_END_HZ: f32 = 8000.0;
+const CHIRP_LEVEL: f32 = 0.5;
+
+/// Time recorded after every chirp in seconds, bounds the measurable latency
+const RECORD_SECONDS: f32 = 1.0;
+
+/// Number of measurements averaged into the result
+const MEASUREMENTS: usize = 5;
+
+/// Correlation peaks below this fraction of the chirp energy are considered "not heard"
+const MIN_CORRELATION: f32 = 0.1;
+
+/// Emits chirps through an output port, records them on an input port and reports the
+/// round-trip latency through everything connected in between.
+///
+/// Usage: `latency-test [playback port] [capture port]`. Without ports the test ports are left
+/// unconnected so they can be wired up manually.
+pub fn run(mut args: impl Iterator<Item = String>) -> anyhow::Result<()> {
+    let playback_port = args.next();
+    let capture_port = args.next();
+
+    let (client, _status) = Client::new(
+        "Audio Multiplexer latency test",
+        jack::ClientOptions::NO_START_SERVER,
+    )
+    .context("Failed to create jack client")?;
+    let sample_rate = client.sample_rate();
+
+    let mut output = client
+        .register_port("chirp", jack::AudioOut::default())
+        .context("Failed to register output port")?;
+    let input = client
+        .register_port("capture", jack::AudioIn::default())
+        .context("Failed to register input port")?;
+    let output_name = output.name()?;
+    let input_name = input.name()?;
+
+    let chirp = chirp(sample_rate);
+    let record_length = (RECORD_SECONDS * sample_rate as f32) as usize;
+
+    let (recording_sender, recording_receiver) = mpsc::sync_channel(MEASUREMENTS);
+    let mut playback_position = 0;
+    let mut recording = Vec::with_capacity(record_length);
+    let mut measurements_left = MEASUREMENTS;
+    let process_chirp = chirp.clone();
+    let process_callback = move |_client: &Client, scope: &ProcessScope| -> Control {
+        let output = output.as_mut_slice(scope);
+        let input = input.as_slice(scope);
+
+        for (output, input) in output.iter_mut().zip(input) {
+            *output = process_chirp.get(playback_position).copied().unwrap_or(0.0);
+            playback_position += 1;
+            recording.push(*input);
+        }
+
+        if recording.len() >= record_length {
+            recording.truncate(record_length);
+            let _ = recording_sender.try_send(std::mem::replace(
+                &mut recording,
+                Vec::with_capacity(record_length),
+            ));
+            playback_position = 0;
+            measurements_left -= 1;
+            if measurements_left == 0 {
+                return Control::Quit;
+            }
+        }
+        Control::Continue
+    };
+    let process = jack::ClosureProcessHandler::new(process_callback);
+    let active_client = client
+        .activate_async((), process)
+        .context("Failed to activate client")?;
+
+    if let Some(playback_port) = playback_port {
+        active_client
+            .as_client()
+            .connect_ports_by_name(&output_name, &playback_port)
+            .with_context(|| format!("Failed to connect {output_name} to {playback_port}"))?;
+    }
+    if let Some(capture_port) = capture_port {
+        active_client
+            .as_client()
+            .connect_ports_by_name(&capture_port, &input_name)
+            .with_context(|| format!("Failed to connect {capture_port} to {input_name}"))?;
+    }
+
+    let mut latencies = Vec::new();
+    for measurement in 1..=MEASUREMENTS {
+        let recording = recording_receiver
+            .recv_timeout(Duration::from_secs_f32(RECORD_SECONDS * 4.0))
+            .context("Timed out waiting for the recording")?;
+        match find_chirp(&recording, &chirp) {
+            Some(offset) => {
+                let milliseconds = offset as f32 * 1000.0 / sample_rate as f32;
+                println!("Measurement {measurement}: {offset} samples ({milliseconds:.2} ms)");
+                latencies.push(offset);
+            }
+            None => println!("Measurement {measurement}: chirp not heard"),
+        }
+    }
+
+    if latencies.is_empty() {
+        bail!("The chirp was never heard, is {output_name} routed back to {input_name}?");
+    }
+    let mean = latencies.iter().sum::<usize>() as f32 / latencies.len() as f32;
+    let min = latencies.iter().min().unwrap();
+    let max = latencies.iter().max().unwrap();
+    println!(
+        "Round-trip latency: {:.2} ms ({mean:.0} samples, min {min}, max {max})",
+        mean * 1000.0 / sample_rate as f32
+    );
+    Ok(())
+}
+
+/// Linear sine sweep with a Hann window, easy to find again by cross-correlation
+fn chirp(sample_rate: usize) -> Vec<f32> {
+    let length = (CHIRP_SECONDS * sample_rate as f32) as usize;
+    let duration = length as f32 / sample_rate as f32;
+    let sweep_rate = (CHIRP_END_HZ - CHIRP_START_HZ) / duration;
+    (0..length)
+        .map(|index| {
+            let time = index as f32 / sample_rate as f32;
+            let phase = 2.0 * PI * (CHIRP_START_HZ * time + 0.5 * sweep_rate * time * time);
+            let window = 0.5 - 0.5 * (2.0 * PI * index as f32 / length as f32).cos();
+            CHIRP_LEVEL * window * phase.sin()
+        })
+        .collect()
+}
+
+/// Offset of the best match of `chirp` in `recording`, if it was heard at all
+fn find_chirp(recording: &[f32], chirp: &[f32]) -> Option<usize> {
+    if recording.len() < chirp.len() {
+        return None;
+    }
+    let chirp_energy: f32 = chirp.iter().map(|sample| sample * sample).sum();
+    let (offset, correlation) = (0..=recording.len() - chirp.len())
+        .map(|offset| {
+            let correlation: f32 = recording[offset..]
+                .iter()
+                .zip(chirp)
+                .map(|(recorded, emitted)| recorded * emitted)
+                .sum();
+            (offset, correlation)
+        })
+        .max_by(|(_, a), (_, b)| a.total_cmp(b))?;
+    (correlation >= chirp_energy * MIN_CORRELATION).then_some(offset)
+}
@@ -0,0 +1,3755 @@
+//! Plays several audio inputs one after the other, catching up on what was missed.
+//!
+//! The engine is a JACK client. [`MultiplexerBuilder`] sets one up for embedding, the command
+//! line in [`cli`] sets one up from the arguments and the config file.
+
+use std::{
+    any::Any,
+    collections::{BTreeMap, VecDeque},
+    fmt,
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    process::Command,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant, SystemTime},
+};
+
+use adaptive_tempo::AdaptiveTempo;
+use anyhow::Context;
+use auto_dnd::AutoDnd;
+use bookmarks::Bookmark;
+pub use buffer::{Buffer, BufferItem};
+use calibrate::{Calibration, PauseCalibration, PauseLatency, PauseStep};
+use catch_up::{CatchUp, FOCUS_BOOST_FACTOR};
+use chain::{db_to_factor, Chain};
+use config::{
+    Config, DuplicatesConfig, FillerConfig, HurryUpConfig, InputConfig, MidiMapping, TempoConfig,
+};
+use crossfade::Crossfade;
+use cue::Cue;
+use default_sink::{DefaultSink, Sink};
+use ducking::{Ducker, Ducking};
+use duplicates::DuplicateSuppression;
+use fallback_output::FallbackOutput;
+use file_player::FilePlayer;
+use filler::FillerDropping;
+use focus_window::FocusedWindow;
+use generator::Generator;
+use hurry_up::HurryUp;
+use idle::Idle;
+use interleave_all::interleave_all;
+use jack::{AudioOut, Client, Control, MidiIn, NotificationHandler, Port, ProcessScope};
+use jingles::JingleSkip;
+use journal::Journal;
+use memory::{Accounting, MemoryBudget};
+use meter::{Ballistics, MeterSettings, OutputMeter, Reading};
+use net::{NetReceiver, NetSender};
+use overlap::{Overlap, OverlapPolicy};
+use realtime::{Link, PortSource};
+use recorder::Recorder;
+use report::SessionReport;
+use schedule::{RecordingRule, ScheduledRecording};
+pub use scheduling::Scheduling;
+use shm::StatusSegment;
+use silence::SilenceDetector;
+use spectrum::{Analyzer, SpectrumSource, Tap};
+use state_frame::StateFrame;
+use stats::InputStats;
+use stretch::TimeStretch;
+pub use stretch::{Engine, SoundTouchSettings};
+use suspend::{Sleep, SleepWatch};
+use timeline::{Event, Timeline};
+use timeshift::TimeShift;
+use transcription::Transcriber;
+mod adaptive_tempo;
+mod auto_dnd;
+mod bookmarks;
+mod buffer;
+mod calibrate;
+mod capacity;
+mod catch_up;
+mod chain;
+#[cfg(feature = "clap-plugins")]
+mod clap_plugin;
+pub mod cli;
+pub mod config;
+mod control;
+mod crossfade;
+mod cue;
+mod default_sink;
+mod discover;
+mod ducking;
+mod duplicates;
+mod encoder;
+mod export;
+mod fallback_output;
+mod file_player;
+mod filler;
+mod fingerprint;
+mod flac;
+mod focus_window;
+mod generator;
+mod hurry_up;
+mod idle;
+mod interleave_all;
+mod janitor;
+mod jingles;
+mod journal;
+#[cfg(feature = "ladspa")]
+mod ladspa;
+mod latency_test;
+#[cfg(feature = "opus")]
+mod listen_later;
+#[cfg(feature = "lv2")]
+mod lv2;
+mod memory;
+mod meter;
+mod metrics;
+mod midi_control;
+#[cfg(feature = "mp3")]
+mod mp3;
+mod mpris;
+mod net;
+#[cfg(feature = "opus")]
+mod ogg_opus;
+mod overlap;
+mod overlay;
+mod realtime;
+mod recorder;
+mod remote;
+mod report;
+mod sample_format;
+mod schedule;
+mod scheduling;
+mod segments;
+mod setup;
+mod shm;
+mod silence;
+mod socket;
+mod sound_touch;
+mod spectrum;
+mod spill;
+mod state_frame;
+mod stats;
+mod stretch;
+mod suspend;
+mod timeline;
+mod timeshift;
+mod transcription;
+mod websocket;
+
+/// Restarts after a panic are given up when there are this many within `RESTART_WINDOW`
+const MAX_RESTARTS: usize = 5;
+const RESTART_WINDOW: Duration = Duration::from_secs(60);
+/// Pause before restarting, so JACK has noticed the old client is gone
+const RESTART_DELAY: Duration = Duration::from_secs(1);
+
+/// Longest the engine thread sleeps without being woken by the process callback
+const ENGINE_WAKEUP: Duration = Duration::from_millis(100);
+/// Longest removing an input waits for the process callback to give up its ports
+const RETIRE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Played audio kept per input for instant replays
+const REPLAY_HISTORY: Duration = Duration::from_secs(30);
+
+struct AutoPausing {
+    /// Paused by the engine, and to be resumed by it
+    source_paused: bool,
+    pause_threshold: usize,
+    resume_threshold: usize,
+    /// Samples the source keeps playing after being paused
+    pause_latency: usize,
+    /// Samples the source takes to play after being resumed
+    resume_latency: usize,
+    backend: PauseBackend,
+    lead_in: Option<LeadIn>,
+}
+
+/// How a source is paused and resumed
+enum PauseBackend {
+    /// Through the MPRIS interface of a player, the first one when `None`
+    Mpris { player: Option<String> },
+    /// By running shell commands
+    Commands { pause: String, resume: String },
+}
+
+impl PauseBackend {
+    /// Checks that the source can be paused this way, the error suggests what to change. A
+    /// player that isn't running only gets a warning, it is paused once it runs.
+    fn probe(&self, input: &str) -> anyhow::Result<()> {
+        match self {
+            PauseBackend::Mpris { player } => {
+                if mpris::client().is_none() {
+                    anyhow::bail!(
+                        "There is no session bus to reach MPRIS players on, give pause and resume \
+                         commands instead"
+                    );
+                }
+                if !mpris::is_running(player.as_deref())? {
+                    let players = mpris::players()?;
+                    let suggestion = if players.is_empty() {
+                        "no player is running yet".to_string()
+                    } else {
+                        format!("running players are {}", players.join(", "))
+                    };
+                    let player = player.as_deref().unwrap_or("an MPRIS player");
+                    eprintln!(
+                        "<4>{input}: {player} isn't running, it is paused once it runs, \
+                         {suggestion}"
+                    );
+                }
+                Ok(())
+            }
+            PauseBackend::Commands { pause, resume } => {
+                for command in [pause, resume] {
+                    let Some(program) = command_program(command) else {
+                        anyhow::bail!("The command '{command}' doesn't run a program");
+                    };
+                    if program_exists(program)? {
+                        continue;
+                    }
+                    if program == "playerctl" {
+                        anyhow::bail!(
+                            "playerctl isn't installed, install it or leave out the pause and \
+                             resume commands to pause the player through MPRIS directly"
+                        );
+                    }
+                    anyhow::bail!(
+                        "'{program}' of the command '{command}' isn't found, install it or give \
+                         its full path"
+                    );
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Program a shell command runs, after the variables it sets
+fn command_program(command: &str) -> Option<&str> {
+    command.split_whitespace().find(|word| {
+        !word.split_once('=').is_some_and(|(name, _)| {
+            !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '_')
+        })
+    })
+}
+
+/// Whether bash finds the program, a builtin counts
+fn program_exists(program: &str) -> anyhow::Result<bool> {
+    let status = Command::new("bash")
+        .arg("-c")
+        .arg("command -v \"$1\"")
+        .arg("bash")
+        .arg(program)
+        .stdout(std::process::Stdio::null())
+        .status()
+        .context("Failed to run bash")?;
+    Ok(status.success())
+}
+
+impl AutoPausing {
+    /// Backlog above which the source is paused, early by its pause latency so the backlog
+    /// doesn't overshoot
+    fn pause_at(&self) -> usize {
+        self.pause_threshold.saturating_sub(self.pause_latency)
+    }
+
+    /// Backlog below which the source is resumed, early by its resume latency so the backlog
+    /// doesn't run dry
+    fn resume_at(&self) -> usize {
+        (self.resume_threshold + self.resume_latency).min(self.pause_at())
+    }
+
+    fn set_latency(&mut self, latency: PauseLatency, sample_rate: usize) {
+        self.pause_latency = (latency.pause.as_secs_f64() * sample_rate as f64) as usize;
+        self.resume_latency = (latency.resume.as_secs_f64() * sample_rate as f64) as usize;
+    }
+
+    /// Pauses the source unless it is paused already, returns whether it did. Players that
+    /// aren't playing are left alone, so they aren't started when resumed later.
+    fn pause(&mut self) -> anyhow::Result<bool> {
+        if self.source_paused {
+            return Ok(false);
+        }
+        match &self.backend {
+            PauseBackend::Mpris { player } => {
+                let Some(client) = mpris::client() else {
+                    return Ok(false);
+                };
+                if client.status(player.as_deref()) != Some(mpris::PlaybackStatus::Playing) {
+                    return Ok(false);
+                }
+                client.send(player.as_deref(), mpris::Action::Pause);
+            }
+            PauseBackend::Commands { pause, .. } => {
+                Command::new("bash")
+                    .arg("-c")
+                    .arg(pause)
+                    .spawn()
+                    .context("Failed to run the pause command")?;
+            }
+        }
+        self.source_paused = true;
+        Ok(true)
+    }
+
+    /// Resumes the source if it was paused by the engine, returns whether it did
+    fn resume(&mut self) -> anyhow::Result<bool> {
+        if !self.source_paused {
+            return Ok(false);
+        }
+        self.source_paused = false;
+        match &self.backend {
+            PauseBackend::Mpris { player } => {
+                if let Some(client) = mpris::client() {
+                    client.send(player.as_deref(), mpris::Action::Play);
+                }
+            }
+            PauseBackend::Commands { resume, .. } => {
+                Command::new("bash")
+                    .arg("-c")
+                    .arg(resume)
+                    .spawn()
+                    .context("Failed to run the resume command")?;
+            }
+        }
+        Ok(true)
+    }
+
+    /// The player was paused by the engine but plays again, resumed by hand
+    fn resumed_elsewhere(&self) -> bool {
+        let PauseBackend::Mpris { player } = &self.backend else {
+            return false;
+        };
+        self.source_paused
+            && mpris::client().is_some_and(|client| {
+                client.status(player.as_deref()) == Some(mpris::PlaybackStatus::Playing)
+            })
+    }
+
+    /// How the janitor resumes the source, see [`janitor::restore`]
+    fn janitor_record(&self) -> String {
+        match &self.backend {
+            PauseBackend::Mpris { player } => {
+                format!(
+                    "{}{}",
+                    janitor::MPRIS_PREFIX,
+                    player.as_deref().unwrap_or("")
+                )
+            }
+            PauseBackend::Commands { resume, .. } => resume.clone(),
+        }
+    }
+}
+
+/// Replays the end of what was played before a paused source resumes, if the input wasn't heard
+/// for a while
+#[derive(Clone, Copy)]
+struct LeadIn {
+    length: Duration,
+    /// Time without playing the input after which the lead-in is replayed
+    after: Duration,
+}
+
+/// Temporary multiplier of an input's urgency
+struct Boost {
+    factor: f32,
+    until: Instant,
+}
+
+/// Where an input gets its audio from
+enum Source {
+    Ports(PortSource),
+    Generator(Generator),
+    /// Output of another instance streamed over the network
+    Network(NetReceiver),
+    /// Local audio files
+    File(FilePlayer),
+}
+
+impl Default for Source {
+    fn default() -> Self {
+        Source::Ports(PortSource::default())
+    }
+}
+
+/// Source of audio queued until it gets its turn
+#[derive(Default)]
+pub struct Input {
+    name: String,
+    source: Source,
+    buffer: Buffer,
+    pausing: Option<AutoPausing>,
+    silence: SilenceDetector,
+    filler: Option<FillerDropping>,
+    /// Notification inputs collapse repetitions of the same clip
+    duplicates: Option<DuplicateSuppression>,
+    /// Radio inputs skip or rush through known jingles and ad bumpers
+    jingles: Option<JingleSkip>,
+    /// Speech segments are turned into text by an external command
+    transcriber: Option<Transcriber>,
+    boost: Option<Boost>,
+    /// Continuous recording of everything captured, independent of playback
+    logger: Option<Recorder>,
+    /// Recording started by a rule of the recording schedule
+    scheduled_recording: Option<ScheduledRecording>,
+    /// Plays the logger recording instead of the live source
+    timeshift: Option<TimeShift>,
+    /// Capture time of the audio played last
+    playback_position: Option<SystemTime>,
+    /// Manually pinned playback speed, overrides the automatic speed
+    speed_override: Option<f64>,
+    /// Automatic speed following the backlog, natural speed without
+    adaptive_tempo: Option<AdaptiveTempo>,
+    /// Key-locked rate change, changes speed and pitch together on top of the tempo
+    rate: f64,
+    /// Pitch shift in semitones, keeping the speed
+    pitch: f64,
+    /// Factor the captured audio is multiplied with, matches the levels of the inputs
+    gain: f32,
+    /// Captures silence, so nothing is queued
+    muted: bool,
+    /// Effects processing the audio around the speed change
+    chain: Chain,
+    /// Changes the speed of the input, of its own so switching inputs never mixes their audio
+    /// or settings. Created when it plays first.
+    stretcher: Option<Box<dyn TimeStretch>>,
+    /// Neither captured nor played, e.g. after its processing panicked
+    disabled: bool,
+    /// Disabled until something connects to its ports within the auto-arm window
+    awaiting_connection: bool,
+    /// Captured but not played until released
+    held: bool,
+    /// Held by do-not-disturb, with its source paused until then
+    deferred: bool,
+    /// Capture time of the latest sound
+    last_sound: Option<SystemTime>,
+    /// Only captured while the JACK transport is in this state
+    transport_gate: Option<TransportGate>,
+    /// Port feeding every channel because it is the only one connected
+    mono_port: Option<usize>,
+    /// Audio played last with its capture time, kept for replays and the lead-in
+    played: VecDeque<(Vec<Vec<f32>>, SystemTime)>,
+    /// When audio of the input was played last
+    last_played: Option<Instant>,
+    /// What happens when the input played its backlog
+    catch_up: CatchUp,
+    /// Had a backlog since it last caught up with its source
+    behind: bool,
+    /// Tier of the input, inputs of higher tiers always play first
+    priority: i32,
+    /// Waits up to this long for the playing input to reach a pause before taking over from it
+    soft_preemption: Option<Duration>,
+    /// Part of the application name of its window, boosting it while focused
+    window: Option<String>,
+    /// Announces the input when it plays after a gap
+    cue: Option<Cue>,
+    /// Turns the input down under inputs of higher priority tiers in mixing mode
+    ducker: Ducker,
+    stats: InputStats,
+}
+
+impl Input {
+    /// Input without a source, fed through [`Input::capture`], e.g. to test scheduling without a
+    /// JACK server
+    pub fn detached(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            rate: 1.0,
+            gain: 1.0,
+            ..Default::default()
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn buffer(&self) -> &Buffer {
+        &self.buffer
+    }
+
+    /// Input with ports as declared in the config, `channel_count` unless it has its own
+    fn from_config(
+        client: &Client,
+        config: &InputConfig,
+        channel_count: usize,
+        sample_rate: usize,
+    ) -> Self {
+        let mut input = Input::new(
+            client,
+            &config.name,
+            config.channels.unwrap_or(channel_count),
+        );
+        input.pausing = Input::from_config_pausing(config, sample_rate);
+        input.gain = db_to_factor(config.gain);
+        input.configure_silence(config, sample_rate);
+        input.priority = config.priority;
+        input.window = config.window.clone();
+        input.adaptive_tempo = config.tempo.as_ref().map(Input::from_config_tempo);
+        input.cue = config.cue.as_ref().and_then(|cue| {
+            Cue::from_config(cue, channel_count, sample_rate)
+                .inspect_err(|error| {
+                    eprintln!("<4>{}: Not announcing the input: {error:#}", config.name)
+                })
+                .ok()
+        });
+        input.filler = config
+            .drop_filler
+            .as_ref()
+            .map(|filler| Input::from_config_filler(filler, sample_rate));
+        input.duplicates = config
+            .suppress_duplicates
+            .as_ref()
+            .map(|duplicates| Input::from_config_duplicates(duplicates, sample_rate));
+        input
+    }
+
+    /// Applies the silence detection settings of the config but the threshold, which may be
+    /// calibrated
+    fn configure_silence(&mut self, config: &InputConfig, sample_rate: usize) {
+        self.silence.level = config.silence_level;
+        self.silence.hysteresis = db_to_factor(config.silence_hysteresis);
+        self.silence.policy = config.silence_policy.clone();
+        self.silence.window = match config.silence_window_ms {
+            None => Some(silence::DEFAULT_WINDOW),
+            Some(0.0) => None,
+            Some(milliseconds) => {
+                Some(((milliseconds / 1000.0 * sample_rate as f64) as usize).max(1))
+            }
+        };
+        self.silence.channel_mask = config.silence_channels.as_ref().map(|channels| {
+            let count = channels.iter().max().map_or(0, |last| last + 1);
+            (0..count)
+                .map(|channel| channels.contains(&channel))
+                .collect()
+        });
+        self.silence.hold = (config.min_silence * sample_rate as f64) as usize;
+        self.silence.max_stored = config
+            .max_silence
+            .map_or(silence::DEFAULT_MAX_STORED, |seconds| {
+                (seconds * sample_rate as f64) as usize
+            });
+    }
+
+    fn from_config_filler(config: &FillerConfig, sample_rate: usize) -> FillerDropping {
+        let samples = |seconds: f64| (seconds * sample_rate as f64) as usize;
+        let mut filler = FillerDropping::default();
+        filler.backlog_threshold = samples(config.backlog);
+        filler.max_segment = samples(config.max_segment).max(1);
+        filler.min_monotonous = samples(config.min_monotonous);
+        filler.monotony_db = config.monotony_db;
+        filler.min_repeated = samples(config.min_repeated);
+        filler.repeat_similarity = config.repeat_similarity;
+        filler
+    }
+
+    fn from_config_duplicates(
+        config: &DuplicatesConfig,
+        sample_rate: usize,
+    ) -> DuplicateSuppression {
+        let mut duplicates = DuplicateSuppression::default();
+        duplicates.window = Duration::from_secs_f64(config.window);
+        duplicates.max_clip = ((config.max_clip * sample_rate as f64) as usize).max(1);
+        duplicates.similarity = config.similarity;
+        duplicates
+    }
+
+    fn from_config_tempo(tempo: &TempoConfig) -> AdaptiveTempo {
+        AdaptiveTempo::new(
+            tempo.min_tempo(),
+            tempo.max_tempo(),
+            tempo.full_backlog(),
+            tempo.ramp(),
+        )
+    }
+
+    /// Pausing as declared in the config, `None` when its source can't be paused, leaving the
+    /// input to only buffer
+    fn from_config_pausing(config: &InputConfig, sample_rate: usize) -> Option<AutoPausing> {
+        let pausing = config.pausing.as_ref()?;
+        let backend = match (&pausing.pause_command, &pausing.resume_command) {
+            (Some(pause), Some(resume)) => PauseBackend::Commands {
+                pause: pause.clone(),
+                resume: resume.clone(),
+            },
+            _ => PauseBackend::Mpris {
+                player: pausing.player.clone(),
+            },
+        };
+        if let Err(error) = backend.probe(&config.name) {
+            eprintln!(
+                "<4>{}: {error:#}. Its source isn't paused, the input only buffers.",
+                config.name
+            );
+            return None;
+        }
+        if let (PauseBackend::Mpris { player }, Some(client)) = (&backend, mpris::client()) {
+            client.watch(player.as_deref());
+        }
+        Some(AutoPausing {
+            source_paused: false,
+            pause_threshold: (pausing.pause_backlog * sample_rate as f64) as usize,
+            resume_threshold: (pausing.resume_backlog * sample_rate as f64) as usize,
+            pause_latency: (pausing.pause_latency * sample_rate as f64) as usize,
+            resume_latency: (pausing.resume_latency * sample_rate as f64) as usize,
+            backend,
+            lead_in: None,
+        })
+    }
+
+    fn new(client: &Client, prefix: &str, channel_count: usize) -> Self {
+        Self {
+            name: prefix.to_string(),
+            source: Source::Ports(PortSource::register(client, prefix, channel_count)),
+            buffer: Buffer::default(),
+            pausing: None,
+            silence: SilenceDetector::default(),
+            filler: None,
+            duplicates: None,
+            jingles: None,
+            transcriber: None,
+            boost: None,
+            logger: None,
+            scheduled_recording: None,
+            timeshift: None,
+            playback_position: None,
+            speed_override: None,
+            adaptive_tempo: None,
+            rate: 1.0,
+            pitch: 0.0,
+            gain: 1.0,
+            muted: false,
+            chain: Chain::default(),
+            stretcher: None,
+            disabled: false,
+            awaiting_connection: false,
+            held: false,
+            deferred: false,
+            last_sound: None,
+            transport_gate: None,
+            mono_port: None,
+            played: VecDeque::new(),
+            last_played: None,
+            catch_up: CatchUp::default(),
+            behind: false,
+            priority: 0,
+            soft_preemption: None,
+            window: None,
+            cue: None,
+            ducker: Ducker::default(),
+            stats: InputStats::default(),
+        }
+    }
+
+    fn with_generator(name: &str, generator: Generator) -> Self {
+        Self {
+            name: name.to_string(),
+            source: Source::Generator(generator),
+            rate: 1.0,
+            gain: 1.0,
+            ..Default::default()
+        }
+    }
+
+    fn with_file_player(name: &str, player: FilePlayer) -> Self {
+        Self {
+            name: name.to_string(),
+            source: Source::File(player),
+            rate: 1.0,
+            gain: 1.0,
+            ..Default::default()
+        }
+    }
+
+    fn with_network(name: &str, receiver: NetReceiver) -> Self {
+        Self {
+            name: name.to_string(),
+            source: Source::Network(receiver),
+            rate: 1.0,
+            gain: 1.0,
+            ..Default::default()
+        }
+    }
+
+    /// Reads one period from the source, `None` if the source has nothing to offer
+    fn read_period(&mut self, frames: usize) -> Option<Vec<Vec<f32>>> {
+        match &mut self.source {
+            Source::Ports(ports) => {
+                let mut period = ports.read(frames);
+                if let Some(port) = self.mono_port {
+                    let channel = period.swap_remove(port);
+                    period = vec![channel; ports.len()];
+                }
+                Some(period)
+            }
+            Source::Generator(generator) => {
+                generator.is_active().then(|| generator.generate(frames))
+            }
+            Source::Network(receiver) => receiver.read(frames),
+            Source::File(player) => player.read(frames),
+        }
+    }
+
+    /// Drops what the ports captured in a period that isn't captured, so it isn't read later
+    fn skip_period(&mut self, frames: usize) {
+        if let Source::Ports(ports) = &mut self.source {
+            ports.skip(frames);
+        }
+    }
+
+    /// Whether anything is connected to the input's ports, other sources are never connected
+    fn is_connected(&self, client: &Client) -> bool {
+        match &self.source {
+            Source::Ports(ports) => ports.connected(client).contains(&true),
+            Source::Generator(_) | Source::Network(_) | Source::File(_) => false,
+        }
+    }
+
+    /// Full names of the input's ports, for connecting them
+    fn port_names(&self) -> Vec<String> {
+        match &self.source {
+            Source::Ports(ports) => ports.names().to_vec(),
+            Source::Generator(_) | Source::Network(_) | Source::File(_) => Vec::new(),
+        }
+    }
+
+    /// Treats the input as mono while only one of its ports is connected, instead of playing it
+    /// on one channel only. Keeps the last layout while nothing is connected.
+    fn detect_layout(&mut self, client: &Client) {
+        let Source::Ports(ports) = &self.source else {
+            return;
+        };
+        let connected: Vec<usize> = ports
+            .connected(client)
+            .into_iter()
+            .enumerate()
+            .filter_map(|(index, connected)| connected.then_some(index))
+            .collect();
+        match connected[..] {
+            [] => {}
+            [port] if ports.len() > 1 => self.mono_port = Some(port),
+            _ => self.mono_port = None,
+        }
+    }
+
+    /// Stores one period of captured audio, split into runs of samples and silence
+    pub fn capture(&mut self, period: Vec<Vec<f32>>, captured_at: SystemTime, sample_rate: usize) {
+        let channels: Vec<&[f32]> = period.iter().map(Vec::as_slice).collect();
+        let segments = self.silence.segments(&channels);
+        let segments = self.silence.hold(segments);
+
+        // Common case: the whole period is either sound or silence
+        if let [(range, silent)] = segments.as_slice() {
+            if *silent {
+                self.push_silence(range.len());
+            } else {
+                self.push_samples(period, captured_at, sample_rate);
+            }
+            return;
+        }
+
+        for (range, silent) in segments {
+            if silent {
+                self.push_silence(range.len());
+            } else {
+                let offset = Duration::from_secs_f64(range.start as f64 / sample_rate as f64);
+                self.push_samples(
+                    period
+                        .iter()
+                        .map(|channel| channel[range.clone()].to_vec())
+                        .collect(),
+                    captured_at + offset,
+                    sample_rate,
+                );
+            }
+        }
+    }
+
+    fn push_silence(&mut self, sample_count: usize) {
+        let max_stored = self.silence.max_stored;
+        match self.buffer.back() {
+            // Last item is silence, increase duration
+            Some(BufferItem::Silence(_)) => self.buffer.extend_silence(sample_count, max_stored),
+            // Buffer empty? Keep it that way to prevent latency when something
+            // does come in
+            None => {}
+            // Samples are buffered, store silence to keep somewhat natural pacing
+            _ if max_stored > 0 => {
+                self.push_back(BufferItem::Silence(sample_count.min(max_stored)))
+            }
+            _ => {}
+        }
+    }
+
+    fn push_samples(
+        &mut self,
+        samples: Vec<Vec<f32>>,
+        captured_at: SystemTime,
+        sample_rate: usize,
+    ) {
+        self.last_sound = Some(captured_at);
+        // Skip silence if new samples come in
+        if self.buffer.len() == 1
+            && self.buffer.spilled_bytes() == 0
+            && matches!(self.buffer.back(), Some(BufferItem::Silence(_)))
+        {
+            self.buffer.pop_front();
+        }
+        // Scanners see the growth of the back item along with the next one
+        if self.buffer.push_samples(samples, captured_at, sample_rate) {
+            self.item_added();
+        }
+    }
+
+    fn push_back(&mut self, item: BufferItem) {
+        self.buffer.push_back(item);
+        self.item_added();
+    }
+
+    /// Lets the buffer scanners know about an item added at the back
+    fn item_added(&mut self) {
+        if let Some(filler) = self.filler.as_mut() {
+            filler.item_added();
+        }
+        if let Some(duplicates) = self.duplicates.as_mut() {
+            duplicates.item_added();
+        }
+        if let Some(jingles) = self.jingles.as_mut() {
+            jingles.item_added();
+        }
+        if let Some(transcriber) = self.transcriber.as_mut() {
+            transcriber.item_added();
+        }
+    }
+
+    /// Takes `frame_count` frames off the queue at natural speed, stored silence included and
+    /// padded with silence if there is not enough
+    pub fn take_frames(
+        &mut self,
+        frame_count: usize,
+        channel_count: usize,
+        sample_rate: usize,
+    ) -> Vec<Vec<f32>> {
+        let mut period = vec![Vec::with_capacity(frame_count); channel_count];
+        let mut taken = 0;
+        while taken < frame_count {
+            let Some(item) = self.buffer.pop_front() else {
+                break;
+            };
+            match item {
+                BufferItem::Samples(mut samples, captured_at) => {
+                    let frames = samples[0].len().min(frame_count - taken);
+                    if frames < samples[0].len() {
+                        let rest = samples
+                            .iter_mut()
+                            .map(|channel| channel.split_off(frames))
+                            .collect();
+                        let offset = Duration::from_secs_f64(frames as f64 / sample_rate as f64);
+                        self.buffer
+                            .push_front(BufferItem::Samples(rest, captured_at + offset));
+                    }
+                    self.playback_position = Some(captured_at);
+                    for (output, channel) in period.iter_mut().zip(&samples) {
+                        output.extend_from_slice(channel);
+                    }
+                    taken += frames;
+                }
+                BufferItem::Silence(sample_count) => {
+                    let frames = sample_count.min(frame_count - taken);
+                    if sample_count > frames {
+                        self.buffer
+                            .push_front(BufferItem::Silence(sample_count - frames));
+                    }
+                    taken += frames;
+                }
+            }
+            // Silence, and the channels an input with fewer channels leaves out
+            for output in period.iter_mut() {
+                output.resize(taken, 0.0);
+            }
+        }
+        for output in period.iter_mut() {
+            output.resize(frame_count, 0.0);
+        }
+        period
+    }
+
+    /// Keeps played audio for replays and the lead-in, as much as either replays
+    fn retain_played(
+        &mut self,
+        samples: Vec<Vec<f32>>,
+        captured_at: SystemTime,
+        sample_rate: usize,
+    ) {
+        self.last_played = Some(Instant::now());
+        self.played.push_back((samples, captured_at));
+        let lead_in = self
+            .pausing
+            .as_ref()
+            .and_then(|pausing| pausing.lead_in)
+            .map_or(Duration::ZERO, |lead_in| lead_in.length);
+        let limit = (REPLAY_HISTORY.max(lead_in).as_secs_f64() * sample_rate as f64) as usize;
+        let mut frames: usize = self
+            .played
+            .iter()
+            .map(|(samples, _)| samples[0].len())
+            .sum();
+        while let Some((oldest, _)) = self.played.front() {
+            if frames - oldest[0].len() < limit {
+                break;
+            }
+            frames -= oldest[0].len();
+            self.played.pop_front();
+        }
+    }
+
+    /// Queues the retained audio ahead of the backlog if the input wasn't played for the lead-in's
+    /// `after` time, returns the number of frames queued
+    fn replay_lead_in(&mut self, sample_rate: usize) -> Option<usize> {
+        let lead_in = self.pausing.as_ref()?.lead_in?;
+        let idle = self
+            .last_played
+            .is_none_or(|played| played.elapsed() >= lead_in.after);
+        if !idle || self.played.is_empty() {
+            return None;
+        }
+        Some(self.requeue_played((lead_in.length.as_secs_f64() * sample_rate as f64) as usize))
+    }
+
+    /// Queues the end of the played audio ahead of the backlog, at least `frames` frames if that
+    /// much was played, and returns the number of frames queued. It is retained again when it
+    /// plays.
+    fn requeue_played(&mut self, frames: usize) -> usize {
+        let mut queued = 0;
+        while queued < frames {
+            let Some((samples, captured_at)) = self.played.pop_back() else {
+                break;
+            };
+            queued += samples[0].len();
+            self.buffer
+                .push_front(BufferItem::Samples(samples, captured_at));
+        }
+        queued
+    }
+
+    /// Whether the scheduler may pick the input
+    fn is_playable(&self) -> bool {
+        !self.disabled && !self.held && !self.deferred && self.buffered_samples() > 0
+    }
+
+    /// Frames of sound queued
+    pub fn buffered_samples(&self) -> usize {
+        self.buffer.sample_frames()
+    }
+
+    /// Playback speed currently applied to the input.
+    ///
+    /// The global `speed_trim` applies on top of the automatic speed, a manually pinned speed
+    /// is used as is. Jingles being rushed through play faster either way.
+    fn tempo(&self, speed_trim: f64) -> f64 {
+        let rush = self
+            .jingles
+            .as_ref()
+            .map_or(1.0, |jingles| jingles.speed_at(self.playback_position));
+        let automatic = self
+            .adaptive_tempo
+            .as_ref()
+            .map_or(1.0, AdaptiveTempo::tempo);
+        self.speed_override.unwrap_or(speed_trim * automatic) * rush
+    }
+
+    fn urgency(&self) -> f32 {
+        let silence_penalty = match self.buffer.front() {
+            Some(BufferItem::Silence(count)) => *count as f32,
+            _ => 0.0,
+        };
+        let boost = match &self.boost {
+            Some(boost) if boost.until > Instant::now() => boost.factor,
+            _ => 1.0,
+        };
+        (self.buffered_samples() as f32).sqrt() * boost - silence_penalty
+    }
+
+    /// Order the scheduler picks inputs in, greater first: higher priority tiers always go first,
+    /// urgency only decides within a tier
+    fn cmp_precedence(&self, other: &Input) -> std::cmp::Ordering {
+        self.priority
+            .cmp(&other.priority)
+            .then_with(|| self.urgency().total_cmp(&other.urgency()))
+    }
+}
+
+#[derive(Default)]
+struct JackState {
+    /// Engine the inputs change their speed with
+    engine: Engine,
+    /// Policy picking the input to play next
+    scheduling: Scheduling,
+    /// Length of the crossfade between inputs when playback switches
+    crossfade: Duration,
+    /// Crossfade in progress
+    fade: Option<Crossfade>,
+    /// All inputs play at once, ducked under those of higher priority tiers
+    mixing: bool,
+    ducking: Ducking,
+    /// Limit on the memory audio takes, from the config
+    memory_budget: Option<MemoryBudget>,
+    /// Memory in use as of the latest status interval
+    memory_usage: Accounting,
+    /// Settings of the hurry-up output, which has no ports without them
+    hurry_up: Option<HurryUpConfig>,
+    /// What the control changes of MIDI controllers change on the inputs
+    midi_mappings: Vec<MidiMapping>,
+    /// Tuning of SoundTouch from the config, applied whenever it becomes the engine
+    soundtouch_settings: SoundTouchSettings,
+    sample_rate: usize,
+    /// Frames per JACK period
+    period_frames: usize,
+    inputs: Vec<Input>,
+    /// Output of the period being processed, a buffer per channel
+    output: Vec<Vec<f32>>,
+    /// Connection to the process callback, while the engine runs
+    realtime: Option<Link>,
+    timeline: Timeline,
+    bookmarks: Vec<Bookmark>,
+    /// Speed multiplier applied to all inputs on top of their automatic speed
+    speed_trim: f64,
+    /// Effects processing the mixed output
+    bus: Chain,
+    /// Bypasses the effect chains of all inputs and the output bus
+    bypass_all: bool,
+    /// Signal shown by the spectrum analyzer
+    spectrum: Option<Tap>,
+    /// Noise floor measurement of an input started by `calibrate`
+    calibration: Option<Calibration>,
+    /// Pause latency measurement of an input started by `calibrate-pause`, the engine doesn't
+    /// pause or resume its source meanwhile
+    pause_calibration: Option<PauseCalibration>,
+    meter: OutputMeter,
+    meter_settings: MeterSettings,
+    /// Output levels of the latest status interval
+    output_reading: Option<Reading>,
+    /// Name of the input played last, to notice switches
+    playing: Option<String>,
+    /// Number of times the engine was restarted after a panic
+    restarts: usize,
+    /// Number of times the JACK client was created again after the system woke up
+    wakeups: usize,
+    /// The system is about to suspend, every input is held
+    suspending: bool,
+    /// Backlogs kept across a suspend with whether the engine paused the source, by input
+    kept_backlogs: Vec<(String, Buffer, bool)>,
+    /// What happens when processing a period panics
+    panic_policy: PanicPolicy,
+    /// Number of periods replaced by silence because processing them panicked
+    callback_panics: usize,
+    /// End of the window in which inputs get enabled when something connects to them
+    auto_arm_deadline: Option<Instant>,
+    /// Streams the output to another instance
+    net_sender: Option<NetSender>,
+    /// Plays the output through the sound server while its ports are unconnected
+    fallback_output: Option<FallbackOutput>,
+    /// Whether the JACK transport was rolling in the last period, known while an input is gated
+    transport_rolling: Option<bool>,
+    /// Frames of silence to play before any input, left by a catch-up gap
+    gap_frames: usize,
+    /// Input played exclusively until it runs out of backlog
+    focus: Option<String>,
+    /// Since when a softly preempting input waits for the playing one to pause
+    preemption_waiting_since: Option<Instant>,
+    /// Which inputs play at the same time instead of one after the other
+    overlap: OverlapPolicy,
+    /// When inputs are recorded automatically
+    recording_rules: Vec<RecordingRule>,
+    /// Delay between writing the output and it being heard, known while following the default
+    /// sink
+    output_latency: Duration,
+    /// Default sink the output follows
+    output_sink: Option<String>,
+    /// Holds every input but the critical ones, e.g. during a call
+    do_not_disturb: bool,
+    /// Inputs still played during do-not-disturb
+    critical_inputs: Vec<String>,
+    /// Holds every input while the session is idle or locked
+    away: bool,
+    /// Turns do-not-disturb on during calls
+    auto_dnd: Option<AutoDnd>,
+    /// Profile of the config in use, `None` for the general settings
+    profile: Option<String>,
+    /// Profiles of the config
+    profiles: Vec<String>,
+    /// Profile to switch to, applied by the status loop as it needs the client
+    requested_profile: Option<Option<String>>,
+    /// Inputs to add or remove, applied by the status loop
+    input_requests: Vec<InputRequest>,
+    /// Inputs added while running, created again when the engine restarts
+    added_inputs: Vec<InputConfig>,
+    /// Silence thresholds applied by `calibrate`, by input
+    calibrated_thresholds: BTreeMap<String, f32>,
+    /// Pause latencies applied by `calibrate-pause`, by input
+    calibrated_latencies: BTreeMap<String, PauseLatency>,
+}
+
+/// Why the engine stopped without panicking
+enum Stopped {
+    Shutdown,
+    /// The system woke up from a suspend, the engine starts again with a new JACK client
+    WokeUp,
+}
+
+/// Change of the inputs while the engine runs
+enum InputRequest {
+    Add(Box<InputConfig>),
+    /// Removes the input with ports of that name
+    Remove(String),
+}
+
+impl JackState {
+    /// Starts over for a restarted engine, keeping what the user set up and the bookmarks
+    fn reset_for_restart(&mut self, reason: String) {
+        self.start_over();
+        self.restarts += 1;
+        self.timeline.push(Event::Restarted { reason });
+    }
+
+    /// Starts over with a new JACK client after the system woke up, keeping the backlogs as well
+    /// and the sources paused by the engine, resumed as usual once the backlog is played
+    fn reset_for_wakeup(&mut self) {
+        let backlogs = self
+            .inputs
+            .iter_mut()
+            .map(|input| {
+                let paused = input
+                    .pausing
+                    .as_ref()
+                    .is_some_and(|pausing| pausing.source_paused);
+                (
+                    input.name.clone(),
+                    std::mem::take(&mut input.buffer),
+                    paused,
+                )
+            })
+            .collect();
+        self.start_over();
+        self.wakeups += 1;
+        self.kept_backlogs = backlogs;
+        self.timeline.push(Event::WokeUp);
+    }
+
+    /// Restores the backlogs kept across a suspend onto the inputs of the same name
+    fn restore_backlogs(&mut self) {
+        for (name, buffer, paused) in std::mem::take(&mut self.kept_backlogs) {
+            let Some(input) = self.inputs.iter_mut().find(|input| input.name == name) else {
+                continue;
+            };
+            input.buffer = buffer;
+            if let Some(pausing) = input.pausing.as_mut() {
+                pausing.source_paused = paused;
+            }
+        }
+    }
+
+    /// State of a new engine, with what is kept across engines taken over from the previous one
+    fn start_over(&mut self) {
+        let previous = std::mem::take(self);
+        self.bookmarks = previous.bookmarks;
+        self.speed_trim = previous.speed_trim;
+        self.bus = previous.bus;
+        self.bypass_all = previous.bypass_all;
+        self.meter_settings = previous.meter_settings;
+        self.restarts = previous.restarts;
+        self.wakeups = previous.wakeups;
+        self.panic_policy = previous.panic_policy;
+        self.overlap = previous.overlap;
+        self.recording_rules = previous.recording_rules;
+        self.do_not_disturb = previous.do_not_disturb;
+        self.critical_inputs = previous.critical_inputs;
+        self.away = previous.away;
+        self.auto_dnd = previous.auto_dnd;
+        self.profile = previous.profile;
+        self.added_inputs = previous.added_inputs;
+        self.calibrated_thresholds = previous.calibrated_thresholds;
+        self.calibrated_latencies = previous.calibrated_latencies;
+        self.callback_panics = previous.callback_panics;
+        self.timeline.keep_counts_of(&previous.timeline);
+    }
+
+    /// Adds inputs with ports after the others with ports, ahead of the generator and players
+    fn insert_port_inputs(&mut self, inputs: Vec<Input>) {
+        let position = self
+            .inputs
+            .iter()
+            .position(|input| !matches!(input.source, Source::Ports(_)))
+            .unwrap_or(self.inputs.len());
+        self.inputs.splice(position..position, inputs);
+        self.attach_ports();
+    }
+
+    /// Hands the speed and backlog of the playing input to the hurry-up output. The clock runs
+    /// while the input is behind and stops once it caught up.
+    fn update_hurry_up(&self) {
+        let (Some(hurry_up), Some(link)) = (&self.hurry_up, &self.realtime) else {
+            return;
+        };
+        let playing = self
+            .playing
+            .as_ref()
+            .and_then(|name| self.inputs.iter().find(|input| input.name == *name));
+        let (speed, backlog) = playing.map_or((0.0, 0.0), |input| {
+            let speed = if input.behind {
+                input.tempo(self.speed_trim) * input.rate
+            } else {
+                0.0
+            };
+            let backlog = input.buffered_samples() as f64 / self.sample_rate.max(1) as f64;
+            (speed, backlog)
+        });
+        let level = (backlog / hurry_up.full_backlog).min(1.0);
+        link.shared.hurry_up.set(speed as f32, level as f32);
+    }
+
+    /// Applies the control changes the MIDI controllers sent since last time
+    fn apply_control_changes(&mut self) {
+        let Some(link) = self.realtime.as_mut() else {
+            return;
+        };
+        for change in link.control_changes() {
+            midi_control::apply(&self.midi_mappings, change, &mut self.inputs);
+        }
+    }
+
+    /// Hands the ports of new inputs to the process callback
+    fn attach_ports(&mut self) {
+        let Some(link) = self.realtime.as_mut() else {
+            return;
+        };
+        for input in self.inputs.iter_mut() {
+            if let Source::Ports(ports) = &mut input.source {
+                link.attach(ports);
+            }
+        }
+    }
+
+    /// Silence threshold of an input, as calibrated or else as configured
+    fn silence_threshold(&self, config: &Config, input: &str) -> f32 {
+        self.calibrated_thresholds
+            .get(input)
+            .copied()
+            .unwrap_or_else(|| config.silence_threshold_of(input))
+    }
+
+    /// Reports a finished calibration, applies its threshold and stores it in the config file
+    /// if asked to
+    fn finish_calibration(&mut self, config_path: Option<&Path>) {
+        if !self.calibration.as_ref().is_some_and(Calibration::is_done) {
+            return;
+        }
+        let Some(calibration) = self.calibration.take() else {
+            return;
+        };
+        let input = calibration.input.clone();
+        let apply = calibration.apply;
+        let measurement = calibration.measure();
+        if apply {
+            if let Some(found) = self.inputs.iter_mut().find(|found| found.name == input) {
+                found.silence.threshold = measurement.threshold;
+            }
+            self.calibrated_thresholds
+                .insert(input.clone(), measurement.threshold);
+            let stored = match config_path {
+                Some(path) => config::store_silence_threshold(path, &input, measurement.threshold),
+                None => Err(anyhow::anyhow!("No config file was loaded")),
+            };
+            if let Err(error) = stored {
+                eprintln!("<4>Calibrated threshold of {input} applied but not stored: {error:#}");
+            }
+        }
+        self.timeline.push(Event::Calibrated {
+            input,
+            noise_floor: measurement.noise_floor_db(),
+            threshold: measurement.threshold_db(),
+            applied: apply,
+        });
+    }
+
+    /// Applies the calibrated pause latency of an input to its pausing created from the config
+    fn apply_calibrated_latency(&mut self, index: usize) {
+        let input = &mut self.inputs[index];
+        if let (Some(pausing), Some(latency)) = (
+            input.pausing.as_mut(),
+            self.calibrated_latencies.get(&input.name),
+        ) {
+            pausing.set_latency(*latency, self.sample_rate);
+        }
+    }
+
+    /// Advances the pause latency calibration: resumes the source once it stopped, and reports
+    /// the latency once it plays again, applying and storing it if asked to
+    fn step_pause_calibration(&mut self, config_path: Option<&Path>) {
+        let Some(calibration) = self.pause_calibration.as_mut() else {
+            return;
+        };
+        let input_name = calibration.input.clone();
+        let step = calibration.step();
+        let Some(input) = self
+            .inputs
+            .iter_mut()
+            .find(|input| input.name == input_name)
+        else {
+            self.pause_calibration = None;
+            return;
+        };
+        let Some(pausing) = input.pausing.as_mut() else {
+            self.pause_calibration = None;
+            return;
+        };
+        let latency = match step {
+            PauseStep::Wait => return,
+            PauseStep::Resume => {
+                if let Err(error) = pausing.resume() {
+                    eprintln!("<4>{input_name}: {error:#}");
+                }
+                return;
+            }
+            PauseStep::Failed(reason) => {
+                eprintln!("<4>Pause calibration of {input_name} failed: {reason}");
+                if let Err(error) = pausing.resume() {
+                    eprintln!("<4>{input_name}: {error:#}");
+                }
+                self.pause_calibration = None;
+                return;
+            }
+            PauseStep::Done(latency) => latency,
+        };
+        let apply = self
+            .pause_calibration
+            .take()
+            .is_some_and(|calibration| calibration.apply);
+        if apply {
+            pausing.set_latency(latency, self.sample_rate);
+            self.calibrated_latencies
+                .insert(input_name.clone(), latency);
+            // Rounded to milliseconds, the file isn't meant to show float noise
+            let seconds = |duration: Duration| (duration.as_secs_f64() * 1e3).round() / 1e3;
+            let stored = match config_path {
+                Some(path) => config::store_pausing_settings(
+                    path,
+                    &input_name,
+                    &[
+                        ("pause_latency", seconds(latency.pause)),
+                        ("resume_latency", seconds(latency.resume)),
+                    ],
+                ),
+                None => Err(anyhow::anyhow!("No config file was loaded")),
+            };
+            if let Err(error) = stored {
+                eprintln!("<4>Pause latency of {input_name} applied but not stored: {error:#}");
+            }
+        }
+        self.timeline.push(Event::PauseCalibrated {
+            input: input_name,
+            pause_latency: latency.pause.as_secs_f32(),
+            resume_latency: latency.resume.as_secs_f32(),
+            applied: apply,
+        });
+    }
+
+    /// Changes the speed of the inputs with `engine` from now on, their pipelines are started
+    /// over
+    fn set_engine(&mut self, engine: Engine, soundtouch_settings: SoundTouchSettings) {
+        self.engine = engine;
+        self.soundtouch_settings = soundtouch_settings;
+        for input in self.inputs.iter_mut() {
+            input.stretcher = None;
+        }
+    }
+
+    /// Defers or releases the inputs according to do-not-disturb, whether the user is away and
+    /// whether the system is about to suspend. Released inputs drain their backlog and resume
+    /// their sources like after any other backlog.
+    fn apply_do_not_disturb(&mut self) {
+        let call_input = self.auto_dnd.as_ref().and_then(AutoDnd::input);
+        for input in self.inputs.iter_mut() {
+            input.deferred = self.suspending
+                || self.away
+                || self.do_not_disturb
+                    && !self.critical_inputs.contains(&input.name)
+                    && call_input != Some(input.name.as_str());
+        }
+    }
+
+    /// Boosts the inputs of the focused application, refreshed while it keeps the focus so the
+    /// boost runs out a while after it lost it
+    fn boost_focused(&mut self, client: &Client, application: &str) {
+        let until = Instant::now() + focus_window::FOCUS_LINGER;
+        for input in self.inputs.iter_mut() {
+            let connected_clients = match &input.source {
+                Source::Ports(ports) => ports.connected_clients(client),
+                Source::Generator(_) | Source::Network(_) | Source::File(_) => Vec::new(),
+            };
+            if !focus_window::matches(application, input.window.as_deref(), &connected_clients) {
+                continue;
+            }
+            match &mut input.boost {
+                // A stronger one given by hand is left alone
+                Some(boost) if boost.factor > focus_window::FOCUS_BOOST_FACTOR => {}
+                Some(boost) if boost.factor == focus_window::FOCUS_BOOST_FACTOR => {
+                    boost.until = until
+                }
+                _ => {
+                    input.boost = Some(Boost {
+                        factor: focus_window::FOCUS_BOOST_FACTOR,
+                        until,
+                    });
+                    self.timeline.push(Event::Boosted {
+                        input: input.name.clone(),
+                        factor: focus_window::FOCUS_BOOST_FACTOR,
+                        seconds: focus_window::FOCUS_LINGER.as_secs_f32(),
+                    });
+                }
+            }
+        }
+    }
+
+    /// Starts do-not-disturb when a call starts, and ends it with the call unless it was on
+    /// before
+    fn apply_auto_dnd(&mut self) {
+        let Some(auto_dnd) = self.auto_dnd.as_mut() else {
+            return;
+        };
+        let input_sound = auto_dnd.input().and_then(|name| {
+            self.inputs
+                .iter()
+                .find(|input| input.name == name)
+                .and_then(|input| input.last_sound)
+        });
+        let in_call = auto_dnd.in_call(input_sound);
+        if !in_call {
+            auto_dnd.dismissed = false;
+        }
+        if in_call && !self.do_not_disturb && !auto_dnd.dismissed {
+            auto_dnd.engaged = true;
+            self.do_not_disturb = true;
+            self.timeline.push(Event::DoNotDisturb { on: true });
+        } else if !in_call && auto_dnd.engaged {
+            auto_dnd.engaged = false;
+            self.do_not_disturb = false;
+            self.timeline.push(Event::DoNotDisturb { on: false });
+        }
+    }
+
+    /// Starts and stops the recordings of the schedule. Stopped recorders are handed out to be
+    /// dropped with the state unlocked, finalizing their files waits for the encoder thread.
+    fn apply_schedule(&mut self, stopped: &mut Vec<Recorder>) {
+        let now = chrono::Local::now();
+        let channel_count = self.output.len();
+        for input in self.inputs.iter_mut() {
+            let rule = self
+                .recording_rules
+                .iter()
+                .find(|rule| rule.input == input.name && rule.is_active(now));
+            match (rule, &input.scheduled_recording) {
+                (Some(rule), None) => {
+                    let path = rule.path(now);
+                    let recorder = match Recorder::start_file(
+                        &path,
+                        channel_count,
+                        self.sample_rate,
+                        rule.encoding,
+                    ) {
+                        Ok(recorder) => {
+                            self.timeline.push(Event::RecordingStarted {
+                                input: input.name.clone(),
+                                file: path.display().to_string(),
+                            });
+                            Some(recorder)
+                        }
+                        Err(error) => {
+                            eprintln!(
+                                "<3>Failed to start the scheduled recording of {}: {error:#}",
+                                input.name
+                            );
+                            None
+                        }
+                    };
+                    input.scheduled_recording = Some(ScheduledRecording { path, recorder });
+                }
+                (None, Some(_)) => {
+                    let recording = input.scheduled_recording.take().unwrap();
+                    if let Some(recorder) = recording.recorder {
+                        stopped.push(recorder);
+                        self.timeline.push(Event::RecordingStopped {
+                            input: input.name.clone(),
+                            file: recording.path.display().to_string(),
+                        });
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Enables the inputs awaiting a connection that got one, and leaves the rest disabled once
+    /// the auto-arm window is over
+    fn auto_arm(&mut self, client: &Client) {
+        let Some(deadline) = self.auto_arm_deadline else {
+            return;
+        };
+        let expired = Instant::now() >= deadline;
+        for input in self
+            .inputs
+            .iter_mut()
+            .filter(|input| input.awaiting_connection)
+        {
+            let connected = input.is_connected(client);
+            if connected || expired {
+                input.awaiting_connection = false;
+                input.disabled = !connected;
+                self.timeline.push(Event::AutoArmed {
+                    input: input.name.clone(),
+                    enabled: connected,
+                });
+            }
+        }
+        if expired {
+            self.auto_arm_deadline = None;
+        }
+    }
+
+    /// The input to play instead of `next`: the one playing, while `next` preempts softly and
+    /// the one playing is in the middle of a sentence, until the wait runs out
+    fn defer_preemption(&mut self, next: usize) -> usize {
+        let current = self
+            .playing
+            .as_ref()
+            .and_then(|playing| self.inputs.iter().position(|input| input.name == *playing))
+            .filter(|&current| current != next);
+        let (Some(max_wait), Some(current)) = (self.inputs[next].soft_preemption, current) else {
+            self.preemption_waiting_since = None;
+            return next;
+        };
+        let input = &self.inputs[current];
+        // Stored silence marks a pause in what the input plays
+        let mid_sentence = input.is_playable()
+            && self.focus.as_ref().is_none_or(|focus| *focus == input.name)
+            && matches!(input.buffer.front(), Some(BufferItem::Samples(..)));
+        let waiting_since = *self
+            .preemption_waiting_since
+            .get_or_insert_with(Instant::now);
+        if mid_sentence && waiting_since.elapsed() < max_wait {
+            current
+        } else {
+            self.preemption_waiting_since = None;
+            next
+        }
+    }
+
+    /// Applies the catch-up behavior of the input at `index`, which just played its backlog
+    fn caught_up(&mut self, index: usize) {
+        let input = &mut self.inputs[index];
+        input.behind = false;
+        self.timeline.push(Event::CaughtUp {
+            input: input.name.clone(),
+        });
+        match input.catch_up {
+            CatchUp::Live => {}
+            CatchUp::Chime => {
+                let chime = catch_up::chime(self.output.len(), self.sample_rate);
+                input
+                    .buffer
+                    .push_back(BufferItem::Samples(chime, SystemTime::now()));
+            }
+            CatchUp::Gap(duration) => {
+                self.gap_frames = (duration.as_secs_f64() * self.sample_rate as f64) as usize;
+            }
+            CatchUp::FocusNext => {
+                let next = self
+                    .inputs
+                    .iter_mut()
+                    .enumerate()
+                    .filter(|(other, input)| {
+                        *other != index && !input.disabled && !input.held && !input.deferred
+                    })
+                    .map(|(_, input)| (input.buffered_samples(), input))
+                    .max_by_key(|(backlog, _)| *backlog);
+                if let Some((backlog, next)) = next.filter(|(backlog, _)| *backlog > 0) {
+                    // Long enough to play the backlog at natural speed
+                    let seconds = backlog as f32 / self.sample_rate.max(1) as f32;
+                    next.boost = Some(Boost {
+                        factor: FOCUS_BOOST_FACTOR,
+                        until: Instant::now() + Duration::from_secs_f32(seconds),
+                    });
+                    self.timeline.push(Event::Boosted {
+                        input: next.name.clone(),
+                        factor: FOCUS_BOOST_FACTOR,
+                        seconds,
+                    });
+                }
+            }
+        }
+    }
+
+    /// Applies the panic policy after processing a period panicked while processing `input`
+    fn callback_panicked(&mut self, input: Option<usize>, reason: String) {
+        self.callback_panics += 1;
+        if self.panic_policy == PanicPolicy::Abort {
+            eprintln!("<2>Processing panicked: {reason}, aborting");
+            std::process::abort();
+        }
+        let mut input = input.and_then(|index| self.inputs.get_mut(index));
+        let disabled = match input.as_deref_mut() {
+            Some(input) if self.panic_policy == PanicPolicy::DisableInput => {
+                input.disabled = true;
+                input.buffer.clear();
+                true
+            }
+            _ => false,
+        };
+        self.timeline.push(Event::CallbackPanicked {
+            input: input.map(|input| input.name.clone()),
+            reason,
+            disabled,
+        });
+    }
+}
+
+/// What happens when processing a JACK period panics
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+enum PanicPolicy {
+    /// Play silence for the period and carry on
+    #[default]
+    Silence,
+    /// Like `Silence`, and disable the input that was being processed
+    DisableInput,
+    /// Abort the process, leaving the cleanup to the janitor
+    Abort,
+}
+
+impl FromStr for PanicPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(text: &str) -> anyhow::Result<Self> {
+        Ok(match text {
+            "silence" => PanicPolicy::Silence,
+            "disable-input" => PanicPolicy::DisableInput,
+            "abort" => PanicPolicy::Abort,
+            _ => anyhow::bail!(
+                "Unknown panic policy '{text}', expected 'silence', 'disable-input' or 'abort'"
+            ),
+        })
+    }
+}
+
+/// JACK transport state an input is captured in
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum TransportGate {
+    /// Only while the transport is rolling, e.g. to queue what is heard during a recording session
+    Rolling,
+    /// Only while the transport is stopped or starting, to leave recording sessions undisturbed
+    Stopped,
+}
+
+impl TransportGate {
+    fn is_open(self, rolling: bool) -> bool {
+        match self {
+            TransportGate::Rolling => rolling,
+            TransportGate::Stopped => !rolling,
+        }
+    }
+}
+
+impl FromStr for TransportGate {
+    type Err = anyhow::Error;
+
+    fn from_str(text: &str) -> anyhow::Result<Self> {
+        Ok(match text {
+            "rolling" => TransportGate::Rolling,
+            "stopped" => TransportGate::Stopped,
+            _ => anyhow::bail!("Unknown transport state '{text}', expected 'rolling' or 'stopped'"),
+        })
+    }
+}
+
+impl fmt::Display for TransportGate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TransportGate::Rolling => write!(f, "rolling"),
+            TransportGate::Stopped => write!(f, "stopped"),
+        }
+    }
+}
+
+/// Message of a caught panic
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    payload
+        .downcast_ref::<&str>()
+        .map(|message| message.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown panic".to_string())
+}
+
+#[derive(Default)]
+struct Options {
+    /// No status output, diagnostics go to the journal
+    headless: bool,
+    /// Address to serve metrics on
+    metrics_address: Option<String>,
+    /// File the timeline events are appended to
+    journal: Option<PathBuf>,
+    /// Unix socket taking JSON commands
+    control_socket: Option<PathBuf>,
+    panic_policy: PanicPolicy,
+    /// Where the session report is written on shutdown
+    report: Option<PathBuf>,
+    /// Receiving instance the output and timeline are streamed to
+    send_address: Option<String>,
+    /// Encoding of the streamed output, `aes67` to send to a multicast group
+    send_codec: net::Codec,
+    /// Address to receive another instance's stream on, played as the `remote` input
+    receive_address: Option<String>,
+    /// Audio held back from the received stream to ride out network hiccups
+    jitter_buffer: net::JitterBuffer,
+    /// Inputs with ports start disabled and are enabled when something connects to them within
+    /// this time
+    auto_arm: Option<Duration>,
+    /// Keeps the output connected to the default PipeWire sink as it changes
+    follow_default_sink: bool,
+    /// Plays the output through the sound server while nothing is connected to its ports
+    fallback_output: bool,
+    /// Boosts the input of the application whose window has the focus
+    focus_follows_window: bool,
+    /// Holds every input while the session is idle or locked
+    hold_when_away: bool,
+    /// Pauses the sources before the system suspends and reconnects to JACK after it woke up
+    suspend_aware: bool,
+    /// Captured audio is queued in items of up to this many frames instead of one per period
+    block_frames: usize,
+    /// Shared memory file the state frames are published in, e.g. in /dev/shm
+    shm: Option<PathBuf>,
+    /// Profile of the config to start with
+    profile: Option<String>,
+    /// File the config was loaded from, calibrated settings are stored in it
+    config_path: Option<PathBuf>,
+}
+
+/// Processes the periods captured by the process callback, stopped when dropped
+struct EngineThread {
+    stop: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl EngineThread {
+    fn spawn(jack_state: Arc<Mutex<JackState>>) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopped = stop.clone();
+        let handle = thread::Builder::new()
+            .name("engine".to_string())
+            .spawn(move || {
+                while !stopped.load(Ordering::Relaxed) {
+                    thread::park_timeout(ENGINE_WAKEUP);
+                    // Poisoned by a panicking status loop, which restarts the engine
+                    let Ok(mut state) = jack_state.lock() else {
+                        return;
+                    };
+                    process_pending(&mut state);
+                }
+            })
+            .expect("Failed to start the engine thread");
+        Self {
+            stop,
+            handle: Some(handle),
+        }
+    }
+
+    /// Handle to wake the thread with
+    fn thread(&self) -> thread::Thread {
+        self.handle
+            .as_ref()
+            .expect("Engine thread already stopped")
+            .thread()
+            .clone()
+    }
+}
+
+impl Drop for EngineThread {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            handle.thread().unpark();
+            let _ = handle.join();
+        }
+    }
+}
+
+/// Processes the periods the process callback handled since last time and queues their output
+fn process_pending(state: &mut JackState) {
+    state.apply_control_changes();
+    while let Some(frames) = state.realtime.as_mut().and_then(Link::next_period) {
+        // Index of the input being processed, blamed if the cycle panics
+        let mut current_input = None;
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            process_cycle(state, frames, &mut current_input)
+        }));
+        if let Err(payload) = result {
+            // Silence instead of whatever was written before the panic
+            for channel in state.output.iter_mut() {
+                channel.fill(0.0);
+            }
+            state.callback_panicked(current_input, panic_message(&*payload));
+        }
+        if let Some(link) = state.realtime.as_mut() {
+            link.write(&state.output);
+        }
+    }
+    state.update_hurry_up();
+}
+
+/// Records JACK notifications in the timeline
+struct Notifications {
+    jack_state: Arc<Mutex<JackState>>,
+}
+
+impl NotificationHandler for Notifications {
+    fn xrun(&mut self, _: &Client) -> Control {
+        self.jack_state.lock().unwrap().timeline.push(Event::Xrun);
+        Control::Continue
+    }
+}
+
+/// The engine with the control interface, the metrics endpoint and the janitor around it
+pub struct Multiplexer {
+    jack_state: Arc<Mutex<JackState>>,
+    options: Options,
+    config: Config,
+    /// Where the janitor finds the sources to resume
+    paused_record: PathBuf,
+    started: SystemTime,
+    /// Set by Ctrl-C or SIGTERM, ends the status loop
+    shutdown: Arc<AtomicBool>,
+}
+
+/// Sets up a [`Multiplexer`] in code instead of from the command line and a config file.
+///
+/// Nothing talks to JACK before [`Multiplexer::supervise`], so building works without a server.
+///
+/// ```no_run
+/// use audio_multiplexer_rs::{config::InputConfig, MultiplexerBuilder};
+///
+/// let multiplexer = MultiplexerBuilder::new()
+///     .with_client_name("Embedded")
+///     .with_input(InputConfig::new("voice"))
+///     .with_input(InputConfig::new("music"))
+///     .build()?;
+/// multiplexer.supervise()?;
+/// # anyhow::Ok(())
+/// ```
+pub struct MultiplexerBuilder {
+    options: Options,
+    config: Config,
+}
+
+impl Default for MultiplexerBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MultiplexerBuilder {
+    /// Headless without inputs, journal or metrics, the defaults of the command line otherwise
+    pub fn new() -> Self {
+        Self {
+            options: Options {
+                headless: true,
+                block_frames: buffer::DEFAULT_BLOCK_FRAMES,
+                ..Options::default()
+            },
+            config: Config {
+                inputs: Vec::new(),
+                ..Config::default()
+            },
+        }
+    }
+
+    /// Starts from a config, e.g. one loaded with [`Config::load`], keeping its inputs
+    pub fn with_config(mut self, config: Config) -> Self {
+        self.config = config;
+        self
+    }
+
+    pub fn with_input(mut self, input: InputConfig) -> Self {
+        self.config.inputs.push(input);
+        self
+    }
+
+    pub fn with_channels(mut self, channel_count: usize) -> Self {
+        self.config.channels = channel_count;
+        self
+    }
+
+    pub fn with_client_name(mut self, name: &str) -> Self {
+        self.config.client_name = name.to_string();
+        self
+    }
+
+    pub fn with_engine(mut self, engine: Engine) -> Self {
+        self.config.engine = engine;
+        self
+    }
+
+    pub fn with_silence_threshold(mut self, threshold: f32) -> Self {
+        self.config.silence_threshold = threshold;
+        self
+    }
+
+    /// Prints the status every 100ms, like the command line does unless `--headless`
+    pub fn with_status_output(mut self) -> Self {
+        self.options.headless = false;
+        self
+    }
+
+    /// Serves metrics and state frames over HTTP
+    pub fn with_metrics(mut self, address: &str) -> Self {
+        self.options.metrics_address = Some(address.to_string());
+        self
+    }
+
+    /// Appends the timeline to a journal file
+    pub fn with_journal(mut self, path: PathBuf) -> Self {
+        self.options.journal = Some(path);
+        self
+    }
+
+    /// Takes JSON commands on a Unix socket
+    pub fn with_control_socket(mut self, path: PathBuf) -> Self {
+        self.options.control_socket = Some(path);
+        self
+    }
+
+    pub fn build(mut self) -> anyhow::Result<Multiplexer> {
+        self.config.apply_presets();
+        self.config.validate()?;
+        Ok(Multiplexer::new(self.options, self.config))
+    }
+}
+
+impl Multiplexer {
+    fn new(options: Options, config: Config) -> Self {
+        let jack_state = Arc::new(Mutex::new(JackState::default()));
+
+        Multiplexer {
+            jack_state,
+            options,
+            config,
+            paused_record: janitor::default_path(),
+            started: SystemTime::now(),
+            shutdown: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Adds an input with ports while the engine runs, within a status interval. Inputs without
+    /// a channel count get the output's.
+    pub fn add_input(&self, input: InputConfig) {
+        let mut state = self.jack_state.lock().unwrap();
+        state
+            .input_requests
+            .push(InputRequest::Add(Box::new(input)));
+    }
+
+    /// Removes an input with ports while the engine runs, resuming its source if it is paused
+    pub fn remove_input(&self, name: &str) {
+        let mut state = self.jack_state.lock().unwrap();
+        state
+            .input_requests
+            .push(InputRequest::Remove(name.to_string()));
+    }
+
+    /// Ends [`Multiplexer::supervise`] within a status interval, like Ctrl-C on the command line
+    pub fn stop(&self) {
+        self.shutdown.store(true, Ordering::Relaxed);
+    }
+
+    /// Runs the engine until [`Multiplexer::stop`], restarting it after panics. The control
+    /// socket, the metrics endpoint and the janitor live outside of the engine and keep running
+    /// across restarts. Stdin and the signals are left to the program.
+    pub fn supervise(&self) -> anyhow::Result<()> {
+        if let Some(path) = &self.options.control_socket {
+            if let Err(error) = socket::serve(path, self.jack_state.clone()) {
+                eprintln!("<4>{error:#}, not taking commands on it");
+            }
+        }
+
+        if let Some(address) = &self.options.metrics_address {
+            metrics::serve(address, self.jack_state.clone())?;
+        }
+
+        // Sources left paused by an engine that died without its janitor
+        let resumed = janitor::restore(&self.paused_record)?;
+        if resumed > 0 {
+            eprintln!("<5>Resumed {resumed} sources left paused by a previous run");
+        }
+        let _janitor = janitor::spawn(&self.paused_record)?;
+
+        let sleep = self.options.suspend_aware.then(SleepWatch::watch);
+        let mut recent_restarts: VecDeque<Instant> = VecDeque::new();
+        loop {
+            let payload = match panic::catch_unwind(AssertUnwindSafe(|| self.run(sleep.as_ref()))) {
+                Ok(Ok(Stopped::WokeUp)) => {
+                    eprintln!("<5>Woke up from suspend, reconnecting to JACK");
+                    self.jack_state.lock().unwrap().reset_for_wakeup();
+                    std::thread::sleep(RESTART_DELAY);
+                    continue;
+                }
+                Ok(result) => {
+                    if let Some(path) = &self.options.report {
+                        let state = self.jack_state.lock().unwrap();
+                        SessionReport::new(&state, self.started).write(path)?;
+                    }
+                    return result.map(|_| ());
+                }
+                Err(payload) => payload,
+            };
+            let reason = panic_message(&*payload);
+
+            recent_restarts.retain(|time| time.elapsed() < RESTART_WINDOW);
+            if recent_restarts.len() >= MAX_RESTARTS {
+                anyhow::bail!(
+                    "Engine panicked {} times within {}s, giving up: {reason}",
+                    recent_restarts.len() + 1,
+                    RESTART_WINDOW.as_secs()
+                );
+            }
+            recent_restarts.push_back(Instant::now());
+
+            match janitor::restore(&self.paused_record) {
+                Ok(resumed) => eprintln!(
+                    "<2>Engine panicked: {reason}, resumed {resumed} paused sources, restarting"
+                ),
+                Err(error) => eprintln!(
+                    "<2>Engine panicked: {reason}, failed to resume paused sources: {error:#}"
+                ),
+            }
+            self.jack_state.clear_poison();
+            self.jack_state.lock().unwrap().reset_for_restart(reason);
+            std::thread::sleep(RESTART_DELAY);
+        }
+    }
+
+    fn run(&self, sleep: Option<&SleepWatch>) -> anyhow::Result<Stopped> {
+        let (client, _status) = Client::new(
+            &self.config.client_name,
+            jack::ClientOptions::NO_START_SERVER,
+        )
+        .expect("Failed to create jack client");
+
+        let mut state = self.jack_state.lock().unwrap();
+
+        if state.restarts == 0 && state.wakeups == 0 {
+            state.speed_trim = 1.0;
+            state.panic_policy = self.options.panic_policy;
+            state.profile = self.options.profile.clone();
+        }
+        state.profiles = self.config.profiles.keys().cloned().collect();
+        let config = self.config.with_profile(state.profile.as_deref())?;
+        let channel_count = config.channels;
+        state.sample_rate = client.sample_rate();
+        state.period_frames = client.buffer_size() as usize;
+        state.set_engine(config.engine, config.soundtouch);
+        state.scheduling = config.scheduling;
+        state.crossfade = Duration::from_secs_f64(config.crossfade);
+        state.fade = None;
+        state.mixing = config.mixing.is_some();
+        state.ducking = config
+            .mixing
+            .as_ref()
+            .map(Ducking::from_config)
+            .unwrap_or_default();
+        state.memory_budget = config.memory.as_ref().map(MemoryBudget::new);
+        state.hurry_up = config.hurry_up.clone();
+        state.midi_mappings = config
+            .midi_control
+            .as_ref()
+            .map(|midi_control| midi_control.mappings.clone())
+            .unwrap_or_default();
+
+        let outputs: Vec<Port<AudioOut>> = (0..channel_count)
+            .map(|index| {
+                client
+                    .register_port(format!("{index}").as_str(), jack::AudioOut::default())
+                    .expect("Failed to register port")
+            })
+            .collect();
+        let hurry_up = config
+            .hurry_up
+            .as_ref()
+            .map(|hurry_up| HurryUp::register(&client, hurry_up));
+        let midi_control: Option<Port<MidiIn>> = config.midi_control.as_ref().map(|_| {
+            client
+                .register_port("midi-control", MidiIn::default())
+                .expect("Failed to register port")
+        });
+        let midi_control_port = midi_control
+            .as_ref()
+            .map(|port| port.name().expect("Failed to get port name"));
+        let output_ports: Vec<String> = outputs
+            .iter()
+            .map(|port| port.name().expect("Failed to get port name"))
+            .collect();
+        state.output = vec![vec![0.0; state.period_frames]; channel_count];
+        for input_config in config.inputs.iter().chain(&state.added_inputs.clone()) {
+            if state
+                .inputs
+                .iter()
+                .any(|input| input.name == input_config.name)
+            {
+                continue;
+            }
+            state.inputs.push(Input::from_config(
+                &client,
+                input_config,
+                channel_count,
+                client.sample_rate(),
+            ));
+        }
+        // Quiet until switched on with the `generator` command
+        state.inputs.push(Input::with_generator(
+            "generator",
+            Generator::new(channel_count, client.sample_rate()),
+        ));
+        // Quiet until a file is played with the `play` command
+        state.inputs.push(Input::with_file_player(
+            "file",
+            FilePlayer::new(channel_count, client.sample_rate()),
+        ));
+        if let Some(address) = &self.options.receive_address {
+            let receiver = NetReceiver::start(
+                address,
+                channel_count,
+                client.sample_rate(),
+                self.options.jitter_buffer.clone(),
+            )?;
+            state.inputs.push(Input::with_network("remote", receiver));
+        }
+        if let Some(address) = &self.options.send_address {
+            state.net_sender = Some(NetSender::start(
+                address,
+                channel_count,
+                client.sample_rate(),
+                self.options.send_codec,
+            )?);
+        }
+        if let Some(sdp) = state.net_sender.as_ref().and_then(NetSender::sdp) {
+            eprintln!("<6>Sending an AES67 stream described by:");
+            for line in sdp.lines() {
+                eprintln!("<6>{line}");
+            }
+        }
+        state.restore_backlogs();
+        for index in 0..state.inputs.len() {
+            let threshold = state.silence_threshold(&config, &state.inputs[index].name);
+            let input = &mut state.inputs[index];
+            input.buffer.block_frames = self.options.block_frames;
+            input.silence.threshold = threshold;
+            state.apply_calibrated_latency(index);
+        }
+        if let Some(window) = self.options.auto_arm {
+            for input in state.inputs.iter_mut() {
+                if matches!(input.source, Source::Ports(_)) {
+                    input.disabled = true;
+                    input.awaiting_connection = true;
+                }
+            }
+            state.auto_arm_deadline = Some(
+                Instant::now()
+                    .checked_add(window)
+                    .context("The auto-arm time is too long")?,
+            );
+        }
+
+        let engine = EngineThread::spawn(self.jack_state.clone());
+        let period_frames = state.period_frames;
+        let sources = state
+            .inputs
+            .iter_mut()
+            .filter_map(|input| match &mut input.source {
+                Source::Ports(ports) => Some(ports),
+                Source::Generator(_) | Source::Network(_) | Source::File(_) => None,
+            });
+        let (mut callback, link) = realtime::Callback::new(
+            outputs,
+            hurry_up,
+            midi_control,
+            sources,
+            period_frames,
+            engine.thread(),
+        );
+        let shared = link.shared.clone();
+        state.realtime = Some(link);
+        drop(state);
+
+        let process_callback = move |client: &Client, scope: &ProcessScope| -> Control {
+            callback.process(client, scope)
+        };
+        let process = jack::ClosureProcessHandler::new(process_callback);
+        let buffer_size = client.buffer_size();
+        let active_client = client
+            .activate_async(
+                Notifications {
+                    jack_state: self.jack_state.clone(),
+                },
+                process,
+            )
+            .expect("Failed to activate client");
+        let mut connections = {
+            let state = self.jack_state.lock().unwrap();
+            config_connections(&config, &state.inputs)
+        };
+        if let (Some(port), Some(midi_control)) = (&midi_control_port, &config.midi_control) {
+            connections.extend(
+                midi_control
+                    .connect
+                    .iter()
+                    .map(|source| (source.clone(), port.clone())),
+            );
+        }
+        connect_ports(active_client.as_client(), &connections);
+
+        if self.options.headless {
+            let state = self.jack_state.lock().unwrap();
+            let inputs: Vec<&str> = state
+                .inputs
+                .iter()
+                .map(|input| input.name.as_str())
+                .collect();
+            eprintln!(
+                "<6>Running headless at {} Hz with {} frames per period, inputs: {}",
+                state.sample_rate,
+                buffer_size,
+                inputs.join(", ")
+            );
+            if let Some(address) = &self.options.metrics_address {
+                eprintln!("<6>Serving metrics on http://{address}/metrics");
+                eprintln!("<6>Serving state frames on http://{address}/state");
+                eprintln!("<6>Serving the streaming overlay on http://{address}/overlay");
+            }
+        }
+
+        let mut journal = match &self.options.journal {
+            Some(path) => Some(Journal::open(path)?),
+            None => None,
+        };
+        let mut status_segment = match &self.options.shm {
+            Some(path) => Some(StatusSegment::create(path)?),
+            None => None,
+        };
+        let mut printed_timeline = 0;
+        let mut recorded_paused: Vec<String> = Vec::new();
+        let mut analyzer = Analyzer::default();
+        let mut ballistics = Ballistics::default();
+        let mut last_reading = Instant::now();
+        let default_sink = self.options.follow_default_sink.then(DefaultSink::watch);
+        let mut sink_connections = Vec::new();
+        let mut missing_frames = 0;
+        let mut dropped_frames = 0;
+        let mut unconnected = fallback_output::Unconnected::default();
+        let focused_window = self
+            .options
+            .focus_follows_window
+            .then(FocusedWindow::watch)
+            .flatten();
+        let mut focused_application = None;
+        let idle = self.options.hold_when_away.then(Idle::watch);
+        while !self.shutdown.load(Ordering::Relaxed) {
+            if let Some(sleep) = sleep {
+                while let Some(event) = sleep.next() {
+                    match event {
+                        Sleep::Suspending => {
+                            self.prepare_for_suspend();
+                            sleep.ready();
+                        }
+                        Sleep::Resumed => return Ok(Stopped::WokeUp),
+                    }
+                }
+            }
+            // Dropped at the end of the iteration, after the state is unlocked
+            let mut stopped_recordings = Vec::new();
+            let input_requests =
+                std::mem::take(&mut self.jack_state.lock().unwrap().input_requests);
+            for request in input_requests {
+                if let Err(error) = self.apply_input_request(active_client.as_client(), request) {
+                    eprintln!("<3>{error:#}");
+                }
+            }
+            let requested_profile = self.jack_state.lock().unwrap().requested_profile.take();
+            if let Some(profile) = requested_profile {
+                if let Err(error) = self.switch_profile(active_client.as_client(), profile) {
+                    eprintln!("<3>Failed to switch profiles: {error:#}");
+                }
+            }
+            if let Some(sink) = default_sink.as_ref().and_then(DefaultSink::changed) {
+                self.move_output(
+                    active_client.as_client(),
+                    &output_ports,
+                    &mut sink_connections,
+                    sink,
+                );
+            }
+            if let Some(application) = focused_window.as_ref().and_then(FocusedWindow::changed) {
+                focused_application = application;
+            }
+            if self.options.fallback_output {
+                self.apply_fallback_output(
+                    active_client.as_client(),
+                    &output_ports,
+                    &mut unconnected,
+                );
+            }
+            let spectrum = {
+                let mut state = self.jack_state.lock().unwrap();
+                let missing = shared.missing_frames.load(Ordering::Relaxed);
+                let dropped = shared.dropped_frames.load(Ordering::Relaxed);
+                if missing > missing_frames || dropped > dropped_frames {
+                    state.timeline.push(Event::EngineBehind {
+                        missing_frames: missing - missing_frames,
+                        dropped_frames: dropped - dropped_frames,
+                    });
+                    (missing_frames, dropped_frames) = (missing, dropped);
+                }
+                if let Some(away) = idle.as_ref().and_then(Idle::changed) {
+                    if away != state.away {
+                        state.away = away;
+                        state.timeline.push(Event::Away { away });
+                    }
+                }
+                state.apply_auto_dnd();
+                // Also covers inputs added since, and the ones of a restarted engine
+                state.apply_do_not_disturb();
+                let JackState {
+                    inputs,
+                    timeline,
+                    sample_rate,
+                    net_sender,
+                    pause_calibration,
+                    memory_budget,
+                    memory_usage,
+                    kept_backlogs,
+                    ..
+                } = &mut *state;
+                for input in inputs.iter_mut() {
+                    if matches!(&input.boost, Some(boost) if boost.until <= Instant::now()) {
+                        input.boost = None;
+                        timeline.push(Event::BoostExpired {
+                            input: input.name.clone(),
+                        });
+                    }
+                    if let Some(duplicates) = input.duplicates.as_mut() {
+                        duplicates.suppress_duplicates(&input.name, &mut input.buffer, timeline);
+                    }
+                    if let Some(jingles) = input.jingles.as_mut() {
+                        jingles.skip_jingles(
+                            &input.name,
+                            &mut input.buffer,
+                            input.playback_position,
+                            *sample_rate,
+                            timeline,
+                        );
+                    }
+                    // After skipping, jingles don't need to be transcribed
+                    if let Some(transcriber) = input.transcriber.as_mut() {
+                        transcriber.transcribe(&input.name, &input.buffer, *sample_rate, timeline);
+                    }
+                    if let Some(filler) = input.filler.as_mut() {
+                        filler.drop_filler(&input.name, &mut input.buffer, *sample_rate, timeline);
+                    }
+                    match &mut input.source {
+                        Source::Network(receiver) => {
+                            for text in receiver.events() {
+                                timeline.push(Event::Remote {
+                                    input: input.name.clone(),
+                                    text,
+                                });
+                            }
+                        }
+                        Source::File(player) => {
+                            if let Some(file) = player.maintain() {
+                                timeline.push(Event::FileStarted {
+                                    input: input.name.clone(),
+                                    file: file.display().to_string(),
+                                });
+                            }
+                        }
+                        Source::Ports(_) | Source::Generator(_) => {}
+                    }
+                }
+
+                for entry in timeline.since(printed_timeline) {
+                    if let Some(sender) = net_sender {
+                        sender.send_event(entry.event.to_string());
+                    }
+                    if self.options.headless {
+                        eprintln!("<6>{}", entry.event);
+                    } else {
+                        println!("{}", entry.event);
+                    }
+                    if let Some(writer) = journal.as_mut() {
+                        if let Err(error) = writer.append(entry) {
+                            eprintln!(
+                                "<3>Failed to write to {}, journal disabled: {error:#}",
+                                writer.path().display()
+                            );
+                            journal = None;
+                        }
+                    }
+                }
+                printed_timeline = timeline.next_sequence();
+
+                memory::read_back_spilled(inputs, *sample_rate);
+                *memory_usage = Accounting::measure(inputs, kept_backlogs);
+                if let Some(budget) = memory_budget.as_mut() {
+                    budget.relieve(memory_usage, inputs, *sample_rate, timeline);
+                }
+                // Sources paused for the memory stay paused, whatever their backlog
+                let memory_pressure = memory_budget
+                    .as_ref()
+                    .is_some_and(MemoryBudget::keeps_sources_paused);
+
+                let calibrating = pause_calibration
+                    .as_ref()
+                    .map(|calibration| calibration.input.as_str());
+                for input in inputs.iter_mut() {
+                    // Disabled inputs keep their source as it is until enabled again, calibrated
+                    // ones as the calibration needs it
+                    if input.disabled || calibrating == Some(input.name.as_str()) {
+                        continue;
+                    }
+                    // Paused right away rather than once the backlog grows, and kept paused
+                    if input.deferred {
+                        if let Some(pausing) = input.pausing.as_mut() {
+                            match pausing.pause() {
+                                Ok(true) => timeline.push(Event::Paused {
+                                    input: input.name.clone(),
+                                }),
+                                Ok(false) => {}
+                                Err(error) => eprintln!("<4>{}: {error:#}", input.name),
+                            }
+                        }
+                        continue;
+                    }
+                    let mut buffered_samples = input.buffered_samples();
+                    let resuming = input.pausing.as_ref().is_some_and(|pausing| {
+                        pausing.source_paused
+                            && buffered_samples < pausing.resume_at()
+                            && !memory_pressure
+                    });
+                    // The source resumes once the lead-in played, like after any other backlog
+                    if resuming {
+                        if let Some(frames) = input.replay_lead_in(*sample_rate) {
+                            timeline.push(Event::LeadIn {
+                                input: input.name.clone(),
+                                seconds: frames as f32 / (*sample_rate).max(1) as f32,
+                            });
+                            buffered_samples = input.buffered_samples();
+                        }
+                    }
+                    if let Some(pausing) = input.pausing.as_mut() {
+                        // Resumed by hand, paused again like any source if the backlog is long
+                        if pausing.resumed_elsewhere() {
+                            pausing.source_paused = false;
+                            timeline.push(Event::Resumed {
+                                input: input.name.clone(),
+                            });
+                        }
+                        let change = if buffered_samples < pausing.resume_at() && !memory_pressure {
+                            pausing.resume().map(|resumed| {
+                                resumed.then(|| Event::Resumed {
+                                    input: input.name.clone(),
+                                })
+                            })
+                        } else if buffered_samples > pausing.pause_at() {
+                            pausing.pause().map(|paused| {
+                                paused.then(|| Event::Paused {
+                                    input: input.name.clone(),
+                                })
+                            })
+                        } else {
+                            Ok(None)
+                        };
+                        match change {
+                            Ok(Some(event)) => timeline.push(event),
+                            Ok(None) => {}
+                            Err(error) => eprintln!("<4>{}: {error:#}", input.name),
+                        }
+                    }
+                }
+                // Sources are also paused by commands, so compare instead of tracking changes
+                let paused_sources: Vec<String> = inputs
+                    .iter()
+                    .filter_map(|input| input.pausing.as_ref())
+                    .filter(|pausing| pausing.source_paused)
+                    .map(AutoPausing::janitor_record)
+                    .collect();
+                if paused_sources != recorded_paused {
+                    match janitor::record_paused(&self.paused_record, &paused_sources) {
+                        Ok(()) => recorded_paused = paused_sources,
+                        Err(error) => eprintln!("<4>Failed to record paused sources: {error:#}"),
+                    }
+                }
+
+                state.auto_arm(active_client.as_client());
+                if let Some(application) = &focused_application {
+                    state.boost_focused(active_client.as_client(), application);
+                }
+                state.attach_ports();
+                state.apply_schedule(&mut stopped_recordings);
+                state.finish_calibration(self.options.config_path.as_deref());
+                state.step_pause_calibration(self.options.config_path.as_deref());
+                for input in state.inputs.iter_mut() {
+                    input.detect_layout(active_client.as_client());
+                }
+
+                let elapsed = last_reading.elapsed();
+                last_reading = Instant::now();
+                let sample_rate = state.sample_rate.max(1) as f64;
+                let speed_trim = state.speed_trim;
+                for input in state.inputs.iter_mut() {
+                    let backlog = input.buffered_samples() as f64 / sample_rate;
+                    let speed = input.tempo(speed_trim) * input.rate;
+                    // Played at its speed within the interval, inputs mixing in play at natural
+                    // speed and save no time
+                    let played = input
+                        .last_played
+                        .is_some_and(|played| played.elapsed() <= elapsed);
+                    input.stats.record(elapsed, backlog, speed, played);
+                    if backlog >= catch_up::MIN_BACKLOG_SECONDS {
+                        input.behind = true;
+                    }
+                }
+
+                let reading = state.meter.take();
+                ballistics.update(&reading, &state.meter_settings, elapsed);
+                if !self.options.headless {
+                    print_status(&state, &ballistics, &reading);
+                }
+                state.output_reading = Some(reading);
+                if let Some(segment) = status_segment.as_mut() {
+                    if let Err(error) = segment.publish(&StateFrame::capture(&state)) {
+                        eprintln!("<4>Failed to publish the state: {error:#}");
+                        status_segment = None;
+                    }
+                }
+
+                match &state.spectrum {
+                    Some(tap) if !self.options.headless => Some((
+                        tap.source.clone(),
+                        tap.spectrogram,
+                        tap.snapshot(),
+                        state.sample_rate,
+                    )),
+                    _ => None,
+                }
+            };
+
+            match spectrum {
+                Some((source, spectrogram, samples, sample_rate)) => {
+                    analyzer.analyze(&samples, sample_rate);
+                    if spectrogram {
+                        for row in analyzer.spectrogram() {
+                            println!("|{row}|");
+                        }
+                    }
+                    println!("Spectrum ({source}): |{}|", analyzer.spectrum());
+                }
+                None => analyzer.clear(),
+            }
+            std::thread::sleep(std::time::Duration::from_millis(100));
+        }
+        Ok(Stopped::Shutdown)
+    }
+
+    /// Holds every input and pauses their sources right away, the system is about to suspend
+    fn prepare_for_suspend(&self) {
+        let mut state = self.jack_state.lock().unwrap();
+        let state = &mut *state;
+        state.suspending = true;
+        state.apply_do_not_disturb();
+        for input in state.inputs.iter_mut() {
+            let Some(pausing) = input.pausing.as_mut() else {
+                continue;
+            };
+            match pausing.pause() {
+                Ok(true) => state.timeline.push(Event::Paused {
+                    input: input.name.clone(),
+                }),
+                Ok(false) => {}
+                Err(error) => eprintln!("<4>{}: {error:#}", input.name),
+            }
+        }
+        state.timeline.push(Event::Suspending);
+    }
+
+    /// Switches to the settings of a profile, `None` for the general ones. Only the inputs and
+    /// connections that differ are changed, the others keep playing. Ports are registered and
+    /// connected without the state locked, as that waits for the server.
+    fn switch_profile(&self, client: &Client, profile: Option<String>) -> anyhow::Result<()> {
+        let previous = {
+            let state = self.jack_state.lock().unwrap();
+            self.config.with_profile(state.profile.as_deref())?
+        };
+        let config = self.config.with_profile(profile.as_deref())?;
+        let port_count = |input: &InputConfig| input.channels.unwrap_or(config.channels);
+
+        // Gone in the new profile, or back with another number of ports
+        self.remove_inputs(client, |input| match &input.source {
+            Source::Ports(ports) => config
+                .inputs
+                .iter()
+                .any(|wanted| wanted.name == input.name && port_count(wanted) == ports.len()),
+            Source::Generator(_) | Source::Network(_) | Source::File(_) => true,
+        })?;
+
+        let existing: Vec<String> = {
+            let state = self.jack_state.lock().unwrap();
+            state
+                .inputs
+                .iter()
+                .map(|input| input.name.clone())
+                .collect()
+        };
+        let added: Vec<Input> = config
+            .inputs
+            .iter()
+            .filter(|wanted| !existing.contains(&wanted.name))
+            .map(|wanted| self.create_input(client, wanted, &config))
+            .collect();
+
+        let (disconnected, connected) = {
+            let mut state = self.jack_state.lock().unwrap();
+            let state = &mut *state;
+            let sample_rate = state.sample_rate;
+            for input in state.inputs.iter_mut() {
+                let Some(wanted) = config
+                    .inputs
+                    .iter()
+                    .find(|wanted| wanted.name == input.name)
+                else {
+                    continue;
+                };
+                let had = previous.inputs.iter().find(|had| had.name == input.name);
+                if had.map(|had| had.gain) != Some(wanted.gain) {
+                    input.gain = db_to_factor(wanted.gain);
+                }
+                if had.map(|had| had.priority) != Some(wanted.priority) {
+                    input.priority = wanted.priority;
+                }
+                input.configure_silence(wanted, sample_rate);
+                input.window = wanted.window.clone();
+                if had.and_then(|had| had.tempo.as_ref()) != wanted.tempo.as_ref() {
+                    input.adaptive_tempo = wanted.tempo.as_ref().map(Input::from_config_tempo);
+                }
+                if had.and_then(|had| had.drop_filler.as_ref()) != wanted.drop_filler.as_ref() {
+                    input.filler = wanted
+                        .drop_filler
+                        .as_ref()
+                        .map(|filler| Input::from_config_filler(filler, sample_rate));
+                }
+                if had.and_then(|had| had.suppress_duplicates.as_ref())
+                    != wanted.suppress_duplicates.as_ref()
+                {
+                    input.duplicates = wanted
+                        .suppress_duplicates
+                        .as_ref()
+                        .map(|duplicates| Input::from_config_duplicates(duplicates, sample_rate));
+                }
+                if had.and_then(|had| had.pausing.as_ref()) == wanted.pausing.as_ref() {
+                    continue;
+                }
+                // Resumed with the commands it was paused with, paused again by the new ones if
+                // the backlog is still long
+                match input
+                    .pausing
+                    .as_mut()
+                    .map_or(Ok(false), AutoPausing::resume)
+                {
+                    Ok(true) => state.timeline.push(Event::Resumed {
+                        input: input.name.clone(),
+                    }),
+                    Ok(false) => {}
+                    Err(error) => {
+                        // Keeps the old pausing, which resumes the source once it can
+                        eprintln!("<4>{}: {error:#}", input.name);
+                        continue;
+                    }
+                }
+                input.pausing = Input::from_config_pausing(wanted, sample_rate);
+            }
+            state.insert_port_inputs(added);
+            // The profile decides about all inputs with ports, added ones included
+            state.added_inputs.clear();
+            for index in 0..state.inputs.len() {
+                state.inputs[index].silence.threshold =
+                    state.silence_threshold(&config, &state.inputs[index].name);
+                state.apply_calibrated_latency(index);
+            }
+            if config.engine != previous.engine || config.soundtouch != previous.soundtouch {
+                state.set_engine(config.engine, config.soundtouch);
+            }
+            state.scheduling = config.scheduling;
+            state.crossfade = Duration::from_secs_f64(config.crossfade);
+            state.fade = None;
+
+            let before = config_connections(&previous, &state.inputs);
+            let after = config_connections(&config, &state.inputs);
+            state.profile = profile.clone();
+            state.timeline.push(Event::ProfileSwitched {
+                profile: profile.unwrap_or_else(|| "general".to_string()),
+            });
+            (
+                before
+                    .iter()
+                    .filter(|pair| !after.contains(pair))
+                    .cloned()
+                    .collect::<Vec<_>>(),
+                after
+                    .iter()
+                    .filter(|pair| !before.contains(pair))
+                    .cloned()
+                    .collect::<Vec<_>>(),
+            )
+        };
+        for (source, destination) in disconnected {
+            // Fails when either is gone already, which is fine
+            let _ = client.disconnect_ports_by_name(&source, &destination);
+        }
+        connect_ports(client, &connected);
+        Ok(())
+    }
+
+    /// Adds or removes an input while the engine runs, the others keep playing
+    fn apply_input_request(&self, client: &Client, request: InputRequest) -> anyhow::Result<()> {
+        match request {
+            InputRequest::Add(mut wanted) => {
+                wanted.apply_preset();
+                let config = {
+                    let state = self.jack_state.lock().unwrap();
+                    if state.inputs.iter().any(|input| input.name == wanted.name) {
+                        anyhow::bail!("There already is an input {}", wanted.name);
+                    }
+                    self.config.with_profile(state.profile.as_deref())?
+                };
+                let input = self.create_input(client, &wanted, &config);
+                let connections = wanted.connections(&input.port_names());
+                {
+                    let mut state = self.jack_state.lock().unwrap();
+                    state.timeline.push(Event::InputAdded {
+                        input: wanted.name.clone(),
+                        channels: input.port_names().len(),
+                    });
+                    state.insert_port_inputs(vec![input]);
+                    state.added_inputs.push(*wanted);
+                }
+                connect_ports(client, &connections);
+            }
+            InputRequest::Remove(name) => {
+                let removed = self.remove_inputs(client, |input| {
+                    input.name != name || !matches!(input.source, Source::Ports(_))
+                })?;
+                if removed.is_empty() {
+                    anyhow::bail!("No input {name} with ports to remove");
+                }
+                let mut state = self.jack_state.lock().unwrap();
+                state.added_inputs.retain(|added| added.name != name);
+                state.timeline.push(Event::InputRemoved { input: name });
+            }
+        }
+        Ok(())
+    }
+
+    /// Creates an input with ports, set up like those created at startup
+    fn create_input(&self, client: &Client, wanted: &InputConfig, config: &Config) -> Input {
+        let mut input = Input::from_config(client, wanted, config.channels, client.sample_rate());
+        input.buffer.block_frames = self.options.block_frames;
+        input.silence.threshold = wanted.threshold().unwrap_or(config.silence_threshold);
+        input
+    }
+
+    /// Takes the inputs `keep` rejects out of the engine, resumes their paused sources and
+    /// unregisters their ports. Returns the names of the removed inputs.
+    fn remove_inputs(
+        &self,
+        client: &Client,
+        keep: impl FnMut(&Input) -> bool,
+    ) -> anyhow::Result<Vec<String>> {
+        let mut ports = Vec::new();
+        // Ports the process callback still has to give up
+        let mut awaited = 0;
+        let mut removed: Vec<Input> = {
+            let mut state = self.jack_state.lock().unwrap();
+            let state = &mut *state;
+            let (kept, mut removed): (Vec<Input>, Vec<Input>) = std::mem::take(&mut state.inputs)
+                .into_iter()
+                .partition(keep);
+            state.inputs = kept;
+            for input in removed.iter_mut() {
+                let Source::Ports(source) = &mut input.source else {
+                    continue;
+                };
+                match source.take_ports() {
+                    Some(pending) => ports.extend(pending),
+                    None => {
+                        if state
+                            .realtime
+                            .as_mut()
+                            .is_some_and(|link| link.detach(source))
+                        {
+                            awaited += source.len();
+                        }
+                    }
+                }
+            }
+            removed
+        };
+        let deadline = Instant::now() + RETIRE_TIMEOUT;
+        while awaited > 0 && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(5));
+            let mut state = self.jack_state.lock().unwrap();
+            if let Some(link) = state.realtime.as_mut() {
+                let retired = link.retired();
+                awaited = awaited.saturating_sub(retired.len());
+                ports.extend(retired);
+            }
+        }
+        for port in ports {
+            if let Err(error) = client.unregister_port(port) {
+                eprintln!("<4>Failed to unregister a port: {error}");
+            }
+        }
+
+        let mut names = Vec::new();
+        for input in removed.iter_mut() {
+            if let Some(pausing) = input.pausing.as_mut() {
+                pausing.resume()?;
+            }
+            names.push(std::mem::take(&mut input.name));
+        }
+        Ok(names)
+    }
+
+    /// Plays the output through the sound server once its ports were unconnected for a while,
+    /// and stops when they are connected again
+    fn apply_fallback_output(
+        &self,
+        client: &Client,
+        output_ports: &[String],
+        unconnected: &mut fallback_output::Unconnected,
+    ) {
+        let connected = output_ports.iter().any(|name| {
+            client
+                .port_by_name(name)
+                .and_then(|port| port.connected_count().ok())
+                .unwrap_or(0)
+                > 0
+        });
+        if connected {
+            unconnected.reset();
+            // Dropped with the state unlocked, stopping waits for pacat
+            let stopped = {
+                let mut state = self.jack_state.lock().unwrap();
+                let stopped = state.fallback_output.take();
+                if stopped.is_some() {
+                    state
+                        .timeline
+                        .push(Event::FallbackOutput { playing: false });
+                }
+                stopped
+            };
+            drop(stopped);
+            return;
+        }
+        if !unconnected.is_due() {
+            return;
+        }
+        let (channel_count, sample_rate) = {
+            let state = self.jack_state.lock().unwrap();
+            (state.output.len(), state.sample_rate)
+        };
+        match FallbackOutput::start(&self.config.client_name, channel_count, sample_rate) {
+            Ok(fallback) => {
+                let mut state = self.jack_state.lock().unwrap();
+                state.fallback_output = Some(fallback);
+                state.timeline.push(Event::FallbackOutput { playing: true });
+            }
+            Err(error) => eprintln!("<3>Failed to start the fallback output: {error:#}"),
+        }
+    }
+
+    /// Moves the output from the sink ports it was connected to onto those of `sink`. Connecting
+    /// waits for the server, so this runs without the state locked.
+    fn move_output(
+        &self,
+        client: &Client,
+        output_ports: &[String],
+        connections: &mut Vec<(String, String)>,
+        sink: Sink,
+    ) {
+        let sink_ports = default_sink::playback_ports(client, &sink);
+        if sink_ports.is_empty() {
+            eprintln!(
+                "<4>Default sink {} has no JACK ports, the output stays where it is",
+                sink.description
+            );
+            return;
+        }
+        for (output, sink_port) in connections.drain(..) {
+            // Fails when the port left with its device, which is fine
+            let _ = client.disconnect_ports_by_name(&output, &sink_port);
+        }
+        for (index, output) in output_ports.iter().enumerate() {
+            // A mono sink gets all channels, a surround one the front
+            let sink_port = &sink_ports[index.min(sink_ports.len() - 1)];
+            match client.connect_ports_by_name(output, sink_port) {
+                Ok(()) => connections.push((output.clone(), sink_port.clone())),
+                Err(error) => eprintln!("<4>Failed to connect {output} to {sink_port}: {error}"),
+            }
+        }
+
+        let latency = default_sink::playback_latency(client, &sink_ports);
+        let mut state = self.jack_state.lock().unwrap();
+        state.output_latency = latency;
+        state.output_sink = Some(sink.description.clone());
+        state.timeline.push(Event::OutputMoved {
+            sink: sink.description,
+            latency: latency.as_secs_f32(),
+        });
+    }
+}
+
+/// Spreads the channels of an input with fewer channels than the output over the output's, the
+/// last one repeated, and drops those an input has beyond the output's
+fn fit_channels(mut period: Vec<Vec<f32>>, channel_count: usize) -> Vec<Vec<f32>> {
+    period.truncate(channel_count);
+    while let Some(last) = period.last().filter(|_| period.len() < channel_count) {
+        period.push(last.clone());
+    }
+    period
+}
+
+/// Connections the config asks for between other clients' ports and those of the inputs
+fn config_connections(config: &Config, inputs: &[Input]) -> Vec<(String, String)> {
+    config
+        .inputs
+        .iter()
+        .filter_map(|wanted| {
+            let input = inputs.iter().find(|input| input.name == wanted.name)?;
+            Some(wanted.connections(&input.port_names()))
+        })
+        .flatten()
+        .collect()
+}
+
+fn connect_ports(client: &Client, connections: &[(String, String)]) {
+    for (source, destination) in connections {
+        if let Err(error) = client.connect_ports_by_name(source, destination) {
+            eprintln!("<4>Failed to connect {source} to {destination}: {error}");
+        }
+    }
+}
+
+/// Captures the inputs and plays the most urgent of them for one JACK period of `frame_size`
+/// frames, into the state's output
+fn process_cycle(state: &mut JackState, frame_size: usize, current_input: &mut Option<usize>) {
+    let sample_rate = state.sample_rate;
+    let speed_trim = state.speed_trim;
+    let bypass_all = state.bypass_all;
+    let channel_count = state.output.len();
+    for channel in state.output.iter_mut() {
+        channel.clear();
+        channel.resize(frame_size, 0.0);
+    }
+
+    let gated = state
+        .inputs
+        .iter()
+        .any(|input| input.transport_gate.is_some());
+    let mut rolling = false;
+    if let Some(link) = &state.realtime {
+        link.shared.transport_gated.store(gated, Ordering::Relaxed);
+        rolling = gated && link.shared.transport_rolling.load(Ordering::Relaxed);
+    }
+    if gated && state.transport_rolling != Some(rolling) {
+        state.transport_rolling = Some(rolling);
+        state.timeline.push(Event::Transport { rolling });
+    } else if !gated {
+        state.transport_rolling = None;
+    }
+
+    for (index, input) in state.inputs.iter_mut().enumerate() {
+        if input.disabled {
+            input.skip_period(frame_size);
+            continue;
+        }
+        if let Some(tempo) = input.adaptive_tempo.as_mut() {
+            let backlog = input.buffer.sample_frames() as f64 / sample_rate as f64;
+            tempo.update(backlog, frame_size as f64 / sample_rate as f64);
+        }
+        *current_input = Some(index);
+        let mut period = input
+            .read_period(frame_size)
+            .map(|period| fit_channels(period, channel_count));
+        let mut captured_at = SystemTime::now();
+        if let (Some(logger), Some(period)) = (input.logger.as_mut(), &period) {
+            logger.write(period);
+        }
+        let scheduled_recorder = input
+            .scheduled_recording
+            .as_mut()
+            .and_then(|recording| recording.recorder.as_mut());
+        if let (Some(recorder), Some(period)) = (scheduled_recorder, &period) {
+            recorder.write(period);
+        }
+        if let Some(timeshift) = input.timeshift.as_mut() {
+            captured_at = timeshift.position();
+            period = timeshift.read(frame_size);
+        }
+        if input
+            .transport_gate
+            .is_some_and(|gate| !gate.is_open(rolling))
+        {
+            continue;
+        }
+        if let Some(mut period) = period {
+            let gain = if input.muted { 0.0 } else { input.gain };
+            if gain != 1.0 {
+                for channel in period.iter_mut() {
+                    channel.iter_mut().for_each(|sample| *sample *= gain);
+                }
+            }
+            input.chain.process_capture(&mut period, bypass_all);
+            if let Some(tap) = state.spectrum.as_mut() {
+                if matches!(&tap.source, SpectrumSource::Input(name) if *name == input.name) {
+                    let channels: Vec<&[f32]> = period.iter().map(Vec::as_slice).collect();
+                    tap.push(&channels);
+                }
+            }
+            if let Some(calibration) = state.calibration.as_mut() {
+                if calibration.input == input.name {
+                    calibration.push(&period);
+                }
+            }
+            if let Some(calibration) = state.pause_calibration.as_mut() {
+                if calibration.input == input.name {
+                    calibration.push(&period);
+                }
+            }
+            input.capture(period, captured_at, sample_rate);
+        }
+    }
+
+    let mut written_samples = 0;
+    // Inputs that played something this period
+    let mut played_inputs = Vec::new();
+    while written_samples < frame_size {
+        if state.gap_frames > 0 {
+            let silent_frames = state.gap_frames.min(frame_size - written_samples);
+            state.output.iter_mut().for_each(|channel| {
+                channel[written_samples..written_samples + silent_frames].fill(0.0)
+            });
+            state.gap_frames -= silent_frames;
+            written_samples += silent_frames;
+            continue;
+        }
+        if let Some(focus) = &state.focus {
+            let playable = state
+                .inputs
+                .iter()
+                .any(|input| input.name == *focus && input.is_playable());
+            if !playable {
+                state.timeline.push(Event::Focused {
+                    input: focus.clone(),
+                    focused: false,
+                });
+                state.focus = None;
+            }
+        }
+
+        let input = match next_input(state.scheduling, &state.inputs, state.focus.as_deref()) {
+            Some(index) => {
+                let index = state.defer_preemption(index);
+                *current_input = Some(index);
+                &mut state.inputs[index]
+            }
+            None => {
+                state
+                    .output
+                    .iter_mut()
+                    .for_each(|channel| channel[written_samples..].fill(0.0));
+                // The input switched away from fades out into the silence
+                if let Some(fade) = state.fade.as_mut() {
+                    if fade.apply(&mut state.output, written_samples, frame_size) {
+                        state.fade = None;
+                    }
+                }
+                break;
+            }
+        };
+        let segment_start = written_samples;
+
+        // Announced ahead of its audio when it plays again after a gap
+        let switching = state.playing.as_ref() != Some(&input.name)
+            && matches!(input.buffer.front(), Some(BufferItem::Samples(..)));
+        if switching {
+            if let Some(cue) = input
+                .cue
+                .as_mut()
+                .and_then(|cue| cue.due(input.last_played))
+            {
+                input
+                    .buffer
+                    .push_front(BufferItem::Samples(cue, SystemTime::now()));
+                state.timeline.push(Event::Cued {
+                    input: input.name.clone(),
+                });
+            }
+        }
+
+        // Enough for the rest of the period at the input's speed
+        let wanted_frames =
+            ((frame_size - written_samples) as f64 * input.tempo(speed_trim) * input.rate).ceil()
+                as usize;
+        let buffer_item = input
+            .buffer
+            .pop_coalesced(wanted_frames, sample_rate)
+            .unwrap();
+        let mut caught_up = false;
+        let mut switched_from = None;
+        match buffer_item {
+            BufferItem::Samples(samples, captured_at) => {
+                if let Some(index) = *current_input {
+                    if !played_inputs.contains(&index) {
+                        played_inputs.push(index);
+                    }
+                }
+                if state.playing.as_ref() != Some(&input.name) {
+                    switched_from = state.playing.replace(input.name.clone());
+                    state.timeline.push(Event::Switched {
+                        input: input.name.clone(),
+                    });
+                }
+                input.playback_position = Some(captured_at);
+                let tempo = input.tempo(speed_trim);
+                let rate = input.rate;
+                let channels = state.output.len();
+                let frame_count = samples[0].len();
+                let interleaved: Vec<f32> = interleave_all(&samples).copied().collect();
+
+                let stretcher = input.stretcher.get_or_insert_with(|| {
+                    state
+                        .engine
+                        .create(channels, sample_rate, &state.soundtouch_settings)
+                });
+                stretcher.set_tempo(tempo);
+                stretcher.set_rate(rate);
+                stretcher.set_pitch_semitones(input.pitch);
+                stretcher.put_samples(&interleaved, frame_count);
+
+                written_samples += play_stretched(
+                    stretcher.as_mut(),
+                    &mut input.chain,
+                    &mut state.output,
+                    written_samples,
+                    frame_size - written_samples,
+                    bypass_all,
+                );
+                input.retain_played(samples, captured_at, sample_rate);
+                caught_up = input.behind && input.buffered_samples() == 0;
+            }
+            BufferItem::Silence(mut sample_count) => {
+                // The end of what was said before is still in the pipeline, it plays in place of
+                // the start of the silence
+                if let Some(stretcher) = input
+                    .stretcher
+                    .as_mut()
+                    .filter(|stretcher| stretcher.pending_frames() > 0)
+                {
+                    stretcher.flush();
+                    let received_frames = play_stretched(
+                        stretcher.as_mut(),
+                        &mut input.chain,
+                        &mut state.output,
+                        written_samples,
+                        (frame_size - written_samples).min(sample_count),
+                        bypass_all,
+                    );
+                    written_samples += received_frames;
+                    sample_count -= received_frames;
+                }
+                // Play stored silence to keep the pacing natural
+                let silent_frames = sample_count.min(frame_size - written_samples);
+                if sample_count > silent_frames {
+                    input
+                        .buffer
+                        .push_front(BufferItem::Silence(sample_count - silent_frames));
+                }
+                state.output.iter_mut().for_each(|channel| {
+                    channel[written_samples..written_samples + silent_frames].fill(0.0)
+                });
+                written_samples += silent_frames;
+            }
+        }
+        // What is left in the pipeline of the input switched away from would play out of
+        // place once it plays again. The start of it fades out under the new input.
+        if let Some(previous) = switched_from {
+            let fade_frames = (state.crossfade.as_secs_f64() * sample_rate as f64) as usize;
+            let mut tail = Vec::new();
+            if let Some(previous) = state.inputs.iter_mut().find(|input| input.name == previous) {
+                if let Some(stretcher) = previous.stretcher.as_mut() {
+                    if fade_frames > 0 {
+                        stretcher.flush();
+                        tail = receive_stretched(
+                            stretcher.as_mut(),
+                            &mut previous.chain,
+                            state.output.len(),
+                            fade_frames,
+                            bypass_all,
+                        );
+                    }
+                    stretcher.clear();
+                }
+            }
+            state.fade = (fade_frames > 0).then(|| Crossfade::new(tail, fade_frames));
+        }
+        if let Some(fade) = state.fade.as_mut() {
+            if fade.apply(&mut state.output, segment_start, written_samples) {
+                state.fade = None;
+            }
+        }
+        if caught_up {
+            if let Some(index) = *current_input {
+                state.caught_up(index);
+            }
+        }
+    }
+
+    // In mixing mode the others play even while the scheduled input plays stored silence
+    if state.mixing || (!state.overlap.is_empty() && !played_inputs.is_empty()) {
+        mix_overlapping(state, frame_size, played_inputs, current_input);
+    }
+
+    *current_input = None;
+
+    if !state.bus.is_empty() {
+        state.bus.process_playback(&mut state.output, bypass_all);
+    }
+    let channels: Vec<&[f32]> = state.output.iter().map(Vec::as_slice).collect();
+    state.meter.measure(&channels, sample_rate);
+    if let Some(tap) = state.spectrum.as_mut() {
+        if tap.source == SpectrumSource::Output {
+            tap.push(&channels);
+        }
+    }
+    if let Some(sender) = state.net_sender.as_mut() {
+        sender.write(&channels);
+    }
+    if let Some(fallback) = state.fallback_output.as_mut() {
+        fallback.write(&channels);
+    }
+}
+
+/// Plays what the stretcher has ready through the input's effects into `output` from `offset` on,
+/// up to `max_frames` frames. Returns the number of frames played.
+fn play_stretched(
+    stretcher: &mut dyn TimeStretch,
+    chain: &mut Chain,
+    output: &mut [Vec<f32>],
+    offset: usize,
+    max_frames: usize,
+    bypass_all: bool,
+) -> usize {
+    let played = receive_stretched(stretcher, chain, output.len(), max_frames, bypass_all);
+    let received_frames = played[0].len();
+    for (channel, samples) in output.iter_mut().zip(&played) {
+        channel[offset..offset + received_frames].copy_from_slice(samples);
+    }
+    received_frames
+}
+
+/// Up to `max_frames` frames the stretcher has ready, through the input's effects, per channel
+fn receive_stretched(
+    stretcher: &mut dyn TimeStretch,
+    chain: &mut Chain,
+    channels: usize,
+    max_frames: usize,
+    bypass_all: bool,
+) -> Vec<Vec<f32>> {
+    let mut mixed_samples = vec![0.0; max_frames * channels];
+    let received_frames = stretcher.receive_samples(&mut mixed_samples, max_frames);
+
+    let mut played: Vec<Vec<f32>> = (0..channels)
+        .map(|index| {
+            (0..received_frames)
+                .map(|frame| mixed_samples[frame * channels + index])
+                .collect()
+        })
+        .collect();
+    chain.process_playback(&mut played, bypass_all);
+    played
+}
+
+/// Adds the inputs mixing with everything played this period on top of it, at natural speed and
+/// most urgent first. In mixing mode every input mixes and they are ducked by priority.
+fn mix_overlapping(
+    state: &mut JackState,
+    frame_size: usize,
+    mut played_inputs: Vec<usize>,
+    current_input: &mut Option<usize>,
+) {
+    let channel_count = state.output.len();
+    let scheduled = played_inputs.clone();
+    let mut candidates: Vec<usize> = (0..state.inputs.len())
+        .filter(|index| !played_inputs.contains(index))
+        .collect();
+    candidates.sort_by(|&a, &b| state.inputs[b].cmp_precedence(&state.inputs[a]));
+    let mut mixed = Vec::new();
+    for index in candidates {
+        let input = &state.inputs[index];
+        let mixes = input.is_playable()
+            && state.focus.is_none()
+            && (state.mixing
+                || played_inputs.iter().all(|&played| {
+                    state.overlap.get(&state.inputs[played].name, &input.name) == Overlap::Mix
+                }));
+        if !mixes {
+            continue;
+        }
+        *current_input = Some(index);
+        let input = &mut state.inputs[index];
+        let mut period = input.take_frames(frame_size, channel_count, state.sample_rate);
+        input.chain.process_playback(&mut period, state.bypass_all);
+        if state.mixing {
+            mixed.push((index, period));
+        } else {
+            add_period(&mut state.output, &period);
+        }
+        played_inputs.push(index);
+    }
+    if state.mixing {
+        mix_ducked(state, &scheduled, mixed);
+    }
+}
+
+/// Mixes the periods of the inputs into the output, where the inputs scheduled this period
+/// played already. Each part is ducked while a part of a higher priority tier has sound.
+fn mix_ducked(state: &mut JackState, scheduled: &[usize], mixed: Vec<(usize, Vec<Vec<f32>>)>) {
+    // The scheduled inputs are one part, ducked as the one played last
+    let mut parts = mixed;
+    if let Some(&last) = scheduled.last() {
+        let output = state.output.clone();
+        state
+            .output
+            .iter_mut()
+            .for_each(|channel| channel.fill(0.0));
+        parts.push((last, output));
+    }
+    let priorities: Vec<i32> = parts
+        .iter()
+        .map(|(index, _)| {
+            if scheduled.contains(index) {
+                scheduled
+                    .iter()
+                    .map(|&played| state.inputs[played].priority)
+                    .max()
+                    .unwrap_or_default()
+            } else {
+                state.inputs[*index].priority
+            }
+        })
+        .collect();
+    let mut ducked = Vec::with_capacity(parts.len());
+    for (part, (index, period)) in parts.iter().enumerate() {
+        let keys: Vec<&[Vec<f32>]> = parts
+            .iter()
+            .zip(&priorities)
+            .filter(|(_, &other)| other > priorities[part])
+            .map(|((_, key), _)| key.as_slice())
+            .collect();
+        let mut period = period.clone();
+        state.inputs[*index]
+            .ducker
+            .apply(&state.ducking, &keys, &mut period, state.sample_rate);
+        ducked.push(period);
+    }
+    for period in &ducked {
+        add_period(&mut state.output, period);
+    }
+}
+
+fn add_period(output: &mut [Vec<f32>], period: &[Vec<f32>]) {
+    for (channel, samples) in output.iter_mut().zip(period) {
+        for (output, sample) in channel.iter_mut().zip(samples) {
+            *output += sample;
+        }
+    }
+}
+
+/// Index of the playable input to play next as the scheduling policy picks it, only the focused
+/// one if there is a focus
+pub fn next_input(scheduling: Scheduling, inputs: &[Input], focus: Option<&str>) -> Option<usize> {
+    match focus {
+        Some(focus) => inputs
+            .iter()
+            .position(|input| input.name == focus && input.is_playable()),
+        None => scheduling.policy().select(inputs),
+    }
+}
+
+fn print_status(state: &JackState, ballistics: &Ballistics, reading: &Reading) {
+    let speed_trim = state.speed_trim;
+    println!();
+    if speed_trim != 1.0 {
+        println!("Speed trim: {speed_trim:.2}x");
+    }
+    if state.bypass_all {
+        println!("All processing bypassed");
+    }
+    if !state.bus.is_empty() {
+        println!("Output bus: {}", state.bus);
+        let costs: Vec<String> = state
+            .bus
+            .costs()
+            .map(|(effect, cost)| format!("{effect} {:.0}µs", cost.as_secs_f64() * 1e6))
+            .collect();
+        println!("Output bus timing: {}", costs.join(", "));
+    }
+    if let Some(profile) = &state.profile {
+        println!("Profile: {profile}");
+    }
+    if state.scheduling != Scheduling::default() {
+        println!("Scheduling: {}", state.scheduling);
+    }
+    if state.mixing {
+        println!(
+            "Mixing, ducking by {:.1} dB",
+            meter::to_db(state.ducking.depth)
+        );
+    }
+    if let Some(hurry_up) = &state.hurry_up {
+        println!("Hurry-up clock at {:.0} BPM", hurry_up.bpm);
+    }
+    println!("Memory: {}", state.memory_usage);
+    if let Some(budget) = &state.memory_budget {
+        let pressure = if budget.under_pressure() {
+            ", under pressure"
+        } else {
+            ""
+        };
+        println!(
+            "Memory budget: {}{pressure}",
+            capacity::format_bytes(budget.budget())
+        );
+    }
+    if state.do_not_disturb {
+        println!("Do not disturb");
+    }
+    if state.away {
+        println!("Away, holding all inputs");
+    }
+    if let Some(auto_dnd) = &state.auto_dnd {
+        println!(
+            "Auto do-not-disturb: {}, ends {:.0}s after the call",
+            auto_dnd.source,
+            auto_dnd.hang_time.as_secs_f32()
+        );
+    }
+    if let Some(sink) = &state.output_sink {
+        println!(
+            "Output device: {sink}, {:.0}ms latency",
+            state.output_latency.as_secs_f32() * 1000.0
+        );
+    }
+    println!("Output {}", ballistics.render(&state.meter_settings));
+    if let Some(meter) = reading.correlation_meter() {
+        println!("Correlation: {meter}");
+    }
+    for input in state.inputs.iter() {
+        if input.disabled {
+            println!("Input {}: disabled", input.name);
+            continue;
+        }
+        if input.held {
+            println!("Input {}: held", input.name);
+        }
+        if input.deferred {
+            println!("Input {}: deferred", input.name);
+        }
+        if state.focus.as_ref() == Some(&input.name) {
+            println!("Input {}: focused", input.name);
+        }
+        if state.mixing && input.ducker.gain() < 0.99 {
+            println!("Ducked: {:+.1} dB", meter::to_db(input.ducker.gain()));
+        }
+        let mixing: Vec<&str> = state.overlap.mixing_with(&input.name).collect();
+        if !mixing.is_empty() {
+            println!("Mixes with: {}", mixing.join(", "));
+        }
+        if let Some(max_wait) = input.soft_preemption {
+            println!(
+                "Soft preemption: waits up to {:.1}s for a pause",
+                max_wait.as_secs_f32()
+            );
+        }
+        if let Some(jingles) = &input.jingles {
+            println!(
+                "Jingles: {}, {} snippets",
+                jingles.action,
+                jingles.snippet_count()
+            );
+        }
+        if let Some(duplicates) = &input.duplicates {
+            let repeats: Vec<String> = duplicates
+                .repeats()
+                .map(|count| format!("×{count}"))
+                .collect();
+            if repeats.is_empty() {
+                println!(
+                    "Duplicates: suppressed within {:.0}s",
+                    duplicates.window.as_secs_f32()
+                );
+            } else {
+                println!(
+                    "Duplicates: suppressed within {:.0}s, clips repeated {}",
+                    duplicates.window.as_secs_f32(),
+                    repeats.join(", ")
+                );
+            }
+        }
+        if let Some(transcriber) = &input.transcriber {
+            println!(
+                "Transcribing: {} segments with '{}'",
+                transcriber.transcripts().len(),
+                transcriber.command
+            );
+            for transcript in transcriber.queued(input.playback_position) {
+                println!(
+                    "Queued: #{} {:.1}s \"{}\"",
+                    transcript.id, transcript.seconds, transcript.text
+                );
+            }
+        }
+        if input.catch_up != CatchUp::Live {
+            println!("Catch-up: {}", input.catch_up);
+        }
+        if let Some(gate) = input.transport_gate {
+            let open = state
+                .transport_rolling
+                .is_some_and(|rolling| gate.is_open(rolling));
+            let state = if open { "capturing" } else { "waiting" };
+            println!("Transport gate: {gate}, {state}");
+        }
+        match (&input.source, input.mono_port) {
+            (Source::Ports(ports), Some(port)) => println!(
+                "Layout: mono, port {} of {} on all channels",
+                port + 1,
+                ports.len()
+            ),
+            (Source::Ports(ports), None) => println!("Layout: {} channels", ports.len()),
+            (Source::Network(receiver), _) => println!("Link: {}", receiver.link_quality()),
+            (Source::File(player), _) => match player.playing() {
+                Some((path, replay_gain)) => {
+                    let (position, length) = player.playlist_position();
+                    let elapsed = player.elapsed().as_secs();
+                    println!(
+                        "Playing {position}/{length}: {} at {}:{:02} ({replay_gain}, {} applied, \
+                         {:+.1} dB)",
+                        path.display(),
+                        elapsed / 60,
+                        elapsed % 60,
+                        player.gain_mode,
+                        20.0 * player.gain().log10()
+                    )
+                }
+                None => println!("Playing: nothing"),
+            },
+            (Source::Generator(_), _) => {}
+        }
+        print!("Input: [");
+        for item in input.buffer.iter() {
+            match item {
+                BufferItem::Samples(..) => {
+                    print!("s")
+                }
+                BufferItem::Silence(..) => print!("_"),
+            }
+        }
+        println!("]");
+        if input.priority != 0 {
+            println!("Priority: {}", input.priority);
+        }
+        println!("{}", input.urgency());
+        let pinned = if input.speed_override.is_some() {
+            " (pinned)"
+        } else if input.adaptive_tempo.is_some() {
+            " (adaptive)"
+        } else {
+            ""
+        };
+        println!("Speed: {:.2}x{pinned}", input.tempo(speed_trim));
+        if input.rate != 1.0 {
+            println!("Rate: {:.2}x", input.rate);
+        }
+        if input.pitch != 0.0 {
+            println!("Pitch: {:+.1} semitones", input.pitch);
+        }
+        if input.gain != 1.0 {
+            println!("Gain: {:+.1} dB", 20.0 * input.gain.log10());
+        }
+        if input.muted {
+            println!("Muted");
+        }
+        if !input.chain.is_empty() {
+            println!("Chain: {}", input.chain);
+        }
+        if let Some(recording) = &input.scheduled_recording {
+            let state = if recording.recorder.is_some() {
+                "recording"
+            } else {
+                "failed"
+            };
+            println!("Scheduled recording: {}, {state}", recording.path.display());
+        }
+        if let Some(logger) = &input.logger {
+            println!(
+                "Logging to {} as {} ({} samples dropped)",
+                logger.directory().display(),
+                logger.encoding(),
+                logger.dropped_samples()
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use config::Preset;
+
+    const SAMPLE_RATE: usize = 48000;
+
+    fn input(name: &str) -> Input {
+        Input {
+            name: name.to_string(),
+            rate: 1.0,
+            gain: 1.0,
+            ..Default::default()
+        }
+    }
+
+    fn tone(frames: usize) -> Vec<Vec<f32>> {
+        vec![vec![0.5; frames]; 2]
+    }
+
+    fn silence(frames: usize) -> Vec<Vec<f32>> {
+        vec![vec![0.0; frames]; 2]
+    }
+
+    #[test]
+    fn builds_without_jack() {
+        let multiplexer = MultiplexerBuilder::new()
+            .with_input(InputConfig::new("voice"))
+            .with_input(InputConfig {
+                preset: Some(Preset::Podcast),
+                ..InputConfig::new("podcast")
+            })
+            .build()
+            .unwrap();
+        assert_eq!(multiplexer.config.inputs.len(), 2);
+        assert_eq!(multiplexer.config.inputs[1].max_silence, Some(0.05));
+    }
+
+    #[test]
+    fn rejects_invalid_inputs() {
+        let result = MultiplexerBuilder::new()
+            .with_input(InputConfig::new("voice"))
+            .with_input(InputConfig::new("voice"))
+            .build();
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn plays_back_what_it_captured() {
+        let mut input = input("voice");
+        input.capture(tone(1024), SystemTime::now(), SAMPLE_RATE);
+        assert_eq!(input.buffered_samples(), 1024);
+
+        let period = input.take_frames(512, 2, SAMPLE_RATE);
+        assert!(period.iter().all(|channel| channel == &[0.5; 512]));
+        assert_eq!(input.buffered_samples(), 512);
+
+        // Padded with silence past the end of the queue
+        let period = input.take_frames(1024, 2, SAMPLE_RATE);
+        assert_eq!(period[0][511], 0.5);
+        assert_eq!(period[0][512], 0.0);
+        assert!(input.buffer().is_empty());
+    }
+
+    #[test]
+    fn queues_no_silence_without_sound() {
+        let mut input = input("voice");
+        input.capture(silence(4800), SystemTime::now(), SAMPLE_RATE);
+        assert!(input.buffer().is_empty());
+        assert!(!input.is_playable());
+    }
+
+    #[test]
+    fn shortens_silence_between_sounds() {
+        let mut input = input("voice");
+        let now = SystemTime::now();
+        input.capture(tone(1024), now, SAMPLE_RATE);
+        for _ in 0..10 {
+            input.capture(silence(4800), now, SAMPLE_RATE);
+        }
+        input.capture(tone(1024), now, SAMPLE_RATE);
+
+        let stored: usize = input
+            .buffer()
+            .iter()
+            .map(|item| match item {
+                BufferItem::Silence(frames) => *frames,
+                BufferItem::Samples(..) => 0,
+            })
+            .sum();
+        assert!(stored > 0 && stored <= silence::DEFAULT_MAX_STORED);
+        assert!(matches!(
+            input.buffer().back(),
+            Some(BufferItem::Samples(..))
+        ));
+    }
+
+    #[test]
+    fn picks_the_next_input() {
+        let mut inputs = vec![input("short"), input("long"), input("mic")];
+        for (input, frames) in inputs.iter_mut().zip([1024, 8192, 256]) {
+            input.capture(tone(frames), SystemTime::now(), SAMPLE_RATE);
+        }
+        inputs[2].priority = 1;
+        assert_eq!(next_input(Scheduling::Urgency, &inputs, None), Some(2));
+        assert_eq!(next_input(Scheduling::Priority, &inputs, None), Some(2));
+
+        inputs[2].held = true;
+        assert_eq!(next_input(Scheduling::Urgency, &inputs, None), Some(1));
+        assert_eq!(next_input(Scheduling::Priority, &inputs, None), Some(0));
+        assert_eq!(
+            next_input(Scheduling::Urgency, &inputs, Some("short")),
+            Some(0)
+        );
+        assert_eq!(next_input(Scheduling::Urgency, &inputs, Some("mic")), None);
+
+        for input in inputs.iter_mut() {
+            input.disabled = true;
+        }
+        assert_eq!(next_input(Scheduling::Urgency, &inputs, None), None);
+    }
+}
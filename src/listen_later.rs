@@ -0,0 +1,132 @@
+//! Saves the backlog of an input to an Ogg Opus file to listen to later, e.g. on a phone, instead
+//! of speeding through it.
+//!
+//! Encoding a long backlog takes a while, so it runs on a thread of its own once the backlog is
+//! out of the queue rather than holding up the recorders' encoder thread.
+
+use std::{
+    path::{Path, PathBuf},
+    thread,
+    time::SystemTime,
+};
+
+use crate::{
+    encoder::{Encoder, Encoding},
+    export,
+    ogg_opus::OpusFile,
+    BufferItem,
+};
+
+/// kbit/s, when no bitrate is given
+pub const BITRATE_PER_CHANNEL: u32 = 48;
+/// Peak the audio is normalized to, -1 dBFS
+const NORMALIZED_PEAK: f32 = 0.89;
+
+/// Tags of the file, as Vorbis comments
+pub struct Metadata {
+    pub title: String,
+    pub artist: String,
+    pub date: String,
+}
+
+/// Backlog items at natural speed, padded to `channel_count` channels. Stored silence is left out
+/// when trimming, the peak is raised or lowered to -1 dBFS when normalizing.
+pub fn render(
+    items: impl IntoIterator<Item = BufferItem>,
+    channel_count: usize,
+    trim: bool,
+    normalize: bool,
+) -> Vec<Vec<f32>> {
+    let mut audio = vec![Vec::new(); channel_count];
+    for item in items {
+        match item {
+            BufferItem::Samples(samples, _) => {
+                let frame_count = samples[0].len();
+                for (channel, output) in audio.iter_mut().enumerate() {
+                    match samples.get(channel) {
+                        Some(samples) => output.extend_from_slice(samples),
+                        // Inputs with fewer channels than the output are padded with silence
+                        None => output.resize(output.len() + frame_count, 0.0),
+                    }
+                }
+            }
+            BufferItem::Silence(_) if trim => {}
+            BufferItem::Silence(frames) => audio
+                .iter_mut()
+                .for_each(|output| output.resize(output.len() + frames, 0.0)),
+        }
+    }
+    if normalize {
+        let peak = audio
+            .iter()
+            .flatten()
+            .fold(0.0f32, |peak, sample| peak.max(sample.abs()));
+        if peak > 0.0 {
+            let gain = NORMALIZED_PEAK / peak;
+            audio
+                .iter_mut()
+                .flatten()
+                .for_each(|sample| *sample *= gain);
+        }
+    }
+    audio
+}
+
+/// Writes the audio to `path` in the background, at `bitrate` kbit/s. If encoding fails it's
+/// written to a WAV file next to it instead, so the backlog isn't lost.
+pub fn save(
+    audio: Vec<Vec<f32>>,
+    sample_rate: usize,
+    bitrate: u32,
+    metadata: Metadata,
+    path: PathBuf,
+) {
+    thread::spawn(move || {
+        let Err(error) = write_opus(&audio, sample_rate, bitrate, &metadata, &path) else {
+            return;
+        };
+        let fallback = path.with_extension("wav");
+        eprintln!(
+            "<3>Failed to save {} for later: {error:#}. Writing {} instead.",
+            metadata.artist,
+            fallback.display()
+        );
+        let channel_count = audio.len();
+        let items = [BufferItem::Samples(audio, SystemTime::now())];
+        if let Err(error) = export::write_items(
+            &items,
+            channel_count,
+            sample_rate,
+            Encoding::default(),
+            &fallback,
+        ) {
+            eprintln!("<3>{} is lost: {error:#}", metadata.artist);
+        }
+    });
+}
+
+fn write_opus(
+    audio: &[Vec<f32>],
+    sample_rate: usize,
+    bitrate: u32,
+    metadata: &Metadata,
+    path: &Path,
+) -> anyhow::Result<()> {
+    let comments = [
+        format!("TITLE={}", metadata.title),
+        format!("ARTIST={}", metadata.artist),
+        format!("DATE={}", metadata.date),
+    ];
+    let mut file = Box::new(OpusFile::create(
+        path,
+        audio.len(),
+        sample_rate,
+        bitrate,
+        &comments,
+    )?);
+    let interleaved: Vec<f32> = (0..audio[0].len())
+        .flat_map(|frame| audio.iter().map(move |channel| channel[frame]))
+        .collect();
+    file.write(&interleaved)?;
+    file.finish()
+}
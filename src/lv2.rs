@@ -0,0 +1,358 @@
+//! LV2 plugin hosting through lilv, enabled with the `lv2` feature
+
+use std::{
+    ffi::{c_char, c_void, CStr, CString},
+    ptr,
+};
+
+use anyhow::{anyhow, bail, Context};
+
+use crate::chain::Effect;
+
+#[repr(C)]
+struct LilvWorld {
+    _private: [u8; 0],
+}
+#[repr(C)]
+struct LilvPlugins {
+    _private: [u8; 0],
+}
+#[repr(C)]
+struct LilvPlugin {
+    _private: [u8; 0],
+}
+#[repr(C)]
+struct LilvPort {
+    _private: [u8; 0],
+}
+#[repr(C)]
+struct LilvNode {
+    _private: [u8; 0],
+}
+
+#[repr(C)]
+struct Lv2Descriptor {
+    uri: *const c_char,
+    instantiate: *const c_void,
+    connect_port: unsafe extern "C" fn(*mut c_void, u32, *mut c_void),
+    activate: Option<unsafe extern "C" fn(*mut c_void)>,
+    run: unsafe extern "C" fn(*mut c_void, u32),
+    deactivate: Option<unsafe extern "C" fn(*mut c_void)>,
+    cleanup: *const c_void,
+    extension_data: *const c_void,
+}
+
+/// Layout of `LilvInstance`, whose accessors are inline functions in lilv.h
+#[repr(C)]
+struct LilvInstance {
+    descriptor: *const Lv2Descriptor,
+    handle: *mut c_void,
+    pimpl: *mut c_void,
+}
+
+#[link(name = "lilv-0")]
+extern "C" {
+    fn lilv_world_new() -> *mut LilvWorld;
+    fn lilv_world_load_all(world: *mut LilvWorld);
+    fn lilv_world_free(world: *mut LilvWorld);
+    fn lilv_world_get_all_plugins(world: *const LilvWorld) -> *const LilvPlugins;
+    fn lilv_new_uri(world: *mut LilvWorld, uri: *const c_char) -> *mut LilvNode;
+    fn lilv_node_free(node: *mut LilvNode);
+    fn lilv_node_as_string(node: *const LilvNode) -> *const c_char;
+    fn lilv_node_as_float(node: *const LilvNode) -> f32;
+    fn lilv_plugins_get_by_uri(
+        plugins: *const LilvPlugins,
+        uri: *const LilvNode,
+    ) -> *const LilvPlugin;
+    fn lilv_plugin_get_num_ports(plugin: *const LilvPlugin) -> u32;
+    fn lilv_plugin_get_port_by_index(plugin: *const LilvPlugin, index: u32) -> *const LilvPort;
+    fn lilv_port_is_a(
+        plugin: *const LilvPlugin,
+        port: *const LilvPort,
+        port_class: *const LilvNode,
+    ) -> bool;
+    fn lilv_port_get_symbol(plugin: *const LilvPlugin, port: *const LilvPort) -> *const LilvNode;
+    fn lilv_port_get_range(
+        plugin: *const LilvPlugin,
+        port: *const LilvPort,
+        default: *mut *mut LilvNode,
+        minimum: *mut *mut LilvNode,
+        maximum: *mut *mut LilvNode,
+    );
+    fn lilv_plugin_instantiate(
+        plugin: *const LilvPlugin,
+        sample_rate: f64,
+        features: *const *const c_void,
+    ) -> *mut LilvInstance;
+    fn lilv_instance_free(instance: *mut LilvInstance);
+}
+
+const INPUT_PORT: &str = "http://lv2plug.in/ns/lv2core#InputPort";
+const AUDIO_PORT: &str = "http://lv2plug.in/ns/lv2core#AudioPort";
+const CONTROL_PORT: &str = "http://lv2plug.in/ns/lv2core#ControlPort";
+
+enum PortKind {
+    AudioIn,
+    AudioOut,
+    /// Index into the control values
+    Control(usize),
+}
+
+/// Control port of a plugin
+struct Control {
+    symbol: String,
+    value: f32,
+}
+
+/// LV2 plugin in an input's chain.
+///
+/// Mono plugins on multi-channel inputs get one instance per channel.
+pub struct Lv2Plugin {
+    world: *mut LilvWorld,
+    instances: Vec<*mut LilvInstance>,
+    ports: Vec<PortKind>,
+    /// Control port values, boxed so the addresses handed to the plugin stay valid
+    controls: Box<[Control]>,
+    /// Output buffer per audio output of every instance
+    outputs: Vec<Vec<f32>>,
+    audio_inputs: usize,
+}
+
+// The raw pointers are owned by the plugin and only used by whoever owns it
+unsafe impl Send for Lv2Plugin {}
+
+/// Creates a plugin node from `<uri>[;symbol=value...]`
+pub fn create(
+    text: &str,
+    channel_count: usize,
+    sample_rate: usize,
+) -> anyhow::Result<Box<dyn Effect>> {
+    let mut parts = text.split(';');
+    let uri = parts.next().unwrap_or_default();
+    if uri.is_empty() {
+        bail!("Usage: lv2:<uri>[;symbol=value...]");
+    }
+    let values = parts
+        .map(|part| {
+            let (symbol, value) = part
+                .split_once('=')
+                .ok_or_else(|| anyhow!("Expected symbol=value, got '{part}'"))?;
+            let value: f32 = value
+                .parse()
+                .with_context(|| format!("Invalid value for '{symbol}'"))?;
+            Ok((symbol.to_string(), value))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    Ok(Box::new(Lv2Plugin::load(
+        uri,
+        &values,
+        channel_count,
+        sample_rate,
+    )?))
+}
+
+impl Lv2Plugin {
+    /// Instantiates the plugin with `uri` and sets the given control values, all other controls
+    /// keep their defaults
+    pub fn load(
+        uri: &str,
+        values: &[(String, f32)],
+        channel_count: usize,
+        sample_rate: usize,
+    ) -> anyhow::Result<Self> {
+        unsafe {
+            let world = lilv_world_new();
+            if world.is_null() {
+                bail!("Failed to create lilv world");
+            }
+            let mut plugin = Lv2Plugin {
+                world,
+                instances: Vec::new(),
+                ports: Vec::new(),
+                controls: Box::new([]),
+                outputs: Vec::new(),
+                audio_inputs: 0,
+            };
+            lilv_world_load_all(world);
+
+            let uri_node = new_uri(world, uri)?;
+            let lilv_plugin = lilv_plugins_get_by_uri(lilv_world_get_all_plugins(world), uri_node);
+            lilv_node_free(uri_node);
+            if lilv_plugin.is_null() {
+                bail!("LV2 plugin {uri} is not installed");
+            }
+
+            let input_class = new_uri(world, INPUT_PORT)?;
+            let audio_class = new_uri(world, AUDIO_PORT)?;
+            let control_class = new_uri(world, CONTROL_PORT)?;
+            let mut controls = Vec::new();
+            let mut audio_outputs = 0;
+            let mut unsupported = None;
+            for index in 0..lilv_plugin_get_num_ports(lilv_plugin) {
+                let port = lilv_plugin_get_port_by_index(lilv_plugin, index);
+                let symbol = node_string(lilv_port_get_symbol(lilv_plugin, port));
+                let is_input = lilv_port_is_a(lilv_plugin, port, input_class);
+                if lilv_port_is_a(lilv_plugin, port, audio_class) {
+                    if is_input {
+                        plugin.audio_inputs += 1;
+                        plugin.ports.push(PortKind::AudioIn);
+                    } else {
+                        audio_outputs += 1;
+                        plugin.ports.push(PortKind::AudioOut);
+                    }
+                } else if lilv_port_is_a(lilv_plugin, port, control_class) {
+                    let mut default = ptr::null_mut();
+                    lilv_port_get_range(
+                        lilv_plugin,
+                        port,
+                        &mut default,
+                        ptr::null_mut(),
+                        ptr::null_mut(),
+                    );
+                    let mut value = if default.is_null() {
+                        0.0
+                    } else {
+                        let value = lilv_node_as_float(default);
+                        lilv_node_free(default);
+                        value
+                    };
+                    if is_input {
+                        if let Some((_, configured)) =
+                            values.iter().find(|(name, _)| *name == symbol)
+                        {
+                            value = *configured;
+                        }
+                    }
+                    plugin.ports.push(PortKind::Control(controls.len()));
+                    controls.push(Control { symbol, value });
+                } else {
+                    unsupported = Some(symbol);
+                }
+            }
+            lilv_node_free(input_class);
+            lilv_node_free(audio_class);
+            lilv_node_free(control_class);
+
+            if let Some(symbol) = unsupported {
+                bail!("Port '{symbol}' of {uri} is neither an audio nor a control port");
+            }
+            if let Some((symbol, _)) = values
+                .iter()
+                .find(|(symbol, _)| !controls.iter().any(|control| control.symbol == *symbol))
+            {
+                bail!("{uri} has no control port '{symbol}'");
+            }
+            if plugin.audio_inputs == 0
+                || plugin.audio_inputs != audio_outputs
+                || !channel_count.is_multiple_of(plugin.audio_inputs)
+            {
+                bail!(
+                    "{uri} has {} audio inputs and {audio_outputs} outputs, can't process {channel_count} channels",
+                    plugin.audio_inputs
+                );
+            }
+            plugin.controls = controls.into_boxed_slice();
+
+            for _ in 0..channel_count / plugin.audio_inputs {
+                let instance =
+                    lilv_plugin_instantiate(lilv_plugin, sample_rate as f64, ptr::null());
+                if instance.is_null() {
+                    bail!("Failed to instantiate {uri}");
+                }
+                plugin.instances.push(instance);
+                let descriptor = &*(*instance).descriptor;
+                for (index, port) in plugin.ports.iter().enumerate() {
+                    if let PortKind::Control(control) = port {
+                        (descriptor.connect_port)(
+                            (*instance).handle,
+                            index as u32,
+                            &mut plugin.controls[*control].value as *mut f32 as *mut c_void,
+                        );
+                    }
+                }
+                if let Some(activate) = descriptor.activate {
+                    activate((*instance).handle);
+                }
+            }
+            plugin.outputs = vec![Vec::new(); channel_count];
+            Ok(plugin)
+        }
+    }
+}
+
+impl Effect for Lv2Plugin {
+    fn set_parameter(&mut self, name: &str, value: f32) -> anyhow::Result<()> {
+        let control = self
+            .controls
+            .iter_mut()
+            .find(|control| control.symbol == name)
+            .ok_or_else(|| anyhow!("No control port '{name}'"))?;
+        control.value = value;
+        Ok(())
+    }
+
+    fn process(&mut self, channels: &mut [Vec<f32>]) {
+        let length = channels.first().map_or(0, |channel| channel.len());
+        for output in self.outputs.iter_mut() {
+            output.resize(length, 0.0);
+        }
+        for (instance_index, &instance) in self.instances.iter().enumerate() {
+            let first_channel = instance_index * self.audio_inputs;
+            let (mut input, mut output) = (first_channel, first_channel);
+            unsafe {
+                let descriptor = &*(*instance).descriptor;
+                for (index, port) in self.ports.iter().enumerate() {
+                    let buffer = match port {
+                        PortKind::AudioIn => {
+                            input += 1;
+                            channels[input - 1].as_mut_ptr()
+                        }
+                        PortKind::AudioOut => {
+                            output += 1;
+                            self.outputs[output - 1].as_mut_ptr()
+                        }
+                        PortKind::Control(_) => continue,
+                    };
+                    (descriptor.connect_port)(
+                        (*instance).handle,
+                        index as u32,
+                        buffer as *mut c_void,
+                    );
+                }
+                (descriptor.run)((*instance).handle, length as u32);
+            }
+        }
+        for (channel, output) in channels.iter_mut().zip(&self.outputs) {
+            channel.copy_from_slice(output);
+        }
+    }
+}
+
+impl Drop for Lv2Plugin {
+    fn drop(&mut self) {
+        unsafe {
+            for &instance in &self.instances {
+                if let Some(deactivate) = (*(*instance).descriptor).deactivate {
+                    deactivate((*instance).handle);
+                }
+                lilv_instance_free(instance);
+            }
+            lilv_world_free(self.world);
+        }
+    }
+}
+
+unsafe fn new_uri(world: *mut LilvWorld, uri: &str) -> anyhow::Result<*mut LilvNode> {
+    let uri = CString::new(uri).context("URI contains a null byte")?;
+    let node = lilv_new_uri(world, uri.as_ptr());
+    if node.is_null() {
+        bail!("Invalid URI {uri:?}");
+    }
+    Ok(node)
+}
+
+unsafe fn node_string(node: *const LilvNode) -> String {
+    CStr::from_ptr(lilv_node_as_string(node))
+        .to_string_lossy()
+        .into_owned()
+}
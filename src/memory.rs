@@ -0,0 +1,330 @@
+//! Budget for the memory audio takes. The input buffers, the replay history, caches and the
+//! queues of recordings are accounted for together, and once they come close to the budget the
+//! configured actions keep them from growing or free memory.
+
+use std::{fmt, mem::size_of, path::PathBuf};
+
+use serde::Deserialize;
+
+use crate::{
+    buffer::Buffer,
+    capacity::format_bytes,
+    config::MemoryConfig,
+    cue::Cue,
+    duplicates::DuplicateSuppression,
+    filler::FillerDropping,
+    jingles::JingleSkip,
+    recorder::Recorder,
+    spill,
+    timeline::{DropReason, Event, Timeline},
+    Input,
+};
+
+/// Seconds of backlog kept in memory ahead of spilled audio, so it's read back before it plays
+const SPILL_HEAD_SECONDS: f64 = 30.0;
+
+/// Fraction of the budget freed below the pressure threshold, so the actions don't run again
+/// right away
+const RELIEF: f64 = 0.1;
+
+/// What is done when the memory in use comes close to the budget
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PressureAction {
+    /// Pauses the sources that can be paused, they aren't resumed until the pressure is over
+    PauseSources,
+    /// Moves backlogs to disk, the largest first, keeping what plays next in memory
+    Spill,
+    /// Drops the replay history and then the oldest backlog, the largest first
+    DropOldest,
+}
+
+impl fmt::Display for PressureAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PressureAction::PauseSources => write!(f, "pause-sources"),
+            PressureAction::Spill => write!(f, "spill"),
+            PressureAction::DropOldest => write!(f, "drop-oldest"),
+        }
+    }
+}
+
+/// Memory in use by area, in bytes
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Accounting {
+    /// Backlogs of the inputs, those kept across a suspend included
+    pub input_buffers: usize,
+    /// Played audio kept for replays and the lead-in
+    pub replay: usize,
+    /// Rendered cues and the fingerprints of snippets, clips and filler
+    pub caches: usize,
+    /// Queues to the encoder thread of the loggers and scheduled recordings
+    pub recording_queues: usize,
+    /// Spilled audio on disk, not part of the total
+    pub spilled: u64,
+}
+
+impl Accounting {
+    pub fn measure(inputs: &[Input], kept_backlogs: &[(String, Buffer, bool)]) -> Self {
+        let mut accounting = Accounting::default();
+        for input in inputs {
+            accounting.input_buffers += input.buffer.memory_bytes();
+            accounting.spilled += input.buffer.spilled_bytes();
+            accounting.replay += replay_bytes(input);
+            accounting.caches += input.cue.as_ref().map_or(0, Cue::memory_bytes)
+                + input.jingles.as_ref().map_or(0, JingleSkip::memory_bytes)
+                + input
+                    .duplicates
+                    .as_ref()
+                    .map_or(0, DuplicateSuppression::memory_bytes)
+                + input
+                    .filler
+                    .as_ref()
+                    .map_or(0, FillerDropping::memory_bytes);
+            let scheduled = input
+                .scheduled_recording
+                .as_ref()
+                .and_then(|recording| recording.recorder.as_ref());
+            accounting.recording_queues += input
+                .logger
+                .iter()
+                .chain(scheduled)
+                .map(Recorder::queue_bytes)
+                .sum::<usize>();
+        }
+        for (_, buffer, _) in kept_backlogs {
+            accounting.input_buffers += buffer.memory_bytes();
+            accounting.spilled += buffer.spilled_bytes();
+        }
+        accounting
+    }
+
+    pub fn total(&self) -> usize {
+        self.input_buffers + self.replay + self.caches + self.recording_queues
+    }
+}
+
+impl fmt::Display for Accounting {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} in use: input buffers {}, replay {}, caches {}, recording queues {}",
+            format_bytes(self.total()),
+            format_bytes(self.input_buffers),
+            format_bytes(self.replay),
+            format_bytes(self.caches),
+            format_bytes(self.recording_queues)
+        )?;
+        if self.spilled > 0 {
+            write!(
+                f,
+                ", {} spilled to disk",
+                format_bytes(self.spilled as usize)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// The budget of the config and whether it is under pressure
+pub struct MemoryBudget {
+    /// Bytes
+    budget: usize,
+    /// Fraction of the budget in use at which the actions start
+    pressure: f64,
+    actions: Vec<PressureAction>,
+    spill_directory: PathBuf,
+    /// Above the pressure threshold, until the actions brought the usage down by the relief
+    under_pressure: bool,
+    /// Spilling failed last time, so the error isn't repeated every status interval
+    spill_failed: bool,
+}
+
+impl MemoryBudget {
+    pub fn new(config: &MemoryConfig) -> Self {
+        Self {
+            budget: (config.budget_mib * 1024.0 * 1024.0) as usize,
+            pressure: config.pressure,
+            actions: config.actions.clone(),
+            spill_directory: config
+                .spill_directory
+                .clone()
+                .unwrap_or_else(spill::default_directory),
+            under_pressure: false,
+            spill_failed: false,
+        }
+    }
+
+    pub fn budget(&self) -> usize {
+        self.budget
+    }
+
+    pub fn under_pressure(&self) -> bool {
+        self.under_pressure
+    }
+
+    /// Whether sources paused under pressure have to stay paused
+    pub fn keeps_sources_paused(&self) -> bool {
+        self.under_pressure && self.actions.contains(&PressureAction::PauseSources)
+    }
+
+    /// Takes the actions in order while the memory in use is above the pressure threshold, until
+    /// they freed enough of it
+    pub fn relieve(
+        &mut self,
+        usage: &Accounting,
+        inputs: &mut [Input],
+        sample_rate: usize,
+        timeline: &mut Timeline,
+    ) {
+        let threshold = (self.budget as f64 * self.pressure) as usize;
+        let target = threshold.saturating_sub((self.budget as f64 * RELIEF) as usize);
+        let used = usage.total();
+        let was_under_pressure = self.under_pressure;
+        self.under_pressure = used > threshold || (was_under_pressure && used > target);
+        if self.under_pressure != was_under_pressure {
+            timeline.push(Event::MemoryPressure {
+                used,
+                budget: self.budget,
+                under_pressure: self.under_pressure,
+            });
+        }
+        if !self.under_pressure {
+            return;
+        }
+
+        let mut excess = used - target;
+        for action in self.actions.clone() {
+            if excess == 0 {
+                break;
+            }
+            let freed = match action {
+                PressureAction::PauseSources => {
+                    pause_sources(inputs, timeline);
+                    0
+                }
+                PressureAction::Spill => self.spill(inputs, excess, sample_rate, timeline),
+                PressureAction::DropOldest => drop_oldest(inputs, excess, sample_rate, timeline),
+            };
+            excess = excess.saturating_sub(freed);
+        }
+    }
+
+    /// Spills the largest backlogs until `excess` bytes are freed, returns the bytes freed
+    fn spill(
+        &mut self,
+        inputs: &mut [Input],
+        excess: usize,
+        sample_rate: usize,
+        timeline: &mut Timeline,
+    ) -> usize {
+        let head_frames = (SPILL_HEAD_SECONDS * sample_rate as f64) as usize;
+        let mut freed = 0;
+        for index in largest_backlogs(inputs) {
+            if freed >= excess {
+                break;
+            }
+            let input = &mut inputs[index];
+            let frames = (excess - freed) / (input.buffer.channel_count() * size_of::<f32>());
+            let before = input.buffer.memory_bytes();
+            match input
+                .buffer
+                .spill(&self.spill_directory, head_frames, frames)
+            {
+                Ok(0) => {}
+                Ok(frames) => {
+                    self.spill_failed = false;
+                    freed += before.saturating_sub(input.buffer.memory_bytes());
+                    timeline.push(Event::Spilled {
+                        input: input.name.clone(),
+                        seconds: frames as f32 / sample_rate.max(1) as f32,
+                    });
+                }
+                Err(error) => {
+                    if !self.spill_failed {
+                        eprintln!("<3>{}: failed to spill: {error:#}", input.name);
+                    }
+                    self.spill_failed = true;
+                }
+            }
+        }
+        freed
+    }
+}
+
+/// Reads spilled audio back that comes up within the head, whether or not there is a budget
+pub fn read_back_spilled(inputs: &mut [Input], sample_rate: usize) {
+    let head_frames = (SPILL_HEAD_SECONDS * sample_rate as f64) as usize;
+    for input in inputs.iter_mut() {
+        if let Err(error) = input.buffer.read_back(head_frames) {
+            eprintln!("<3>{}: {error:#}, the spilled audio is lost", input.name);
+        }
+    }
+}
+
+fn pause_sources(inputs: &mut [Input], timeline: &mut Timeline) {
+    for input in inputs.iter_mut().filter(|input| !input.disabled) {
+        let Some(pausing) = input.pausing.as_mut() else {
+            continue;
+        };
+        match pausing.pause() {
+            Ok(true) => timeline.push(Event::Paused {
+                input: input.name.clone(),
+            }),
+            Ok(false) => {}
+            Err(error) => eprintln!("<4>{}: {error:#}", input.name),
+        }
+    }
+}
+
+/// Drops the replay history, then the front of the largest backlogs until `excess` bytes are
+/// freed. Returns the bytes freed.
+fn drop_oldest(
+    inputs: &mut [Input],
+    excess: usize,
+    sample_rate: usize,
+    timeline: &mut Timeline,
+) -> usize {
+    // Played already, and older than any backlog
+    let mut freed = 0;
+    for input in inputs.iter_mut() {
+        freed += replay_bytes(input);
+        input.played.clear();
+    }
+    for index in largest_backlogs(inputs) {
+        if freed >= excess {
+            break;
+        }
+        let input = &mut inputs[index];
+        let frames = (excess - freed) / (input.buffer.channel_count() * size_of::<f32>());
+        let before = input.buffer.memory_bytes();
+        let dropped = input.buffer.drop_front(frames.max(1));
+        if dropped > 0 {
+            freed += before.saturating_sub(input.buffer.memory_bytes());
+            timeline.push(Event::Dropped {
+                input: input.name.clone(),
+                seconds: dropped as f32 / sample_rate.max(1) as f32,
+                reason: DropReason::Oldest,
+            });
+        }
+    }
+    freed
+}
+
+/// Indices of the inputs with audio in memory, the largest backlog first
+fn largest_backlogs(inputs: &[Input]) -> Vec<usize> {
+    let mut indices: Vec<usize> = (0..inputs.len())
+        .filter(|index| inputs[*index].buffer.channel_count() > 0)
+        .collect();
+    indices.sort_by_key(|index| std::cmp::Reverse(inputs[*index].buffer.memory_bytes()));
+    indices
+}
+
+fn replay_bytes(input: &Input) -> usize {
+    input
+        .played
+        .iter()
+        .flat_map(|(samples, _)| samples)
+        .map(|channel| channel.capacity() * size_of::<f32>())
+        .sum()
+}
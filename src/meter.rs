@@ -0,0 +1,334 @@
+use std::{collections::VecDeque, f64::consts::PI, fmt, str::FromStr, time::Duration};
+
+use anyhow::bail;
+
+/// Correlation below which the output is reported as out of phase
+const PHASE_WARNING: f32 = -0.3;
+
+/// Window of the momentary loudness
+const MOMENTARY_WINDOW: Duration = Duration::from_millis(400);
+
+/// Width of the level bars in characters
+const BAR_WIDTH: usize = 30;
+
+/// What the level meters show
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum MeterMode {
+    /// Highest sample per channel
+    #[default]
+    Peak,
+    /// Root mean square per channel
+    Rms,
+    /// Momentary loudness (400 ms, K-weighted) of all channels together, in LUFS
+    LufsMomentary,
+}
+
+impl FromStr for MeterMode {
+    type Err = anyhow::Error;
+
+    fn from_str(text: &str) -> anyhow::Result<Self> {
+        Ok(match text {
+            "peak" => MeterMode::Peak,
+            "rms" => MeterMode::Rms,
+            "lufs" => MeterMode::LufsMomentary,
+            _ => bail!("Unknown meter mode '{text}', expected 'peak', 'rms' or 'lufs'"),
+        })
+    }
+}
+
+impl fmt::Display for MeterMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MeterMode::Peak => write!(f, "peak dBFS"),
+            MeterMode::Rms => write!(f, "RMS dBFS"),
+            MeterMode::LufsMomentary => write!(f, "LUFS-M"),
+        }
+    }
+}
+
+/// How the meters are shown, shared by everything displaying levels
+#[derive(Clone, Debug)]
+pub struct MeterSettings {
+    pub mode: MeterMode,
+    /// Level shown as an empty bar
+    pub floor_db: f32,
+    /// Level shown as a full bar
+    pub ceiling_db: f32,
+    /// How fast a shown level falls after the signal got quieter, in dB per second. Rises are
+    /// shown immediately.
+    pub decay_db_per_second: f32,
+}
+
+impl Default for MeterSettings {
+    fn default() -> Self {
+        Self {
+            mode: MeterMode::default(),
+            floor_db: -60.0,
+            ceiling_db: 0.0,
+            decay_db_per_second: 20.0,
+        }
+    }
+}
+
+/// Levels of the output accumulated by the audio thread between status updates
+#[derive(Default)]
+pub struct OutputMeter {
+    /// Highest absolute sample per channel
+    peaks: Vec<f32>,
+    /// Sums of squares per channel
+    squares: Vec<f64>,
+    /// Sums of squares of the K-weighted signal per channel
+    weighted_squares: Vec<f64>,
+    /// Sum of the products of the first two channels
+    product: f64,
+    frames: usize,
+    /// K-weighting filter per channel
+    filters: Vec<KWeighting>,
+    sample_rate: usize,
+}
+
+/// Snapshot of an `OutputMeter`
+pub struct Reading {
+    pub peaks_db: Vec<f32>,
+    pub rms_db: Vec<f32>,
+    /// Mean square of the K-weighted signal per channel
+    weighted_mean_squares: Vec<f64>,
+    frames: usize,
+    sample_rate: usize,
+    /// Correlation of the first two channels from -1.0 (out of phase) to 1.0 (mono), `None` for
+    /// mono outputs or silence
+    pub correlation: Option<f32>,
+}
+
+impl OutputMeter {
+    pub fn measure(&mut self, channels: &[&[f32]], sample_rate: usize) {
+        if self.filters.len() != channels.len() || self.sample_rate != sample_rate {
+            self.filters = vec![KWeighting::new(sample_rate); channels.len()];
+            self.sample_rate = sample_rate;
+        }
+        self.peaks.resize(channels.len(), 0.0);
+        self.squares.resize(channels.len(), 0.0);
+        self.weighted_squares.resize(channels.len(), 0.0);
+        for (index, channel) in channels.iter().enumerate() {
+            for &sample in channel.iter() {
+                self.peaks[index] = self.peaks[index].max(sample.abs());
+                self.squares[index] += (sample * sample) as f64;
+                let weighted = self.filters[index].process(sample as f64);
+                self.weighted_squares[index] += weighted * weighted;
+            }
+        }
+        if let [left, right, ..] = channels {
+            self.product += left
+                .iter()
+                .zip(right.iter())
+                .map(|(left, right)| (left * right) as f64)
+                .sum::<f64>();
+        }
+        self.frames += channels.first().map_or(0, |channel| channel.len());
+    }
+
+    /// Returns the levels since the last reading and starts over
+    pub fn take(&mut self) -> Reading {
+        let frames = self.frames.max(1) as f64;
+        let reading = Reading {
+            peaks_db: self.peaks.iter().map(|&peak| to_db(peak)).collect(),
+            rms_db: self
+                .squares
+                .iter()
+                .map(|&squares| to_db((squares / frames).sqrt() as f32))
+                .collect(),
+            weighted_mean_squares: self
+                .weighted_squares
+                .iter()
+                .map(|&squares| squares / frames)
+                .collect(),
+            frames: self.frames,
+            sample_rate: self.sample_rate,
+            correlation: match self.squares[..] {
+                [left, right, ..] if left > 0.0 && right > 0.0 => {
+                    Some((self.product / (left * right).sqrt()) as f32)
+                }
+                _ => None,
+            },
+        };
+        self.peaks.fill(0.0);
+        self.squares.fill(0.0);
+        self.weighted_squares.fill(0.0);
+        self.product = 0.0;
+        self.frames = 0;
+        reading
+    }
+}
+
+impl Reading {
+    /// Renders the correlation as a scale from -1 to +1, with a warning when folding down to
+    /// mono would cancel the signal
+    pub fn correlation_meter(&self) -> Option<String> {
+        let correlation = self.correlation?;
+        let width = 20;
+        let position = (((correlation + 1.0) / 2.0) * width as f32).round() as usize;
+        let scale: String = (0..=width)
+            .map(|index| match index {
+                _ if index == position => '#',
+                _ if index == width / 2 => '|',
+                _ => '-',
+            })
+            .collect();
+        let warning = if correlation < PHASE_WARNING {
+            " out of phase, cancels out in mono!"
+        } else {
+            ""
+        };
+        Some(format!("-1 [{scale}] +1 {correlation:+.2}{warning}"))
+    }
+}
+
+/// Turns readings into the levels shown, applying the mode and the decay
+#[derive(Default)]
+pub struct Ballistics {
+    /// Levels currently shown, one per channel (one in total for loudness)
+    shown: Vec<f32>,
+    /// Recent (frames, weighted mean squares) for the momentary loudness window
+    recent: VecDeque<(usize, Vec<f64>)>,
+}
+
+impl Ballistics {
+    /// Updates the shown levels with a reading taken `elapsed` after the previous one
+    pub fn update(&mut self, reading: &Reading, settings: &MeterSettings, elapsed: Duration) {
+        self.recent
+            .push_back((reading.frames, reading.weighted_mean_squares.clone()));
+        let window_frames = (MOMENTARY_WINDOW.as_secs_f64() * reading.sample_rate as f64) as usize;
+        while self.recent.len() > 1
+            && self
+                .recent
+                .iter()
+                .skip(1)
+                .map(|(frames, _)| frames)
+                .sum::<usize>()
+                >= window_frames
+        {
+            self.recent.pop_front();
+        }
+
+        let current = match settings.mode {
+            MeterMode::Peak => reading.peaks_db.clone(),
+            MeterMode::Rms => reading.rms_db.clone(),
+            MeterMode::LufsMomentary => vec![self.momentary_loudness()],
+        };
+        if self.shown.len() != current.len() {
+            self.shown = current;
+            return;
+        }
+        let decay = settings.decay_db_per_second * elapsed.as_secs_f32();
+        for (shown, current) in self.shown.iter_mut().zip(current) {
+            *shown = current.max(*shown - decay);
+        }
+    }
+
+    /// Momentary loudness per ITU-R BS.1770 over the recent readings
+    fn momentary_loudness(&self) -> f32 {
+        let total_frames: usize = self.recent.iter().map(|(frames, _)| frames).sum();
+        if total_frames == 0 {
+            return f32::NEG_INFINITY;
+        }
+        let power: f64 = self
+            .recent
+            .iter()
+            .map(|(frames, mean_squares)| *frames as f64 * mean_squares.iter().sum::<f64>())
+            .sum::<f64>()
+            / total_frames as f64;
+        (-0.691 + 10.0 * power.max(1e-18).log10()) as f32
+    }
+
+    /// Renders the shown levels as bars between the floor and ceiling of the settings
+    pub fn render(&self, settings: &MeterSettings) -> String {
+        let bars: Vec<String> = self
+            .shown
+            .iter()
+            .map(|&level| {
+                let position = ((level - settings.floor_db)
+                    / (settings.ceiling_db - settings.floor_db))
+                    .clamp(0.0, 1.0);
+                let filled = (position * BAR_WIDTH as f32).round() as usize;
+                format!(
+                    "[{}{}] {level:.1}",
+                    "#".repeat(filled),
+                    "-".repeat(BAR_WIDTH - filled)
+                )
+            })
+            .collect();
+        format!("{}: {}", settings.mode, bars.join(" "))
+    }
+}
+
+/// Pre-filter of the loudness measurement: a high shelf modelling the head followed by a high
+/// pass (the "RLB" curve), both as biquads
+#[derive(Clone)]
+struct KWeighting {
+    shelf: Biquad,
+    high_pass: Biquad,
+}
+
+impl KWeighting {
+    /// Coefficients for arbitrary sample rates, as derived for libebur128
+    fn new(sample_rate: usize) -> Self {
+        let rate = sample_rate.max(1) as f64;
+
+        let (f0, gain, q) = (1681.974450955533, 3.999843853973347, 0.7071752369554196);
+        let k = (PI * f0 / rate).tan();
+        let vh = 10.0_f64.powf(gain / 20.0);
+        let vb = vh.powf(0.4996667741545416);
+        let a0 = 1.0 + k / q + k * k;
+        let shelf = Biquad::new(
+            [
+                (vh + vb * k / q + k * k) / a0,
+                2.0 * (k * k - vh) / a0,
+                (vh - vb * k / q + k * k) / a0,
+            ],
+            [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+        );
+
+        let (f0, q) = (38.13547087602444, 0.5003270373238773);
+        let k = (PI * f0 / rate).tan();
+        let a0 = 1.0 + k / q + k * k;
+        let high_pass = Biquad::new(
+            [1.0, -2.0, 1.0],
+            [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+        );
+
+        Self { shelf, high_pass }
+    }
+
+    fn process(&mut self, sample: f64) -> f64 {
+        self.high_pass.process(self.shelf.process(sample))
+    }
+}
+
+/// Direct form II transposed biquad, `a0` normalized to 1
+#[derive(Clone)]
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2],
+    state: [f64; 2],
+}
+
+impl Biquad {
+    fn new(b: [f64; 3], a: [f64; 2]) -> Self {
+        Self {
+            b,
+            a,
+            state: [0.0; 2],
+        }
+    }
+
+    fn process(&mut self, input: f64) -> f64 {
+        let output = self.b[0] * input + self.state[0];
+        self.state[0] = self.b[1] * input - self.a[0] * output + self.state[1];
+        self.state[1] = self.b[2] * input - self.a[1] * output;
+        output
+    }
+}
+
+pub fn to_db(amplitude: f32) -> f32 {
+    20.0 * amplitude.max(1e-9).log10()
+}
@@ -0,0 +1,425 @@
+use std::{
+    fmt::Write as _,
+    io::{BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    sync::{Arc, Mutex, PoisonError},
+    time::Duration,
+};
+
+use anyhow::{bail, Context};
+
+use crate::{
+    net::LinkQuality,
+    overlay, remote,
+    state_frame::{self, StateFrame},
+    stats::InputStats,
+    JackState, Source,
+};
+
+/// Address of the metrics endpoint in headless mode when none is given
+pub const DEFAULT_ADDRESS: &str = "127.0.0.1:9187";
+
+/// Shortest interval between streamed state frames, a bit faster than screens refresh
+const MIN_INTERVAL_MS: u64 = 10;
+
+/// Largest request body read, the forms of the remote are far smaller
+const MAX_BODY: usize = 4096;
+
+/// Serves the state in the Prometheus text format on `http://<address>/metrics`, as state
+/// frames for UIs on `http://<address>/state`, the phone remote on `http://<address>/remote` and
+/// the streaming overlay on `http://<address>/overlay`
+pub fn serve(address: &str, jack_state: Arc<Mutex<JackState>>) -> anyhow::Result<()> {
+    let listener = TcpListener::bind(address)
+        .with_context(|| format!("Failed to listen for metrics on {address}"))?;
+    std::thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            // A broken client must not take the endpoint down
+            let _ = respond(stream, &jack_state);
+        }
+    });
+    Ok(())
+}
+
+fn respond(mut stream: TcpStream, jack_state: &Arc<Mutex<JackState>>) -> std::io::Result<()> {
+    let (request_line, websocket_key, request_body) = read_request(&stream)?;
+    let mut request_line = request_line.split_whitespace();
+    let method = request_line.next().unwrap_or_default();
+    let target = request_line.next().unwrap_or_default();
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+
+    let (status, content_type, body) = match path {
+        "/metrics" => (
+            "200 OK",
+            "text/plain; version=0.0.4",
+            render(&jack_state.lock().unwrap_or_else(PoisonError::into_inner)).into_bytes(),
+        ),
+        "/state" => match StateRequest::parse(query) {
+            Ok(request) if request.interval.is_some() => {
+                // Streams until the client goes away, without holding up other requests
+                let jack_state = jack_state.clone();
+                std::thread::spawn(move || {
+                    let _ = stream_state(stream, &jack_state, &request);
+                });
+                return Ok(());
+            }
+            Ok(request) => {
+                let frame =
+                    StateFrame::capture(&jack_state.lock().unwrap_or_else(PoisonError::into_inner));
+                ("200 OK", request.content_type(), request.encode(&frame))
+            }
+            Err(error) => (
+                "400 Bad Request",
+                "text/plain",
+                format!("{error}\n").into_bytes(),
+            ),
+        },
+        "/overlay" => (
+            "200 OK",
+            "text/html; charset=utf-8",
+            overlay::page().into_bytes(),
+        ),
+        "/overlay/ws" => match websocket_key {
+            Some(key) => {
+                let jack_state = jack_state.clone();
+                std::thread::spawn(move || {
+                    let _ = overlay::stream(stream, &key, &jack_state);
+                });
+                return Ok(());
+            }
+            None => (
+                "400 Bad Request",
+                "text/plain",
+                b"Expected a WebSocket upgrade\n".to_vec(),
+            ),
+        },
+        "/remote" => {
+            let mut state = jack_state.lock().unwrap_or_else(PoisonError::into_inner);
+            let message = (method == "POST").then(|| {
+                match remote::press(&String::from_utf8_lossy(&request_body), &mut state) {
+                    Ok(response) => response,
+                    Err(error) => format!("Error: {error:#}"),
+                }
+            });
+            (
+                "200 OK",
+                "text/html; charset=utf-8",
+                remote::page(&state, message.as_deref()).into_bytes(),
+            )
+        }
+        _ => (
+            "404 Not Found",
+            "text/plain",
+            b"Metrics are at /metrics, the state at /state, the remote at /remote, the overlay at \
+              /overlay\n"
+                .to_vec(),
+        ),
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        body.len()
+    )?;
+    stream.write_all(&body)
+}
+
+/// Reads the request line, the key of a WebSocket upgrade and the body of a request, skipping the
+/// other headers
+fn read_request(stream: &TcpStream) -> std::io::Result<(String, Option<String>, Vec<u8>)> {
+    let mut reader = BufReader::new(stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut content_length = 0;
+    let mut websocket_key = None;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().unwrap_or(0);
+            } else if name.eq_ignore_ascii_case("sec-websocket-key") {
+                websocket_key = Some(value.trim().to_string());
+            }
+        }
+    }
+    let mut body = vec![0; content_length.min(MAX_BODY)];
+    reader.read_exact(&mut body)?;
+    Ok((request_line, websocket_key, body))
+}
+
+/// `/state?format=json|binary&version=<version>&interval=<ms>`. Clients give the oldest version
+/// of the binary format they can read, newer ones only add fields. With an interval the frames
+/// keep coming on the connection, binary ones each preceded by its length as a little endian u32,
+/// JSON ones one per line.
+struct StateRequest {
+    binary: bool,
+    interval: Option<Duration>,
+}
+
+impl StateRequest {
+    fn parse(query: &str) -> anyhow::Result<Self> {
+        let mut request = StateRequest {
+            binary: false,
+            interval: None,
+        };
+        for parameter in query.split('&').filter(|parameter| !parameter.is_empty()) {
+            match parameter.split_once('=').unwrap_or((parameter, "")) {
+                ("format", "json") => request.binary = false,
+                ("format", "binary") => request.binary = true,
+                ("version", version) => {
+                    let version: u8 = version
+                        .parse()
+                        .with_context(|| format!("Invalid version '{version}'"))?;
+                    if version > state_frame::VERSION {
+                        bail!(
+                            "Version {version} is not supported, this engine writes version {}, \
+                             or use format=json",
+                            state_frame::VERSION
+                        );
+                    }
+                }
+                ("interval", interval) => {
+                    let milliseconds: u64 = interval
+                        .parse()
+                        .with_context(|| format!("Invalid interval '{interval}'"))?;
+                    request.interval =
+                        Some(Duration::from_millis(milliseconds.max(MIN_INTERVAL_MS)));
+                }
+                _ => bail!("Unknown parameter '{parameter}'"),
+            }
+        }
+        Ok(request)
+    }
+
+    fn content_type(&self) -> &'static str {
+        if self.binary {
+            "application/octet-stream"
+        } else {
+            "application/json"
+        }
+    }
+
+    fn encode(&self, frame: &StateFrame) -> Vec<u8> {
+        if self.binary {
+            frame.to_bytes()
+        } else {
+            frame.to_json().into_bytes()
+        }
+    }
+}
+
+fn stream_state(
+    mut stream: TcpStream,
+    jack_state: &Mutex<JackState>,
+    request: &StateRequest,
+) -> std::io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 200 OK\r\nContent-Type: {}\r\nConnection: close\r\n\r\n",
+        request.content_type()
+    )?;
+    let interval = request.interval.unwrap_or_default();
+    loop {
+        let frame = StateFrame::capture(&jack_state.lock().unwrap_or_else(PoisonError::into_inner));
+        let body = request.encode(&frame);
+        if request.binary {
+            stream.write_all(&(body.len() as u32).to_le_bytes())?;
+            stream.write_all(&body)?;
+        } else {
+            stream.write_all(&body)?;
+            stream.write_all(b"\n")?;
+        }
+        std::thread::sleep(interval);
+    }
+}
+
+fn render(state: &JackState) -> String {
+    let mut text = String::new();
+    let sample_rate = state.sample_rate.max(1) as f64;
+
+    let _ = writeln!(text, "# TYPE audiomux_engine_restarts counter");
+    let _ = writeln!(text, "audiomux_engine_restarts {}", state.restarts);
+    let _ = writeln!(text, "# TYPE audiomux_callback_panics counter");
+    let _ = writeln!(text, "audiomux_callback_panics {}", state.callback_panics);
+    let usage = &state.memory_usage;
+    let _ = writeln!(text, "# TYPE audiomux_memory_bytes gauge");
+    for (area, bytes) in [
+        ("input_buffers", usage.input_buffers),
+        ("replay", usage.replay),
+        ("caches", usage.caches),
+        ("recording_queues", usage.recording_queues),
+    ] {
+        let _ = writeln!(text, "audiomux_memory_bytes{{area=\"{area}\"}} {bytes}");
+    }
+    let _ = writeln!(text, "# TYPE audiomux_spilled_bytes gauge");
+    let _ = writeln!(text, "audiomux_spilled_bytes {}", usage.spilled);
+    if let Some(budget) = &state.memory_budget {
+        let _ = writeln!(text, "# TYPE audiomux_memory_budget_bytes gauge");
+        let _ = writeln!(text, "audiomux_memory_budget_bytes {}", budget.budget());
+    }
+    let _ = writeln!(text, "# TYPE audiomux_input_enabled gauge");
+    for input in &state.inputs {
+        let _ = writeln!(
+            text,
+            "audiomux_input_enabled{{input=\"{}\"}} {}",
+            input.name,
+            u8::from(!input.disabled)
+        );
+    }
+    let _ = writeln!(text, "# TYPE audiomux_buffered_seconds gauge");
+    for input in &state.inputs {
+        let _ = writeln!(
+            text,
+            "audiomux_buffered_seconds{{input=\"{}\"}} {}",
+            input.name,
+            input.buffered_samples() as f64 / sample_rate
+        );
+    }
+    let _ = writeln!(text, "# TYPE audiomux_urgency gauge");
+    for input in &state.inputs {
+        let _ = writeln!(
+            text,
+            "audiomux_urgency{{input=\"{}\"}} {}",
+            input.name,
+            input.urgency()
+        );
+    }
+    let _ = writeln!(text, "# TYPE audiomux_priority gauge");
+    for input in &state.inputs {
+        let _ = writeln!(
+            text,
+            "audiomux_priority{{input=\"{}\"}} {}",
+            input.name, input.priority
+        );
+    }
+    let _ = writeln!(text, "# TYPE audiomux_speed gauge");
+    for input in &state.inputs {
+        let _ = writeln!(
+            text,
+            "audiomux_speed{{input=\"{}\"}} {}",
+            input.name,
+            input.tempo(state.speed_trim)
+        );
+    }
+    let session_gauges = [
+        (
+            "audiomux_active_ratio",
+            InputStats::duty_cycle as fn(&InputStats) -> f64,
+        ),
+        (
+            "audiomux_backlog_average_seconds",
+            InputStats::average_backlog,
+        ),
+        ("audiomux_backlog_peak_seconds", InputStats::peak_backlog),
+        ("audiomux_speed_average", InputStats::average_speed),
+    ];
+    for (name, value) in session_gauges {
+        let _ = writeln!(text, "# TYPE {name} gauge");
+        for input in &state.inputs {
+            let _ = writeln!(
+                text,
+                "{name}{{input=\"{}\"}} {}",
+                input.name,
+                value(&input.stats)
+            );
+        }
+    }
+    let _ = writeln!(text, "# TYPE audiomux_logger_dropped_samples counter");
+    for input in &state.inputs {
+        if let Some(logger) = &input.logger {
+            let _ = writeln!(
+                text,
+                "audiomux_logger_dropped_samples{{input=\"{}\"}} {}",
+                input.name,
+                logger.dropped_samples()
+            );
+        }
+    }
+    let _ = writeln!(text, "# TYPE audiomux_bus_effect_seconds gauge");
+    for (effect, cost) in state.bus.costs() {
+        let _ = writeln!(
+            text,
+            "audiomux_bus_effect_seconds{{effect=\"{effect}\"}} {}",
+            cost.as_secs_f64()
+        );
+    }
+    let links: Vec<(&str, LinkQuality)> = state
+        .inputs
+        .iter()
+        .filter_map(|input| match &input.source {
+            Source::Network(receiver) => Some((input.name.as_str(), receiver.link_quality())),
+            _ => None,
+        })
+        .collect();
+    let link_metrics = [
+        (
+            "audiomux_network_latency_seconds gauge",
+            (|quality| quality.latency) as fn(&LinkQuality) -> f64,
+        ),
+        ("audiomux_network_jitter_seconds gauge", |quality| {
+            quality.jitter
+        }),
+        ("audiomux_network_received_packets counter", |quality| {
+            quality.received as f64
+        }),
+        ("audiomux_network_lost_packets counter", |quality| {
+            quality.lost as f64
+        }),
+        ("audiomux_network_recovered_packets counter", |quality| {
+            quality.recovered as f64
+        }),
+        ("audiomux_network_reordered_packets counter", |quality| {
+            quality.reordered as f64
+        }),
+        ("audiomux_network_late_packets counter", |quality| {
+            quality.late as f64
+        }),
+        ("audiomux_network_rejected_packets counter", |quality| {
+            quality.rejected as f64
+        }),
+        ("audiomux_network_jitter_buffer_seconds gauge", |quality| {
+            quality.buffer
+        }),
+        ("audiomux_network_underruns counter", |quality| {
+            quality.underruns as f64
+        }),
+    ];
+    for (metric, value) in link_metrics.into_iter().filter(|_| !links.is_empty()) {
+        let _ = writeln!(text, "# TYPE {metric}");
+        let name = metric.split(' ').next().unwrap_or_default();
+        for (input, quality) in &links {
+            let _ = writeln!(text, "{name}{{input=\"{input}\"}} {}", value(quality));
+        }
+    }
+    if let Some(sender) = &state.net_sender {
+        let _ = writeln!(text, "# TYPE audiomux_network_send_dropped_samples counter");
+        let _ = writeln!(
+            text,
+            "audiomux_network_send_dropped_samples {}",
+            sender.dropped_samples()
+        );
+    }
+
+    if let Some(reading) = &state.output_reading {
+        let _ = writeln!(text, "# TYPE audiomux_output_peak_dbfs gauge");
+        for (channel, peak) in reading.peaks_db.iter().enumerate() {
+            let _ = writeln!(
+                text,
+                "audiomux_output_peak_dbfs{{channel=\"{channel}\"}} {peak}"
+            );
+        }
+        let _ = writeln!(text, "# TYPE audiomux_output_rms_dbfs gauge");
+        for (channel, rms) in reading.rms_db.iter().enumerate() {
+            let _ = writeln!(
+                text,
+                "audiomux_output_rms_dbfs{{channel=\"{channel}\"}} {rms}"
+            );
+        }
+        if let Some(correlation) = reading.correlation {
+            let _ = writeln!(text, "# TYPE audiomux_output_correlation gauge");
+            let _ = writeln!(text, "audiomux_output_correlation {correlation}");
+        }
+    }
+    text
+}
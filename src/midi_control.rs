@@ -0,0 +1,79 @@
+//! Hardware controllers driving the inputs live: control change messages arriving on the
+//! `midi-control` port set the gain, tempo or mute of the inputs they are mapped to in the
+//! config. The process callback passes the messages on to the engine thread, which applies them.
+
+use serde::Deserialize;
+
+use crate::{chain::db_to_factor, config::MidiMapping, Input};
+
+/// Control changes queued between the callback and the engine thread, a controller sweeping
+/// sends one every few milliseconds
+pub const QUEUE_LENGTH: usize = 256;
+
+/// Range of the gain in dB when the mapping doesn't give one
+pub const DEFAULT_GAIN: (f64, f64) = (-60.0, 6.0);
+
+/// Range of the tempo when the mapping doesn't give one, natural speed in the middle
+pub const DEFAULT_TEMPO: (f64, f64) = (0.5, 2.0);
+
+/// What a controller changes on its input
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MidiAction {
+    /// The gain applied to what the input captures, in dB from `min` to `max`
+    Gain,
+    /// The pinned playback speed, from `min` to `max` on a logarithmic scale
+    Tempo,
+    /// Mutes the input from the middle of the range up, the way buttons send 127 and 0
+    Mute,
+}
+
+/// A control change message
+#[derive(Clone, Copy, Debug)]
+pub struct ControlChange {
+    /// 1 to 16
+    pub channel: u8,
+    pub controller: u8,
+    pub value: u8,
+}
+
+impl ControlChange {
+    /// The control change of a raw MIDI message, `None` for other messages
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        match *bytes {
+            [status, controller, value] if status & 0xf0 == 0xb0 => Some(Self {
+                channel: (status & 0x0f) + 1,
+                controller,
+                value,
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// Applies a control change to the inputs of the mappings it matches
+pub fn apply(mappings: &[MidiMapping], change: ControlChange, inputs: &mut [Input]) {
+    let matching = mappings.iter().filter(|mapping| {
+        mapping.controller == change.controller
+            && mapping
+                .channel
+                .is_none_or(|channel| channel == change.channel)
+    });
+    for mapping in matching {
+        let Some(input) = inputs.iter_mut().find(|input| input.name == mapping.input) else {
+            continue;
+        };
+        let position = change.value as f64 / 127.0;
+        match mapping.action {
+            MidiAction::Gain => {
+                let (min, max) = mapping.range(DEFAULT_GAIN);
+                input.gain = db_to_factor((min + (max - min) * position) as f32);
+            }
+            MidiAction::Tempo => {
+                let (min, max) = mapping.range(DEFAULT_TEMPO);
+                input.speed_override = Some(min * (max / min).powf(position));
+            }
+            MidiAction::Mute => input.muted = change.value >= 64,
+        }
+    }
+}
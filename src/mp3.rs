@@ -0,0 +1,130 @@
+//! MP3 files encoded by LAME, for players that know nothing else.
+
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use anyhow::{anyhow, bail, Context};
+use mp3lame_encoder::{Bitrate, Builder, FlushNoGap, InterleavedPcm, MonoPcm};
+
+use crate::encoder::Encoder;
+
+/// kbit/s, when not given
+pub const DEFAULT_BITRATE: u32 = 192;
+/// The bitrates MP3 has, in kbit/s
+pub const BITRATES: [u32; 16] = [
+    8, 16, 24, 32, 40, 48, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320,
+];
+
+pub struct Mp3Encoder {
+    encoder: mp3lame_encoder::Encoder,
+    file: BufWriter<File>,
+    channel_count: usize,
+    /// Encoded frames, reused between writes
+    output: Vec<u8>,
+    bytes: u64,
+}
+
+impl Mp3Encoder {
+    pub fn create(
+        path: &Path,
+        channel_count: usize,
+        sample_rate: usize,
+        bitrate: u32,
+    ) -> anyhow::Result<Self> {
+        if !(1..=2).contains(&channel_count) {
+            bail!("MP3 files have one or two channels, not {channel_count}");
+        }
+        let mut builder = Builder::new().ok_or_else(|| anyhow!("Failed to set up LAME"))?;
+        builder
+            .set_num_channels(channel_count as u8)
+            .map_err(|error| anyhow!("Invalid channel count: {error:?}"))?;
+        builder
+            .set_sample_rate(sample_rate as u32)
+            .map_err(|error| anyhow!("Invalid sample rate: {error:?}"))?;
+        builder
+            .set_brate(bitrate_of(bitrate)?)
+            .map_err(|error| anyhow!("Invalid bitrate: {error:?}"))?;
+        let encoder = builder
+            .build()
+            .map_err(|error| anyhow!("Failed to set up LAME: {error:?}"))?;
+        let file =
+            File::create(path).with_context(|| format!("Failed to create {}", path.display()))?;
+        Ok(Self {
+            encoder,
+            file: BufWriter::new(file),
+            channel_count,
+            output: Vec::new(),
+            bytes: 0,
+        })
+    }
+
+    /// Writes out what the last call to LAME left in `output`, `length` bytes of it
+    fn write_output(&mut self, length: usize) -> anyhow::Result<()> {
+        // SAFETY: LAME initialized the first `length` bytes of the spare capacity
+        unsafe { self.output.set_len(length) };
+        self.file.write_all(&self.output)?;
+        self.bytes += length as u64;
+        self.output.clear();
+        Ok(())
+    }
+}
+
+impl Encoder for Mp3Encoder {
+    fn write(&mut self, samples: &[f32]) -> anyhow::Result<()> {
+        let frame_count = samples.len() / self.channel_count;
+        self.output
+            .reserve(mp3lame_encoder::max_required_buffer_size(frame_count));
+        let output = self.output.spare_capacity_mut();
+        let length = match self.channel_count {
+            1 => self.encoder.encode(MonoPcm(samples), output),
+            _ => self.encoder.encode(InterleavedPcm(samples), output),
+        }
+        .map_err(|error| anyhow!("Failed to encode MP3: {error:?}"))?;
+        self.write_output(length)
+    }
+
+    fn flush(&mut self) -> anyhow::Result<()> {
+        Ok(self.file.flush()?)
+    }
+
+    fn bytes(&self) -> u64 {
+        self.bytes
+    }
+
+    fn finish(mut self: Box<Self>) -> anyhow::Result<()> {
+        // Room for the last frames LAME holds back
+        self.output
+            .reserve(mp3lame_encoder::max_required_buffer_size(0));
+        let length = self
+            .encoder
+            .flush::<FlushNoGap>(self.output.spare_capacity_mut())
+            .map_err(|error| anyhow!("Failed to encode MP3: {error:?}"))?;
+        self.write_output(length)?;
+        Ok(self.file.flush()?)
+    }
+}
+
+fn bitrate_of(kbps: u32) -> anyhow::Result<Bitrate> {
+    Ok(match kbps {
+        8 => Bitrate::Kbps8,
+        16 => Bitrate::Kbps16,
+        24 => Bitrate::Kbps24,
+        32 => Bitrate::Kbps32,
+        40 => Bitrate::Kbps40,
+        48 => Bitrate::Kbps48,
+        64 => Bitrate::Kbps64,
+        80 => Bitrate::Kbps80,
+        96 => Bitrate::Kbps96,
+        112 => Bitrate::Kbps112,
+        128 => Bitrate::Kbps128,
+        160 => Bitrate::Kbps160,
+        192 => Bitrate::Kbps192,
+        224 => Bitrate::Kbps224,
+        256 => Bitrate::Kbps256,
+        320 => Bitrate::Kbps320,
+        _ => bail!("MP3 has no bitrate of {kbps} kbit/s"),
+    })
+}
@@ -0,0 +1,268 @@
+//! Pausing and resuming media players through their MPRIS D-Bus interface.
+//!
+//! Calls are made by a thread of their own, a player slow to answer never holds up the monitor
+//! loop. The thread also polls the playback status of every player it controls, so
+//! the engine notices when a player it paused was resumed by hand.
+
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{mpsc, Arc, Mutex, OnceLock},
+    thread,
+    time::Duration,
+};
+
+use anyhow::{anyhow, Context};
+use zbus::{blocking::Connection, zvariant::OwnedValue};
+
+/// Prefix of the bus names of MPRIS players
+const BUS_PREFIX: &str = "org.mpris.MediaPlayer2.";
+const OBJECT_PATH: &str = "/org/mpris/MediaPlayer2";
+const PLAYER_INTERFACE: &str = "org.mpris.MediaPlayer2.Player";
+
+/// Interval at which the playback status of the controlled players is polled
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Longest a player gets to answer a call
+const CALL_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PlaybackStatus {
+    Playing,
+    Paused,
+    Stopped,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Action {
+    Pause,
+    Play,
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Action::Pause => write!(f, "pause"),
+            Action::Play => write!(f, "play"),
+        }
+    }
+}
+
+/// Handle of the thread talking to the players. Players are named by the end of their bus name,
+/// e.g. `spotify` or `firefox.instance_1_23`, a name matches instances of it as well. `None`
+/// addresses the first player on the bus, like `playerctl` does.
+#[derive(Clone)]
+pub struct MprisClient {
+    requests: mpsc::Sender<Request>,
+    statuses: Arc<Mutex<HashMap<Option<String>, PlaybackStatus>>>,
+}
+
+enum Request {
+    /// Polls the player's status from now on
+    Watch(Option<String>),
+    Act(Option<String>, Action),
+}
+
+/// The client of the session bus, `None` if there is no session bus
+pub fn client() -> Option<&'static MprisClient> {
+    static CLIENT: OnceLock<Option<MprisClient>> = OnceLock::new();
+    CLIENT
+        .get_or_init(|| match MprisClient::start() {
+            Ok(client) => Some(client),
+            Err(error) => {
+                eprintln!("<3>MPRIS players can't be paused: {error:#}");
+                None
+            }
+        })
+        .as_ref()
+}
+
+impl MprisClient {
+    fn start() -> anyhow::Result<Self> {
+        let connection = connect()?;
+        let (requests, receiver) = mpsc::channel();
+        let statuses = Arc::new(Mutex::new(HashMap::new()));
+        let polled = statuses.clone();
+        thread::Builder::new()
+            .name("mpris".to_string())
+            .spawn(move || serve(&connection, &receiver, &polled))?;
+        Ok(Self { requests, statuses })
+    }
+
+    /// Starts polling the player's status, so it is known before the player is first paused
+    pub fn watch(&self, player: Option<&str>) {
+        let _ = self
+            .requests
+            .send(Request::Watch(player.map(str::to_string)));
+    }
+
+    /// Asks the player to pause or play, without waiting for it
+    pub fn send(&self, player: Option<&str>, action: Action) {
+        let player = player.map(str::to_string);
+        // Assumed until the next poll, so a pause isn't taken for a resume by hand meanwhile
+        let expected = match action {
+            Action::Pause => PlaybackStatus::Paused,
+            Action::Play => PlaybackStatus::Playing,
+        };
+        self.statuses
+            .lock()
+            .unwrap()
+            .insert(player.clone(), expected);
+        let _ = self.requests.send(Request::Act(player, action));
+    }
+
+    /// Playback status of the player as last polled, `None` while it isn't running or before
+    /// it was first polled
+    pub fn status(&self, player: Option<&str>) -> Option<PlaybackStatus> {
+        self.statuses
+            .lock()
+            .unwrap()
+            .get(&player.map(str::to_string))
+            .copied()
+    }
+}
+
+/// Performs the requests and polls the watched players, until the client is dropped
+fn serve(
+    connection: &Connection,
+    requests: &mpsc::Receiver<Request>,
+    statuses: &Mutex<HashMap<Option<String>, PlaybackStatus>>,
+) {
+    let mut watched: Vec<Option<String>> = Vec::new();
+    loop {
+        match requests.recv_timeout(POLL_INTERVAL) {
+            Ok(Request::Watch(player)) => {
+                if !watched.contains(&player) {
+                    watched.push(player);
+                }
+            }
+            Ok(Request::Act(player, action)) => {
+                if let Err(error) = call(connection, player.as_deref(), action) {
+                    eprintln!(
+                        "<4>Failed to {action} {}: {error:#}",
+                        describe(player.as_deref())
+                    );
+                }
+                if !watched.contains(&player) {
+                    watched.push(player);
+                }
+                // Polled again once the player had time to act on it
+                continue;
+            }
+            Err(mpsc::RecvTimeoutError::Timeout) => {}
+            Err(mpsc::RecvTimeoutError::Disconnected) => return,
+        }
+        for player in &watched {
+            let status = playback_status(connection, player.as_deref()).ok();
+            let mut statuses = statuses.lock().unwrap();
+            match status {
+                Some(status) => statuses.insert(player.clone(), status),
+                None => statuses.remove(player),
+            };
+        }
+    }
+}
+
+/// Players on the session bus, by the names they are addressed with
+pub fn players() -> anyhow::Result<Vec<String>> {
+    Ok(bus_names(&connect()?)?
+        .into_iter()
+        .filter_map(|name| name.strip_prefix(BUS_PREFIX).map(str::to_string))
+        .collect())
+}
+
+/// Whether the player is on the session bus
+pub fn is_running(player: Option<&str>) -> anyhow::Result<bool> {
+    Ok(bus_name(&connect()?, player).is_ok())
+}
+
+/// Tells the player to pause or play and waits for it, for when there is no engine running the
+/// client
+pub fn call_now(player: Option<&str>, action: Action) -> anyhow::Result<()> {
+    call(&connect()?, player, action)
+}
+
+fn connect() -> anyhow::Result<Connection> {
+    zbus::blocking::connection::Builder::session()?
+        .method_timeout(CALL_TIMEOUT)
+        .build()
+        .context("Failed to connect to the session bus")
+}
+
+fn bus_names(connection: &Connection) -> anyhow::Result<Vec<String>> {
+    let reply = connection.call_method(
+        Some("org.freedesktop.DBus"),
+        "/org/freedesktop/DBus",
+        Some("org.freedesktop.DBus"),
+        "ListNames",
+        &(),
+    )?;
+    let mut names: Vec<String> = reply.body().deserialize()?;
+    names.sort();
+    Ok(names)
+}
+
+/// Bus name of the player
+fn bus_name(connection: &Connection, player: Option<&str>) -> anyhow::Result<String> {
+    bus_names(connection)?
+        .into_iter()
+        .find(|name| {
+            let Some(suffix) = name.strip_prefix(BUS_PREFIX) else {
+                return false;
+            };
+            match player {
+                Some(player) => {
+                    suffix == player
+                        || suffix
+                            .strip_prefix(player)
+                            .is_some_and(|instance| instance.starts_with('.'))
+                }
+                None => true,
+            }
+        })
+        .ok_or_else(|| anyhow!("{} isn't running", describe(player)))
+}
+
+fn call(connection: &Connection, player: Option<&str>, action: Action) -> anyhow::Result<()> {
+    let name = bus_name(connection, player)?;
+    let method = match action {
+        Action::Pause => "Pause",
+        Action::Play => "Play",
+    };
+    connection.call_method(
+        Some(name.as_str()),
+        OBJECT_PATH,
+        Some(PLAYER_INTERFACE),
+        method,
+        &(),
+    )?;
+    Ok(())
+}
+
+fn playback_status(
+    connection: &Connection,
+    player: Option<&str>,
+) -> anyhow::Result<PlaybackStatus> {
+    let name = bus_name(connection, player)?;
+    let reply = connection.call_method(
+        Some(name.as_str()),
+        OBJECT_PATH,
+        Some("org.freedesktop.DBus.Properties"),
+        "Get",
+        &(PLAYER_INTERFACE, "PlaybackStatus"),
+    )?;
+    let value: OwnedValue = reply.body().deserialize()?;
+    match String::try_from(value)?.as_str() {
+        "Playing" => Ok(PlaybackStatus::Playing),
+        "Paused" => Ok(PlaybackStatus::Paused),
+        "Stopped" => Ok(PlaybackStatus::Stopped),
+        status => Err(anyhow!("Unknown playback status {status}")),
+    }
+}
+
+fn describe(player: Option<&str>) -> String {
+    match player {
+        Some(player) => format!("player {player}"),
+        None => "the first player".to_string(),
+    }
+}
@@ -0,0 +1,893 @@
+//! Streaming the scheduled output of one instance to an input of another over UDP.
+//!
+//! Every datagram starts with a kind byte. Audio packets carry the channel count, a sequence
+//! number and the sender's wall clock time in microseconds (both `u64`) followed by interleaved
+//! samples, all little-endian. Samples are 32 bit floats, or in integer PCM packets of the
+//! format given by the first payload byte. With the `opus` feature the samples can be replaced
+//! by an Opus packet with in-band forward error correction. Event packets carry a line of UTF-8 text
+//! describing a timeline event of the sender. Both instances have to run at the same sample
+//! rate, latency is only meaningful with synchronized clocks.
+//!
+//! Alternatively the output is sent as an AES67 compatible RTP stream, which can go to a
+//! multicast group to be picked up by any number of pro-audio receivers on the LAN.
+
+use std::{
+    collections::{hash_map::RandomState, BTreeMap},
+    fmt,
+    hash::{BuildHasher, Hasher},
+    net::{SocketAddr, ToSocketAddrs, UdpSocket},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc, Arc, Mutex,
+    },
+    thread::JoinHandle,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{bail, Context};
+use ringbuf::{HeapConsumer, HeapProducer, HeapRb};
+
+use crate::{control::parse_duration, sample_format::SampleFormat};
+
+/// Frames per uncompressed 32 bit audio packet, keeps stereo packets below a typical MTU
+const PACKET_FRAMES: usize = 128;
+
+/// Seconds of audio the ring buffers on either side can hold
+const BUFFER_SECONDS: usize = 2;
+
+/// How long the sender thread sleeps when there is nothing to send
+const SEND_INTERVAL: Duration = Duration::from_millis(2);
+
+/// How often the receiving thread checks whether it should stop
+const RECEIVE_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Packets held back waiting for a missing one before it is concealed
+const REORDER_PACKETS: usize = 4;
+
+/// Sequence jumps larger than this restart the stream, e.g. when the sender was restarted
+const RESYNC_PACKETS: u64 = 1000;
+
+/// Weight of a new measurement in the smoothed latency
+const LATENCY_SMOOTHING: f64 = 1.0 / 64.0;
+
+/// Attenuation of a concealed packet relative to the one before it
+const CONCEALMENT_DECAY: f32 = 0.5;
+
+/// The adaptive jitter buffer holds this many times the measured jitter
+const JITTER_FACTOR: f64 = 4.0;
+
+/// Fraction of the difference by which an adaptive jitter buffer shrinks per period
+const JITTER_SHRINK: f64 = 1.0 / 256.0;
+
+const KIND_AUDIO: u8 = 0;
+const KIND_EVENT: u8 = 1;
+const KIND_OPUS: u8 = 2;
+const KIND_PCM: u8 = 3;
+
+/// Bytes before the payload of an audio packet
+const AUDIO_HEADER_BYTES: usize = 18;
+
+/// Frames per AES67 packet, the 1 ms packet time every AES67 device supports
+const RTP_PACKET_FRAMES: usize = 48;
+
+const RTP_SAMPLE_RATE: usize = 48000;
+
+const RTP_MAX_CHANNELS: usize = 8;
+
+/// Dynamic payload type announced in the session description
+const RTP_PAYLOAD_TYPE: u8 = 96;
+
+/// How the sender encodes audio
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Codec {
+    /// Uncompressed, 32 bit float unless `pcm-s16`, `pcm-s24`, `pcm-s32` or `pcm-f64` is asked
+    /// for
+    Pcm(SampleFormat),
+    /// Opus in 10 ms packets, each carrying a low bitrate copy of the previous one
+    #[cfg(feature = "opus")]
+    Opus,
+    /// AES67 compatible RTP, for pro-audio receivers, without timeline events
+    Aes67,
+}
+
+impl Default for Codec {
+    fn default() -> Self {
+        Codec::Pcm(SampleFormat::F32)
+    }
+}
+
+impl FromStr for Codec {
+    type Err = anyhow::Error;
+
+    fn from_str(text: &str) -> anyhow::Result<Self> {
+        Ok(match text {
+            "pcm" => Codec::Pcm(SampleFormat::F32),
+            #[cfg(feature = "opus")]
+            "opus" => Codec::Opus,
+            #[cfg(not(feature = "opus"))]
+            "opus" => bail!("Opus needs the 'opus' feature"),
+            "aes67" => Codec::Aes67,
+            _ if text.starts_with("pcm-") => Codec::Pcm(text["pcm-".len()..].parse()?),
+            _ => bail!("Unknown codec '{text}', expected 'pcm', 'opus' or 'aes67'"),
+        })
+    }
+}
+
+/// Audio a network input holds back before playing, to ride out uneven packet arrival
+#[derive(Clone, Debug)]
+pub struct JitterBuffer {
+    pub min: Duration,
+    /// Adapts between `min` and `max` to the measured jitter and underruns, fixed at `min` if
+    /// both are the same
+    pub max: Duration,
+}
+
+impl Default for JitterBuffer {
+    fn default() -> Self {
+        Self {
+            min: Duration::from_millis(20),
+            max: Duration::from_millis(500),
+        }
+    }
+}
+
+impl JitterBuffer {
+    /// Parses `<size>` for a fixed buffer or `<min>:<max>` for an adaptive one, e.g. `20ms:500ms`
+    pub fn parse(text: &str) -> anyhow::Result<Self> {
+        let (min, max) = match text.split_once(':') {
+            Some((min, max)) => (parse_duration(min)?, parse_duration(max)?),
+            None => (parse_duration(text)?, parse_duration(text)?),
+        };
+        if min > max {
+            bail!("The jitter buffer minimum must not be above its maximum");
+        }
+        Ok(Self { min, max })
+    }
+}
+
+/// How well packets arrive at a network input
+#[derive(Clone, Debug, Default)]
+pub struct LinkQuality {
+    pub received: u64,
+    /// Packets that never arrived in time and were concealed
+    pub lost: u64,
+    /// Lost packets restored from the error correction data of the following one
+    pub recovered: u64,
+    /// Packets that arrived out of order but in time to be put back in sequence
+    pub reordered: u64,
+    /// Packets that arrived after they had been concealed
+    pub late: u64,
+    /// Malformed packets or packets with the wrong channel count
+    pub rejected: u64,
+    /// Smoothed one-way latency in seconds
+    pub latency: f64,
+    /// Interarrival jitter in seconds as defined for RTP
+    pub jitter: f64,
+    /// Current jitter buffer size in seconds
+    pub buffer: f64,
+    /// Times the jitter buffer ran dry
+    pub underruns: u64,
+}
+
+impl fmt::Display for LinkQuality {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let expected = (self.received + self.lost).max(1);
+        write!(
+            f,
+            "{:.1} ms latency, {:.1} ms jitter, {:.1}% lost, {} reordered, {} late, {:.0} ms buffer, {} underruns",
+            self.latency * 1000.0,
+            self.jitter * 1000.0,
+            self.lost as f64 * 100.0 / expected as f64,
+            self.reordered,
+            self.late,
+            self.buffer * 1000.0,
+            self.underruns
+        )?;
+        if self.recovered > 0 {
+            write!(f, ", {} recovered", self.recovered)?;
+        }
+        Ok(())
+    }
+}
+
+/// Sends the output to a receiving instance, fed by the process callback
+pub struct NetSender {
+    producer: HeapProducer<f32>,
+    channel_count: usize,
+    events: mpsc::Sender<String>,
+    /// Samples that did not fit into the ring buffer and were lost
+    dropped: Arc<AtomicUsize>,
+    /// Session description of an AES67 stream
+    sdp: Option<String>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl NetSender {
+    pub fn start(
+        address: &str,
+        channel_count: usize,
+        sample_rate: usize,
+        codec: Codec,
+    ) -> anyhow::Result<Self> {
+        let socket = UdpSocket::bind("0.0.0.0:0").context("Failed to open a UDP socket")?;
+        let address = address
+            .to_socket_addrs()
+            .with_context(|| format!("Invalid address '{address}'"))?
+            .next()
+            .with_context(|| format!("'{address}' did not resolve"))?;
+        socket
+            .connect(address)
+            .with_context(|| format!("Failed to send to {address}"))?;
+        let encoder = Encoder::new(codec, channel_count, sample_rate)?;
+        let sdp = match &encoder {
+            Encoder::Rtp(stream) => Some(stream.sdp(socket.local_addr()?, address, channel_count)),
+            _ => None,
+        };
+
+        let (producer, consumer) =
+            HeapRb::new(BUFFER_SECONDS * sample_rate * channel_count).split();
+        let (events, event_receiver) = mpsc::channel();
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = stop.clone();
+        let thread = std::thread::spawn(move || {
+            send_loop(
+                &socket,
+                consumer,
+                &event_receiver,
+                encoder,
+                channel_count,
+                &thread_stop,
+            )
+        });
+        Ok(Self {
+            producer,
+            channel_count,
+            events,
+            dropped: Arc::new(AtomicUsize::new(0)),
+            sdp,
+            stop,
+            thread: Some(thread),
+        })
+    }
+
+    /// Queues a block of the output for sending, called from the process callback
+    pub fn write(&mut self, channels: &[&[f32]]) {
+        let frame_count = channels.first().map_or(0, |channel| channel.len());
+        if self.producer.free_len() < frame_count * self.channel_count {
+            self.dropped
+                .fetch_add(frame_count * self.channel_count, Ordering::Relaxed);
+            return;
+        }
+        for frame in 0..frame_count {
+            for channel in channels {
+                let _ = self.producer.push(channel[frame]);
+            }
+        }
+    }
+
+    /// Passes a timeline event on to the receiver
+    pub fn send_event(&self, text: String) {
+        let _ = self.events.send(text);
+    }
+
+    pub fn dropped_samples(&self) -> usize {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Session description to configure receivers with, for AES67 streams
+    pub fn sdp(&self) -> Option<&str> {
+        self.sdp.as_deref()
+    }
+}
+
+impl Drop for NetSender {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn send_loop(
+    socket: &UdpSocket,
+    mut consumer: HeapConsumer<f32>,
+    events: &mpsc::Receiver<String>,
+    mut encoder: Encoder,
+    channel_count: usize,
+    stop: &AtomicBool,
+) {
+    let mut samples = vec![0.0; encoder.packet_frames() * channel_count];
+    let mut packet = Vec::with_capacity(AUDIO_HEADER_BYTES + samples.len() * 4);
+    let mut sequence: u64 = 0;
+    while !stop.load(Ordering::Relaxed) {
+        for text in events.try_iter() {
+            // RTP receivers wouldn't know what to make of them
+            if matches!(encoder, Encoder::Rtp(_)) {
+                continue;
+            }
+            let mut packet = vec![KIND_EVENT];
+            packet.extend_from_slice(text.as_bytes());
+            // Nobody might be listening yet, the stream carries on regardless
+            let _ = socket.send(&packet);
+        }
+        if consumer.len() < samples.len() {
+            std::thread::sleep(SEND_INTERVAL);
+            continue;
+        }
+        consumer.pop_slice(&mut samples);
+        packet.clear();
+        if let Err(error) = encoder.encode(sequence, &samples, channel_count, &mut packet) {
+            eprintln!("<4>Failed to encode network audio: {error:#}");
+            continue;
+        }
+        sequence += 1;
+        let _ = socket.send(&packet);
+    }
+}
+
+/// Turns samples into audio packets
+enum Encoder {
+    Pcm(SampleFormat),
+    #[cfg(feature = "opus")]
+    Opus {
+        encoder: audiopus::coder::Encoder,
+        sample_rate: usize,
+    },
+    Rtp(RtpStream),
+}
+
+impl Encoder {
+    fn new(codec: Codec, channel_count: usize, sample_rate: usize) -> anyhow::Result<Self> {
+        Ok(match codec {
+            Codec::Pcm(format) => Encoder::Pcm(format),
+            #[cfg(feature = "opus")]
+            Codec::Opus => {
+                let mut encoder = audiopus::coder::Encoder::new(
+                    opus_sample_rate(sample_rate)?,
+                    opus_channels(channel_count)?,
+                    audiopus::Application::Audio,
+                )?;
+                encoder.set_inband_fec(true)?;
+                // Tells the encoder how much redundancy is worth spending bits on
+                encoder.set_packet_loss_perc(10)?;
+                Encoder::Opus {
+                    encoder,
+                    sample_rate,
+                }
+            }
+            Codec::Aes67 => Encoder::Rtp(RtpStream::new(channel_count, sample_rate)?),
+        })
+    }
+
+    fn packet_frames(&self) -> usize {
+        match self {
+            // The same size in bytes whatever the format
+            Encoder::Pcm(format) => PACKET_FRAMES * 4 / format.bytes(),
+            #[cfg(feature = "opus")]
+            Encoder::Opus { sample_rate, .. } => opus_packet_frames(*sample_rate),
+            Encoder::Rtp(_) => RTP_PACKET_FRAMES,
+        }
+    }
+
+    /// Writes the packet with number `sequence` carrying `samples` to `packet`
+    fn encode(
+        &mut self,
+        sequence: u64,
+        samples: &[f32],
+        channel_count: usize,
+        packet: &mut Vec<u8>,
+    ) -> anyhow::Result<()> {
+        let kind = match self {
+            Encoder::Pcm(SampleFormat::F32) => KIND_AUDIO,
+            Encoder::Pcm(_) => KIND_PCM,
+            #[cfg(feature = "opus")]
+            Encoder::Opus { .. } => KIND_OPUS,
+            Encoder::Rtp(stream) => {
+                stream.encode(sequence, samples, channel_count, packet);
+                return Ok(());
+            }
+        };
+        packet.extend_from_slice(&[kind, channel_count as u8]);
+        packet.extend_from_slice(&sequence.to_le_bytes());
+        packet.extend_from_slice(&now_micros().to_le_bytes());
+        match self {
+            #[cfg(feature = "opus")]
+            Encoder::Opus { encoder, .. } => {
+                let start = packet.len();
+                packet.resize(start + 4000, 0);
+                let length = encoder.encode_float(samples, &mut packet[start..])?;
+                packet.truncate(start + length);
+            }
+            Encoder::Pcm(format) => {
+                if kind == KIND_PCM {
+                    packet.push(format.id());
+                }
+                for &sample in samples {
+                    format.encode(sample, packet);
+                }
+            }
+            Encoder::Rtp(_) => unreachable!(),
+        }
+        Ok(())
+    }
+}
+
+/// An AES67 stream: RTP with 24 bit big-endian PCM in 1 ms packets at 48 kHz.
+///
+/// There is no PTP clock, the timestamps count samples from a random start and the stream runs
+/// at whatever rate JACK runs, so receivers locked to a PTP grandmaster slowly drift against it.
+struct RtpStream {
+    ssrc: u32,
+    /// Offsets of the sequence numbers and timestamps, random as RFC 3550 asks
+    first_sequence: u16,
+    first_timestamp: u32,
+}
+
+impl RtpStream {
+    fn new(channel_count: usize, sample_rate: usize) -> anyhow::Result<Self> {
+        if sample_rate != RTP_SAMPLE_RATE {
+            bail!("AES67 streams run at {RTP_SAMPLE_RATE} Hz, JACK runs at {sample_rate} Hz");
+        }
+        if !(1..=RTP_MAX_CHANNELS).contains(&channel_count) {
+            bail!("AES67 streams have 1 to {RTP_MAX_CHANNELS} channels, not {channel_count}");
+        }
+        let mut hasher = RandomState::new().build_hasher();
+        let random = hasher.finish();
+        hasher.write_u64(random);
+        Ok(Self {
+            ssrc: random as u32,
+            first_sequence: (random >> 32) as u16,
+            first_timestamp: hasher.finish() as u32,
+        })
+    }
+
+    fn encode(&self, sequence: u64, samples: &[f32], channel_count: usize, packet: &mut Vec<u8>) {
+        let timestamp = self
+            .first_timestamp
+            .wrapping_add((sequence * RTP_PACKET_FRAMES as u64) as u32);
+        // Version 2 without padding, extensions or contributing sources, no marker
+        packet.extend_from_slice(&[0x80, RTP_PAYLOAD_TYPE]);
+        packet.extend_from_slice(
+            &self
+                .first_sequence
+                .wrapping_add(sequence as u16)
+                .to_be_bytes(),
+        );
+        packet.extend_from_slice(&timestamp.to_be_bytes());
+        packet.extend_from_slice(&self.ssrc.to_be_bytes());
+        debug_assert_eq!(samples.len(), RTP_PACKET_FRAMES * channel_count);
+        for sample in samples {
+            let sample = (sample.clamp(-1.0, 1.0) * 8_388_607.0) as i32;
+            packet.extend_from_slice(&sample.to_be_bytes()[1..]);
+        }
+    }
+
+    /// Session description for receivers that can't discover the stream themselves
+    fn sdp(&self, source: SocketAddr, destination: SocketAddr, channel_count: usize) -> String {
+        let scope = if destination.ip().is_multicast() {
+            "/32"
+        } else {
+            ""
+        };
+        format!(
+            "v=0\n\
+             o=- {ssrc} 0 IN IP4 {source}\n\
+             s=audiomux\n\
+             c=IN IP4 {destination}{scope}\n\
+             t=0 0\n\
+             m=audio {port} RTP/AVP {RTP_PAYLOAD_TYPE}\n\
+             a=rtpmap:{RTP_PAYLOAD_TYPE} L24/{RTP_SAMPLE_RATE}/{channel_count}\n\
+             a=ptime:1\n\
+             a=ts-refclk:local\n\
+             a=mediaclk:direct={offset}\n",
+            ssrc = self.ssrc,
+            source = source.ip(),
+            destination = destination.ip(),
+            port = destination.port(),
+            offset = self.first_timestamp,
+        )
+    }
+}
+
+/// Audio and events received from a sending instance, the source of a network input
+pub struct NetReceiver {
+    consumer: HeapConsumer<f32>,
+    channel_count: usize,
+    sample_rate: usize,
+    events: mpsc::Receiver<String>,
+    quality: Arc<Mutex<LinkQuality>>,
+    jitter_buffer: JitterBuffer,
+    /// Frames to collect before playing starts again
+    target_frames: usize,
+    /// Waiting for the jitter buffer to fill up
+    buffering: bool,
+    underruns: u64,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl NetReceiver {
+    pub fn start(
+        address: &str,
+        channel_count: usize,
+        sample_rate: usize,
+        jitter_buffer: JitterBuffer,
+    ) -> anyhow::Result<Self> {
+        let socket =
+            UdpSocket::bind(address).with_context(|| format!("Failed to listen on {address}"))?;
+        socket.set_read_timeout(Some(RECEIVE_TIMEOUT))?;
+        let buffer_seconds = BUFFER_SECONDS + jitter_buffer.max.as_secs() as usize;
+        let (producer, consumer) =
+            HeapRb::new(buffer_seconds * sample_rate * channel_count).split();
+        let (event_sender, events) = mpsc::channel();
+        let quality = Arc::new(Mutex::new(LinkQuality::default()));
+        let thread_quality = quality.clone();
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = stop.clone();
+        let thread = std::thread::spawn(move || {
+            let sequencer = Sequencer::new(channel_count, sample_rate);
+            receive_loop(
+                &socket,
+                producer,
+                &event_sender,
+                sequencer,
+                channel_count,
+                &thread_quality,
+                &thread_stop,
+            )
+        });
+        Ok(Self {
+            consumer,
+            channel_count,
+            sample_rate,
+            events,
+            quality,
+            target_frames: (jitter_buffer.min.as_secs_f64() * sample_rate as f64) as usize,
+            jitter_buffer,
+            buffering: true,
+            underruns: 0,
+            stop,
+            thread: Some(thread),
+        })
+    }
+
+    /// Takes one period of received audio, `None` while the jitter buffer fills up
+    pub fn read(&mut self, frame_count: usize) -> Option<Vec<Vec<f32>>> {
+        let available = self.consumer.len() / self.channel_count;
+        if self.buffering {
+            if available < self.target_frames + frame_count {
+                return None;
+            }
+            self.buffering = false;
+        }
+        if available < frame_count {
+            self.buffering = true;
+            self.underruns += 1;
+            // Ran dry, so hold back more from now on
+            self.target_frames =
+                (self.target_frames * 3 / 2 + frame_count).min(self.frames(self.jitter_buffer.max));
+            return None;
+        }
+        self.adapt();
+
+        let mut period = vec![Vec::with_capacity(frame_count); self.channel_count];
+        for _ in 0..frame_count {
+            for channel in period.iter_mut() {
+                channel.push(self.consumer.pop().unwrap_or(0.0));
+            }
+        }
+        Some(period)
+    }
+
+    /// Moves the jitter buffer size towards a multiple of the measured jitter, growing at once
+    /// and shrinking slowly
+    fn adapt(&mut self) {
+        if self.jitter_buffer.min == self.jitter_buffer.max {
+            return;
+        }
+        // The audio thread must not wait for the receiving thread
+        let Ok(quality) = self.quality.try_lock() else {
+            return;
+        };
+        let desired = ((JITTER_FACTOR * quality.jitter * self.sample_rate as f64) as usize).clamp(
+            self.frames(self.jitter_buffer.min),
+            self.frames(self.jitter_buffer.max),
+        );
+        if desired > self.target_frames {
+            self.target_frames = desired;
+        } else {
+            let shrink = ((self.target_frames - desired) as f64 * JITTER_SHRINK).ceil();
+            self.target_frames -= shrink as usize;
+        }
+    }
+
+    fn frames(&self, duration: Duration) -> usize {
+        (duration.as_secs_f64() * self.sample_rate as f64) as usize
+    }
+
+    /// Timeline events the sender reported since the last call
+    pub fn events(&self) -> impl Iterator<Item = String> + '_ {
+        self.events.try_iter()
+    }
+
+    pub fn link_quality(&self) -> LinkQuality {
+        let mut quality = self.quality.lock().unwrap().clone();
+        quality.buffer = self.target_frames as f64 / self.sample_rate.max(1) as f64;
+        quality.underruns = self.underruns;
+        quality
+    }
+}
+
+impl Drop for NetReceiver {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn receive_loop(
+    socket: &UdpSocket,
+    mut producer: HeapProducer<f32>,
+    events: &mpsc::Sender<String>,
+    mut sequencer: Sequencer,
+    channel_count: usize,
+    quality: &Mutex<LinkQuality>,
+    stop: &AtomicBool,
+) {
+    let mut packet = vec![0; 65536];
+    while !stop.load(Ordering::Relaxed) {
+        let Ok(length) = socket.recv(&mut packet) else {
+            // Timed out or a transient error, check whether to stop
+            continue;
+        };
+        let mut quality = quality.lock().unwrap();
+        let payload = match &packet[..length] {
+            [KIND_EVENT, text @ ..] => {
+                let _ = events.send(String::from_utf8_lossy(text).into_owned());
+                continue;
+            }
+            [kind @ (KIND_AUDIO | KIND_OPUS | KIND_PCM), channels, rest @ ..]
+                if *channels as usize == channel_count && rest.len() >= AUDIO_HEADER_BYTES - 2 =>
+            {
+                let (header, data) = rest.split_at(AUDIO_HEADER_BYTES - 2);
+                match Payload::parse(*kind, data, channel_count) {
+                    Some(payload) => (header, payload),
+                    None => {
+                        quality.rejected += 1;
+                        continue;
+                    }
+                }
+            }
+            _ => {
+                quality.rejected += 1;
+                continue;
+            }
+        };
+        let (header, payload) = payload;
+        let (sequence, sent_at) = header.split_at(8);
+        sequencer.receive(
+            u64::from_le_bytes(sequence.try_into().unwrap()),
+            u64::from_le_bytes(sent_at.try_into().unwrap()),
+            payload,
+            &mut quality,
+            &mut producer,
+        );
+    }
+}
+
+/// Audio of a packet as received
+enum Payload {
+    Pcm(Vec<f32>),
+    #[cfg(feature = "opus")]
+    Opus(Vec<u8>),
+}
+
+impl Payload {
+    /// `None` for payloads this build can't play
+    fn parse(kind: u8, data: &[u8], channel_count: usize) -> Option<Self> {
+        match kind {
+            KIND_AUDIO => Self::parse_pcm(SampleFormat::F32, data, channel_count),
+            KIND_PCM => {
+                let (&format, data) = data.split_first()?;
+                Self::parse_pcm(SampleFormat::from_id(format)?, data, channel_count)
+            }
+            #[cfg(feature = "opus")]
+            KIND_OPUS => Some(Payload::Opus(data.to_vec())),
+            _ => None,
+        }
+    }
+
+    fn parse_pcm(format: SampleFormat, data: &[u8], channel_count: usize) -> Option<Self> {
+        if !data.len().is_multiple_of(format.bytes() * channel_count) {
+            return None;
+        }
+        Some(Payload::Pcm(
+            data.chunks_exact(format.bytes())
+                .map(|sample| format.decode(sample))
+                .collect(),
+        ))
+    }
+}
+
+/// Puts audio packets back in order and conceals the lost ones
+struct Sequencer {
+    /// Sequence number of the packet to be played next
+    next: Option<u64>,
+    /// Packets that arrived ahead of `next`
+    pending: BTreeMap<u64, Payload>,
+    /// Samples of the last packet passed on, repeated to conceal losses
+    last: Vec<f32>,
+    /// Transit time of the previous packet in seconds, for the jitter
+    previous_transit: Option<f64>,
+    #[cfg(feature = "opus")]
+    decoder: Option<audiopus::coder::Decoder>,
+    #[cfg(feature = "opus")]
+    channel_count: usize,
+    #[cfg(feature = "opus")]
+    sample_rate: usize,
+}
+
+impl Sequencer {
+    fn new(channel_count: usize, sample_rate: usize) -> Self {
+        #[cfg(not(feature = "opus"))]
+        let _ = (channel_count, sample_rate);
+        Self {
+            next: None,
+            pending: BTreeMap::new(),
+            last: Vec::new(),
+            previous_transit: None,
+            #[cfg(feature = "opus")]
+            decoder: None,
+            #[cfg(feature = "opus")]
+            channel_count,
+            #[cfg(feature = "opus")]
+            sample_rate,
+        }
+    }
+
+    fn receive(
+        &mut self,
+        sequence: u64,
+        sent_at_micros: u64,
+        payload: Payload,
+        quality: &mut LinkQuality,
+        producer: &mut HeapProducer<f32>,
+    ) {
+        quality.received += 1;
+        let transit = now_micros() as f64 / 1e6 - sent_at_micros as f64 / 1e6;
+        if let Some(previous) = self.previous_transit {
+            quality.jitter += ((transit - previous).abs() - quality.jitter) / 16.0;
+            quality.latency += (transit - quality.latency) * LATENCY_SMOOTHING;
+        } else {
+            quality.latency = transit;
+        }
+        self.previous_transit = Some(transit);
+
+        let next = *self.next.get_or_insert(sequence);
+        if sequence.abs_diff(next) > RESYNC_PACKETS {
+            self.next = Some(sequence);
+            self.pending.clear();
+        } else if sequence < next {
+            quality.late += 1;
+            return;
+        } else if self
+            .pending
+            .last_key_value()
+            .is_some_and(|(&newest, _)| newest > sequence)
+        {
+            quality.reordered += 1;
+        }
+        self.pending.insert(sequence, payload);
+
+        while let Some(next) = self.next {
+            let samples = match self.pending.remove(&next) {
+                Some(payload) => self.decode(payload),
+                None if self.pending.len() > REORDER_PACKETS => {
+                    quality.lost += 1;
+                    self.conceal(next, quality)
+                }
+                None => break,
+            };
+            // Whole packets only, so the channels never get out of step
+            if producer.free_len() >= samples.len() {
+                producer.push_slice(&samples);
+            }
+            self.last = samples;
+            self.next = Some(next + 1);
+        }
+    }
+
+    fn decode(&mut self, payload: Payload) -> Vec<f32> {
+        match payload {
+            Payload::Pcm(samples) => samples,
+            #[cfg(feature = "opus")]
+            Payload::Opus(data) => self.decode_opus(Some(&data), false),
+        }
+    }
+
+    /// Replaces the lost packet `sequence`
+    fn conceal(&mut self, sequence: u64, quality: &mut LinkQuality) -> Vec<f32> {
+        #[cfg(feature = "opus")]
+        if self.decoder.is_some() {
+            // Every Opus packet carries a low bitrate copy of the one before it
+            if let Some(Payload::Opus(data)) = self.pending.get(&(sequence + 1)) {
+                let data = data.clone();
+                quality.recovered += 1;
+                return self.decode_opus(Some(&data), true);
+            }
+            return self.decode_opus(None, false);
+        }
+        let _ = (sequence, quality);
+        if self.last.is_empty() {
+            return Vec::new();
+        }
+        // Repeats the last packet attenuated, so consecutive losses fade out
+        self.last
+            .iter()
+            .map(|sample| sample * CONCEALMENT_DECAY)
+            .collect()
+    }
+
+    /// Decodes a packet, its error correction data with `fec`, or conceals a loss without data
+    #[cfg(feature = "opus")]
+    fn decode_opus(&mut self, data: Option<&[u8]>, fec: bool) -> Vec<f32> {
+        let mut samples = vec![0.0; opus_packet_frames(self.sample_rate) * self.channel_count];
+        if self.decoder.is_none() {
+            let decoder = opus_sample_rate(self.sample_rate).and_then(|sample_rate| {
+                Ok(audiopus::coder::Decoder::new(
+                    sample_rate,
+                    opus_channels(self.channel_count)?,
+                )?)
+            });
+            match decoder {
+                Ok(decoder) => self.decoder = Some(decoder),
+                Err(error) => {
+                    eprintln!("<4>Failed to create an Opus decoder: {error:#}");
+                    return samples;
+                }
+            }
+        }
+        let decoder = self.decoder.as_mut().unwrap();
+        let packet = data.and_then(|data| audiopus::packet::Packet::try_from(data).ok());
+        let decoded = audiopus::MutSignals::try_from(&mut samples[..])
+            .and_then(|output| decoder.decode_float(packet, output, fec));
+        match decoded {
+            Ok(frames) => samples.truncate(frames * self.channel_count),
+            Err(error) => eprintln!("<4>Failed to decode network audio: {error}"),
+        }
+        samples
+    }
+}
+
+#[cfg(feature = "opus")]
+fn opus_sample_rate(sample_rate: usize) -> anyhow::Result<audiopus::SampleRate> {
+    audiopus::SampleRate::try_from(sample_rate as i32)
+        .map_err(|_| anyhow::anyhow!("Opus doesn't support a sample rate of {sample_rate} Hz"))
+}
+
+#[cfg(feature = "opus")]
+fn opus_channels(channel_count: usize) -> anyhow::Result<audiopus::Channels> {
+    match channel_count {
+        1 => Ok(audiopus::Channels::Mono),
+        2 => Ok(audiopus::Channels::Stereo),
+        _ => bail!("Opus streams have one or two channels, not {channel_count}"),
+    }
+}
+
+/// 10 ms, the shortest packets error correction works with
+#[cfg(feature = "opus")]
+fn opus_packet_frames(sample_rate: usize) -> usize {
+    sample_rate / 100
+}
+
+fn now_micros() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_micros() as u64
+}
@@ -0,0 +1,294 @@
+//! Ogg Opus files. The Ogg pages are written here, libopus only encodes the packets.
+
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    ops::RangeInclusive,
+    path::Path,
+};
+
+use anyhow::{bail, Context};
+
+use crate::{
+    encoder::Encoder,
+    stretch::{Resampler, TimeStretch},
+};
+
+/// kbit/s for all channels, when not given
+pub const DEFAULT_BITRATE: u32 = 96;
+/// kbit/s libopus accepts
+pub const BITRATES: RangeInclusive<u32> = 6..=510;
+
+/// The only rate Opus files are decoded at, other rates are resampled to it
+const OPUS_RATE: usize = 48000;
+/// 20 ms, the usual packet length for stored audio
+const PACKET_FRAMES: usize = OPUS_RATE / 50;
+/// Packets per Ogg page, a second of audio
+const PACKETS_PER_PAGE: usize = 50;
+/// Frames taken from the resampler at a time
+const RESAMPLE_CHUNK_FRAMES: usize = 4096;
+
+/// Encodes to an Ogg Opus file as the audio comes in
+pub struct OpusFile {
+    encoder: audiopus::coder::Encoder,
+    ogg: OggWriter<BufWriter<File>>,
+    channel_count: usize,
+    /// Converts other sample rates to 48 kHz
+    resampler: Option<Resampler>,
+    /// Interleaved samples at 48 kHz short of a whole packet
+    pending: Vec<f32>,
+    /// Packets of the page being filled
+    packets: Vec<Vec<u8>>,
+    pre_skip: usize,
+    /// Frames put into packets, the padding of the last one included
+    encoded: usize,
+    /// Frames of audio at 48 kHz
+    frames: usize,
+}
+
+impl OpusFile {
+    /// `comments` are Vorbis comments like `TITLE=...`
+    pub fn create(
+        path: &Path,
+        channel_count: usize,
+        sample_rate: usize,
+        bitrate: u32,
+        comments: &[String],
+    ) -> anyhow::Result<Self> {
+        let channels = match channel_count {
+            1 => audiopus::Channels::Mono,
+            2 => audiopus::Channels::Stereo,
+            _ => bail!("Opus files have one or two channels, not {channel_count}"),
+        };
+        let mut encoder = audiopus::coder::Encoder::new(
+            audiopus::SampleRate::Hz48000,
+            channels,
+            audiopus::Application::Audio,
+        )?;
+        encoder.set_bitrate(audiopus::Bitrate::BitsPerSecond(bitrate as i32 * 1000))?;
+        let pre_skip = encoder.lookahead()? as u16;
+
+        let resampler = (sample_rate != OPUS_RATE).then(|| {
+            let mut resampler = Resampler::default();
+            resampler.set_channels(channel_count);
+            resampler.set_rate(sample_rate as f64 / OPUS_RATE as f64);
+            resampler
+        });
+
+        let file =
+            File::create(path).with_context(|| format!("Failed to create {}", path.display()))?;
+        let mut ogg = OggWriter::new(BufWriter::new(file));
+        ogg.write_page(&[opus_head(channel_count as u8, pre_skip)], 0, false)?;
+        ogg.write_page(&[opus_tags(comments)], 0, false)?;
+        Ok(Self {
+            encoder,
+            ogg,
+            channel_count,
+            resampler,
+            pending: Vec::new(),
+            packets: Vec::new(),
+            pre_skip: pre_skip.into(),
+            encoded: 0,
+            frames: 0,
+        })
+    }
+
+    /// Takes what the resampler has ready
+    fn receive_resampled(&mut self) {
+        let Some(resampler) = &mut self.resampler else {
+            return;
+        };
+        let mut chunk = vec![0.0; RESAMPLE_CHUNK_FRAMES * self.channel_count];
+        loop {
+            let frames = resampler.receive_samples(&mut chunk, RESAMPLE_CHUNK_FRAMES);
+            if frames == 0 {
+                break;
+            }
+            self.pending
+                .extend_from_slice(&chunk[..frames * self.channel_count]);
+            self.frames += frames;
+        }
+    }
+
+    /// Encodes the whole packets pending, or with the end of the stream given everything up to
+    /// it, padding the last packet with silence
+    fn encode_pending(&mut self, end_of_stream: Option<usize>) -> anyhow::Result<()> {
+        let packet_samples = PACKET_FRAMES * self.channel_count;
+        let mut start = 0;
+        loop {
+            let finishing = end_of_stream.is_some_and(|end| self.encoded < end);
+            if self.pending.len() - start < packet_samples && !finishing {
+                break;
+            }
+            let mut frame = vec![0.0; packet_samples];
+            let end = (start + packet_samples).min(self.pending.len());
+            frame[..end - start].copy_from_slice(&self.pending[start..end]);
+            start = end;
+            let mut packet = vec![0; 4000];
+            let length = self.encoder.encode_float(&frame, &mut packet)?;
+            packet.truncate(length);
+
+            // Pages hold up to 255 lacing values, a value per started 255 bytes of a packet
+            let lacing: usize = self
+                .packets
+                .iter()
+                .map(|packet| packet.len() / 255 + 1)
+                .sum();
+            if lacing + packet.len() / 255 + 1 > 255 {
+                self.ogg
+                    .write_page(&self.packets, self.encoded as u64, false)?;
+                self.packets.clear();
+            }
+            self.packets.push(packet);
+            self.encoded += PACKET_FRAMES;
+
+            if let Some(end) = end_of_stream.filter(|end| self.encoded >= *end) {
+                // Decoders cut the last page at its granule position, dropping the padding
+                self.ogg.write_page(&self.packets, end as u64, true)?;
+                self.packets.clear();
+                break;
+            }
+            if self.packets.len() == PACKETS_PER_PAGE {
+                self.ogg
+                    .write_page(&self.packets, self.encoded as u64, false)?;
+                self.packets.clear();
+            }
+        }
+        self.pending.drain(..start);
+        Ok(())
+    }
+}
+
+impl Encoder for OpusFile {
+    fn write(&mut self, samples: &[f32]) -> anyhow::Result<()> {
+        match &mut self.resampler {
+            Some(resampler) => {
+                resampler.put_samples(samples, samples.len() / self.channel_count);
+                self.receive_resampled();
+            }
+            None => {
+                self.pending.extend_from_slice(samples);
+                self.frames += samples.len() / self.channel_count;
+            }
+        }
+        self.encode_pending(None)
+    }
+
+    fn flush(&mut self) -> anyhow::Result<()> {
+        Ok(self.ogg.output.flush()?)
+    }
+
+    fn bytes(&self) -> u64 {
+        self.ogg.bytes
+    }
+
+    fn finish(mut self: Box<Self>) -> anyhow::Result<()> {
+        if let Some(resampler) = &mut self.resampler {
+            resampler.flush();
+            self.receive_resampled();
+        }
+        // The encoder's lookahead is made up for with silence at the end
+        let end_of_stream = self.frames + self.pre_skip;
+        self.encode_pending(Some(end_of_stream))?;
+        Ok(self.ogg.output.flush()?)
+    }
+}
+
+/// Identification header of an Ogg Opus stream, as RFC 7845 describes it
+fn opus_head(channel_count: u8, pre_skip: u16) -> Vec<u8> {
+    let mut head = b"OpusHead".to_vec();
+    head.push(1);
+    head.push(channel_count);
+    head.extend_from_slice(&pre_skip.to_le_bytes());
+    head.extend_from_slice(&(OPUS_RATE as u32).to_le_bytes());
+    // Output gain, and the channel mapping for mono and stereo
+    head.extend_from_slice(&0i16.to_le_bytes());
+    head.push(0);
+    head
+}
+
+/// Comment header with the given Vorbis comments
+fn opus_tags(comments: &[String]) -> Vec<u8> {
+    fn push_string(tags: &mut Vec<u8>, text: &str) {
+        tags.extend_from_slice(&(text.len() as u32).to_le_bytes());
+        tags.extend_from_slice(text.as_bytes());
+    }
+    let mut tags = b"OpusTags".to_vec();
+    // The vendor string
+    push_string(&mut tags, "Audio Multiplexer");
+    tags.extend_from_slice(&(comments.len() as u32).to_le_bytes());
+    for comment in comments {
+        push_string(&mut tags, comment);
+    }
+    tags
+}
+
+/// Writes packets into the pages of a single logical Ogg stream
+struct OggWriter<W: Write> {
+    output: W,
+    serial: u32,
+    sequence: u32,
+    bytes: u64,
+}
+
+impl<W: Write> OggWriter<W> {
+    fn new(output: W) -> Self {
+        Self {
+            output,
+            serial: std::process::id(),
+            sequence: 0,
+            bytes: 0,
+        }
+    }
+
+    /// Writes a page of whole packets, at most 255 lacing values of them
+    fn write_page(&mut self, packets: &[Vec<u8>], granule: u64, last: bool) -> anyhow::Result<()> {
+        let mut lacing = Vec::new();
+        for packet in packets {
+            lacing.extend(std::iter::repeat_n(255, packet.len() / 255));
+            lacing.push((packet.len() % 255) as u8);
+        }
+        if lacing.len() > 255 {
+            bail!("Too many packets for an Ogg page");
+        }
+        let header_type = match (self.sequence, last) {
+            (0, _) => 0x02,
+            (_, true) => 0x04,
+            _ => 0x00,
+        };
+        let mut page = b"OggS".to_vec();
+        page.push(0);
+        page.push(header_type);
+        page.extend_from_slice(&granule.to_le_bytes());
+        page.extend_from_slice(&self.serial.to_le_bytes());
+        page.extend_from_slice(&self.sequence.to_le_bytes());
+        // The checksum, filled in once the page is complete
+        page.extend_from_slice(&[0; 4]);
+        page.push(lacing.len() as u8);
+        page.extend_from_slice(&lacing);
+        for packet in packets {
+            page.extend_from_slice(packet);
+        }
+        let crc = ogg_crc(&page);
+        page[22..26].copy_from_slice(&crc.to_le_bytes());
+        self.output.write_all(&page)?;
+        self.sequence += 1;
+        self.bytes += page.len() as u64;
+        Ok(())
+    }
+}
+
+/// CRC-32 of Ogg pages: polynomial 0x04c11db7, no reflection, starting from zero
+fn ogg_crc(data: &[u8]) -> u32 {
+    data.iter().fold(0u32, |crc, &byte| {
+        let mut crc = crc ^ (u32::from(byte) << 24);
+        for _ in 0..8 {
+            crc = if crc & 0x8000_0000 != 0 {
+                (crc << 1) ^ 0x04c1_1db7
+            } else {
+                crc << 1
+            };
+        }
+        crc
+    })
+}
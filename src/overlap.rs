@@ -0,0 +1,93 @@
+use std::{collections::BTreeSet, fmt, str::FromStr};
+
+use anyhow::bail;
+
+/// How two inputs with audio queued at the same time share the output
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Overlap {
+    /// One after the other, the default
+    Serialize,
+    /// Both at once, the less urgent one at natural speed
+    Mix,
+}
+
+impl FromStr for Overlap {
+    type Err = anyhow::Error;
+
+    fn from_str(text: &str) -> anyhow::Result<Self> {
+        Ok(match text {
+            "serialize" => Overlap::Serialize,
+            "mix" => Overlap::Mix,
+            _ => bail!("Unknown overlap policy '{text}', expected 'mix' or 'serialize'"),
+        })
+    }
+}
+
+impl fmt::Display for Overlap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Overlap::Serialize => write!(f, "serialize"),
+            Overlap::Mix => write!(f, "mix"),
+        }
+    }
+}
+
+/// Symmetric matrix of overlap policies between pairs of inputs, by name
+#[derive(Clone, Debug, Default)]
+pub struct OverlapPolicy {
+    /// Pairs that mix, the smaller name first
+    mixing: BTreeSet<(String, String)>,
+}
+
+impl OverlapPolicy {
+    pub fn set(&mut self, first: &str, second: &str, overlap: Overlap) {
+        let pair = ordered(first, second);
+        match overlap {
+            Overlap::Mix => self.mixing.insert(pair),
+            Overlap::Serialize => self.mixing.remove(&pair),
+        };
+    }
+
+    pub fn get(&self, first: &str, second: &str) -> Overlap {
+        // Compared in place, this runs in the process callback
+        let (first, second) = if first <= second {
+            (first, second)
+        } else {
+            (second, first)
+        };
+        if self
+            .mixing
+            .iter()
+            .any(|pair| pair.0 == first && pair.1 == second)
+        {
+            Overlap::Mix
+        } else {
+            Overlap::Serialize
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.mixing.is_empty()
+    }
+
+    /// Names of the inputs mixing with `input`
+    pub fn mixing_with<'a>(&'a self, input: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.mixing.iter().filter_map(move |(first, second)| {
+            if first == input {
+                Some(second.as_str())
+            } else if second == input {
+                Some(first.as_str())
+            } else {
+                None
+            }
+        })
+    }
+}
+
+fn ordered(first: &str, second: &str) -> (String, String) {
+    if first <= second {
+        (first.to_string(), second.to_string())
+    } else {
+        (second.to_string(), first.to_string())
+    }
+}
@@ -0,0 +1,63 @@
+//! Overlay for the browser sources of OBS and other streaming software: the input playing, how
+//! far behind it is and at what speed, on a transparent page. The page gets the state frames over
+//! a WebSocket and reconnects when the engine restarts.
+
+use std::{
+    net::TcpStream,
+    sync::{Mutex, PoisonError},
+    time::Duration,
+};
+
+use crate::{state_frame::StateFrame, websocket, JackState};
+
+/// Interval of the state frames pushed to the page
+const INTERVAL: Duration = Duration::from_millis(250);
+
+/// Backlog below which the input counts as live
+const LIVE_SECONDS: f32 = 0.5;
+
+pub fn page() -> String {
+    format!(
+        "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><title>Audio Multiplexer</title>\
+         <style>{STYLE}</style></head><body><div id=\"overlay\" class=\"idle\">\
+         <div id=\"source\"></div><div id=\"detail\"></div></div>\
+         <script>const LIVE={LIVE_SECONDS};{SCRIPT}</script></body></html>"
+    )
+}
+
+const STYLE: &str = "html,body{background:transparent;margin:0}\
+#overlay{display:inline-block;padding:.3em .6em;font:bold 28px sans-serif;color:#fff;\
+text-shadow:0 0 4px #000,0 0 8px #000}#overlay.idle{display:none}\
+#detail{font-size:.7em;font-weight:normal}";
+
+const SCRIPT: &str = concat!(
+    "function connect(){",
+    "const socket=new WebSocket('ws://'+location.host+'/overlay/ws');",
+    "socket.onmessage=event=>{",
+    "const frame=JSON.parse(event.data);",
+    "const input=frame.playing===null?null:frame.inputs[frame.playing];",
+    "const overlay=document.getElementById('overlay');",
+    "overlay.className=input?'':'idle';",
+    "if(!input)return;",
+    "document.getElementById('source').textContent=input.name;",
+    "const behind=input.backlog_seconds<LIVE?'live':input.backlog_seconds.toFixed(1)+'s behind';",
+    "document.getElementById('detail').textContent=behind+' at '+input.speed.toFixed(2)+'x';",
+    "};",
+    "socket.onclose=()=>setTimeout(connect,2000);",
+    "}",
+    "connect();",
+);
+
+/// Pushes a state frame to the page every interval, until it goes away
+pub fn stream(
+    mut stream: TcpStream,
+    key: &str,
+    jack_state: &Mutex<JackState>,
+) -> std::io::Result<()> {
+    websocket::accept(&mut stream, key)?;
+    loop {
+        let frame = StateFrame::capture(&jack_state.lock().unwrap_or_else(PoisonError::into_inner));
+        websocket::write_text(&mut stream, &frame.to_json())?;
+        std::thread::sleep(INTERVAL);
+    }
+}
@@ -0,0 +1,373 @@
+//! The JACK process callback and what connects it to the engine thread.
+//!
+//! The callback owns the ports and only copies between them and ring buffers allocated up
+//! front: captured audio into one ring per input port, the output out of one ring per output
+//! channel. It takes no locks and allocates nothing, so a long status update or a slow effect
+//! can't make it miss its deadline. The engine thread is woken after every period, processes
+//! what was captured with the state locked and queues the output for the next period, which
+//! delays the output by one period.
+
+use std::{
+    iter,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
+    thread::Thread,
+};
+
+use jack::{AudioIn, AudioOut, Client, Control, MidiIn, Port, PortFlags, ProcessScope};
+use ringbuf::{HeapConsumer, HeapProducer, HeapRb};
+
+use crate::{
+    default_sink::AUDIO_PORT_TYPE,
+    hurry_up::{HurryUp, HurryUpLevels},
+    midi_control::{self, ControlChange},
+};
+
+/// Frames each ring holds per channel, the engine thread may fall behind by this much before
+/// audio is lost
+const RING_FRAMES: usize = 1 << 14;
+
+/// Inputs with ports the callback holds without allocating, and port changes queued at once
+const MAX_INPUTS: usize = 64;
+
+/// State the callback and the engine thread share without locking
+#[derive(Default)]
+pub struct Shared {
+    /// Periods the callback handled, the engine thread processes as many
+    cycles: AtomicU64,
+    /// Frames of the latest period
+    frames: AtomicUsize,
+    /// The callback asks the JACK transport for its state, because an input is gated by it
+    pub transport_gated: AtomicBool,
+    pub transport_rolling: AtomicBool,
+    /// Frames of silence played because the engine thread had no output ready
+    pub missing_frames: AtomicUsize,
+    /// Frames of captured audio lost because the engine thread didn't take them in time, and of
+    /// output dropped to catch up after it fell behind
+    pub dropped_frames: AtomicUsize,
+    /// Speed and backlog of the playing input for the hurry-up output
+    pub hurry_up: HurryUpLevels,
+}
+
+/// Ports of an input with the producing ends of their rings, owned by the callback
+struct RtInput {
+    id: u64,
+    ports: Vec<Port<AudioIn>>,
+    producers: Vec<HeapProducer<f32>>,
+}
+
+enum PortChange {
+    Add(RtInput),
+    Remove(u64),
+}
+
+/// Captured audio of an input with ports, read by the engine thread
+#[derive(Default)]
+pub struct PortSource {
+    id: u64,
+    names: Vec<String>,
+    consumers: Vec<HeapConsumer<f32>>,
+    /// Ports not handed to the callback yet
+    pending: Option<RtInput>,
+}
+
+impl PortSource {
+    /// Registers a port per channel, named `<prefix>.<index>`
+    pub fn register(client: &Client, prefix: &str, channel_count: usize) -> Self {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        let ports: Vec<Port<AudioIn>> = (0..channel_count)
+            .map(|index| {
+                client
+                    .register_port(&format!("{prefix}.{index}"), AudioIn::default())
+                    .expect("Failed to register port")
+            })
+            .collect();
+        let names = ports
+            .iter()
+            .map(|port| port.name().expect("Failed to get port name"))
+            .collect();
+        let (producers, consumers) = (0..channel_count)
+            .map(|_| HeapRb::new(RING_FRAMES).split())
+            .unzip();
+        Self {
+            id,
+            names,
+            consumers,
+            pending: Some(RtInput {
+                id,
+                ports,
+                producers,
+            }),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    /// Full names of the ports
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    /// The next `frames` captured frames of every port, silence for what wasn't captured
+    pub fn read(&mut self, frames: usize) -> Vec<Vec<f32>> {
+        self.consumers
+            .iter_mut()
+            .map(|consumer| {
+                let mut channel = vec![0.0; frames];
+                consumer.pop_slice(&mut channel);
+                channel
+            })
+            .collect()
+    }
+
+    /// Drops the next `frames` captured frames, for inputs that aren't captured
+    pub fn skip(&mut self, frames: usize) {
+        for consumer in self.consumers.iter_mut() {
+            consumer.skip(frames);
+        }
+    }
+
+    /// Whether anything is connected to each of the ports
+    pub fn connected(&self, client: &Client) -> Vec<bool> {
+        self.names
+            .iter()
+            .map(|name| {
+                client
+                    .port_by_name(name)
+                    .and_then(|port| port.connected_count().ok())
+                    .unwrap_or(0)
+                    > 0
+            })
+            .collect()
+    }
+
+    /// Clients with ports connected to any of the ports, e.g. the application captured
+    pub fn connected_clients(&self, client: &Client) -> Vec<String> {
+        let ports: Vec<_> = self
+            .names
+            .iter()
+            .filter_map(|name| client.port_by_name(name))
+            .collect();
+        let mut clients: Vec<String> = client
+            .ports(None, Some(AUDIO_PORT_TYPE), PortFlags::IS_OUTPUT)
+            .into_iter()
+            .filter(|name| {
+                ports
+                    .iter()
+                    .any(|port| port.is_connected_to(name).unwrap_or(false))
+            })
+            .filter_map(|name| name.split_once(':').map(|(client, _)| client.to_string()))
+            .collect();
+        clients.sort();
+        clients.dedup();
+        clients
+    }
+
+    /// The ports, if they weren't handed to the callback
+    pub fn take_ports(&mut self) -> Option<Vec<Port<AudioIn>>> {
+        self.pending.take().map(|input| input.ports)
+    }
+}
+
+/// The engine thread's ends of the rings and of the port changes
+pub struct Link {
+    pub shared: Arc<Shared>,
+    /// Periods the engine thread processed
+    processed: u64,
+    outputs: Vec<HeapProducer<f32>>,
+    changes: HeapProducer<PortChange>,
+    retired: HeapConsumer<RtInput>,
+    control_changes: HeapConsumer<ControlChange>,
+}
+
+impl Link {
+    /// Hands the ports of an input to the callback, unless the queue of changes is full
+    pub fn attach(&mut self, source: &mut PortSource) {
+        let Some(input) = source.pending.take() else {
+            return;
+        };
+        if let Err(PortChange::Add(input)) = self.changes.push(PortChange::Add(input)) {
+            source.pending = Some(input);
+        }
+    }
+
+    /// Asks the callback to give up the ports of an input, returns whether it was asked
+    pub fn detach(&mut self, source: &PortSource) -> bool {
+        self.changes.push(PortChange::Remove(source.id)).is_ok()
+    }
+
+    /// Ports the callback gave up since last asked
+    pub fn retired(&mut self) -> Vec<Port<AudioIn>> {
+        self.retired
+            .pop_iter()
+            .flat_map(|input| input.ports)
+            .collect()
+    }
+
+    /// Control change messages received since last asked
+    pub fn control_changes(&mut self) -> Vec<ControlChange> {
+        self.control_changes.pop_iter().collect()
+    }
+
+    /// Frames of the next period to process, `None` once all are processed
+    pub fn next_period(&mut self) -> Option<usize> {
+        if self.processed >= self.shared.cycles.load(Ordering::Acquire) {
+            return None;
+        }
+        self.processed += 1;
+        Some(self.shared.frames.load(Ordering::Relaxed))
+    }
+
+    /// Queues a processed period for the callback. Dropped if one is queued already, because
+    /// the engine thread catches up after falling behind and would delay the output for good.
+    pub fn write(&mut self, output: &[Vec<f32>]) {
+        let frames = output.first().map_or(0, Vec::len);
+        if self
+            .outputs
+            .first()
+            .is_some_and(|producer| producer.len() >= frames)
+        {
+            self.shared
+                .dropped_frames
+                .fetch_add(frames, Ordering::Relaxed);
+            return;
+        }
+        for (producer, channel) in self.outputs.iter_mut().zip(output) {
+            producer.push_slice(channel);
+        }
+    }
+}
+
+/// What runs in the JACK process thread
+pub struct Callback {
+    shared: Arc<Shared>,
+    inputs: Vec<RtInput>,
+    outputs: Vec<(Port<AudioOut>, HeapConsumer<f32>)>,
+    hurry_up: Option<HurryUp>,
+    /// Port of the MIDI controllers, the control changes it receives go to the engine thread
+    midi_control: Option<(Port<MidiIn>, HeapProducer<ControlChange>)>,
+    changes: HeapConsumer<PortChange>,
+    retired: HeapProducer<RtInput>,
+    /// Woken after every period
+    engine: Thread,
+}
+
+impl Callback {
+    /// The callback playing through `outputs` and capturing the ports of `sources`, with the
+    /// engine thread's link to it. The first period plays silence, the output of the engine
+    /// thread follows a period behind.
+    pub fn new<'a>(
+        outputs: Vec<Port<AudioOut>>,
+        hurry_up: Option<HurryUp>,
+        midi_control: Option<Port<MidiIn>>,
+        sources: impl Iterator<Item = &'a mut PortSource>,
+        period_frames: usize,
+        engine: Thread,
+    ) -> (Self, Link) {
+        let shared = Arc::new(Shared::default());
+        let (producers, consumers): (Vec<_>, Vec<_>) = outputs
+            .iter()
+            .map(|_| {
+                let (mut producer, consumer) = HeapRb::new(RING_FRAMES).split();
+                producer.push_iter(&mut iter::repeat_n(0.0, period_frames));
+                (producer, consumer)
+            })
+            .unzip();
+        let (changes, changes_consumer) = HeapRb::new(MAX_INPUTS).split();
+        let (retired_producer, retired) = HeapRb::new(MAX_INPUTS).split();
+        let (control_changes_producer, control_changes) =
+            HeapRb::new(midi_control::QUEUE_LENGTH).split();
+        let mut inputs = Vec::with_capacity(MAX_INPUTS);
+        inputs.extend(sources.filter_map(|source| source.pending.take()));
+        let callback = Self {
+            shared: shared.clone(),
+            inputs,
+            outputs: outputs.into_iter().zip(consumers).collect(),
+            hurry_up,
+            midi_control: midi_control.map(|port| (port, control_changes_producer)),
+            changes: changes_consumer,
+            retired: retired_producer,
+            engine,
+        };
+        let link = Link {
+            shared,
+            processed: 0,
+            outputs: producers,
+            changes,
+            retired,
+            control_changes,
+        };
+        (callback, link)
+    }
+
+    pub fn process(&mut self, client: &Client, scope: &ProcessScope) -> Control {
+        while let Some(change) = self.changes.pop() {
+            match change {
+                // Only allocates beyond `MAX_INPUTS`
+                PortChange::Add(input) => self.inputs.push(input),
+                PortChange::Remove(id) => {
+                    if let Some(index) = self.inputs.iter().position(|input| input.id == id) {
+                        // Handed back so the rings are freed outside of the process thread
+                        let _ = self.retired.push(self.inputs.swap_remove(index));
+                    }
+                }
+            }
+        }
+
+        let frames = scope.n_frames() as usize;
+        for input in self.inputs.iter_mut() {
+            for (port, producer) in input.ports.iter().zip(input.producers.iter_mut()) {
+                let written = producer.push_slice(port.as_slice(scope));
+                if written < frames {
+                    self.shared
+                        .dropped_frames
+                        .fetch_add(frames - written, Ordering::Relaxed);
+                }
+            }
+        }
+        let mut missing = 0;
+        for (port, consumer) in self.outputs.iter_mut() {
+            let output = port.as_mut_slice(scope);
+            let read = consumer.pop_slice(output);
+            output[read..].fill(0.0);
+            missing = missing.max(frames - read);
+        }
+        if missing > 0 {
+            self.shared
+                .missing_frames
+                .fetch_add(missing, Ordering::Relaxed);
+        }
+        if let Some((port, producer)) = self.midi_control.as_mut() {
+            // Lost if the engine thread fell that far behind, controllers send the next soon
+            for change in port
+                .iter(scope)
+                .filter_map(|event| ControlChange::parse(event.bytes))
+            {
+                let _ = producer.push(change);
+            }
+        }
+        if let Some(hurry_up) = self.hurry_up.as_mut() {
+            hurry_up.process(&self.shared.hurry_up, scope);
+        }
+        // Only asked for while it matters, it's a call into the server every period
+        if self.shared.transport_gated.load(Ordering::Relaxed) {
+            let rolling = matches!(
+                client.transport().query_state(),
+                Ok(jack::TransportState::Rolling)
+            );
+            self.shared
+                .transport_rolling
+                .store(rolling, Ordering::Relaxed);
+        }
+
+        self.shared.frames.store(frames, Ordering::Relaxed);
+        self.shared.cycles.fetch_add(1, Ordering::Release);
+        self.engine.unpark();
+        Control::Continue
+    }
+}
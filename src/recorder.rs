@@ -0,0 +1,457 @@
+use std::{
+    fs,
+    io::BufReader,
+    mem::size_of,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc::{self, Receiver, Sender},
+        Arc, OnceLock,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, bail, Context};
+use ringbuf::{HeapConsumer, HeapProducer, HeapRb};
+
+use crate::{
+    encoder::{self, Encoder, Encoding},
+    sample_format::wav_samples,
+};
+
+/// Seconds of audio the ring buffer between the process callback and the writer can hold
+const BUFFER_SECONDS: usize = 5;
+
+/// How often the encoder thread wakes up to write out buffered audio
+const WRITE_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Frames `export` reads and encodes at a time
+const EXPORT_CHUNK_FRAMES: usize = 4096;
+
+/// Format of the start time in recording file names
+const TIMESTAMP_FORMAT: &str = "%Y%m%d-%H%M%S";
+
+/// When to start a new file and how many old files to keep
+#[derive(Clone, Debug)]
+pub struct Rotation {
+    /// Start a new file at every multiple of this interval (wall clock, e.g. every full hour)
+    pub every: Option<Duration>,
+    /// Start a new file once the current one reaches this size
+    pub max_bytes: Option<u64>,
+    /// Delete the oldest files beyond this count
+    pub keep_files: Option<usize>,
+}
+
+impl Default for Rotation {
+    fn default() -> Self {
+        Self {
+            every: Some(Duration::from_secs(3600)),
+            max_bytes: Some(2 * 1024 * 1024 * 1024),
+            keep_files: Some(48),
+        }
+    }
+}
+
+/// Continuously records an input to rotating files.
+///
+/// The process callback only copies samples into a ring buffer. A single encoder thread shared
+/// by all recorders does the encoding and file handling.
+pub struct Recorder {
+    producer: HeapProducer<f32>,
+    channel_count: usize,
+    directory: PathBuf,
+    encoding: Encoding,
+    /// Samples that did not fit into the ring buffer and were lost
+    dropped: Arc<AtomicUsize>,
+    stop: Arc<AtomicBool>,
+    /// Closed by the encoder thread once the file is finalized
+    finished: Receiver<()>,
+}
+
+impl Recorder {
+    pub fn start(
+        name: &str,
+        directory: &Path,
+        channel_count: usize,
+        sample_rate: usize,
+        rotation: Rotation,
+        encoding: Encoding,
+    ) -> anyhow::Result<Self> {
+        Self::spawn(
+            name,
+            directory,
+            None,
+            channel_count,
+            sample_rate,
+            rotation,
+            encoding,
+        )
+    }
+
+    /// Records to a single file at `path` until dropped
+    pub fn start_file(
+        path: &Path,
+        channel_count: usize,
+        sample_rate: usize,
+        encoding: Encoding,
+    ) -> anyhow::Result<Self> {
+        let name = path
+            .file_stem()
+            .and_then(|name| name.to_str())
+            .unwrap_or_default();
+        let never = Rotation {
+            every: None,
+            max_bytes: None,
+            keep_files: None,
+        };
+        Self::spawn(
+            name,
+            path.parent().unwrap_or(Path::new("")),
+            Some(path.to_path_buf()),
+            channel_count,
+            sample_rate,
+            never,
+            encoding,
+        )
+    }
+
+    fn spawn(
+        name: &str,
+        directory: &Path,
+        file: Option<PathBuf>,
+        channel_count: usize,
+        sample_rate: usize,
+        rotation: Rotation,
+        encoding: Encoding,
+    ) -> anyhow::Result<Self> {
+        fs::create_dir_all(directory)
+            .with_context(|| format!("Failed to create {}", directory.display()))?;
+
+        let ring_buffer = HeapRb::new(BUFFER_SECONDS * sample_rate * channel_count);
+        let (producer, consumer) = ring_buffer.split();
+        let stop = Arc::new(AtomicBool::new(false));
+        let (finished_sender, finished) = mpsc::channel();
+
+        let writer = Writer {
+            name: name.to_string(),
+            directory: directory.to_path_buf(),
+            encoding,
+            channel_count,
+            sample_rate,
+            rotation,
+            fixed_path: file,
+            consumer,
+            file: None,
+            stop: stop.clone(),
+            _finished: finished_sender,
+        };
+        encoder_thread()
+            .send(writer)
+            .map_err(|_| anyhow!("The encoder thread is gone"))?;
+
+        Ok(Self {
+            producer,
+            channel_count,
+            directory: directory.to_path_buf(),
+            encoding,
+            dropped: Arc::new(AtomicUsize::new(0)),
+            stop,
+            finished,
+        })
+    }
+
+    /// Queues one period for writing, called from the process callback
+    pub fn write(&mut self, period: &[Vec<f32>]) {
+        let frame_count = period.first().map_or(0, |channel| channel.len());
+        // Only ever queue whole frames so the channels never get out of step
+        if self.producer.free_len() < frame_count * self.channel_count {
+            self.dropped
+                .fetch_add(frame_count * self.channel_count, Ordering::Relaxed);
+            return;
+        }
+        for frame in 0..frame_count {
+            for channel in period {
+                let _ = self.producer.push(channel[frame]);
+            }
+        }
+    }
+
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    pub fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+
+    pub fn dropped_samples(&self) -> usize {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Memory of the queue to the encoder thread, allocated up front
+    pub fn queue_bytes(&self) -> usize {
+        self.producer.capacity() * size_of::<f32>()
+    }
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        // Returns once the encoder thread dropped its end, with the file finalized
+        let _ = self.finished.recv();
+    }
+}
+
+/// Hands recorders' writers to the encoder thread, starting it with the first one
+fn encoder_thread() -> &'static Sender<Writer> {
+    static SENDER: OnceLock<Sender<Writer>> = OnceLock::new();
+    SENDER.get_or_init(|| {
+        let (sender, receiver) = mpsc::channel();
+        std::thread::spawn(move || encode(&receiver));
+        sender
+    })
+}
+
+/// Writes out the audio of all recorders until they stop, encoding one after another
+fn encode(receiver: &Receiver<Writer>) {
+    let mut writers: Vec<Writer> = Vec::new();
+    let mut samples = Vec::new();
+    loop {
+        if writers.is_empty() {
+            // Nothing to do until the next recorder starts
+            match receiver.recv() {
+                Ok(writer) => writers.push(writer),
+                Err(_) => return,
+            }
+        }
+        writers.extend(receiver.try_iter());
+        writers.retain_mut(|writer| writer.write_buffered(&mut samples));
+        std::thread::sleep(WRITE_INTERVAL);
+    }
+}
+
+struct OpenFile {
+    encoder: Box<dyn Encoder>,
+    /// Wall clock time at which the file has to be rotated
+    rotate_at: Option<SystemTime>,
+}
+
+struct Writer {
+    name: String,
+    directory: PathBuf,
+    encoding: Encoding,
+    channel_count: usize,
+    sample_rate: usize,
+    rotation: Rotation,
+    /// Single file written instead of timestamped ones
+    fixed_path: Option<PathBuf>,
+    consumer: HeapConsumer<f32>,
+    file: Option<OpenFile>,
+    stop: Arc<AtomicBool>,
+    /// Dropped with the writer, which tells the recorder its file is finalized
+    _finished: Sender<()>,
+}
+
+impl Writer {
+    /// Writes what the ring buffer holds, returns `false` once the recorder stopped and the file
+    /// is finalized
+    fn write_buffered(&mut self, samples: &mut Vec<f32>) -> bool {
+        let stopping = self.stop.load(Ordering::Relaxed);
+        samples.resize(self.consumer.capacity(), 0.0);
+        // Ring buffer only ever holds whole frames
+        let count = self.consumer.pop_slice(samples);
+        if count > 0 {
+            if let Err(error) = self.write(&samples[..count]) {
+                eprintln!("Recording {} failed: {error:#}", self.name);
+                self.file = None;
+            }
+        }
+        if !stopping {
+            return true;
+        }
+        if let Some(file) = self.file.take() {
+            if let Err(error) = file.encoder.finish() {
+                eprintln!("Failed to finalize recording of {}: {error:#}", self.name);
+            }
+        }
+        false
+    }
+
+    fn write(&mut self, samples: &[f32]) -> anyhow::Result<()> {
+        if self.needs_rotation() {
+            self.rotate()?;
+        }
+        let file = self.file.as_mut().unwrap();
+        file.encoder.write(samples)?;
+        // Keep the file readable while it is recorded
+        file.encoder.flush()
+    }
+
+    fn needs_rotation(&self) -> bool {
+        let Some(file) = &self.file else {
+            return true;
+        };
+        let too_large = self
+            .rotation
+            .max_bytes
+            .is_some_and(|max_bytes| file.encoder.bytes() >= max_bytes);
+        let too_old = file
+            .rotate_at
+            .is_some_and(|rotate_at| SystemTime::now() >= rotate_at);
+        too_large || too_old
+    }
+
+    fn rotate(&mut self) -> anyhow::Result<()> {
+        if let Some(file) = self.file.take() {
+            file.encoder.finish()?;
+        }
+
+        let now = SystemTime::now();
+        let path = self.fixed_path.clone().unwrap_or_else(|| {
+            self.directory.join(format!(
+                "{}-{}.{}",
+                self.name,
+                chrono::Local::now().format(TIMESTAMP_FORMAT),
+                self.encoding.extension()
+            ))
+        });
+        let encoder = self
+            .encoding
+            .create(&path, self.channel_count, self.sample_rate)?;
+        self.file = Some(OpenFile {
+            encoder,
+            rotate_at: self.rotation.every.map(|every| next_boundary(now, every)),
+        });
+
+        if let Some(keep_files) = self.rotation.keep_files {
+            prune(&self.directory, &self.name, keep_files)?;
+        }
+        Ok(())
+    }
+}
+
+/// Next multiple of `every` since the epoch after `now`
+fn next_boundary(now: SystemTime, every: Duration) -> SystemTime {
+    let every = every.as_secs().max(1);
+    let seconds = now.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    UNIX_EPOCH + Duration::from_secs((seconds / every + 1) * every)
+}
+
+/// Recordings of `name` in `directory` in any encoding, oldest first
+pub fn recordings(directory: &Path, name: &str) -> anyhow::Result<Vec<PathBuf>> {
+    let prefix = format!("{name}-");
+    let mut files: Vec<PathBuf> = fs::read_dir(directory)
+        .with_context(|| format!("Failed to list {}", directory.display()))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            let file_name = path.file_name().and_then(|name| name.to_str());
+            let extension = path.extension().and_then(|extension| extension.to_str());
+            file_name.is_some_and(|file_name| file_name.starts_with(&prefix))
+                && extension.is_some_and(|extension| encoder::EXTENSIONS.contains(&extension))
+        })
+        .collect();
+    // Timestamps in the file names sort chronologically
+    files.sort();
+    Ok(files)
+}
+
+/// Wall clock time a recording of `name` started at, taken from its file name
+pub fn recording_start(path: &Path, name: &str) -> Option<SystemTime> {
+    let file_name = path.file_stem()?.to_str()?;
+    let timestamp = file_name.strip_prefix(name)?.strip_prefix('-')?;
+    let time = chrono::NaiveDateTime::parse_from_str(timestamp, TIMESTAMP_FORMAT).ok()?;
+    let time = time.and_local_timezone(chrono::Local).earliest()?;
+    Some(time.into())
+}
+
+/// Opens a recording to read it back, which only WAV recordings can be
+pub fn open_recording(path: &Path) -> anyhow::Result<hound::WavReader<BufReader<fs::File>>> {
+    if path.extension().is_none_or(|extension| extension != "wav") {
+        bail!(
+            "{} isn't a WAV file, only WAV recordings can be played back or exported",
+            path.display()
+        );
+    }
+    hound::WavReader::open(path).with_context(|| format!("Failed to open {}", path.display()))
+}
+
+/// Copies the recorded audio of `name` between wall clock times `from` and `to` to a file of
+/// the given encoding.
+///
+/// Returns the number of frames written.
+pub fn export(
+    directory: &Path,
+    name: &str,
+    from: SystemTime,
+    to: SystemTime,
+    encoding: Encoding,
+    path: &Path,
+) -> anyhow::Result<usize> {
+    let files = recordings(directory, name)?;
+    let mut encoder = None;
+    let mut frames_written = 0;
+
+    for (index, file) in files.iter().enumerate() {
+        let Some(file_start) = recording_start(file, name) else {
+            continue;
+        };
+        if file_start >= to {
+            break;
+        }
+        let next_start = files
+            .get(index + 1)
+            .and_then(|next| recording_start(next, name));
+        if next_start.is_some_and(|next_start| next_start <= from) {
+            continue;
+        }
+
+        let mut reader = open_recording(file)?;
+        let spec = reader.spec();
+        let frame_at = |time: SystemTime| {
+            let offset = time.duration_since(file_start).unwrap_or_default();
+            ((offset.as_secs_f64() * spec.sample_rate as f64) as u32).min(reader.duration())
+        };
+        let (first, last) = (frame_at(from), frame_at(to));
+        reader.seek(first)?;
+
+        let encoder = match &mut encoder {
+            Some(encoder) => encoder,
+            None => encoder.insert(encoding.create(
+                path,
+                spec.channels.into(),
+                spec.sample_rate as usize,
+            )?),
+        };
+        let sample_count = (last - first) as usize * spec.channels as usize;
+        let mut samples = wav_samples(&mut reader).take(sample_count);
+        // Whole frames at a time, recordings don't fit into memory at once
+        let chunk_length = EXPORT_CHUNK_FRAMES * spec.channels as usize;
+        let mut chunk = Vec::with_capacity(chunk_length);
+        loop {
+            chunk.clear();
+            for sample in samples.by_ref().take(chunk_length) {
+                chunk.push(sample?);
+            }
+            if chunk.is_empty() {
+                break;
+            }
+            encoder.write(&chunk)?;
+        }
+        frames_written += (last - first) as usize;
+    }
+
+    match encoder {
+        Some(encoder) => encoder.finish()?,
+        None => bail!("No recordings of {name} in the requested time range"),
+    }
+    Ok(frames_written)
+}
+
+fn prune(directory: &Path, name: &str, keep_files: usize) -> anyhow::Result<()> {
+    let files = recordings(directory, name)?;
+    let excess = files.len().saturating_sub(keep_files);
+    for file in &files[..excess] {
+        fs::remove_file(file).with_context(|| format!("Failed to delete {}", file.display()))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,126 @@
+//! Tiny page for controlling the engine from a phone: the backlog of every input with big Hold and
+//! Flush buttons, and Skip for what is playing. Plain HTML forms, no scripts, so it works in any
+//! browser.
+
+use std::fmt::Write as _;
+
+use anyhow::{anyhow, bail};
+
+use crate::{control::Command, JackState};
+
+/// Seconds after which the page loads itself again, for a fresh backlog
+const REFRESH_SECONDS: u32 = 5;
+
+const STYLE: &str = "body{font-family:sans-serif;margin:0;padding:1em;background:#111;color:#eee}\
+h1{font-size:1.2em}.input{margin:0 0 1.5em}.backlog{color:#aaa}\
+form{display:inline}button{font-size:1.4em;padding:.6em 0;margin:.3em 0;width:100%;\
+border:0;border-radius:.4em;background:#345;color:#fff}.held{background:#a60}\
+.skip{background:#464}.message{background:#222;padding:.5em}";
+
+/// The page, with the response to the last button pressed if there was one
+pub fn page(state: &JackState, message: Option<&str>) -> String {
+    let sample_rate = state.sample_rate.max(1) as f32;
+    let mut html = String::new();
+    let _ = write!(
+        html,
+        "<!DOCTYPE html><html><head><meta charset=\"utf-8\">\
+         <meta name=\"viewport\" content=\"width=device-width,initial-scale=1\">\
+         <meta http-equiv=\"refresh\" content=\"{REFRESH_SECONDS};url=/remote\">\
+         <title>Audio Multiplexer</title><style>{STYLE}</style></head><body>"
+    );
+    match &state.playing {
+        Some(playing) => {
+            let _ = write!(html, "<h1>Playing {}</h1>", escape(playing));
+        }
+        None => html.push_str("<h1>Nothing playing</h1>"),
+    }
+    if let Some(message) = message {
+        let _ = write!(html, "<p class=\"message\">{}</p>", escape(message));
+    }
+    html.push_str(&button("skip", None, "Skip", "skip"));
+    for input in state.inputs.iter().filter(|input| !input.disabled) {
+        let name = escape(&input.name);
+        let seconds = input.buffered_samples() as f32 / sample_rate;
+        let _ = write!(
+            html,
+            "<div class=\"input\"><h2>{name}</h2><span class=\"backlog\">{seconds:.1}s queued</span>"
+        );
+        let (hold, class) = if input.held {
+            ("Release", "held")
+        } else {
+            ("Hold", "")
+        };
+        html.push_str(&button("hold", Some(&name), hold, class));
+        html.push_str(&button("flush", Some(&name), "Flush", ""));
+        html.push_str("</div>");
+    }
+    html.push_str("</body></html>");
+    html
+}
+
+/// A button posting `action` for an input, whose name is escaped already
+fn button(action: &str, input: Option<&str>, label: &str, class: &str) -> String {
+    let input = input.map_or(String::new(), |input| {
+        format!("<input type=\"hidden\" name=\"input\" value=\"{input}\">")
+    });
+    format!(
+        "<form method=\"post\" action=\"/remote\"><input type=\"hidden\" name=\"action\" \
+         value=\"{action}\">{input}<button class=\"{class}\">{label}</button></form>"
+    )
+}
+
+/// Applies the button pressed, given the posted form, and returns the response to show
+pub fn press(form: &str, state: &mut JackState) -> anyhow::Result<String> {
+    let mut action = None;
+    let mut input = None;
+    for field in form.split('&') {
+        let (name, value) = field.split_once('=').unwrap_or((field, ""));
+        match name {
+            "action" => action = Some(decode(value)?),
+            "input" => input = Some(decode(value)?),
+            _ => {}
+        }
+    }
+    let input = || input.clone().ok_or_else(|| anyhow!("No input given"));
+    let command = match action.as_deref() {
+        Some("hold") => Command::Hold {
+            input: input()?,
+            held: None,
+        },
+        Some("flush") => Command::Flush { input: input()? },
+        Some("skip") => Command::Skip { input: None },
+        Some(action) => bail!("Unknown action '{action}'"),
+        None => bail!("No action given"),
+    };
+    command.apply(state)
+}
+
+/// Decodes a value of a URL encoded form
+fn decode(value: &str) -> anyhow::Result<String> {
+    let mut bytes = Vec::with_capacity(value.len());
+    let mut rest = value.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        rest = tail;
+        match byte {
+            b'+' => bytes.push(b' '),
+            b'%' => {
+                let hex = rest
+                    .get(..2)
+                    .and_then(|hex| std::str::from_utf8(hex).ok())
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                    .ok_or_else(|| anyhow!("Invalid form encoding"))?;
+                bytes.push(hex);
+                rest = &rest[2..];
+            }
+            byte => bytes.push(byte),
+        }
+    }
+    String::from_utf8(bytes).map_err(|_| anyhow!("Invalid form encoding"))
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
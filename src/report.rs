@@ -0,0 +1,147 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    fs,
+    path::Path,
+    time::{Duration, SystemTime},
+};
+
+use anyhow::Context;
+use chrono::{DateTime, Local, SecondsFormat};
+use serde::Serialize;
+
+use crate::JackState;
+
+/// Summary of a session, written on shutdown
+#[derive(Serialize)]
+pub struct SessionReport {
+    started: String,
+    ended: String,
+    duration_seconds: f64,
+    xruns: usize,
+    engine_restarts: usize,
+    /// Number of timeline events per kind
+    events: BTreeMap<&'static str, usize>,
+    inputs: Vec<InputReport>,
+}
+
+#[derive(Serialize)]
+struct InputReport {
+    name: String,
+    active_ratio: f64,
+    backlog_average_seconds: f64,
+    backlog_peak_seconds: f64,
+    /// Time the input played, only it counts towards the speed and the time saved
+    played_seconds: f64,
+    speed_average: f64,
+    time_saved_seconds: f64,
+}
+
+impl SessionReport {
+    pub fn new(state: &JackState, started: SystemTime) -> Self {
+        let ended = SystemTime::now();
+        let events = state.timeline.counts().clone();
+        Self {
+            started: format_time(started),
+            ended: format_time(ended),
+            duration_seconds: ended
+                .duration_since(started)
+                .unwrap_or(Duration::ZERO)
+                .as_secs_f64(),
+            xruns: events.get("xrun").copied().unwrap_or(0),
+            engine_restarts: state.restarts,
+            events,
+            inputs: state
+                .inputs
+                .iter()
+                .map(|input| InputReport {
+                    name: input.name.clone(),
+                    active_ratio: input.stats.duty_cycle(),
+                    backlog_average_seconds: input.stats.average_backlog(),
+                    backlog_peak_seconds: input.stats.peak_backlog(),
+                    played_seconds: input.stats.played_seconds(),
+                    speed_average: input.stats.average_speed(),
+                    time_saved_seconds: input.stats.time_saved(),
+                })
+                .collect(),
+        }
+    }
+
+    /// Writes the report as CSV if the path ends in `.csv`, as JSON otherwise
+    pub fn write(&self, path: &Path) -> anyhow::Result<()> {
+        let text = if path.extension().is_some_and(|extension| extension == "csv") {
+            self.to_csv()
+        } else {
+            serde_json::to_string_pretty(self)? + "\n"
+        };
+        fs::write(path, text).with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    /// One `scope,metric,value` row per number, the scope being `session`, `events` or an input
+    fn to_csv(&self) -> String {
+        let mut text = String::from("scope,metric,value\n");
+        let _ = writeln!(text, "session,started,{}", self.started);
+        let _ = writeln!(text, "session,ended,{}", self.ended);
+        let _ = writeln!(text, "session,duration_seconds,{}", self.duration_seconds);
+        let _ = writeln!(text, "session,xruns,{}", self.xruns);
+        let _ = writeln!(text, "session,engine_restarts,{}", self.engine_restarts);
+        for (kind, count) in &self.events {
+            let _ = writeln!(text, "events,{kind},{count}");
+        }
+        for input in &self.inputs {
+            let rows = [
+                ("active_ratio", input.active_ratio),
+                ("backlog_average_seconds", input.backlog_average_seconds),
+                ("backlog_peak_seconds", input.backlog_peak_seconds),
+                ("played_seconds", input.played_seconds),
+                ("speed_average", input.speed_average),
+                ("time_saved_seconds", input.time_saved_seconds),
+            ];
+            for (metric, value) in rows {
+                let _ = writeln!(text, "{},{metric},{value}", csv_field(&input.name));
+            }
+        }
+        text
+    }
+}
+
+fn format_time(time: SystemTime) -> String {
+    DateTime::<Local>::from(time).to_rfc3339_opts(SecondsFormat::Secs, false)
+}
+
+fn csv_field(text: &str) -> String {
+    if text.contains([',', '"', '\n']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Input;
+
+    #[test]
+    fn saves_no_time_for_audio_queued_behind_another_input() {
+        let mut state = JackState::default();
+        for (name, played) in [("music", true), ("podcast", false)] {
+            let mut input = Input {
+                name: name.to_string(),
+                ..Default::default()
+            };
+            input
+                .stats
+                .record(Duration::from_secs(10), 30.0, 1.5, played);
+            state.inputs.push(input);
+        }
+
+        let report = SessionReport::new(&state, SystemTime::now());
+        let csv = report.to_csv();
+        assert!(csv.contains("music,played_seconds,10\n"));
+        assert!(csv.contains("music,time_saved_seconds,5\n"));
+        assert!(csv.contains("podcast,played_seconds,0\n"));
+        assert!(csv.contains("podcast,time_saved_seconds,0\n"));
+        assert!(csv.contains("podcast,speed_average,1\n"));
+    }
+}
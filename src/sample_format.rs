@@ -0,0 +1,213 @@
+//! Conversion between the 32 bit float samples audiomux works with and other sample formats.
+//!
+//! Integers are scaled by their full range, so `i16::MIN` becomes exactly -1.0 and 1.0 becomes
+//! `i16::MAX` after clipping. Samples outside -1.0 to 1.0 are clipped when converted to integers.
+
+use std::{
+    fmt,
+    io::{Read, Seek, Write},
+    str::FromStr,
+};
+
+use anyhow::bail;
+
+use crate::stretch::{Resampler, TimeStretch};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum SampleFormat {
+    I16,
+    /// Packed into three bytes
+    I24,
+    I32,
+    #[default]
+    F32,
+    F64,
+}
+
+impl SampleFormat {
+    /// Bytes per sample in packed little-endian form
+    pub fn bytes(self) -> usize {
+        match self {
+            SampleFormat::I16 => 2,
+            SampleFormat::I24 => 3,
+            SampleFormat::I32 | SampleFormat::F32 => 4,
+            SampleFormat::F64 => 8,
+        }
+    }
+
+    /// Identifies the format in network packets
+    pub fn id(self) -> u8 {
+        match self {
+            SampleFormat::I16 => 0,
+            SampleFormat::I24 => 1,
+            SampleFormat::I32 => 2,
+            SampleFormat::F32 => 3,
+            SampleFormat::F64 => 4,
+        }
+    }
+
+    pub fn from_id(id: u8) -> Option<Self> {
+        Some(match id {
+            0 => SampleFormat::I16,
+            1 => SampleFormat::I24,
+            2 => SampleFormat::I32,
+            3 => SampleFormat::F32,
+            4 => SampleFormat::F64,
+            _ => return None,
+        })
+    }
+
+    /// Converts one little-endian sample of `self.bytes()` bytes
+    pub fn decode(self, bytes: &[u8]) -> f32 {
+        match self {
+            SampleFormat::I16 => from_int(i16::from_le_bytes([bytes[0], bytes[1]]).into(), 16),
+            // Shifted into the top of an i32 and back to extend the sign
+            SampleFormat::I24 => from_int(
+                i32::from_le_bytes([0, bytes[0], bytes[1], bytes[2]]) >> 8,
+                24,
+            ),
+            SampleFormat::I32 => from_int(i32::from_le_bytes(bytes.try_into().unwrap()), 32),
+            SampleFormat::F32 => f32::from_le_bytes(bytes.try_into().unwrap()),
+            SampleFormat::F64 => f64::from_le_bytes(bytes.try_into().unwrap()) as f32,
+        }
+    }
+
+    /// Appends `sample` in little-endian form
+    pub fn encode(self, sample: f32, bytes: &mut Vec<u8>) {
+        match self {
+            SampleFormat::I16 => {
+                bytes.extend_from_slice(&(to_int(sample, 16) as i16).to_le_bytes())
+            }
+            SampleFormat::I24 => bytes.extend_from_slice(&to_int(sample, 24).to_le_bytes()[..3]),
+            SampleFormat::I32 => bytes.extend_from_slice(&to_int(sample, 32).to_le_bytes()),
+            SampleFormat::F32 => bytes.extend_from_slice(&sample.to_le_bytes()),
+            SampleFormat::F64 => bytes.extend_from_slice(&f64::from(sample).to_le_bytes()),
+        }
+    }
+
+    /// The WAV format storing samples of this format, WAV files have no 64 bit floats
+    pub fn wav_spec(
+        self,
+        channel_count: usize,
+        sample_rate: usize,
+    ) -> anyhow::Result<hound::WavSpec> {
+        let (bits_per_sample, sample_format) = match self {
+            SampleFormat::I16 => (16, hound::SampleFormat::Int),
+            SampleFormat::I24 => (24, hound::SampleFormat::Int),
+            SampleFormat::I32 => (32, hound::SampleFormat::Int),
+            SampleFormat::F32 => (32, hound::SampleFormat::Float),
+            SampleFormat::F64 => bail!("WAV files can't hold 64 bit float samples"),
+        };
+        Ok(hound::WavSpec {
+            channels: channel_count as u16,
+            sample_rate: sample_rate as u32,
+            bits_per_sample,
+            sample_format,
+        })
+    }
+}
+
+impl FromStr for SampleFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(text: &str) -> anyhow::Result<Self> {
+        Ok(match text {
+            "s16" => SampleFormat::I16,
+            "s24" => SampleFormat::I24,
+            "s32" => SampleFormat::I32,
+            "f32" => SampleFormat::F32,
+            "f64" => SampleFormat::F64,
+            _ => bail!("Unknown sample format '{text}', expected s16, s24, s32, f32 or f64"),
+        })
+    }
+}
+
+impl fmt::Display for SampleFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            SampleFormat::I16 => "s16",
+            SampleFormat::I24 => "s24",
+            SampleFormat::I32 => "s32",
+            SampleFormat::F32 => "f32",
+            SampleFormat::F64 => "f64",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// Scales an integer sample of `bits` significant bits to -1.0 up to just below 1.0
+pub fn from_int(sample: i32, bits: u32) -> f32 {
+    (f64::from(sample) / (1u64 << (bits - 1)) as f64) as f32
+}
+
+/// Scales a sample to an integer of `bits` significant bits, clipping it to the range
+pub fn to_int(sample: f32, bits: u32) -> i32 {
+    let scale = (1u64 << (bits - 1)) as f64;
+    (f64::from(sample) * scale)
+        .round()
+        .clamp(-scale, scale - 1.0) as i32
+}
+
+/// The samples of a WAV file of any format as floats
+pub fn wav_samples<R: Read>(
+    reader: &mut hound::WavReader<R>,
+) -> Box<dyn Iterator<Item = hound::Result<f32>> + '_> {
+    let spec = reader.spec();
+    match spec.sample_format {
+        hound::SampleFormat::Float => Box::new(reader.samples::<f32>()),
+        hound::SampleFormat::Int => {
+            let bits = u32::from(spec.bits_per_sample);
+            Box::new(
+                reader
+                    .samples::<i32>()
+                    .map(move |sample| sample.map(|sample| from_int(sample, bits))),
+            )
+        }
+    }
+}
+
+/// The channels of a WAV file, resampled to `sample_rate` if it was recorded at another rate
+pub fn wav_channels<R: Read>(
+    reader: &mut hound::WavReader<R>,
+    sample_rate: usize,
+) -> hound::Result<Vec<Vec<f32>>> {
+    let spec = reader.spec();
+    let channel_count = usize::from(spec.channels.max(1));
+    let mut samples = wav_samples(reader).collect::<Result<Vec<f32>, _>>()?;
+    if spec.sample_rate as usize != sample_rate {
+        let mut resampler = Resampler::default();
+        resampler.set_channels(channel_count);
+        resampler.set_rate(f64::from(spec.sample_rate) / sample_rate as f64);
+        let frame_count = samples.len() / channel_count;
+        resampler.put_samples(&samples, frame_count);
+        let max_frames =
+            (frame_count as f64 * sample_rate as f64 / f64::from(spec.sample_rate)) as usize + 1;
+        samples = vec![0.0; max_frames * channel_count];
+        let frames = resampler.receive_samples(&mut samples, max_frames);
+        samples.truncate(frames * channel_count);
+    }
+    Ok((0..channel_count)
+        .map(|channel| {
+            samples
+                .iter()
+                .skip(channel)
+                .step_by(channel_count)
+                .copied()
+                .collect()
+        })
+        .collect())
+}
+
+/// Writes a float sample in whatever format the file was created with
+pub fn write_wav_sample<W: Write + Seek>(
+    writer: &mut hound::WavWriter<W>,
+    sample: f32,
+) -> hound::Result<()> {
+    let spec = writer.spec();
+    match spec.sample_format {
+        hound::SampleFormat::Float => writer.write_sample(sample),
+        hound::SampleFormat::Int => {
+            writer.write_sample(to_int(sample, u32::from(spec.bits_per_sample)))
+        }
+    }
+}
@@ -0,0 +1,171 @@
+//! Recording inputs at fixed times of the day, e.g. the news every morning from 8:00 to 8:15.
+
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use anyhow::{anyhow, bail, Context};
+use chrono::{DateTime, Datelike, Local, NaiveTime, Weekday};
+
+use crate::{encoder::Encoding, recorder::Recorder};
+
+/// File name used when a rule has no template
+pub const DEFAULT_TEMPLATE: &str = "{input}-{date}-{time}";
+
+const WEEKDAYS: [Weekday; 7] = [
+    Weekday::Mon,
+    Weekday::Tue,
+    Weekday::Wed,
+    Weekday::Thu,
+    Weekday::Fri,
+    Weekday::Sat,
+    Weekday::Sun,
+];
+
+/// Days of the week a rule applies to
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Days {
+    /// One bit per day, Monday first
+    mask: u8,
+}
+
+impl Days {
+    fn contains(self, day: Weekday) -> bool {
+        self.mask & (1 << day.num_days_from_monday()) != 0
+    }
+}
+
+impl FromStr for Days {
+    type Err = anyhow::Error;
+
+    /// Parses `daily`, `weekdays`, `weekends` or a list of days like `mon,wed,fri`
+    fn from_str(text: &str) -> anyhow::Result<Self> {
+        let mask = match text {
+            "daily" => 0b111_1111,
+            "weekdays" => 0b001_1111,
+            "weekends" => 0b110_0000,
+            _ => text.split(',').try_fold(0, |mask, day| {
+                let day: Weekday = day
+                    .parse()
+                    .map_err(|_| anyhow!("Unknown day '{day}', expected e.g. mon or tue"))?;
+                anyhow::Ok(mask | 1 << day.num_days_from_monday())
+            })?,
+        };
+        Ok(Days { mask })
+    }
+}
+
+impl fmt::Display for Days {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.mask {
+            0b111_1111 => write!(f, "daily"),
+            0b001_1111 => write!(f, "weekdays"),
+            0b110_0000 => write!(f, "weekends"),
+            _ => {
+                let days: Vec<String> = WEEKDAYS
+                    .iter()
+                    .filter(|day| self.contains(**day))
+                    .map(|day| day.to_string().to_lowercase())
+                    .collect();
+                write!(f, "{}", days.join(","))
+            }
+        }
+    }
+}
+
+/// Records an input between two times on some days of the week
+#[derive(Clone, Debug)]
+pub struct RecordingRule {
+    pub input: String,
+    pub days: Days,
+    pub start: NaiveTime,
+    /// Before `start` for recordings running past midnight, which count for the day they start
+    pub end: NaiveTime,
+    pub directory: PathBuf,
+    /// File name with `{input}`, `{date}` and `{time}` replaced by the input name and the start
+    /// of the recording
+    pub template: String,
+    pub encoding: Encoding,
+}
+
+impl RecordingRule {
+    /// Parses the time range as `HH:MM-HH:MM`
+    pub fn new(
+        input: String,
+        days: Days,
+        range: &str,
+        directory: PathBuf,
+        template: Option<String>,
+        encoding: Encoding,
+    ) -> anyhow::Result<Self> {
+        let (start, end) = range
+            .split_once('-')
+            .ok_or_else(|| anyhow!("Invalid time range '{range}', expected HH:MM-HH:MM"))?;
+        let parse = |time: &str| {
+            NaiveTime::parse_from_str(time, "%H:%M")
+                .with_context(|| format!("Invalid time '{time}'"))
+        };
+        let (start, end) = (parse(start)?, parse(end)?);
+        if start == end {
+            bail!("Recording from {start} to {end} would never stop");
+        }
+        Ok(Self {
+            input,
+            days,
+            start,
+            end,
+            directory,
+            template: template.unwrap_or_else(|| DEFAULT_TEMPLATE.to_string()),
+            encoding,
+        })
+    }
+
+    pub fn is_active(&self, now: DateTime<Local>) -> bool {
+        let time = now.time();
+        let today = now.weekday();
+        if self.start < self.end {
+            self.days.contains(today) && self.start <= time && time < self.end
+        } else {
+            (self.days.contains(today) && time >= self.start)
+                || (self.days.contains(today.pred()) && time < self.end)
+        }
+    }
+
+    /// Where a recording started at `now` goes
+    pub fn path(&self, now: DateTime<Local>) -> PathBuf {
+        let mut name = self
+            .template
+            .replace("{input}", &self.input)
+            .replace("{date}", &now.format("%Y-%m-%d").to_string())
+            .replace("{time}", &now.format("%H%M%S").to_string());
+        if Path::new(&name).extension().is_none() {
+            name.push('.');
+            name.push_str(self.encoding.extension());
+        }
+        self.directory.join(name)
+    }
+}
+
+impl fmt::Display for RecordingRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {}-{} to {} as {}",
+            self.input,
+            self.days,
+            self.start.format("%H:%M"),
+            self.end.format("%H:%M"),
+            self.directory.join(&self.template).display(),
+            self.encoding
+        )
+    }
+}
+
+/// A recording started by a rule, for as long as the rule is active
+pub struct ScheduledRecording {
+    pub path: PathBuf,
+    /// `None` if the recording failed to start, it is not tried again until the next time
+    pub recorder: Option<Recorder>,
+}
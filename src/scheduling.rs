@@ -0,0 +1,274 @@
+//! How the next input to play is picked among those with audio queued.
+//!
+//! Policies run for every item played, so they make a single pass over the inputs without
+//! allocating. Those taking turns only switch at a pause, never in the middle of what an input
+//! says.
+
+use std::{fmt, str::FromStr};
+
+use anyhow::bail;
+use serde::Deserialize;
+
+use crate::{BufferItem, Input};
+
+/// Picks the input to play next
+pub trait SchedulingPolicy: Sync {
+    /// Index of the playable input to play next, if any has audio queued
+    fn select(&self, inputs: &[Input]) -> Option<usize>;
+}
+
+/// Available scheduling policies
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Scheduling {
+    /// The highest priority tier first, the largest backlog within it
+    #[default]
+    Urgency,
+    /// The highest priority tier first, the first declared within it
+    Priority,
+    /// Each input in turn, in the order they are declared
+    RoundRobin,
+    /// The input that went without playing the longest
+    LeastRecent,
+}
+
+impl Scheduling {
+    pub fn policy(self) -> &'static dyn SchedulingPolicy {
+        match self {
+            Scheduling::Urgency => &Urgency,
+            Scheduling::Priority => &StrictPriority,
+            Scheduling::RoundRobin => &RoundRobin,
+            Scheduling::LeastRecent => &LeastRecentlyPlayed,
+        }
+    }
+}
+
+impl FromStr for Scheduling {
+    type Err = anyhow::Error;
+
+    fn from_str(text: &str) -> anyhow::Result<Self> {
+        Ok(match text {
+            "urgency" => Scheduling::Urgency,
+            "priority" => Scheduling::Priority,
+            "round-robin" => Scheduling::RoundRobin,
+            "least-recent" => Scheduling::LeastRecent,
+            _ => bail!(
+                "Unknown scheduling policy '{text}', expected urgency, priority, round-robin or \
+                 least-recent"
+            ),
+        })
+    }
+}
+
+impl fmt::Display for Scheduling {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Scheduling::Urgency => write!(f, "urgency"),
+            Scheduling::Priority => write!(f, "priority"),
+            Scheduling::RoundRobin => write!(f, "round-robin"),
+            Scheduling::LeastRecent => write!(f, "least-recent"),
+        }
+    }
+}
+
+/// By priority tier, then by urgency, the first of equally urgent inputs wins
+pub struct Urgency;
+
+impl SchedulingPolicy for Urgency {
+    fn select(&self, inputs: &[Input]) -> Option<usize> {
+        playable(inputs)
+            .min_by(|(_, a), (_, b)| b.cmp_precedence(a))
+            .map(|(index, _)| index)
+    }
+}
+
+/// By priority tier only, the first declared input of the tier wins
+pub struct StrictPriority;
+
+impl SchedulingPolicy for StrictPriority {
+    fn select(&self, inputs: &[Input]) -> Option<usize> {
+        playable(inputs)
+            .min_by_key(|(_, input)| -input.priority)
+            .map(|(index, _)| index)
+    }
+}
+
+/// The inputs take turns in the order they are declared
+pub struct RoundRobin;
+
+impl SchedulingPolicy for RoundRobin {
+    fn select(&self, inputs: &[Input]) -> Option<usize> {
+        let Some(current) = most_recently_played(inputs) else {
+            return playable(inputs).next().map(|(index, _)| index);
+        };
+        if mid_sentence(&inputs[current]) {
+            return Some(current);
+        }
+        // The ones after the current input first, the current one last
+        playable(inputs)
+            .min_by_key(|&(index, _)| (index + inputs.len() - current - 1) % inputs.len())
+            .map(|(index, _)| index)
+    }
+}
+
+/// The input that went without playing the longest, one never played before any other
+pub struct LeastRecentlyPlayed;
+
+impl SchedulingPolicy for LeastRecentlyPlayed {
+    fn select(&self, inputs: &[Input]) -> Option<usize> {
+        if let Some(current) = most_recently_played(inputs) {
+            if mid_sentence(&inputs[current]) {
+                return Some(current);
+            }
+        }
+        playable(inputs)
+            .min_by_key(|(_, input)| input.last_played)
+            .map(|(index, _)| index)
+    }
+}
+
+fn playable(inputs: &[Input]) -> impl Iterator<Item = (usize, &Input)> {
+    inputs
+        .iter()
+        .enumerate()
+        .filter(|(_, input)| input.is_playable())
+}
+
+fn most_recently_played(inputs: &[Input]) -> Option<usize> {
+    inputs
+        .iter()
+        .enumerate()
+        .filter(|(_, input)| input.last_played.is_some())
+        .max_by_key(|(_, input)| input.last_played)
+        .map(|(index, _)| index)
+}
+
+/// Whether the input is playable and in the middle of a sound, stored silence marks a pause
+fn mid_sentence(input: &Input) -> bool {
+    input.is_playable() && matches!(input.buffer.front(), Some(BufferItem::Samples(..)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant, SystemTime};
+
+    use super::*;
+
+    /// An input in priority tier `priority` with `frames` frames of sound queued, behind stored
+    /// silence if `at_pause`
+    fn input(priority: i32, frames: usize, at_pause: bool) -> Input {
+        let mut input = Input {
+            priority,
+            ..Default::default()
+        };
+        if at_pause {
+            input.buffer.push_back(BufferItem::Silence(480));
+        }
+        input.buffer.push_back(BufferItem::Samples(
+            vec![vec![0.5; frames]],
+            SystemTime::now(),
+        ));
+        input
+    }
+
+    /// Marks the inputs as played in the order given, the last one most recently, and the rest
+    /// as never played
+    fn played_in_order(inputs: &mut [Input], order: &[usize]) {
+        let start = Instant::now();
+        for input in inputs.iter_mut() {
+            input.last_played = None;
+        }
+        for (step, &index) in order.iter().enumerate() {
+            inputs[index].last_played = Some(start + Duration::from_secs(step as u64));
+        }
+    }
+
+    #[test]
+    fn urgency_prefers_the_tier_then_the_backlog() {
+        let mut inputs = vec![
+            input(0, 100, false),
+            input(0, 400, false),
+            input(1, 10, false),
+        ];
+        assert_eq!(Urgency.select(&inputs), Some(2));
+        inputs.pop();
+        assert_eq!(Urgency.select(&inputs), Some(1));
+        // A pause up front makes the larger backlog wait
+        inputs[1] = input(0, 400, true);
+        assert_eq!(Urgency.select(&inputs), Some(0));
+        // The first of equally urgent inputs wins
+        let inputs = vec![input(0, 100, false), input(0, 100, false)];
+        assert_eq!(Urgency.select(&inputs), Some(0));
+    }
+
+    #[test]
+    fn strict_priority_takes_the_first_of_the_tier() {
+        let inputs = vec![
+            input(0, 400, false),
+            input(1, 10, false),
+            input(1, 400, false),
+        ];
+        assert_eq!(StrictPriority.select(&inputs), Some(1));
+    }
+
+    #[test]
+    fn round_robin_takes_turns_at_pauses() {
+        let mut inputs = vec![
+            input(0, 100, true),
+            input(0, 100, true),
+            input(0, 100, true),
+        ];
+        assert_eq!(RoundRobin.select(&inputs), Some(0));
+        played_in_order(&mut inputs, &[0]);
+        assert_eq!(RoundRobin.select(&inputs), Some(1));
+        played_in_order(&mut inputs, &[0, 1, 2]);
+        assert_eq!(RoundRobin.select(&inputs), Some(0));
+
+        // Unplayable inputs are skipped
+        played_in_order(&mut inputs, &[0]);
+        inputs[1].disabled = true;
+        assert_eq!(RoundRobin.select(&inputs), Some(2));
+
+        // The current input keeps playing in the middle of a sound
+        inputs[0] = input(0, 100, false);
+        played_in_order(&mut inputs, &[0]);
+        assert_eq!(RoundRobin.select(&inputs), Some(0));
+    }
+
+    #[test]
+    fn least_recent_plays_the_one_waiting_longest() {
+        let mut inputs = vec![
+            input(0, 100, true),
+            input(0, 100, true),
+            input(0, 100, true),
+        ];
+        played_in_order(&mut inputs, &[0, 2]);
+        // Never played goes first
+        assert_eq!(LeastRecentlyPlayed.select(&inputs), Some(1));
+        played_in_order(&mut inputs, &[1, 0, 2]);
+        assert_eq!(LeastRecentlyPlayed.select(&inputs), Some(1));
+
+        inputs[2] = input(0, 100, false);
+        played_in_order(&mut inputs, &[1, 0, 2]);
+        assert_eq!(LeastRecentlyPlayed.select(&inputs), Some(2));
+    }
+
+    #[test]
+    fn selects_nothing_without_a_playable_input() {
+        let mut inputs = vec![input(0, 100, false), Input::default()];
+        inputs[0].held = true;
+        for scheduling in [
+            Scheduling::Urgency,
+            Scheduling::Priority,
+            Scheduling::RoundRobin,
+            Scheduling::LeastRecent,
+        ] {
+            assert_eq!(scheduling.policy().select(&inputs), None);
+            assert_eq!(
+                scheduling.to_string().parse::<Scheduling>().unwrap(),
+                scheduling
+            );
+        }
+        assert!("fastest".parse::<Scheduling>().is_err());
+    }
+}
@@ -0,0 +1,56 @@
+use std::ops::Range;
+
+use crate::{buffer::Buffer, BufferItem};
+
+/// Finds the completed segments of sound in `buffer` starting at item `start`.
+///
+/// Segments end at silence or once they reach `max_length` samples. Returns the item ranges of
+/// all completed segments and the index of the first item belonging to the still growing
+/// segment at the back of the buffer.
+pub fn completed_segments(
+    buffer: &Buffer,
+    start: usize,
+    max_length: usize,
+) -> (Vec<Range<usize>>, usize) {
+    let mut segments = Vec::new();
+    let mut segment_start = start;
+    let mut segment_length = 0;
+
+    for (index, item) in buffer.iter().enumerate().skip(start) {
+        match item {
+            BufferItem::Samples(samples, _) => {
+                segment_length += samples[0].len();
+                if segment_length >= max_length {
+                    segments.push(segment_start..index + 1);
+                    segment_start = index + 1;
+                    segment_length = 0;
+                }
+            }
+            BufferItem::Silence(_) => {
+                if segment_length > 0 {
+                    segments.push(segment_start..index);
+                }
+                segment_start = index + 1;
+                segment_length = 0;
+            }
+        }
+    }
+
+    (segments, segment_start.min(buffer.len()))
+}
+
+/// Samples of sound in the given item
+pub fn item_length(item: &BufferItem) -> usize {
+    match item {
+        BufferItem::Samples(samples, _) => samples[0].len(),
+        BufferItem::Silence(_) => 0,
+    }
+}
+
+/// Per-channel sample chunks of the items in `range`
+pub fn chunks(buffer: &Buffer, range: Range<usize>) -> impl Iterator<Item = &Vec<Vec<f32>>> {
+    buffer.range(range).filter_map(|item| match item {
+        BufferItem::Samples(samples, _) => Some(samples),
+        BufferItem::Silence(_) => None,
+    })
+}
@@ -0,0 +1,308 @@
+//! Guided first-run setup in the terminal: choosing the applications to take inputs from,
+//! trying out pausing their players and measuring their levels for the silence threshold.
+
+use std::{
+    io::{self, BufRead, Write as _},
+    path::PathBuf,
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Context};
+use jack::{Client, Control, ProcessScope};
+
+use crate::{
+    config,
+    discover::{self, Application},
+    meter::to_db,
+    mpris,
+    silence::SILENCE_THRESHOLD,
+};
+
+/// Time each source is listened to while playing
+const LISTEN_TIME: Duration = Duration::from_secs(3);
+
+/// Time each source is listened to while paused, for the noise it makes when not playing
+const PAUSED_LISTEN_TIME: Duration = Duration::from_secs(1);
+
+/// Time players get to react to a pause or resume command
+const PLAYER_REACTION_TIME: Duration = Duration::from_millis(500);
+
+/// Share of the periods of a playing source that are its quiet passages, the threshold stays
+/// below them
+const QUIET_FRACTION: f32 = 0.1;
+
+/// Bounds of a calibrated threshold, quieter is inaudible and louder cuts off speech
+const THRESHOLD_RANGE: (f32, f32) = (0.0005, 0.05);
+
+/// Walks through setting up the inputs and writes the config.
+///
+/// Usage: `setup [file]`, the file is where the engine reads its config by default.
+pub fn run(mut args: impl Iterator<Item = String>) -> anyhow::Result<()> {
+    let path = args
+        .next()
+        .map(PathBuf::from)
+        .unwrap_or_else(config::default_path);
+    if path.exists()
+        && !confirm(
+            &format!("{} exists already, replace it?", path.display()),
+            false,
+        )?
+    {
+        return Ok(());
+    }
+
+    println!("Start playing everything audiomux should take inputs from, then press enter.");
+    prompt("")?;
+    let mut applications = discover::applications()?;
+    if applications.is_empty() {
+        bail!("No application has audio ports");
+    }
+    let players = match mpris::players() {
+        Ok(players) => players,
+        Err(error) => {
+            println!("Players can't be paused: {error:#}");
+            Vec::new()
+        }
+    };
+
+    println!();
+    for (index, application) in applications.iter().enumerate() {
+        println!(
+            "{}: {} ({})",
+            index + 1,
+            application.client,
+            application.ports.join(", ")
+        );
+    }
+    let answer = prompt("Numbers of the applications to take inputs from, empty for all")?;
+    if !answer.is_empty() {
+        let mut chosen = Vec::new();
+        for word in answer.split([' ', ',']).filter(|word| !word.is_empty()) {
+            let index = word
+                .parse::<usize>()
+                .ok()
+                .filter(|number| (1..=applications.len()).contains(number))
+                .with_context(|| format!("{word} is none of the listed numbers"))?;
+            chosen.push(index - 1);
+        }
+        chosen.sort_unstable();
+        chosen.dedup();
+        applications = applications
+            .into_iter()
+            .enumerate()
+            .filter(|(index, _)| chosen.contains(index))
+            .map(|(_, application)| application)
+            .collect();
+    }
+
+    for application in applications.iter_mut() {
+        println!();
+        println!("{}", application.client);
+        application.player = choose_player(application, &players)?;
+    }
+
+    println!();
+    let listener = Listener::start()?;
+    let mut thresholds = Vec::new();
+    for application in &applications {
+        println!();
+        println!(
+            "Make sure {} is playing, then press enter.",
+            application.client
+        );
+        prompt("")?;
+        let threshold = listener.calibrate(application)?;
+        println!(
+            "Suggesting silence below {:.1} dBFS for {}",
+            to_db(threshold),
+            application.client
+        );
+        thresholds.push(threshold);
+    }
+    // No source's quiet passages may count as silence
+    let threshold = thresholds
+        .into_iter()
+        .reduce(f32::min)
+        .unwrap_or(THRESHOLD_RANGE.0);
+
+    discover::write(
+        &path,
+        &discover::starter_config(&applications, Some(threshold)),
+    )?;
+    println!();
+    println!(
+        "Wrote {} inputs with silence below {:.1} dBFS to {}",
+        applications.len(),
+        to_db(threshold),
+        path.display()
+    );
+    Ok(())
+}
+
+/// The player pausing an application, tried out before it is kept
+fn choose_player(application: &Application, players: &[String]) -> anyhow::Result<Option<String>> {
+    let mut player = application.player.clone();
+    loop {
+        let Some(candidate) = player.take() else {
+            if players.is_empty() {
+                println!("No player to pause it with, it will play on while others are heard");
+                return Ok(None);
+            }
+            for (index, player) in players.iter().enumerate() {
+                println!("{}: {player}", index + 1);
+            }
+            let answer = prompt("Number of the player pausing it, empty for none")?;
+            if answer.is_empty() {
+                return Ok(None);
+            }
+            match answer.parse::<usize>() {
+                Ok(number) if (1..=players.len()).contains(&number) => {
+                    // Without the instance, which changes when the player restarts
+                    let name = &players[number - 1];
+                    player = Some(name.split('.').next().unwrap_or(name).to_string());
+                }
+                _ => println!("{answer} is none of the listed numbers"),
+            }
+            continue;
+        };
+        println!("Pausing it through {candidate}");
+        control(&candidate, mpris::Action::Pause);
+        let paused = confirm("Did it pause?", true)?;
+        control(&candidate, mpris::Action::Play);
+        if paused && confirm("Did it resume?", true)? {
+            return Ok(Some(candidate));
+        }
+        println!("Not pausing it through {candidate}");
+    }
+}
+
+fn control(player: &str, action: mpris::Action) {
+    if let Err(error) = mpris::call_now(Some(player), action) {
+        println!("Failed to {action} {player}: {error:#}");
+    }
+    thread::sleep(PLAYER_REACTION_TIME);
+}
+
+/// JACK client measuring the peak of every period of the ports connected to it
+struct Listener {
+    client: jack::AsyncClient<(), jack::ClosureProcessHandler<ProcessCallback>>,
+    port_names: Vec<String>,
+    peaks: mpsc::Receiver<f32>,
+}
+
+type ProcessCallback = Box<dyn FnMut(&Client, &ProcessScope) -> Control + Send>;
+
+impl Listener {
+    fn start() -> anyhow::Result<Self> {
+        let (client, _status) = Client::new("audiomux-setup", jack::ClientOptions::NO_START_SERVER)
+            .context("Failed to create jack client")?;
+        let ports = (1..=2)
+            .map(|number| {
+                client
+                    .register_port(&format!("listen_{number}"), jack::AudioIn::default())
+                    .context("Failed to register input port")
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let port_names = ports
+            .iter()
+            .map(|port| port.name())
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // Holds a minute of periods, the listener drains it more often than that
+        let (sender, peaks) = mpsc::sync_channel(1 << 14);
+        let callback: ProcessCallback = Box::new(move |_client, scope| {
+            let peak = ports
+                .iter()
+                .flat_map(|port| port.as_slice(scope))
+                .fold(0.0f32, |peak, sample| peak.max(sample.abs()));
+            let _ = sender.try_send(peak);
+            Control::Continue
+        });
+        let client = client
+            .activate_async((), jack::ClosureProcessHandler::new(callback))
+            .context("Failed to activate client")?;
+        Ok(Self {
+            client,
+            port_names,
+            peaks,
+        })
+    }
+
+    /// Threshold between the noise of the application while paused and its quiet passages
+    fn calibrate(&self, application: &Application) -> anyhow::Result<f32> {
+        let client = self.client.as_client();
+        let connections: Vec<(&String, &String)> =
+            application.ports.iter().zip(&self.port_names).collect();
+        for (source, port) in &connections {
+            client
+                .connect_ports_by_name(source, port)
+                .with_context(|| format!("Failed to connect {source} to {port}"))?;
+        }
+        println!("Listening to it for {} s", LISTEN_TIME.as_secs());
+        let mut playing = self.listen(LISTEN_TIME);
+        let noise = match &application.player {
+            Some(player) => {
+                control(player, mpris::Action::Pause);
+                let noise = self.listen(PAUSED_LISTEN_TIME).into_iter().reduce(f32::max);
+                control(player, mpris::Action::Play);
+                noise
+            }
+            None => None,
+        };
+        for (source, port) in &connections {
+            let _ = client.disconnect_ports_by_name(source, port);
+        }
+
+        playing.retain(|peak| *peak > 0.0);
+        if playing.is_empty() {
+            println!("Heard nothing, keeping the default threshold");
+            return Ok(SILENCE_THRESHOLD);
+        }
+        playing.sort_unstable_by(f32::total_cmp);
+        let quiet = playing[((playing.len() - 1) as f32 * QUIET_FRACTION) as usize];
+        let threshold = match noise {
+            // Halfway between them in dB
+            Some(noise) if noise < quiet => (noise.max(THRESHOLD_RANGE.0) * quiet).sqrt(),
+            _ => quiet / 4.0,
+        };
+        Ok(threshold.clamp(THRESHOLD_RANGE.0, THRESHOLD_RANGE.1))
+    }
+
+    /// Peaks of the periods during the time
+    fn listen(&self, time: Duration) -> Vec<f32> {
+        while self.peaks.try_recv().is_ok() {}
+        let end = Instant::now() + time;
+        let mut peaks = Vec::new();
+        while let Some(left) = end.checked_duration_since(Instant::now()) {
+            match self.peaks.recv_timeout(left) {
+                Ok(peak) => peaks.push(peak),
+                Err(mpsc::RecvTimeoutError::Timeout) => break,
+                Err(mpsc::RecvTimeoutError::Disconnected) => break,
+            }
+        }
+        peaks
+    }
+}
+
+fn prompt(question: &str) -> anyhow::Result<String> {
+    if !question.is_empty() {
+        print!("{question}: ");
+    }
+    io::stdout().flush()?;
+    let mut line = String::new();
+    if io::stdin().lock().read_line(&mut line)? == 0 {
+        bail!("Setup aborted");
+    }
+    Ok(line.trim().to_string())
+}
+
+fn confirm(question: &str, default: bool) -> anyhow::Result<bool> {
+    let options = if default { "[Y/n]" } else { "[y/N]" };
+    let answer = prompt(&format!("{question} {options}"))?;
+    Ok(match answer.to_lowercase().as_str() {
+        "" => default,
+        answer => answer.starts_with('y'),
+    })
+}
@@ -0,0 +1,89 @@
+//! State frames published in a shared memory segment, for UIs polling faster than HTTP allows.
+//!
+//! The segment is a file on tmpfs that readers map. Reading it takes no system calls and costs
+//! the engine nothing per reader, it writes one frame per status interval regardless of how many
+//! read it. Layout, little endian:
+//!
+//! ```text
+//! "AMXM" sequence:u32 length:u32 state frame:[u8; length]
+//! ```
+//!
+//! The sequence is odd while a frame is written. Readers copy the frame and use it if the
+//! sequence was even and unchanged before and after copying, otherwise they try again.
+
+use std::{
+    fs::{self, OpenOptions},
+    path::{Path, PathBuf},
+    sync::atomic::{fence, AtomicU32, Ordering},
+};
+
+use anyhow::{bail, Context};
+use memmap2::MmapMut;
+
+use crate::state_frame::StateFrame;
+
+pub const MAGIC: &[u8; 4] = b"AMXM";
+
+/// Size of the segment, frames of a few hundred inputs fit
+const SIZE: usize = 64 * 1024;
+
+const SEQUENCE_OFFSET: usize = 4;
+const LENGTH_OFFSET: usize = 8;
+const FRAME_OFFSET: usize = 12;
+
+pub struct StatusSegment {
+    path: PathBuf,
+    map: MmapMut,
+}
+
+impl StatusSegment {
+    pub fn create(path: &Path) -> anyhow::Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)
+            .with_context(|| format!("Failed to create {}", path.display()))?;
+        file.set_len(SIZE as u64)?;
+        // Safe as long as nobody but this segment writes the file, readers only read it
+        let mut map = unsafe { MmapMut::map_mut(&file) }
+            .with_context(|| format!("Failed to map {}", path.display()))?;
+        map[..MAGIC.len()].copy_from_slice(MAGIC);
+        Ok(Self {
+            path: path.to_path_buf(),
+            map,
+        })
+    }
+
+    pub fn publish(&mut self, frame: &StateFrame) -> anyhow::Result<()> {
+        let bytes = frame.to_bytes();
+        if FRAME_OFFSET + bytes.len() > SIZE {
+            bail!(
+                "State frame of {} bytes doesn't fit the segment",
+                bytes.len()
+            );
+        }
+        let sequence = self.sequence().load(Ordering::Relaxed);
+        self.sequence()
+            .store(sequence.wrapping_add(1), Ordering::Relaxed);
+        fence(Ordering::Release);
+        self.map[LENGTH_OFFSET..FRAME_OFFSET].copy_from_slice(&(bytes.len() as u32).to_le_bytes());
+        self.map[FRAME_OFFSET..FRAME_OFFSET + bytes.len()].copy_from_slice(&bytes);
+        self.sequence()
+            .store(sequence.wrapping_add(2), Ordering::Release);
+        Ok(())
+    }
+
+    fn sequence(&self) -> &AtomicU32 {
+        // The map is page aligned, so the offset keeps the counter aligned
+        unsafe { &*(self.map.as_ptr().add(SEQUENCE_OFFSET) as *const AtomicU32) }
+    }
+}
+
+impl Drop for StatusSegment {
+    fn drop(&mut self) {
+        // Readers holding a mapping keep the last frame, new ones find nothing
+        let _ = fs::remove_file(&self.path);
+    }
+}
@@ -0,0 +1,394 @@
+use std::ops::Range;
+
+use serde::Deserialize;
+
+/// Default amplitude below which a sample is considered silent
+pub const SILENCE_THRESHOLD: f32 = 0.01;
+
+/// Default length of the silence analysis window in samples (~5 ms at 48 kHz)
+pub const DEFAULT_WINDOW: usize = 256;
+
+/// Default minimum length of a silent gap inside a period to split there (~20 ms at 48 kHz)
+pub const DEFAULT_MIN_SPLIT: usize = 960;
+
+/// Default number of samples kept around sound when moving a split point (~2 ms at 48 kHz)
+pub const DEFAULT_HANGOVER: usize = 96;
+
+/// Default length of the longest silence stored between sounds (~100 ms at 48 kHz)
+pub const DEFAULT_MAX_STORED: usize = 4800;
+
+/// How the per-channel silence decisions of an input are combined, `"all"`, `"any"` or
+/// `{ weighted = [...] }` in the config
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SilencePolicy {
+    /// The input is silent when all considered channels are silent
+    #[default]
+    All,
+    /// The input is silent as soon as any considered channel is silent
+    Any,
+    /// The input is silent when the weighted mean of the channel peaks is below the threshold.
+    /// Channels without a weight are weighted with 1.0.
+    Weighted(Vec<f32>),
+}
+
+/// How loud an analysis window is taken to be
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Level {
+    /// Its largest sample, a single click breaks a silence
+    #[default]
+    Peak,
+    /// Its root mean square, which brief transients and dither barely raise
+    Rms,
+}
+
+impl Level {
+    pub fn of(self, samples: &[f32]) -> f32 {
+        match self {
+            Level::Peak => peak(samples),
+            Level::Rms if samples.is_empty() => 0.0,
+            Level::Rms => {
+                let energy: f32 = samples.iter().map(|sample| sample * sample).sum();
+                (energy / samples.len() as f32).sqrt()
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SilenceDetector {
+    /// Level below which a window is considered silent
+    pub threshold: f32,
+    pub level: Level,
+    /// Factor the level has to exceed the threshold by to end a silence, 1.0 for none. Keeps
+    /// noise hovering around the threshold from breaking a silence up.
+    pub hysteresis: f32,
+    pub policy: SilencePolicy,
+    /// Channels taking part in silence detection, `None` considers all channels.
+    /// Useful for mono sources delivered on a stereo pair with one dead channel.
+    pub channel_mask: Option<Vec<bool>>,
+    /// Length of the analysis window in samples, `None` analyses whole periods at once.
+    /// Keeps the trimming granularity independent of the JACK buffer size.
+    pub window: Option<usize>,
+    /// Minimum length in samples of a silent gap enclosed by sound within a period for the
+    /// period to be split there. Silence at the period edges is always split off.
+    pub min_split: usize,
+    /// Samples of silence kept next to sound when moving split points to the exact boundary,
+    /// so decaying word endings are not cut off
+    pub hangover: usize,
+    /// Samples of silence after sound that still count as sound, across periods. Keeps quiet
+    /// passages of music from being taken for silence.
+    pub hold: usize,
+    /// Longest silence stored between sounds in samples, longer silences are shortened to it
+    pub max_stored: usize,
+    /// Samples of silence since the last sound
+    silent_run: usize,
+    /// Whether the last window was silent, across periods
+    silent: bool,
+}
+
+impl Default for SilenceDetector {
+    fn default() -> Self {
+        Self {
+            threshold: SILENCE_THRESHOLD,
+            level: Level::default(),
+            hysteresis: 1.0,
+            policy: SilencePolicy::default(),
+            channel_mask: None,
+            window: Some(DEFAULT_WINDOW),
+            min_split: DEFAULT_MIN_SPLIT,
+            hangover: DEFAULT_HANGOVER,
+            hold: 0,
+            max_stored: DEFAULT_MAX_STORED,
+            silent_run: 0,
+            silent: false,
+        }
+    }
+}
+
+impl SilenceDetector {
+    fn is_considered(&self, channel: usize) -> bool {
+        match &self.channel_mask {
+            Some(mask) => mask.get(channel).copied().unwrap_or(false),
+            None => true,
+        }
+    }
+
+    /// Whether the samples in `range` of every channel are silent, judged in place because this
+    /// runs for every period
+    fn is_below(&self, channels: &[&[f32]], range: Range<usize>, threshold: f32) -> bool {
+        let mut levels = channels
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| self.is_considered(*index))
+            .map(|(index, samples)| (index, self.level.of(&samples[range.clone()])));
+
+        match &self.policy {
+            SilencePolicy::All => levels.all(|(_, level)| level < threshold),
+            SilencePolicy::Any => levels.any(|(_, level)| level < threshold),
+            SilencePolicy::Weighted(weights) => {
+                let (weighted_sum, weight_sum) =
+                    levels.fold((0.0, 0.0), |(weighted_sum, weight_sum), (index, level)| {
+                        let weight = weights.get(index).copied().unwrap_or(1.0);
+                        (weighted_sum + weight * level, weight_sum + weight)
+                    });
+                // No channel carries any weight, nothing can be heard
+                if weight_sum <= 0.0 {
+                    return true;
+                }
+                weighted_sum / weight_sum < threshold
+            }
+        }
+    }
+
+    /// Splits a period at its silence boundaries into consecutive runs of sound and silence.
+    ///
+    /// Returns the sample range of every run together with whether it is silent.
+    pub fn segments(&mut self, channels: &[&[f32]]) -> Vec<(Range<usize>, bool)> {
+        let mut segments = self.window_segments(channels);
+
+        // Window edges rarely coincide with the actual boundary, move every split point to the
+        // first/last sample that can be heard
+        for index in 1..segments.len() {
+            let (previous, current) = segments.split_at_mut(index);
+            let (previous_range, previous_silent) = previous.last_mut().unwrap();
+            let (current_range, _) = &mut current[0];
+            let boundary = if *previous_silent {
+                self.first_audible(channels, current_range.clone())
+                    .map_or(current_range.end, |first| {
+                        first.saturating_sub(self.hangover).max(current_range.start)
+                    })
+            } else {
+                self.last_audible(channels, previous_range.clone())
+                    .map_or(previous_range.start, |last| {
+                        (last + 1 + self.hangover).min(previous_range.end)
+                    })
+            };
+            previous_range.end = boundary;
+            current_range.start = boundary;
+        }
+
+        // Short gaps between sound are part of the sound (pauses between words and the like)
+        let length = channels.first().map_or(0, |channel| channel.len());
+        for (range, silent) in segments.iter_mut() {
+            let enclosed = range.start > 0 && range.end < length;
+            if *silent && enclosed && range.len() < self.min_split {
+                *silent = false;
+            }
+        }
+
+        let mut merged: Vec<(Range<usize>, bool)> = Vec::with_capacity(segments.len());
+        for (range, silent) in segments.into_iter().filter(|(range, _)| !range.is_empty()) {
+            match merged.last_mut() {
+                Some((previous_range, previous_silent)) if *previous_silent == silent => {
+                    previous_range.end = range.end
+                }
+                _ => merged.push((range, silent)),
+            }
+        }
+        merged
+    }
+
+    /// Turns the silence of the segments of a period into sound until it lasted `hold` samples,
+    /// counting the silence at the end of the previous periods
+    pub fn hold(&mut self, segments: Vec<(Range<usize>, bool)>) -> Vec<(Range<usize>, bool)> {
+        if self.hold == 0 {
+            return segments;
+        }
+        let mut held: Vec<(Range<usize>, bool)> = Vec::with_capacity(segments.len() + 1);
+        let mut push = |range: Range<usize>, silent: bool| match held.last_mut() {
+            Some((previous_range, previous_silent)) if *previous_silent == silent => {
+                previous_range.end = range.end
+            }
+            _ => held.push((range, silent)),
+        };
+        for (range, silent) in segments {
+            if !silent {
+                self.silent_run = 0;
+                push(range, false);
+                continue;
+            }
+            let sound = self.hold.saturating_sub(self.silent_run).min(range.len());
+            self.silent_run += range.len();
+            if sound > 0 {
+                push(range.start..range.start + sound, false);
+            }
+            if sound < range.len() {
+                push(range.start + sound..range.end, true);
+            }
+        }
+        held
+    }
+
+    /// Splits a period into consecutive runs of silent and non-silent analysis windows. A
+    /// silence lasts until the level exceeds the threshold raised by the hysteresis.
+    fn window_segments(&mut self, channels: &[&[f32]]) -> Vec<(Range<usize>, bool)> {
+        let length = channels.first().map_or(0, |channel| channel.len());
+        let window = self.window.unwrap_or(length).max(1);
+
+        let mut segments: Vec<(Range<usize>, bool)> = Vec::new();
+        for start in (0..length).step_by(window) {
+            let end = (start + window).min(length);
+            let threshold = if self.silent {
+                self.threshold * self.hysteresis
+            } else {
+                self.threshold
+            };
+            let silent = self.is_below(channels, start..end, threshold);
+            self.silent = silent;
+
+            match segments.last_mut() {
+                // Same classification as the previous window, extend the run
+                Some((range, previous_silent)) if *previous_silent == silent => range.end = end,
+                _ => segments.push((start..end, silent)),
+            }
+        }
+        segments
+    }
+
+    fn first_audible(&self, channels: &[&[f32]], range: Range<usize>) -> Option<usize> {
+        range
+            .into_iter()
+            .find(|&index| !self.is_below(channels, index..index + 1, self.threshold))
+    }
+
+    fn last_audible(&self, channels: &[&[f32]], range: Range<usize>) -> Option<usize> {
+        range
+            .into_iter()
+            .rev()
+            .find(|&index| !self.is_below(channels, index..index + 1, self.threshold))
+    }
+}
+
+fn peak(samples: &[f32]) -> f32 {
+    samples
+        .iter()
+        .fold(0.0, |peak, sample| peak.max(sample.abs()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `parts` of (length, amplitude) one after another, the sign alternating every sample
+    fn signal(parts: &[(usize, f32)]) -> Vec<f32> {
+        parts
+            .iter()
+            .flat_map(|&(length, amplitude)| {
+                (0..length).map(move |index| {
+                    if index % 2 == 0 {
+                        amplitude
+                    } else {
+                        -amplitude
+                    }
+                })
+            })
+            .collect()
+    }
+
+    fn detector() -> SilenceDetector {
+        SilenceDetector {
+            hangover: 0,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn splits_at_the_exact_boundary() {
+        let samples = signal(&[(1000, 0.0), (2000, 0.5), (1500, 0.0)]);
+        assert_eq!(
+            detector().segments(&[&samples]),
+            [(0..1000, true), (1000..3000, false), (3000..4500, true)]
+        );
+    }
+
+    #[test]
+    fn keeps_the_hangover_next_to_sound() {
+        let samples = signal(&[(1000, 0.0), (1900, 0.5), (1600, 0.0)]);
+        let mut detector = SilenceDetector::default();
+        assert_eq!(
+            detector.segments(&[&samples]),
+            [(0..904, true), (904..2996, false), (2996..4500, true)]
+        );
+    }
+
+    #[test]
+    fn splits_only_at_long_enough_gaps() {
+        let short = signal(&[(1000, 0.5), (500, 0.0), (1500, 0.5)]);
+        assert_eq!(detector().segments(&[&short]), [(0..3000, false)]);
+
+        let long = signal(&[(1000, 0.5), (2000, 0.0), (1500, 0.5)]);
+        assert_eq!(
+            detector().segments(&[&long]),
+            [(0..1000, false), (1000..3000, true), (3000..4500, false)]
+        );
+    }
+
+    #[test]
+    fn hysteresis_keeps_a_silence_going() {
+        let silence = signal(&[(512, 0.0)]);
+        let noise = signal(&[(512, 0.015)]);
+        let mut detector = SilenceDetector {
+            hysteresis: 2.0,
+            ..detector()
+        };
+        detector.segments(&[&silence]);
+        assert_eq!(detector.segments(&[&noise]), [(0..512, true)]);
+
+        let mut detector = self::detector();
+        detector.segments(&[&silence]);
+        assert_eq!(detector.segments(&[&noise]), [(0..512, false)]);
+    }
+
+    #[test]
+    fn holds_silence_after_sound_across_periods() {
+        let mut detector = SilenceDetector {
+            hold: 1000,
+            ..detector()
+        };
+        assert_eq!(
+            detector.hold(vec![(0..500, false), (500..1100, true)]),
+            [(0..1100, false)]
+        );
+        assert_eq!(
+            detector.hold(vec![(0..600, true)]),
+            [(0..400, false), (400..600, true)]
+        );
+        assert_eq!(detector.hold(vec![(0..600, true)]), [(0..600, true)]);
+    }
+
+    #[test]
+    fn combines_the_channels_by_policy_and_mask() {
+        let sound = signal(&[(256, 0.5)]);
+        let dead = signal(&[(256, 0.0)]);
+        let channels: &[&[f32]] = &[&sound, &dead];
+        let silent = |detector: SilenceDetector| detector.is_below(channels, 0..256, 0.01);
+
+        assert!(!silent(detector()));
+        assert!(silent(SilenceDetector {
+            policy: SilencePolicy::Any,
+            ..detector()
+        }));
+        assert!(!silent(SilenceDetector {
+            policy: SilencePolicy::Weighted(vec![1.0, 1.0]),
+            ..detector()
+        }));
+        assert!(silent(SilenceDetector {
+            policy: SilencePolicy::Weighted(vec![0.0, 1.0]),
+            ..detector()
+        }));
+        assert!(silent(SilenceDetector {
+            channel_mask: Some(vec![false, true]),
+            ..detector()
+        }));
+    }
+
+    #[test]
+    fn measures_peak_and_rms() {
+        let samples = [1.0, -1.0, 0.0, 0.0];
+        assert_eq!(Level::Peak.of(&samples), 1.0);
+        assert_eq!(Level::Rms.of(&samples), 0.5f32.sqrt());
+        assert_eq!(Level::Rms.of(&[]), 0.0);
+    }
+}
@@ -0,0 +1,340 @@
+//! Control interface on a Unix socket for scripts and external tools, taking JSON commands one
+//! object per line:
+//!
+//! ```text
+//! {"command": "list-inputs"}
+//! {"command": "set-gain", "input": "music", "db": -6.0}
+//! {"command": "set-tempo", "input": "podcast", "speed": 1.5}
+//! {"command": "pause", "input": "music"}
+//! {"command": "resume", "input": "music"}
+//! {"command": "flush", "input": "music"}
+//! {"command": "backlog", "input": "podcast"}
+//! ```
+//!
+//! Every command is answered by one line, an object with `ok` and the result, or `ok` false and
+//! the `error`. The `ctl` subcommand is a client for the shell.
+
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    io::{BufRead, BufReader, Write},
+    os::unix::{
+        fs::PermissionsExt,
+        net::{UnixListener, UnixStream},
+    },
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, PoisonError},
+};
+
+use anyhow::{anyhow, bail, Context};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::{
+    control::{check_range, Command, GAIN_RANGE, SPEED_RANGE},
+    meter::to_db,
+    Input, JackState,
+};
+
+/// `$XDG_RUNTIME_DIR/audiomux.sock`. There is no fallback to the temporary directory, where
+/// other users could take the name before the engine does.
+pub fn default_path() -> anyhow::Result<PathBuf> {
+    let directory = env::var_os("XDG_RUNTIME_DIR").context("XDG_RUNTIME_DIR is not set")?;
+    Ok(PathBuf::from(directory).join("audiomux.sock"))
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(tag = "command", rename_all = "kebab-case", deny_unknown_fields)]
+enum Request {
+    /// The inputs with their backlog, speed, gain and whether they are paused
+    ListInputs,
+    /// Gain in dB applied to what an input captures
+    SetGain { input: String, db: f64 },
+    /// Pins the playback speed of an input, back to following its backlog without a speed
+    SetTempo { input: String, speed: Option<f64> },
+    /// Stops playing an input, pausing its source if it can
+    Pause { input: String },
+    /// Plays an input again, resuming its source if it was paused
+    Resume { input: String },
+    /// Discards the audio queued on an input
+    Flush { input: String },
+    /// Seconds queued on an input, or on all of them without one
+    Backlog { input: Option<String> },
+}
+
+#[derive(Serialize)]
+struct InputStatus {
+    name: String,
+    /// Seconds of sound queued
+    backlog: f64,
+    speed: f64,
+    gain_db: f32,
+    playing: bool,
+    disabled: bool,
+    held: bool,
+    source_paused: bool,
+}
+
+impl Request {
+    /// Applies the request, returns the fields of the reply besides `ok`
+    fn apply(self, state: &mut JackState) -> anyhow::Result<Value> {
+        let command = match self {
+            Request::ListInputs => {
+                let inputs: Vec<InputStatus> = state
+                    .inputs
+                    .iter()
+                    .map(|input| InputStatus {
+                        name: input.name.clone(),
+                        backlog: backlog(state, input),
+                        speed: input.tempo(state.speed_trim) * input.rate,
+                        gain_db: to_db(input.gain),
+                        playing: state.playing.as_ref() == Some(&input.name),
+                        disabled: input.disabled,
+                        held: input.held,
+                        source_paused: input
+                            .pausing
+                            .as_ref()
+                            .is_some_and(|pausing| pausing.source_paused),
+                    })
+                    .collect();
+                return Ok(json!({ "inputs": inputs }));
+            }
+            Request::Backlog { input } => {
+                let backlogs: BTreeMap<&str, f64> = state
+                    .inputs
+                    .iter()
+                    .filter(|candidate| input.as_ref().is_none_or(|name| candidate.name == *name))
+                    .map(|candidate| (candidate.name.as_str(), backlog(state, candidate)))
+                    .collect();
+                if backlogs.is_empty() {
+                    bail!("No input named '{}'", input.unwrap_or_default());
+                }
+                return Ok(json!({ "backlog": backlogs }));
+            }
+            Request::SetGain { input, db } => Command::SetGain {
+                input,
+                gain: check_range(db, "gain", GAIN_RANGE)? as f32,
+            },
+            Request::SetTempo { input, speed } => Command::SetSpeed {
+                input,
+                speed: speed
+                    .map(|speed| check_range(speed, "speed", SPEED_RANGE))
+                    .transpose()?,
+            },
+            Request::Pause { input } => {
+                let pause_source = state
+                    .inputs
+                    .iter()
+                    .any(|candidate| candidate.name == input && candidate.pausing.is_some());
+                Command::Disable {
+                    input,
+                    pause_source,
+                }
+            }
+            Request::Resume { input } => Command::Enable { input },
+            Request::Flush { input } => Command::Flush { input },
+        };
+        Ok(json!({ "message": command.apply(state)? }))
+    }
+}
+
+fn backlog(state: &JackState, input: &Input) -> f64 {
+    input.buffered_samples() as f64 / state.sample_rate.max(1) as f64
+}
+
+/// Listens on `path` and serves every connection on a thread of its own. A socket left behind
+/// by an engine that is gone is replaced, one another engine listens on is not. Only the user
+/// running the engine may connect.
+pub fn serve(path: &Path, jack_state: Arc<Mutex<JackState>>) -> anyhow::Result<()> {
+    if UnixStream::connect(path).is_ok() {
+        bail!("Another engine listens on {}", path.display());
+    }
+    if path.exists() {
+        fs::remove_file(path)
+            .with_context(|| format!("Failed to remove the stale {}", path.display()))?;
+    }
+    let listener = UnixListener::bind(path)
+        .with_context(|| format!("Failed to listen for commands on {}", path.display()))?;
+    fs::set_permissions(path, fs::Permissions::from_mode(0o600))
+        .with_context(|| format!("Failed to restrict access to {}", path.display()))?;
+    std::thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let jack_state = jack_state.clone();
+            std::thread::spawn(move || {
+                // A broken client must not take the interface down
+                let _ = respond(stream, &jack_state);
+            });
+        }
+    });
+    Ok(())
+}
+
+fn respond(stream: UnixStream, jack_state: &Arc<Mutex<JackState>>) -> std::io::Result<()> {
+    let mut writer = stream.try_clone()?;
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let result = serde_json::from_str::<Request>(&line)
+            .context("Invalid command")
+            // Keeps working while a panicked engine is being restarted
+            .and_then(|request| {
+                request.apply(&mut jack_state.lock().unwrap_or_else(PoisonError::into_inner))
+            });
+        let reply = match result {
+            Ok(mut reply) => {
+                reply["ok"] = true.into();
+                reply
+            }
+            Err(error) => json!({ "ok": false, "error": format!("{error:#}") }),
+        };
+        writeln!(writer, "{reply}")?;
+    }
+    Ok(())
+}
+
+/// Sends a command to the running engine and prints the reply.
+///
+/// Usage: `ctl [--socket <file>] [--json] <command> [arguments...]` with the commands `status`,
+/// `backlog [input]`, `set-gain <input> <dB>`, `set-tempo <input> <speed|auto>`,
+/// `pause <input>`, `resume <input>` and `flush <input>`. `--json` prints the reply as is.
+pub fn run(mut args: impl Iterator<Item = String>) -> anyhow::Result<()> {
+    let mut path = None;
+    let mut json = false;
+    let mut words = Vec::new();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--socket" => {
+                path = Some(PathBuf::from(
+                    args.next()
+                        .ok_or_else(|| anyhow!("Missing value for {arg}"))?,
+                ))
+            }
+            "--json" => json = true,
+            _ => words.push(arg),
+        }
+    }
+    let request = parse_request(&words)?;
+    let path = match path {
+        Some(path) => path,
+        None => default_path().context("No default control socket, pass --socket <file>")?,
+    };
+
+    let stream = UnixStream::connect(&path).with_context(|| {
+        format!(
+            "Failed to connect to {}, is the engine running?",
+            path.display()
+        )
+    })?;
+    writeln!(&stream, "{}", serde_json::to_string(&request)?)?;
+    let mut line = String::new();
+    BufReader::new(&stream).read_line(&mut line)?;
+    let reply: Value = serde_json::from_str(&line).context("Invalid reply from the engine")?;
+    if json {
+        println!("{reply}");
+    }
+    if reply["ok"] != true {
+        bail!(
+            "{}",
+            reply["error"]
+                .as_str()
+                .unwrap_or("The engine gave no reason")
+        );
+    }
+    if !json {
+        print_reply(&reply);
+    }
+    Ok(())
+}
+
+fn parse_request(words: &[String]) -> anyhow::Result<Request> {
+    let words: Vec<&str> = words.iter().map(String::as_str).collect();
+    let input = |name: &&str| name.to_string();
+    Ok(match words.as_slice() {
+        ["status"] => Request::ListInputs,
+        ["backlog"] => Request::Backlog { input: None },
+        ["backlog", name] => Request::Backlog {
+            input: Some(input(name)),
+        },
+        ["set-gain", name, db] => Request::SetGain {
+            input: input(name),
+            db: db.parse().with_context(|| format!("Invalid gain '{db}'"))?,
+        },
+        ["set-tempo", name, "auto"] => Request::SetTempo {
+            input: input(name),
+            speed: None,
+        },
+        ["set-tempo", name, speed] => Request::SetTempo {
+            input: input(name),
+            speed: Some(
+                speed
+                    .parse()
+                    .with_context(|| format!("Invalid speed '{speed}'"))?,
+            ),
+        },
+        ["pause", name] => Request::Pause { input: input(name) },
+        ["resume", name] => Request::Resume { input: input(name) },
+        ["flush", name] => Request::Flush { input: input(name) },
+        [] => bail!("Missing command, e.g. status"),
+        _ => bail!("Unknown command or arguments '{}'", words.join(" ")),
+    })
+}
+
+fn print_reply(reply: &Value) {
+    if let Some(inputs) = reply["inputs"].as_array() {
+        for input in inputs {
+            let mut line = format!(
+                "{}: {:.1}s backlog at {:.2}x, {:+.1} dB",
+                input["name"].as_str().unwrap_or_default(),
+                input["backlog"].as_f64().unwrap_or_default(),
+                input["speed"].as_f64().unwrap_or_default(),
+                input["gain_db"].as_f64().unwrap_or_default()
+            );
+            for flag in ["playing", "disabled", "held", "source_paused"] {
+                if input[flag] == true {
+                    line.push_str(", ");
+                    line.push_str(&flag.replace('_', " "));
+                }
+            }
+            println!("{line}");
+        }
+    }
+    if let Some(backlogs) = reply["backlog"].as_object() {
+        for (name, seconds) in backlogs {
+            println!("{name}: {:.1}s", seconds.as_f64().unwrap_or_default());
+        }
+    }
+    if let Some(message) = reply["message"].as_str() {
+        println!("{message}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_the_owner_may_connect() {
+        let path = env::temp_dir().join(format!("audiomux-test-{}.sock", std::process::id()));
+        let jack_state = Arc::new(Mutex::new(JackState {
+            inputs: vec![Input {
+                name: "music".to_string(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        }));
+        serve(&path, jack_state).unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        let stream = UnixStream::connect(&path).unwrap();
+        writeln!(&stream, r#"{{"command": "list-inputs"}}"#).unwrap();
+        let mut line = String::new();
+        BufReader::new(&stream).read_line(&mut line).unwrap();
+        let reply: Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(reply["ok"], true);
+        assert_eq!(reply["inputs"][0]["name"], "music");
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -4,7 +4,7 @@ use std::ffi::{c_int, c_void};
 
 use soundtouch_sys::{soundtouch_SoundTouch, uint};
 
-enum Setting {
+pub enum Setting {
     /// Enable/disable anti-alias filter in pitch transposer (0 = disable)
     UseAaFilter,
 
@@ -45,6 +45,8 @@ enum Setting {
     /// - This is read-only parameter, i.e. setSetting ignores this parameter
     /// - This parameter value is not pub constant but change depending on
     ///   tempo/pitch/rate/samplerate settings.
+    // Read-only, for the complete list of IDs, the wrapper only sets settings
+    #[allow(dead_code)]
     NominalInputSequence,
 
     /// Call "getSetting" with this ID to query nominal average processing output
@@ -55,6 +57,8 @@ enum Setting {
     /// - This is read-only parameter, i.e. setSetting ignores this parameter
     /// - This parameter value is not pub constant but change depending on
     ///   tempo/pitch/rate/samplerate settings.
+    // Read-only, for the complete list of IDs, the wrapper only sets settings
+    #[allow(dead_code)]
     NominalOutputSequence,
 
     /// Call "getSetting" with this ID to query initial processing latency, i.e.
@@ -84,6 +88,8 @@ enum Setting {
     /// - This is read-only parameter, i.e. setSetting ignores this parameter
     /// - This parameter value is not pub constant but change depending on
     ///   tempo/pitch/rate/samplerate settings.
+    // Read-only, for the complete list of IDs, the wrapper only sets settings
+    #[allow(dead_code)]
     InitialLatency,
 }
 
@@ -105,21 +111,29 @@ impl Setting {
 
 pub struct SoundTouch {
     inner: soundtouch_SoundTouch,
+    // Last values set, SoundTouch has no getters for them
+    tempo: f64,
+    rate: f64,
+    pitch: f64,
 }
 
 unsafe impl Send for SoundTouch {}
 
 impl Default for SoundTouch {
     fn default() -> Self {
-        let inner = unsafe { soundtouch_SoundTouch::new() };
-        Self { inner }
+        Self::new()
     }
 }
 
 impl SoundTouch {
     pub fn new() -> Self {
         let inner = unsafe { soundtouch_SoundTouch::new() };
-        Self { inner }
+        Self {
+            inner,
+            tempo: 1.0,
+            rate: 1.0,
+            pitch: 1.0,
+        }
     }
 
     pub fn set_channels(&mut self, num_channels: u32) {
@@ -132,12 +146,50 @@ impl SoundTouch {
         }
     }
 
+    // Sets new tempo control value. Normal tempo = 1.0, smaller values represent slower
+    // tempo, larger faster tempo.
     pub fn set_tempo(&mut self, tempo: f64) {
+        self.tempo = tempo;
         unsafe {
             self.inner.setTempo(tempo);
         }
     }
 
+    pub fn tempo(&self) -> f64 {
+        self.tempo
+    }
+
+    // Sets new rate control value. Normal rate = 1.0, smaller values represent slower rate,
+    // larger faster rates.
+    pub fn set_rate(&mut self, rate: f64) {
+        self.rate = rate;
+        unsafe {
+            self.inner.setRate(rate);
+        }
+    }
+
+    pub fn rate(&self) -> f64 {
+        self.rate
+    }
+
+    // Sets new pitch control value. Original pitch = 1.0, smaller values represent lower
+    // pitches, larger values higher pitch.
+    pub fn set_pitch(&mut self, pitch: f64) {
+        self.pitch = pitch;
+        unsafe {
+            self.inner.setPitch(pitch);
+        }
+    }
+
+    // Sets pitch change in semi-tones compared to the original pitch (-12 .. +12)
+    pub fn set_pitch_semitones(&mut self, semitones: f64) {
+        self.set_pitch(2f64.powf(semitones / 12.0));
+    }
+
+    pub fn pitch(&self) -> f64 {
+        self.pitch
+    }
+
     pub fn set_setting(&mut self, setting: Setting, value: i64) {
         unsafe {
             self.inner.setSetting(setting.as_c_int(), value as c_int);
@@ -172,10 +224,36 @@ impl SoundTouch {
         }
     }
 
+    // Clears all the samples in the object's output and internal processing buffers.
+    pub fn clear(&mut self) {
+        unsafe {
+            soundtouch_sys::soundtouch_SoundTouch_clear(&mut self.inner as *mut _ as *mut c_void);
+        }
+    }
+
+    // Flushes the last samples from the processing pipeline to the output. Clears also the
+    // internal processing buffers.
+    //
+    // Note: This function is meant for extracting the last samples of a sound stream. This
+    // function may introduce additional blank samples in the end of the sound stream, and thus
+    // it's not recommended to call this function in the middle of a sound stream.
+    pub fn flush(&mut self) {
+        unsafe {
+            soundtouch_sys::soundtouch_SoundTouch_flush(&mut self.inner);
+        }
+    }
+
+    // Returns number of samples currently available for receiving.
     pub fn num_samples(&self) -> usize {
         unsafe {
-            println!("{:?}", (*self.inner._base.output).vtable_);
-            0
+            soundtouch_sys::soundtouch_SoundTouch_numSamples(
+                &self.inner as *const _ as *const c_void,
+            ) as usize
         }
     }
+
+    // Get number of samples in the internal buffers, not yet processed to the output.
+    pub fn num_unprocessed_samples(&self) -> usize {
+        unsafe { soundtouch_sys::soundtouch_SoundTouch_numUnprocessedSamples(&self.inner) as usize }
+    }
 }
@@ -0,0 +1,186 @@
+use std::{collections::VecDeque, f32::consts::PI, fmt};
+
+/// Samples per analysis, a power of two
+pub const FFT_SIZE: usize = 2048;
+
+/// Number of log spaced bands shown
+const BANDS: usize = 32;
+const LOWEST_FREQUENCY: f32 = 40.0;
+const HIGHEST_FREQUENCY: f32 = 16000.0;
+
+/// Level range of the display in dBFS
+const FLOOR_DB: f32 = -90.0;
+const CEILING_DB: f32 = 0.0;
+
+/// Rows kept in the spectrogram
+const SPECTROGRAM_ROWS: usize = 12;
+
+const LEVEL_CHARACTERS: [char; 9] = [' ', '▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+const SPECTROGRAM_CHARACTERS: [char; 5] = [' ', '░', '▒', '▓', '█'];
+
+/// What the analyzer looks at
+#[derive(Clone, Debug, PartialEq)]
+pub enum SpectrumSource {
+    Output,
+    /// Captured audio of the input with this name
+    Input(String),
+}
+
+impl fmt::Display for SpectrumSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SpectrumSource::Output => write!(f, "output"),
+            SpectrumSource::Input(name) => write!(f, "input {name}"),
+        }
+    }
+}
+
+/// Collects the latest samples of the analyzed signal in the audio thread
+pub struct Tap {
+    pub source: SpectrumSource,
+    pub spectrogram: bool,
+    samples: VecDeque<f32>,
+}
+
+impl Tap {
+    pub fn new(source: SpectrumSource, spectrogram: bool) -> Self {
+        Self {
+            source,
+            spectrogram,
+            samples: VecDeque::with_capacity(FFT_SIZE),
+        }
+    }
+
+    /// Adds a block of per-channel samples, mixed down to mono
+    pub fn push(&mut self, channels: &[&[f32]]) {
+        let length = channels.first().map_or(0, |channel| channel.len());
+        for index in 0..length {
+            if self.samples.len() == FFT_SIZE {
+                self.samples.pop_front();
+            }
+            let sum: f32 = channels.iter().map(|channel| channel[index]).sum();
+            self.samples.push_back(sum / channels.len() as f32);
+        }
+    }
+
+    /// Copies the latest samples, so the analysis can run without holding up the audio thread
+    pub fn snapshot(&self) -> Vec<f32> {
+        self.samples.iter().copied().collect()
+    }
+}
+
+/// Computes and renders spectra outside of the audio thread
+#[derive(Default)]
+pub struct Analyzer {
+    /// Band levels in dBFS of the latest analyses, newest last
+    history: VecDeque<Vec<f32>>,
+}
+
+impl Analyzer {
+    /// Analyzes the latest samples of the tapped signal
+    pub fn analyze(&mut self, samples: &[f32], sample_rate: usize) {
+        if self.history.len() == SPECTROGRAM_ROWS {
+            self.history.pop_front();
+        }
+        self.history.push_back(band_levels(samples, sample_rate));
+    }
+
+    pub fn clear(&mut self) {
+        self.history.clear();
+    }
+
+    /// Renders the latest spectrum as a line of bars
+    pub fn spectrum(&self) -> String {
+        self.history
+            .back()
+            .map_or_else(String::new, |levels| render(levels, &LEVEL_CHARACTERS))
+    }
+
+    /// Renders the recent spectra as rows of shades, oldest first
+    pub fn spectrogram(&self) -> Vec<String> {
+        self.history
+            .iter()
+            .map(|levels| render(levels, &SPECTROGRAM_CHARACTERS))
+            .collect()
+    }
+}
+
+fn render(levels: &[f32], characters: &[char]) -> String {
+    levels
+        .iter()
+        .map(|level| {
+            let position = ((level - FLOOR_DB) / (CEILING_DB - FLOOR_DB)).clamp(0.0, 1.0);
+            characters[(position * (characters.len() - 1) as f32).round() as usize]
+        })
+        .collect()
+}
+
+/// Peak magnitude in dBFS of log spaced bands between `LOWEST_FREQUENCY` and `HIGHEST_FREQUENCY`
+fn band_levels(samples: &[f32], sample_rate: usize) -> Vec<f32> {
+    let mut real = vec![0.0; FFT_SIZE];
+    let mut imaginary = vec![0.0; FFT_SIZE];
+    // Hann window, the latest samples at the end
+    let offset = FFT_SIZE.saturating_sub(samples.len());
+    for (index, sample) in samples.iter().rev().take(FFT_SIZE).rev().enumerate() {
+        let position = index + offset;
+        let window = 0.5 - 0.5 * (2.0 * PI * position as f32 / FFT_SIZE as f32).cos();
+        real[position] = sample * window;
+    }
+    fft(&mut real, &mut imaginary);
+
+    let bin_width = sample_rate as f32 / FFT_SIZE as f32;
+    let ratio = HIGHEST_FREQUENCY / LOWEST_FREQUENCY;
+    (0..BANDS)
+        .map(|band| {
+            let low = LOWEST_FREQUENCY * ratio.powf(band as f32 / BANDS as f32);
+            let high = LOWEST_FREQUENCY * ratio.powf((band + 1) as f32 / BANDS as f32);
+            let first_bin = ((low / bin_width) as usize).max(1);
+            let last_bin = ((high / bin_width) as usize)
+                .max(first_bin)
+                .min(FFT_SIZE / 2 - 1);
+            let magnitude = (first_bin..=last_bin)
+                .map(|bin| (real[bin] * real[bin] + imaginary[bin] * imaginary[bin]).sqrt())
+                .fold(0.0, f32::max);
+            // A full scale sine shows as 0 dBFS, the Hann window halves the amplitude
+            let amplitude = magnitude * 4.0 / FFT_SIZE as f32;
+            20.0 * amplitude.max(1e-9).log10()
+        })
+        .collect()
+}
+
+/// In-place iterative radix-2 FFT, the length must be a power of two
+fn fft(real: &mut [f32], imaginary: &mut [f32]) {
+    let length = real.len();
+    let mut j = 0;
+    for i in 1..length {
+        let mut bit = length >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            real.swap(i, j);
+            imaginary.swap(i, j);
+        }
+    }
+
+    let mut size = 2;
+    while size <= length {
+        let angle = -2.0 * PI / size as f32;
+        for start in (0..length).step_by(size) {
+            for k in 0..size / 2 {
+                let (sin, cos) = (angle * k as f32).sin_cos();
+                let even = start + k;
+                let odd = even + size / 2;
+                let odd_real = real[odd] * cos - imaginary[odd] * sin;
+                let odd_imaginary = real[odd] * sin + imaginary[odd] * cos;
+                real[odd] = real[even] - odd_real;
+                imaginary[odd] = imaginary[even] - odd_imaginary;
+                real[even] += odd_real;
+                imaginary[even] += odd_imaginary;
+            }
+        }
+        size <<= 1;
+    }
+}
@@ -0,0 +1,182 @@
+//! Buffered audio moved to disk under memory pressure, read back before it plays.
+//!
+//! A spill is a queue of buffer items in a file that is deleted right after it is created, so
+//! nothing is left behind when the engine stops or crashes. Items are appended at the end and
+//! read from the front, the file is dropped once everything was read back.
+
+use std::{
+    env,
+    fs::{self, File, OpenOptions},
+    mem::size_of,
+    os::unix::fs::FileExt,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{Duration, UNIX_EPOCH},
+};
+
+use anyhow::Context;
+
+use crate::{segments::item_length, BufferItem};
+
+const SAMPLES_TAG: u8 = 0;
+const SILENCE_TAG: u8 = 1;
+/// Tag, channel count, frame count and the capture time as seconds and nanoseconds
+const SAMPLES_HEADER_BYTES: usize = 1 + 4 + 4 + 8 + 4;
+const SILENCE_BYTES: usize = 1 + 8;
+
+/// `$XDG_CACHE_HOME/audiomux/spill`, falling back to `~/.cache`
+pub fn default_directory() -> PathBuf {
+    let cache_home = env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".cache")))
+        .unwrap_or_else(env::temp_dir);
+    cache_home.join("audiomux").join("spill")
+}
+
+/// Queue of buffer items in an anonymous file
+pub struct Spill {
+    file: File,
+    /// Where the next item is written
+    write_offset: u64,
+    /// Where the next item is read from
+    read_offset: u64,
+    items: usize,
+    /// Frames of sound in the items, stored silence not included
+    sample_frames: usize,
+}
+
+impl Spill {
+    /// Creates an anonymous spill file in `directory`
+    pub fn create(directory: &Path) -> anyhow::Result<Self> {
+        static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+        fs::create_dir_all(directory)
+            .with_context(|| format!("Failed to create {}", directory.display()))?;
+        let path = directory.join(format!(
+            "{}-{}",
+            std::process::id(),
+            NEXT_ID.fetch_add(1, Ordering::Relaxed)
+        ));
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)
+            .with_context(|| format!("Failed to create {}", path.display()))?;
+        // The open file keeps the data until it is closed
+        fs::remove_file(&path).with_context(|| format!("Failed to remove {}", path.display()))?;
+        Ok(Self {
+            file,
+            write_offset: 0,
+            read_offset: 0,
+            items: 0,
+            sample_frames: 0,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items == 0
+    }
+
+    pub fn sample_frames(&self) -> usize {
+        self.sample_frames
+    }
+
+    /// Bytes the spilled audio takes on disk
+    pub fn bytes(&self) -> u64 {
+        self.write_offset - self.read_offset
+    }
+
+    /// Appends items to the end of the queue
+    pub fn push<'a>(
+        &mut self,
+        items: impl IntoIterator<Item = &'a BufferItem>,
+    ) -> anyhow::Result<()> {
+        let mut data = Vec::new();
+        let mut count = 0;
+        let mut frames = 0;
+        for item in items {
+            match item {
+                BufferItem::Samples(samples, captured_at) => {
+                    let since_epoch = captured_at.duration_since(UNIX_EPOCH).unwrap_or_default();
+                    data.push(SAMPLES_TAG);
+                    data.extend_from_slice(&(samples.len() as u32).to_le_bytes());
+                    data.extend_from_slice(&(samples[0].len() as u32).to_le_bytes());
+                    data.extend_from_slice(&since_epoch.as_secs().to_le_bytes());
+                    data.extend_from_slice(&since_epoch.subsec_nanos().to_le_bytes());
+                    for channel in samples {
+                        for sample in channel {
+                            data.extend_from_slice(&sample.to_le_bytes());
+                        }
+                    }
+                }
+                BufferItem::Silence(length) => {
+                    data.push(SILENCE_TAG);
+                    data.extend_from_slice(&(*length as u64).to_le_bytes());
+                }
+            }
+            count += 1;
+            frames += item_length(item);
+        }
+        self.file
+            .write_all_at(&data, self.write_offset)
+            .context("Failed to write spilled audio")?;
+        self.write_offset += data.len() as u64;
+        self.items += count;
+        self.sample_frames += frames;
+        Ok(())
+    }
+
+    /// Takes the item at the front of the queue
+    pub fn pop(&mut self) -> anyhow::Result<Option<BufferItem>> {
+        if self.items == 0 {
+            return Ok(None);
+        }
+        let mut header = [0; SAMPLES_HEADER_BYTES];
+        let available = (self.write_offset - self.read_offset) as usize;
+        let header = &mut header[..available.min(SAMPLES_HEADER_BYTES)];
+        self.read(header)?;
+        let item = match header[0] {
+            SILENCE_TAG => {
+                let length = u64::from_le_bytes(field(&header[1..SILENCE_BYTES]));
+                self.read_offset += SILENCE_BYTES as u64;
+                BufferItem::Silence(length as usize)
+            }
+            _ => {
+                let channel_count = u32::from_le_bytes(field(&header[1..5])) as usize;
+                let frame_count = u32::from_le_bytes(field(&header[5..9])) as usize;
+                let seconds = u64::from_le_bytes(field(&header[9..17]));
+                let nanos = u32::from_le_bytes(field(&header[17..21]));
+                self.read_offset += SAMPLES_HEADER_BYTES as u64;
+
+                let mut data = vec![0; channel_count * frame_count * size_of::<f32>()];
+                self.read(&mut data)?;
+                self.read_offset += data.len() as u64;
+                let samples = (0..channel_count)
+                    .map(|channel| {
+                        let bytes = frame_count * size_of::<f32>();
+                        data[channel * bytes..(channel + 1) * bytes]
+                            .chunks_exact(size_of::<f32>())
+                            .map(|sample| f32::from_le_bytes(field(sample)))
+                            .collect()
+                    })
+                    .collect();
+                let captured_at = UNIX_EPOCH + Duration::new(seconds, nanos);
+                BufferItem::Samples(samples, captured_at)
+            }
+        };
+        self.items -= 1;
+        self.sample_frames -= item_length(&item);
+        Ok(Some(item))
+    }
+
+    fn read(&self, data: &mut [u8]) -> anyhow::Result<()> {
+        self.file
+            .read_exact_at(data, self.read_offset)
+            .context("Failed to read spilled audio")
+    }
+}
+
+/// A field of an item as an array of its length, to be converted from little endian
+fn field<const N: usize>(bytes: &[u8]) -> [u8; N] {
+    bytes.try_into().expect("Field of another length")
+}
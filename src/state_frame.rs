@@ -0,0 +1,102 @@
+//! Compact snapshot of the meters and backlogs for UIs polling at high rates.
+//!
+//! The binary encoding, little endian throughout:
+//!
+//! ```text
+//! "AMXS" version:u8
+//! channel count:u8, then per channel peak dBFS:f32 and RMS dBFS:f32
+//! playing input:u8 (index, 255 for none)
+//! input count:u8, then per input
+//!     name length:u8 name:utf8 backlog seconds:f32 speed:f32 flags:u8
+//! ```
+//!
+//! Input flags are 1 for disabled, 2 for held and 4 for deferred by do-not-disturb. Fields are
+//! only ever appended, in a new version, so readers of older versions can stop at the end of
+//! what they know. JSON carries the same fields for clients that don't care about the cost.
+
+use serde::Serialize;
+
+use crate::JackState;
+
+pub const MAGIC: &[u8; 4] = b"AMXS";
+
+/// Version of the encoding this engine writes
+pub const VERSION: u8 = 1;
+
+pub const DISABLED: u8 = 1;
+pub const HELD: u8 = 2;
+pub const DEFERRED: u8 = 4;
+
+#[derive(Serialize)]
+pub struct StateFrame {
+    version: u8,
+    peaks_db: Vec<f32>,
+    rms_db: Vec<f32>,
+    playing: Option<usize>,
+    inputs: Vec<InputFrame>,
+}
+
+#[derive(Serialize)]
+struct InputFrame {
+    name: String,
+    backlog_seconds: f32,
+    speed: f32,
+    flags: u8,
+}
+
+impl StateFrame {
+    pub fn capture(state: &JackState) -> Self {
+        let (peaks_db, rms_db) = match &state.output_reading {
+            Some(reading) => (reading.peaks_db.clone(), reading.rms_db.clone()),
+            None => (Vec::new(), Vec::new()),
+        };
+        let sample_rate = state.sample_rate.max(1) as f32;
+        Self {
+            version: VERSION,
+            peaks_db,
+            rms_db,
+            playing: state
+                .playing
+                .as_ref()
+                .and_then(|playing| state.inputs.iter().position(|input| input.name == *playing)),
+            inputs: state
+                .inputs
+                .iter()
+                .map(|input| InputFrame {
+                    name: input.name.clone(),
+                    backlog_seconds: input.buffered_samples() as f32 / sample_rate,
+                    speed: input.tempo(state.speed_trim) as f32,
+                    flags: (u8::from(input.disabled) * DISABLED)
+                        | (u8::from(input.held) * HELD)
+                        | (u8::from(input.deferred) * DEFERRED),
+                })
+                .collect(),
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(16 + self.inputs.len() * 24);
+        bytes.extend_from_slice(MAGIC);
+        bytes.push(self.version);
+        bytes.push(self.peaks_db.len() as u8);
+        for (peak, rms) in self.peaks_db.iter().zip(&self.rms_db) {
+            bytes.extend_from_slice(&peak.to_le_bytes());
+            bytes.extend_from_slice(&rms.to_le_bytes());
+        }
+        bytes.push(self.playing.map_or(u8::MAX, |index| index as u8));
+        bytes.push(self.inputs.len() as u8);
+        for input in &self.inputs {
+            let name = &input.name.as_bytes()[..input.name.len().min(u8::MAX as usize)];
+            bytes.push(name.len() as u8);
+            bytes.extend_from_slice(name);
+            bytes.extend_from_slice(&input.backlog_seconds.to_le_bytes());
+            bytes.extend_from_slice(&input.speed.to_le_bytes());
+            bytes.push(input.flags);
+        }
+        bytes
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("State frames are plain data")
+    }
+}
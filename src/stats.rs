@@ -0,0 +1,126 @@
+use std::{fmt, time::Duration};
+
+/// Session statistics of an input, sampled by the status loop
+#[derive(Clone, Debug, Default)]
+pub struct InputStats {
+    /// Time covered by the samples
+    observed: Duration,
+    /// Time the input had audio queued
+    active: Duration,
+    /// Time the input played
+    playing: Duration,
+    /// Backlog in seconds integrated over the observed time
+    backlog_integral: f64,
+    peak_backlog: f64,
+    /// Playback speed integrated over the time the input played
+    speed_integral: f64,
+    /// Audio played in less time than it took to capture, in seconds
+    time_saved: f64,
+}
+
+impl InputStats {
+    /// Adds a sample covering the `elapsed` time since the previous one. Only time the input
+    /// `played` counts towards its speed, queued behind another input it saves no time.
+    pub fn record(&mut self, elapsed: Duration, backlog_seconds: f64, speed: f64, played: bool) {
+        let seconds = elapsed.as_secs_f64();
+        self.observed += elapsed;
+        self.backlog_integral += backlog_seconds * seconds;
+        self.peak_backlog = self.peak_backlog.max(backlog_seconds);
+        if backlog_seconds > 0.0 {
+            self.active += elapsed;
+        }
+        if played {
+            self.playing += elapsed;
+            self.speed_integral += speed * seconds;
+            self.time_saved += (speed - 1.0) * seconds;
+        }
+    }
+
+    /// Fraction of the session the input had something to play
+    pub fn duty_cycle(&self) -> f64 {
+        ratio(self.active.as_secs_f64(), self.observed.as_secs_f64())
+    }
+
+    pub fn average_backlog(&self) -> f64 {
+        ratio(self.backlog_integral, self.observed.as_secs_f64())
+    }
+
+    pub fn peak_backlog(&self) -> f64 {
+        self.peak_backlog
+    }
+
+    /// Seconds the input played, what the average speed and the time saved are taken over
+    pub fn played_seconds(&self) -> f64 {
+        self.playing.as_secs_f64()
+    }
+
+    /// Average playback speed while the input played, 1.0 if it never did
+    pub fn average_speed(&self) -> f64 {
+        if self.playing.is_zero() {
+            1.0
+        } else {
+            self.speed_integral / self.playing.as_secs_f64()
+        }
+    }
+
+    /// Seconds saved by playing faster than real time, negative if it was slowed down
+    pub fn time_saved(&self) -> f64 {
+        self.time_saved
+    }
+}
+
+impl fmt::Display for InputStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let minutes = self.observed.as_secs() / 60;
+        write!(
+            f,
+            "active {:.0}% of {}h{:02}m, backlog avg {:.1}s peak {:.1}s, speed avg {:.2}x",
+            self.duty_cycle() * 100.0,
+            minutes / 60,
+            minutes % 60,
+            self.average_backlog(),
+            self.peak_backlog,
+            self.average_speed()
+        )
+    }
+}
+
+fn ratio(numerator: f64, denominator: f64) -> f64 {
+    if denominator > 0.0 {
+        numerator / denominator
+    } else {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECOND: Duration = Duration::from_secs(1);
+
+    #[test]
+    fn counts_speed_only_while_playing() {
+        let mut stats = InputStats::default();
+        // Queued behind another input
+        stats.record(SECOND, 10.0, 1.5, false);
+        assert_eq!(stats.average_speed(), 1.0);
+        assert_eq!(stats.time_saved(), 0.0);
+
+        stats.record(SECOND, 8.0, 1.5, true);
+        stats.record(SECOND, 6.0, 1.0, true);
+        assert_eq!(stats.average_speed(), 1.25);
+        assert_eq!(stats.time_saved(), 0.5);
+    }
+
+    #[test]
+    fn integrates_the_backlog_either_way() {
+        let mut stats = InputStats::default();
+        stats.record(SECOND, 4.0, 1.0, false);
+        stats.record(SECOND, 2.0, 1.0, true);
+        stats.record(2 * SECOND, 0.0, 1.0, false);
+        assert_eq!(stats.average_backlog(), 1.5);
+        assert_eq!(stats.peak_backlog(), 4.0);
+        assert_eq!(stats.duty_cycle(), 0.5);
+    }
+}
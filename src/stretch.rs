@@ -0,0 +1,263 @@
+use std::str::FromStr;
+
+use anyhow::bail;
+use serde::Deserialize;
+
+use crate::sound_touch::{Setting, SoundTouch};
+
+/// Available speed change engines
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Engine {
+    #[default]
+    SoundTouch,
+    Resample,
+}
+
+impl FromStr for Engine {
+    type Err = anyhow::Error;
+
+    fn from_str(text: &str) -> anyhow::Result<Self> {
+        Ok(match text {
+            "soundtouch" => Engine::SoundTouch,
+            "resample" => Engine::Resample,
+            _ => bail!("Unknown engine '{text}', expected 'soundtouch' or 'resample'"),
+        })
+    }
+}
+
+/// Tuning of the SoundTouch engine, settings left out keep SoundTouch's defaults
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SoundTouchSettings {
+    /// Length of the pieces the sound is chopped into
+    pub sequence_ms: Option<u32>,
+    /// How far around a joint the best overlap is searched for
+    pub seek_window_ms: Option<u32>,
+    /// Length of the crossfade between two pieces
+    pub overlap_ms: Option<u32>,
+    /// Faster search for the overlap at a small cost in quality
+    pub quick_seek: Option<bool>,
+    pub anti_alias_filter: Option<bool>,
+    /// Taps of the anti-alias filter of pitch shifting, a multiple of 4 from 8 to 128
+    pub anti_alias_length: Option<u32>,
+}
+
+impl SoundTouchSettings {
+    fn apply(&self, soundtouch: &mut SoundTouch) {
+        let settings = [
+            (Setting::SequenceMs, self.sequence_ms.map(i64::from)),
+            (Setting::SeekwindowMs, self.seek_window_ms.map(i64::from)),
+            (Setting::OverlapMs, self.overlap_ms.map(i64::from)),
+            (Setting::UseQuickseek, self.quick_seek.map(i64::from)),
+            (Setting::UseAaFilter, self.anti_alias_filter.map(i64::from)),
+            (
+                Setting::AaFilterLength,
+                self.anti_alias_length.map(i64::from),
+            ),
+        ];
+        for (setting, value) in settings {
+            if let Some(value) = value {
+                soundtouch.set_setting(setting, value);
+            }
+        }
+    }
+}
+
+impl Engine {
+    pub fn create(
+        self,
+        channel_count: usize,
+        sample_rate: usize,
+        settings: &SoundTouchSettings,
+    ) -> Box<dyn TimeStretch> {
+        let mut stretcher: Box<dyn TimeStretch> = match self {
+            Engine::SoundTouch => {
+                let mut soundtouch = SoundTouch::default();
+                settings.apply(&mut soundtouch);
+                Box::new(soundtouch)
+            }
+            Engine::Resample => Box::new(Resampler::default()),
+        };
+        stretcher.set_channels(channel_count);
+        stretcher.set_sample_rate(sample_rate);
+        stretcher
+    }
+}
+
+/// Engine changing the speed of audio streams.
+///
+/// `tempo` changes the speed while keeping the pitch, `rate` changes speed and pitch together
+/// like playing a tape faster. Engines that can't keep the pitch map both to a plain rate
+/// change.
+pub trait TimeStretch: Send {
+    fn set_channels(&mut self, channel_count: usize);
+    fn set_sample_rate(&mut self, sample_rate: usize);
+    fn set_tempo(&mut self, tempo: f64);
+    fn set_rate(&mut self, rate: f64);
+    /// Shifts the pitch by `semitones` without changing the speed, engines that can't keep the
+    /// pitch ignore it
+    fn set_pitch_semitones(&mut self, semitones: f64);
+    /// Adds `frame_count` frames of interleaved samples
+    fn put_samples(&mut self, samples: &[f32], frame_count: usize);
+    /// Takes up to `max_frames` frames of interleaved samples, returns the number of frames
+    fn receive_samples(&mut self, samples: &mut [f32], max_frames: usize) -> usize;
+    /// Drops the samples in the pipeline, processed or not
+    fn clear(&mut self);
+    /// Processes what is left in the pipeline so it can all be received, for the end of a stream
+    fn flush(&mut self);
+    /// Frames in the pipeline, ready to be received or still processing
+    fn pending_frames(&self) -> usize;
+}
+
+impl Default for Box<dyn TimeStretch> {
+    fn default() -> Self {
+        Box::new(SoundTouch::default())
+    }
+}
+
+impl TimeStretch for SoundTouch {
+    fn set_channels(&mut self, channel_count: usize) {
+        SoundTouch::set_channels(self, channel_count as u32)
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: usize) {
+        SoundTouch::set_sample_rate(self, sample_rate as u32)
+    }
+
+    // Set every period, SoundTouch only hears about changes
+    fn set_tempo(&mut self, tempo: f64) {
+        if tempo != self.tempo() {
+            SoundTouch::set_tempo(self, tempo)
+        }
+    }
+
+    fn set_rate(&mut self, rate: f64) {
+        if rate != self.rate() {
+            SoundTouch::set_rate(self, rate)
+        }
+    }
+
+    fn set_pitch_semitones(&mut self, semitones: f64) {
+        if 2f64.powf(semitones / 12.0) != self.pitch() {
+            SoundTouch::set_pitch_semitones(self, semitones)
+        }
+    }
+
+    fn put_samples(&mut self, samples: &[f32], frame_count: usize) {
+        SoundTouch::put_samples(self, samples, frame_count)
+    }
+
+    fn receive_samples(&mut self, samples: &mut [f32], max_frames: usize) -> usize {
+        SoundTouch::receive_samples(self, samples, max_frames)
+    }
+
+    fn clear(&mut self) {
+        SoundTouch::clear(self)
+    }
+
+    fn flush(&mut self) {
+        SoundTouch::flush(self)
+    }
+
+    fn pending_frames(&self) -> usize {
+        self.num_samples() + self.num_unprocessed_samples()
+    }
+}
+
+/// Varispeed engine using linear interpolation, for when SoundTouch is not wanted.
+///
+/// Can't keep the pitch, so tempo and rate both change speed and pitch.
+pub struct Resampler {
+    channel_count: usize,
+    tempo: f64,
+    rate: f64,
+    /// Interleaved input frames not fully consumed yet
+    input: Vec<f32>,
+    /// Fractional read position in `input` in frames
+    position: f64,
+}
+
+impl Default for Resampler {
+    fn default() -> Self {
+        Self {
+            channel_count: 1,
+            tempo: 1.0,
+            rate: 1.0,
+            input: Vec::new(),
+            position: 0.0,
+        }
+    }
+}
+
+impl TimeStretch for Resampler {
+    fn set_channels(&mut self, channel_count: usize) {
+        self.channel_count = channel_count.max(1);
+        self.input.clear();
+        self.position = 0.0;
+    }
+
+    fn set_sample_rate(&mut self, _sample_rate: usize) {}
+
+    fn set_tempo(&mut self, tempo: f64) {
+        self.tempo = tempo;
+    }
+
+    fn set_rate(&mut self, rate: f64) {
+        self.rate = rate;
+    }
+
+    fn set_pitch_semitones(&mut self, _semitones: f64) {}
+
+    fn put_samples(&mut self, samples: &[f32], frame_count: usize) {
+        self.input
+            .extend_from_slice(&samples[..frame_count * self.channel_count]);
+    }
+
+    fn receive_samples(&mut self, samples: &mut [f32], max_frames: usize) -> usize {
+        let channels = self.channel_count;
+        let available_frames = self.input.len() / channels;
+        let step = self.tempo * self.rate;
+
+        let mut frames = 0;
+        while frames < max_frames {
+            let index = self.position as usize;
+            // Interpolation needs the following frame as well
+            if index + 1 >= available_frames {
+                break;
+            }
+            let fraction = (self.position - index as f64) as f32;
+            for channel in 0..channels {
+                let current = self.input[index * channels + channel];
+                let next = self.input[(index + 1) * channels + channel];
+                samples[frames * channels + channel] = current + (next - current) * fraction;
+            }
+            self.position += step;
+            frames += 1;
+        }
+
+        let consumed = (self.position as usize).min(available_frames);
+        self.input.drain(..consumed * channels);
+        self.position -= consumed as f64;
+        frames
+    }
+
+    fn clear(&mut self) {
+        self.input.clear();
+        self.position = 0.0;
+    }
+
+    fn flush(&mut self) {
+        // Repeat the last frame so the one before it can be interpolated up to its end
+        let channels = self.channel_count;
+        if self.input.len() >= channels {
+            let last = self.input.len() - channels;
+            self.input.extend_from_within(last..);
+        }
+    }
+
+    fn pending_frames(&self) -> usize {
+        let available_frames = self.input.len() / self.channel_count;
+        available_frames.saturating_sub(self.position as usize + 1)
+    }
+}
@@ -0,0 +1,111 @@
+//! Notices the system going to sleep and waking up, as logind announces it, so the sources are
+//! paused before a suspend and the JACK client is created again afterwards. JACK servers often
+//! come back from a suspend with their devices reset, leaving the graph of the old client broken.
+//!
+//! A delay inhibitor lock gives the engine time to pause the sources: logind waits until it is
+//! released, for up to its `InhibitDelayMaxSec`.
+
+use std::{
+    sync::mpsc::{self, Receiver, Sender},
+    thread,
+    time::Duration,
+};
+
+use anyhow::Context;
+use zbus::{
+    blocking::{Connection, Proxy},
+    zvariant::OwnedFd,
+};
+
+/// Longest the suspend is held off for the engine to get ready, logind gives up on the lock
+/// after five seconds by default
+const READY_TIMEOUT: Duration = Duration::from_secs(3);
+
+pub enum Sleep {
+    /// The system is about to suspend
+    Suspending,
+    /// The system woke up again
+    Resumed,
+}
+
+/// Reports the system suspending and waking up
+pub struct SleepWatch {
+    events: Receiver<Sleep>,
+    ready: Sender<()>,
+}
+
+impl SleepWatch {
+    pub fn watch() -> Self {
+        let (sender, events) = mpsc::channel();
+        let (ready, ready_receiver) = mpsc::channel();
+        thread::spawn(move || {
+            if let Err(error) = watch(&sender, &ready_receiver) {
+                eprintln!("<4>Not preparing for suspend: {error:#}");
+            }
+        });
+        SleepWatch { events, ready }
+    }
+
+    /// The next of the events not handled yet
+    pub fn next(&self) -> Option<Sleep> {
+        self.events.try_recv().ok()
+    }
+
+    /// The engine is ready for the suspend it was told about, it may go on
+    pub fn ready(&self) {
+        let _ = self.ready.send(());
+    }
+}
+
+fn watch(events: &Sender<Sleep>, ready: &Receiver<()>) -> anyhow::Result<()> {
+    let connection = Connection::system().context("Failed to connect to the system bus")?;
+    let manager = Proxy::new(
+        &connection,
+        "org.freedesktop.login1",
+        "/org/freedesktop/login1",
+        "org.freedesktop.login1.Manager",
+    )?;
+    let signals = manager
+        .receive_signal("PrepareForSleep")
+        .context("Failed to subscribe to logind's sleep signal")?;
+    let mut _lock = Some(inhibit(&manager)?);
+    for signal in signals {
+        let suspending: bool = signal.body().deserialize()?;
+        if suspending {
+            // Left over from a suspend the engine was late for
+            while ready.try_recv().is_ok() {}
+            if events.send(Sleep::Suspending).is_err() {
+                return Ok(());
+            }
+            let _ = ready.recv_timeout(READY_TIMEOUT);
+            _lock = None;
+        } else {
+            if events.send(Sleep::Resumed).is_err() {
+                return Ok(());
+            }
+            _lock = match inhibit(&manager) {
+                Ok(lock) => Some(lock),
+                Err(error) => {
+                    eprintln!("<4>The next suspend won't wait for the sources: {error:#}");
+                    None
+                }
+            };
+        }
+    }
+    Ok(())
+}
+
+/// Takes a delay lock on suspending, held until the returned descriptor is closed
+fn inhibit(manager: &Proxy) -> anyhow::Result<OwnedFd> {
+    manager
+        .call(
+            "Inhibit",
+            &(
+                "sleep",
+                "Audio Multiplexer",
+                "Pausing sources before suspending",
+                "delay",
+            ),
+        )
+        .context("Failed to take an inhibitor lock")
+}
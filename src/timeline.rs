@@ -0,0 +1,478 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fmt,
+    time::SystemTime,
+};
+
+use crate::capacity::format_bytes;
+
+/// Number of entries kept in memory
+const CAPACITY: usize = 1024;
+
+#[derive(Clone, Debug)]
+pub enum DropReason {
+    /// Steady low-information sound like hum or a drone
+    Monotonous,
+    /// Segment matching one heard shortly before, like a repeated jingle
+    Repeated,
+    /// Audio matching one of the given snippets, like a station jingle or an ad bumper
+    Jingle(String),
+    /// Skipped by hand, or queued before a message playback jumped to
+    Skipped,
+    /// The oldest queued audio, dropped under memory pressure
+    Oldest,
+}
+
+impl fmt::Display for DropReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DropReason::Monotonous => write!(f, "monotonous"),
+            DropReason::Repeated => write!(f, "repeated"),
+            DropReason::Jingle(name) => write!(f, "matching {name}"),
+            DropReason::Skipped => write!(f, "skipped"),
+            DropReason::Oldest => write!(f, "the oldest"),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum Event {
+    /// Buffered audio was discarded without being played
+    Dropped {
+        input: String,
+        seconds: f32,
+        reason: DropReason,
+    },
+    /// The backlog of an input was taken out of the queue and saved to a file to listen to later
+    #[cfg(feature = "opus")]
+    SavedForLater {
+        input: String,
+        seconds: f32,
+        path: String,
+    },
+    /// Buffered audio matching a snippet is played faster
+    Rushed {
+        input: String,
+        jingle: String,
+        seconds: f32,
+    },
+    /// Text recognized in a segment of an input's audio
+    Transcribed {
+        input: String,
+        message: usize,
+        seconds: f32,
+        text: String,
+    },
+    /// A clip queued again shortly after the first one was not played again
+    Repeated { input: String, count: usize },
+    /// An input's urgency was temporarily multiplied
+    Boosted {
+        input: String,
+        factor: f32,
+        seconds: f32,
+    },
+    /// A temporary boost ran out
+    BoostExpired { input: String },
+    /// An input switched to playing its recording from some time ago
+    TimeShifted { input: String, seconds_behind: f32 },
+    /// An input switched back to its live source
+    Live { input: String },
+    /// A bookmark was placed on an input
+    Bookmarked { input: String, name: String },
+    /// Playback switched to another input
+    Switched { input: String },
+    /// An input was announced by its cue as it played after a gap
+    Cued { input: String },
+    /// An input's source was paused because its backlog grew too long
+    Paused { input: String },
+    /// An input's source was resumed after its backlog was played
+    Resumed { input: String },
+    /// An input was enabled again
+    Enabled { input: String },
+    /// An input stopped being captured and played
+    Disabled { input: String },
+    /// An input was enabled because something connected to it after startup, or stays disabled
+    /// because nothing did
+    AutoArmed { input: String, enabled: bool },
+    /// An event the instance streaming to a network input reported
+    Remote { input: String, text: String },
+    /// Playback of an input was held or released
+    Held { input: String, held: bool },
+    /// Do-not-disturb started or ended
+    DoNotDisturb { on: bool },
+    /// The session went idle or was locked, holding every input, or the user is back
+    Away { away: bool },
+    /// The settings of another profile of the config took effect
+    ProfileSwitched { profile: String },
+    /// An input with ports was added while running
+    InputAdded { input: String, channels: usize },
+    /// An input with ports was removed while running
+    InputRemoved { input: String },
+    /// The noise floor of an input was measured, in dBFS like the suggested silence threshold
+    Calibrated {
+        input: String,
+        noise_floor: f32,
+        threshold: f32,
+        applied: bool,
+    },
+    /// It was measured how long the source of an input keeps playing after being paused and
+    /// takes to play after being resumed, in seconds
+    PauseCalibrated {
+        input: String,
+        pause_latency: f32,
+        resume_latency: f32,
+        applied: bool,
+    },
+    /// Playback was locked to an input, or the lock ended
+    Focused { input: String, focused: bool },
+    /// An input played its backlog and reached its live source
+    CaughtUp { input: String },
+    /// The end of what an input played before was queued again ahead of its resuming source
+    LeadIn { input: String, seconds: f32 },
+    /// The end of what an input played was queued again on request
+    Replayed { input: String, seconds: f32 },
+    /// A file of the file input's playlist started playing
+    FileStarted { input: String, file: String },
+    /// A rule of the recording schedule started recording an input
+    RecordingStarted { input: String, file: String },
+    /// A scheduled recording of an input ended
+    RecordingStopped { input: String, file: String },
+    /// The queued audio of an input was discarded
+    Flushed { input: String, seconds: f32 },
+    /// The output was connected to a new default sink, `latency` is its playback latency
+    OutputMoved { sink: String, latency: f32 },
+    /// The output started or stopped playing through the sound server, while nothing is
+    /// connected to its ports
+    FallbackOutput { playing: bool },
+    /// JACK reported an over- or underrun
+    Xrun,
+    /// The engine thread fell behind the process callback, which played silence for the output
+    /// it didn't have ready and dropped audio it couldn't queue
+    EngineBehind {
+        missing_frames: usize,
+        dropped_frames: usize,
+    },
+    /// The memory in use went above the pressure threshold of the budget, or back below it
+    MemoryPressure {
+        used: usize,
+        budget: usize,
+        under_pressure: bool,
+    },
+    /// Part of an input's backlog was moved to disk under memory pressure
+    Spilled { input: String, seconds: f32 },
+    /// The JACK transport started or stopped rolling, noticed while an input is gated on it
+    Transport { rolling: bool },
+    /// The engine panicked and was started again
+    Restarted { reason: String },
+    /// The system is about to suspend, the sources were paused
+    Suspending,
+    /// The system woke up and the engine started again with its backlogs
+    WokeUp,
+    /// Processing a period panicked and it was replaced by silence
+    CallbackPanicked {
+        input: Option<String>,
+        reason: String,
+        /// Whether the input was disabled because of it
+        disabled: bool,
+    },
+}
+
+impl Event {
+    /// Short name of the kind of event, for summaries
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Event::Dropped { .. } => "dropped",
+            #[cfg(feature = "opus")]
+            Event::SavedForLater { .. } => "saved-for-later",
+            Event::Rushed { .. } => "rushed",
+            Event::Transcribed { .. } => "transcribed",
+            Event::Repeated { .. } => "repeated",
+            Event::Boosted { .. } => "boosted",
+            Event::BoostExpired { .. } => "boost-expired",
+            Event::TimeShifted { .. } => "time-shifted",
+            Event::Live { .. } => "live",
+            Event::Bookmarked { .. } => "bookmarked",
+            Event::Switched { .. } => "switched",
+            Event::Cued { .. } => "cued",
+            Event::Paused { .. } => "paused",
+            Event::Resumed { .. } => "resumed",
+            Event::Enabled { .. } => "enabled",
+            Event::Disabled { .. } => "disabled",
+            Event::AutoArmed { .. } => "auto-armed",
+            Event::Remote { .. } => "remote",
+            Event::Held { .. } => "held",
+            Event::DoNotDisturb { .. } => "do-not-disturb",
+            Event::Away { .. } => "away",
+            Event::ProfileSwitched { .. } => "profile-switched",
+            Event::InputAdded { .. } => "input-added",
+            Event::InputRemoved { .. } => "input-removed",
+            Event::Calibrated { .. } => "calibrated",
+            Event::PauseCalibrated { .. } => "pause-calibrated",
+            Event::Focused { .. } => "focused",
+            Event::CaughtUp { .. } => "caught-up",
+            Event::LeadIn { .. } => "lead-in",
+            Event::Replayed { .. } => "replayed",
+            Event::FileStarted { .. } => "file-started",
+            Event::RecordingStarted { .. } => "recording-started",
+            Event::RecordingStopped { .. } => "recording-stopped",
+            Event::Flushed { .. } => "flushed",
+            Event::OutputMoved { .. } => "output-moved",
+            Event::FallbackOutput { .. } => "fallback-output",
+            Event::Xrun => "xrun",
+            Event::EngineBehind { .. } => "engine-behind",
+            Event::MemoryPressure { .. } => "memory-pressure",
+            Event::Spilled { .. } => "spilled",
+            Event::Transport { .. } => "transport",
+            Event::Restarted { .. } => "restarted",
+            Event::Suspending => "suspending",
+            Event::WokeUp => "woke-up",
+            Event::CallbackPanicked { .. } => "callback-panicked",
+        }
+    }
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Event::Dropped {
+                input,
+                seconds,
+                reason,
+            } => write!(f, "{input}: dropped {seconds:.1}s of {reason} audio"),
+            #[cfg(feature = "opus")]
+            Event::SavedForLater {
+                input,
+                seconds,
+                path,
+            } => write!(f, "{input}: saved {seconds:.1}s for later to {path}"),
+            Event::Rushed {
+                input,
+                jingle,
+                seconds,
+            } => write!(
+                f,
+                "{input}: rushing through {seconds:.1}s matching {jingle}"
+            ),
+            Event::Transcribed {
+                input,
+                message,
+                seconds,
+                text,
+            } => write!(f, "{input}: #{message} {seconds:.1}s saying \"{text}\""),
+            Event::Repeated { input, count } => write!(f, "{input}: clip repeated ×{count}"),
+            Event::Boosted {
+                input,
+                factor,
+                seconds,
+            } => write!(f, "{input}: boosted by {factor} for {seconds:.0}s"),
+            Event::BoostExpired { input } => write!(f, "{input}: boost expired"),
+            Event::TimeShifted {
+                input,
+                seconds_behind,
+            } => write!(f, "{input}: playing from {seconds_behind:.0}s ago"),
+            Event::Live { input } => write!(f, "{input}: back to live"),
+            Event::Bookmarked { input, name } => write!(f, "{input}: bookmarked as '{name}'"),
+            Event::Switched { input } => write!(f, "{input}: now playing"),
+            Event::Cued { input } => write!(f, "{input}: announced"),
+            Event::Paused { input } => write!(f, "{input}: source paused"),
+            Event::Resumed { input } => write!(f, "{input}: source resumed"),
+            Event::Enabled { input } => write!(f, "{input}: enabled"),
+            Event::Disabled { input } => write!(f, "{input}: disabled"),
+            Event::AutoArmed {
+                input,
+                enabled: true,
+            } => write!(f, "{input}: connected, enabled"),
+            Event::AutoArmed {
+                input,
+                enabled: false,
+            } => write!(f, "{input}: nothing connected, stays disabled"),
+            Event::Remote { input, text } => write!(f, "{input} (remote): {text}"),
+            Event::Held { input, held: true } => write!(f, "{input}: held"),
+            Event::Held { input, held: false } => write!(f, "{input}: released"),
+            Event::DoNotDisturb { on: true } => write!(f, "do not disturb"),
+            Event::DoNotDisturb { on: false } => write!(f, "do not disturb ended"),
+            Event::Away { away: true } => write!(f, "away, holding all inputs"),
+            Event::Away { away: false } => write!(f, "back"),
+            Event::ProfileSwitched { profile } => write!(f, "switched to profile {profile}"),
+            Event::InputAdded { input, channels } => {
+                write!(f, "{input}: added with {channels} channels")
+            }
+            Event::InputRemoved { input } => write!(f, "{input}: removed"),
+            Event::Calibrated {
+                input,
+                noise_floor,
+                threshold,
+                applied,
+            } => {
+                let action = if *applied { "applied" } else { "suggested" };
+                write!(
+                    f,
+                    "{input}: noise floor at {noise_floor:.1} dBFS, silence threshold of \
+                     {threshold:.1} dBFS {action}"
+                )
+            }
+            Event::PauseCalibrated {
+                input,
+                pause_latency,
+                resume_latency,
+                applied,
+            } => {
+                let action = if *applied { "applied" } else { "measured" };
+                write!(
+                    f,
+                    "{input}: pause latency of {:.0}ms and resume latency of {:.0}ms {action}",
+                    pause_latency * 1000.0,
+                    resume_latency * 1000.0
+                )
+            }
+            Event::Focused {
+                input,
+                focused: true,
+            } => write!(f, "{input}: focused"),
+            Event::Focused {
+                input,
+                focused: false,
+            } => write!(f, "{input}: focus released"),
+            Event::CaughtUp { input } => write!(f, "{input}: caught up"),
+            Event::LeadIn { input, seconds } => {
+                write!(
+                    f,
+                    "{input}: replaying the last {seconds:.1}s before resuming"
+                )
+            }
+            Event::Replayed { input, seconds } => {
+                write!(f, "{input}: replaying the last {seconds:.1}s")
+            }
+            Event::FileStarted { input, file } => write!(f, "{input}: playing {file}"),
+            Event::RecordingStarted { input, file } => write!(f, "{input}: recording to {file}"),
+            Event::RecordingStopped { input, file } => {
+                write!(f, "{input}: finished recording {file}")
+            }
+            Event::Flushed { input, seconds } => write!(f, "{input}: flushed {seconds:.1}s"),
+            Event::OutputMoved { sink, latency } => {
+                write!(
+                    f,
+                    "output moved to {sink}, {:.0}ms latency",
+                    latency * 1000.0
+                )
+            }
+            Event::FallbackOutput { playing: true } => {
+                write!(f, "output unconnected, playing through the sound server")
+            }
+            Event::FallbackOutput { playing: false } => {
+                write!(
+                    f,
+                    "output connected, stopped playing through the sound server"
+                )
+            }
+            Event::Xrun => write!(f, "xrun"),
+            Event::EngineBehind {
+                missing_frames,
+                dropped_frames,
+            } => write!(
+                f,
+                "engine fell behind, {missing_frames} frames of silence played, \
+                 {dropped_frames} frames dropped"
+            ),
+            Event::MemoryPressure {
+                used,
+                budget,
+                under_pressure: true,
+            } => write!(
+                f,
+                "memory pressure, {} of {} in use",
+                format_bytes(*used),
+                format_bytes(*budget)
+            ),
+            Event::MemoryPressure {
+                used,
+                budget,
+                under_pressure: false,
+            } => write!(
+                f,
+                "memory pressure over, {} of {} in use",
+                format_bytes(*used),
+                format_bytes(*budget)
+            ),
+            Event::Spilled { input, seconds } => {
+                write!(f, "{input}: spilled {seconds:.1}s to disk")
+            }
+            Event::Transport { rolling: true } => write!(f, "transport rolling"),
+            Event::Transport { rolling: false } => write!(f, "transport stopped"),
+            Event::Restarted { reason } => write!(f, "engine restarted after panic: {reason}"),
+            Event::Suspending => write!(f, "suspending, sources paused"),
+            Event::WokeUp => write!(f, "woke up, reconnected to JACK"),
+            Event::CallbackPanicked {
+                input: Some(input),
+                reason,
+                disabled,
+            } => {
+                write!(f, "{input}: processing panicked, period silenced: {reason}")?;
+                if *disabled {
+                    write!(f, ", input disabled")?;
+                }
+                Ok(())
+            }
+            Event::CallbackPanicked {
+                input: None,
+                reason,
+                ..
+            } => write!(f, "processing panicked, period silenced: {reason}"),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Entry {
+    /// Monotonic number of the entry, keeps counting when old entries are discarded
+    pub sequence: u64,
+    pub time: SystemTime,
+    pub event: Event,
+}
+
+/// Record of noteworthy things the multiplexer did, newest last
+#[derive(Default)]
+pub struct Timeline {
+    entries: VecDeque<Entry>,
+    next_sequence: u64,
+    /// Number of events of each kind ever pushed, including discarded ones
+    counts: BTreeMap<&'static str, usize>,
+}
+
+impl Timeline {
+    pub fn push(&mut self, event: Event) {
+        if self.entries.len() == CAPACITY {
+            self.entries.pop_front();
+        }
+        *self.counts.entry(event.kind()).or_default() += 1;
+        self.entries.push_back(Entry {
+            sequence: self.next_sequence,
+            time: SystemTime::now(),
+            event,
+        });
+        self.next_sequence += 1;
+    }
+
+    /// Entries with a sequence number of at least `sequence`
+    pub fn since(&self, sequence: u64) -> impl Iterator<Item = &Entry> {
+        self.entries
+            .iter()
+            .filter(move |entry| entry.sequence >= sequence)
+    }
+
+    /// Sequence number the next entry will get
+    pub fn next_sequence(&self) -> u64 {
+        self.next_sequence
+    }
+
+    pub fn counts(&self) -> &BTreeMap<&'static str, usize> {
+        &self.counts
+    }
+
+    /// Continues the counts of a previous timeline, whose entries are left behind
+    pub fn keep_counts_of(&mut self, previous: &Timeline) {
+        for (kind, count) in &previous.counts {
+            *self.counts.entry(kind).or_default() += count;
+        }
+    }
+}
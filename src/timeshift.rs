@@ -0,0 +1,181 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::JoinHandle,
+    time::{Duration, SystemTime},
+};
+
+use anyhow::anyhow;
+use ringbuf::{HeapConsumer, HeapProducer, HeapRb};
+
+use crate::{
+    recorder::{open_recording, recording_start, recordings},
+    sample_format::wav_samples,
+};
+
+/// Seconds of audio read ahead of playback
+const READ_AHEAD_SECONDS: usize = 5;
+
+/// How long the reader waits before looking for new audio at the live edge
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Plays an input from its logger recordings instead of live, DVR style.
+///
+/// A reader thread streams the recorded files into a ring buffer, following the recording
+/// into newer files as it goes.
+pub struct TimeShift {
+    consumer: HeapConsumer<f32>,
+    channel_count: usize,
+    sample_rate: usize,
+    /// Wall clock time of the first sample played
+    pub start: SystemTime,
+    /// Frames handed out since `start`
+    frames_read: usize,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl TimeShift {
+    /// Starts playing the recordings of `name` in `directory` from wall clock time `start`
+    pub fn start(
+        name: &str,
+        directory: &Path,
+        channel_count: usize,
+        sample_rate: usize,
+        start: SystemTime,
+    ) -> anyhow::Result<Self> {
+        let files = recordings(directory, name)?;
+        let (file_index, file_start) = files
+            .iter()
+            .enumerate()
+            .filter_map(|(index, file)| Some((index, recording_start(file, name)?)))
+            .take_while(|(_, file_start)| *file_start <= start)
+            .last()
+            .or_else(|| {
+                // Before the oldest recording, start at its beginning
+                let file_start = recording_start(files.first()?, name)?;
+                Some((0, file_start))
+            })
+            .ok_or_else(|| anyhow!("No recordings of {name} in {}", directory.display()))?;
+        let offset = start.duration_since(file_start).unwrap_or_default();
+        let frame = (offset.as_secs_f64() * sample_rate as f64) as u32;
+
+        let ring_buffer = HeapRb::new(READ_AHEAD_SECONDS * sample_rate * channel_count);
+        let (producer, consumer) = ring_buffer.split();
+        let stop = Arc::new(AtomicBool::new(false));
+        let mut reader = Reader {
+            name: name.to_string(),
+            directory: directory.to_path_buf(),
+            file: files[file_index].clone(),
+            frame,
+            channel_count,
+            producer,
+        };
+        let thread_stop = stop.clone();
+        let thread = std::thread::spawn(move || {
+            if let Err(error) = reader.run(&thread_stop) {
+                eprintln!("Time shifted playback of {} failed: {error:#}", reader.name);
+            }
+        });
+
+        Ok(Self {
+            consumer,
+            channel_count,
+            sample_rate,
+            start: file_start + offset,
+            frames_read: 0,
+            stop,
+            thread: Some(thread),
+        })
+    }
+
+    /// Original capture time of the next frame to be read
+    pub fn position(&self) -> SystemTime {
+        self.start + Duration::from_secs_f64(self.frames_read as f64 / self.sample_rate as f64)
+    }
+
+    /// Takes up to `frame_count` frames of recorded audio, called from the process callback
+    pub fn read(&mut self, frame_count: usize) -> Option<Vec<Vec<f32>>> {
+        let available = (self.consumer.len() / self.channel_count).min(frame_count);
+        if available == 0 {
+            return None;
+        }
+        self.frames_read += available;
+        let mut period = vec![Vec::with_capacity(available); self.channel_count];
+        for _ in 0..available {
+            for channel in period.iter_mut() {
+                channel.push(self.consumer.pop().unwrap_or(0.0));
+            }
+        }
+        Some(period)
+    }
+}
+
+impl Drop for TimeShift {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+struct Reader {
+    name: String,
+    directory: PathBuf,
+    file: PathBuf,
+    /// Next frame to read from `file`
+    frame: u32,
+    channel_count: usize,
+    producer: HeapProducer<f32>,
+}
+
+impl Reader {
+    fn run(&mut self, stop: &AtomicBool) -> anyhow::Result<()> {
+        while !stop.load(Ordering::Relaxed) {
+            // Reopen every time, the header of a file that is still recorded grows
+            let mut reader = open_recording(&self.file)?;
+            if reader.spec().channels as usize != self.channel_count {
+                return Err(anyhow!(
+                    "{} has the wrong channel count",
+                    self.file.display()
+                ));
+            }
+
+            let duration = reader.duration();
+            if self.frame >= duration {
+                match self.next_file()? {
+                    Some(next) => {
+                        self.file = next;
+                        self.frame = 0;
+                    }
+                    // Caught up with the live recording
+                    None => std::thread::sleep(POLL_INTERVAL),
+                }
+                continue;
+            }
+
+            reader.seek(self.frame)?;
+            let free_frames = self.producer.free_len() / self.channel_count;
+            let frame_count = free_frames.min((duration - self.frame) as usize);
+            if frame_count == 0 {
+                std::thread::sleep(POLL_INTERVAL);
+                continue;
+            }
+            let mut samples = wav_samples(&mut reader);
+            for sample in samples.by_ref().take(frame_count * self.channel_count) {
+                let _ = self.producer.push(sample?);
+            }
+            self.frame += frame_count as u32;
+        }
+        Ok(())
+    }
+
+    fn next_file(&self) -> anyhow::Result<Option<PathBuf>> {
+        let files = recordings(&self.directory, &self.name)?;
+        Ok(files.into_iter().find(|file| *file > self.file))
+    }
+}
@@ -0,0 +1,237 @@
+use std::{collections::VecDeque, path::Path, process::Command, sync::mpsc, time::SystemTime};
+
+use anyhow::{bail, Context};
+
+use crate::{
+    buffer::Buffer,
+    sample_format::write_wav_sample,
+    segments::{chunks, completed_segments},
+    timeline::{Event, Timeline},
+    BufferItem,
+};
+
+/// Segments are cut after this many seconds even without silence, about what speech
+/// recognizers take in one go
+const MAX_SEGMENT_SECONDS: usize = 30;
+
+/// Shorter segments are clicks and breaths rather than speech
+const MIN_SEGMENT_SECONDS: f32 = 0.5;
+
+/// Number of transcripts kept per input
+const KEPT_TRANSCRIPTS: usize = 1000;
+
+/// Text recognized in a segment of an input's audio
+#[derive(Clone, Debug)]
+pub struct Transcript {
+    /// Number of the message, counting up per input
+    pub id: usize,
+    /// Wall clock time the segment's first sample was captured at, identifies the segment
+    pub captured_at: SystemTime,
+    pub seconds: f32,
+    pub text: String,
+}
+
+impl Transcript {
+    /// Whether the text contains all words of the query, ignoring case
+    pub fn matches(&self, query: &str) -> bool {
+        let text = self.text.to_lowercase();
+        query
+            .to_lowercase()
+            .split_whitespace()
+            .all(|word| text.contains(word))
+    }
+}
+
+struct Job {
+    captured_at: SystemTime,
+    samples: Vec<Vec<f32>>,
+}
+
+/// Opt-in transcription of an input's buffered speech by an external command.
+///
+/// Every completed segment (sound between two silences) is written to a temporary WAV file and
+/// handed to the command, e.g. `whisper-cli -nt -f {file}`, which has to print the text. `{file}`
+/// is replaced by the path of the file, which is appended if the command doesn't contain it.
+/// Commands run one at a time on a thread of their own, so a slow recognizer only delays the
+/// text.
+pub struct Transcriber {
+    pub command: String,
+    /// Number of items at the back of the buffer that have not been checked yet
+    unchecked: usize,
+    jobs: mpsc::Sender<Job>,
+    results: mpsc::Receiver<Transcript>,
+    transcripts: VecDeque<Transcript>,
+    next_id: usize,
+}
+
+impl Transcriber {
+    pub fn start(command: &str, channel_count: usize, sample_rate: usize) -> anyhow::Result<Self> {
+        if command.trim().is_empty() {
+            bail!("Missing transcription command");
+        }
+        let (jobs, job_receiver) = mpsc::channel::<Job>();
+        let (result_sender, results) = mpsc::channel();
+        let thread_command = command.to_string();
+        // Ends with the transcriber, when the job sender is dropped
+        std::thread::spawn(move || {
+            let spec = hound::WavSpec {
+                channels: channel_count as u16,
+                sample_rate: sample_rate as u32,
+                bits_per_sample: 16,
+                sample_format: hound::SampleFormat::Int,
+            };
+            for (number, job) in job_receiver.iter().enumerate() {
+                let path = std::env::temp_dir().join(format!(
+                    "audiomux-transcription-{}-{number}.wav",
+                    std::process::id()
+                ));
+                let result = transcribe(&thread_command, &path, spec, &job.samples);
+                let _ = std::fs::remove_file(&path);
+                match result {
+                    Ok(text) if text.is_empty() => {}
+                    Ok(text) => {
+                        let transcript = Transcript {
+                            id: 0,
+                            captured_at: job.captured_at,
+                            seconds: job.samples[0].len() as f32 / sample_rate as f32,
+                            text,
+                        };
+                        if result_sender.send(transcript).is_err() {
+                            return;
+                        }
+                    }
+                    Err(error) => eprintln!("<4>Transcription failed: {error:#}"),
+                }
+            }
+        });
+        Ok(Self {
+            command: command.to_string(),
+            unchecked: 0,
+            jobs,
+            results,
+            transcripts: VecDeque::new(),
+            next_id: 1,
+        })
+    }
+
+    /// Has to be called for every item added to the back of the buffer
+    pub fn item_added(&mut self) {
+        self.unchecked += 1;
+    }
+
+    /// Transcripts of this input, oldest first
+    pub fn transcripts(&self) -> &VecDeque<Transcript> {
+        &self.transcripts
+    }
+
+    pub fn find(&self, id: usize) -> Option<&Transcript> {
+        self.transcripts
+            .iter()
+            .find(|transcript| transcript.id == id)
+    }
+
+    /// Transcripts of segments that have not been played yet
+    pub fn queued(
+        &self,
+        playback_position: Option<SystemTime>,
+    ) -> impl Iterator<Item = &Transcript> {
+        self.transcripts.iter().filter(move |transcript| {
+            playback_position.is_none_or(|position| transcript.captured_at > position)
+        })
+    }
+
+    /// Hands the segments completed since the last call to the command and puts the text that
+    /// came back in the timeline
+    pub fn transcribe(
+        &mut self,
+        input_name: &str,
+        buffer: &Buffer,
+        sample_rate: usize,
+        timeline: &mut Timeline,
+    ) {
+        self.unchecked = self.unchecked.min(buffer.len());
+        // The front item may be partly played already
+        let start = (buffer.len() - self.unchecked).max(1);
+        let (segments, open_start) =
+            completed_segments(buffer, start, MAX_SEGMENT_SECONDS * sample_rate);
+        self.unchecked = buffer.len() - open_start;
+
+        for segment in segments {
+            let Some(BufferItem::Samples(_, captured_at)) = buffer.range(segment.clone()).next()
+            else {
+                continue;
+            };
+            let mut samples: Vec<Vec<f32>> = Vec::new();
+            for chunk in chunks(buffer, segment) {
+                samples.resize(chunk.len(), Vec::new());
+                for (channel, chunk_channel) in samples.iter_mut().zip(chunk) {
+                    channel.extend_from_slice(chunk_channel);
+                }
+            }
+            let length = samples.first().map_or(0, Vec::len);
+            if (length as f32) < MIN_SEGMENT_SECONDS * sample_rate as f32 {
+                continue;
+            }
+            let _ = self.jobs.send(Job {
+                captured_at: *captured_at,
+                samples,
+            });
+        }
+
+        for mut transcript in self.results.try_iter() {
+            // Numbered here rather than by the worker, which skips segments without speech
+            transcript.id = self.next_id;
+            self.next_id += 1;
+            timeline.push(Event::Transcribed {
+                input: input_name.to_string(),
+                message: transcript.id,
+                seconds: transcript.seconds,
+                text: transcript.text.clone(),
+            });
+            if self.transcripts.len() == KEPT_TRANSCRIPTS {
+                self.transcripts.pop_front();
+            }
+            self.transcripts.push_back(transcript);
+        }
+    }
+}
+
+/// Runs the command on the audio and returns what it printed
+fn transcribe(
+    command: &str,
+    path: &Path,
+    spec: hound::WavSpec,
+    samples: &[Vec<f32>],
+) -> anyhow::Result<String> {
+    let mut writer = hound::WavWriter::create(path, spec)
+        .with_context(|| format!("Failed to create {}", path.display()))?;
+    for frame in 0..samples[0].len() {
+        for channel in samples {
+            write_wav_sample(&mut writer, channel[frame])?;
+        }
+    }
+    writer.finalize()?;
+
+    let file = format!("'{}'", path.display());
+    let command = if command.contains("{file}") {
+        command.replace("{file}", &file)
+    } else {
+        format!("{command} {file}")
+    };
+    let output = Command::new("bash")
+        .arg("-c")
+        .arg(&command)
+        .output()
+        .with_context(|| format!("Failed to run '{command}'"))?;
+    if !output.status.success() {
+        bail!(
+            "'{command}' failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    // Recognizers tend to print one line per sentence
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" "))
+}